@@ -86,8 +86,11 @@ impl Bc {
 }
 
 fn bc_msg<'a, 'b>(context: &'a mut BcContext, buf: &'b [u8]) -> IResult<&'b [u8], Bc> {
-    let (buf, header) = bc_header(buf)?;
-    let (buf, body) = bc_body(context, &header, buf)?;
+    let (rest, header) = bc_header(buf)?;
+    if context.capture {
+        context.captured = Some(buf[..buf.len() - rest.len()].to_vec());
+    }
+    let (buf, body) = bc_body(context, &header, rest)?;
 
     let bc = Bc {
         meta: header.to_meta(),
@@ -106,10 +109,14 @@ fn bc_body<'a, 'b, 'c>(
         let (buf, body) = bc_modern_msg(context, header, buf)?;
         Ok((buf, BcBody::ModernMsg(body)))
     } else {
-        let (buf, body) = match header.msg_id {
+        let (rest, body) = match header.msg_id {
             MSG_ID_LOGIN => bc_legacy_login_msg(buf)?,
             _ => (buf, LegacyMsg::UnknownMsg),
         };
+        if let Some(captured) = &mut context.captured {
+            captured.extend_from_slice(&buf[..buf.len() - rest.len()]);
+        }
+        let buf = rest;
         Ok((buf, BcBody::LegacyMsg(body)))
     }
 }
@@ -168,6 +175,10 @@ fn bc_modern_msg<'a, 'b>(
         }
     };
 
+    if let Some(captured) = &mut context.captured {
+        captured.extend_from_slice(processed_ext_buf);
+    }
+
     // Now we'll take the buffer that Nom gave a ref to and parse it.
     let extension;
     if ext_len > 0 {
@@ -198,8 +209,14 @@ fn bc_modern_msg<'a, 'b>(
         let processed_payload_buf =
             xml_crypto::decrypt(header.channel_id as u32, payload_buf, &encryption_protocol);
         if context.in_bin_mode.contains(&(header.msg_num)) {
+            if let Some(captured) = &mut context.captured {
+                captured.extend_from_slice(payload_buf);
+            }
             payload = Some(BcPayloads::Binary(payload_buf.to_vec()));
         } else {
+            if let Some(captured) = &mut context.captured {
+                captured.extend_from_slice(&processed_payload_buf);
+            }
             let xml = BcXml::try_parse(processed_payload_buf.as_slice())
                 .map_err(|_| Err::Error(make_error(buf, ErrorKind::MapRes)))?;
             payload = Some(BcPayloads::BcXml(xml));
@@ -10,28 +10,58 @@ pub const MSG_ID_LOGIN: u32 = 1;
 pub const MSG_ID_LOGOUT: u32 = 2;
 /// Video and Audio Streams messages have this ID
 pub const MSG_ID_VIDEO: u32 = 3;
+/// Stopping a video stream is done with this ID
+pub const MSG_ID_VIDEO_STOP: u32 = 4;
+/// Playing back a recording from the storage is done with this ID
+pub const MSG_ID_REPLAY: u32 = 5;
+/// Stopping the playback of a recording is done with this ID
+pub const MSG_ID_REPLAY_STOP: u32 = 7;
 /// TalkAbility messages have this ID
 pub const MSG_ID_TALKABILITY: u32 = 10;
 /// TalkReset messages have this ID
 pub const MSG_ID_TALKRESET: u32 = 11;
+/// Starting a search of the recordings on the storage is done with this ID
+pub const MSG_ID_FILE_INFO_LIST_OPEN: u32 = 14;
+/// Getting the next recordings of a search is done with this ID
+pub const MSG_ID_FILE_INFO_LIST: u32 = 15;
+/// Ending a search of the recordings is done with this ID
+pub const MSG_ID_FILE_INFO_LIST_CLOSE: u32 = 16;
 /// Reboot messages have this ID
 pub const MSG_ID_REBOOT: u32 = 23;
 /// Request motion detection messages
 pub const MSG_ID_MOTION_REQUEST: u32 = 31;
 /// Motion detection messages
 pub const MSG_ID_MOTION: u32 = 33;
+/// Getting the SD recording settings such as overwrite is done with this ID
+pub const MSG_ID_GET_RECORD_CFG: u32 = 54;
+/// Setting the SD recording settings is done with this ID
+pub const MSG_ID_SET_RECORD_CFG: u32 = 55;
+/// Getting the encoder settings of the streams is done with this ID
+pub const MSG_ID_GET_COMPRESSION: u32 = 56;
+/// Setting the encoder settings of the streams is done with this ID
+pub const MSG_ID_SET_COMPRESSION: u32 = 57;
+/// Downloading a backup of the camera settings is done with this ID
+pub const MSG_ID_CONFIG_EXPORT: u32 = 65;
 /// Version messages have this ID
 pub const MSG_ID_VERSION: u32 = 80;
+/// Getting the recording schedule is done with this ID
+pub const MSG_ID_GET_RECORD: u32 = 81;
+/// Setting the recording schedule is done with this ID
+pub const MSG_ID_SET_RECORD: u32 = 82;
 /// Getting PIR status messages have this ID
 pub const MSG_ID_GET_PIR_ALARM: u32 = 212;
 /// Setting PIR status messages have this ID
 pub const MSG_ID_START_PIR_ALARM: u32 = 213;
 /// Ping messages have this ID
 pub const MSG_ID_PING: u32 = 93;
+/// Storage (SD card or HDD) info messages have this ID
+pub const MSG_ID_HDD_INFO_LIST: u32 = 102;
 /// General system info messages have this ID
 pub const MSG_ID_GET_GENERAL: u32 = 104;
 /// Setting general system info (clock mostly) messages have this ID
 pub const MSG_ID_SET_GENERAL: u32 = 105;
+/// Getting the encoder settings that the streams support is done with this ID
+pub const MSG_ID_STREAM_INFO_LIST: u32 = 146;
 /// Will send the talk config for talk back data to follow this msg
 pub const MSG_ID_TALKCONFIG: u32 = 201;
 /// Used to send talk back binary data
@@ -42,6 +72,18 @@ pub const MSG_ID_GET_LED_STATUS: u32 = 208;
 pub const MSG_ID_SET_LED_STATUS: u32 = 209;
 /// UDP Keep alive
 pub const MSG_ID_UDP_KEEP_ALIVE: u32 = 234;
+/// Playing the alarm audio of the siren is done with this ID
+pub const MSG_ID_PLAY_AUDIO: u32 = 263;
+/// Turning the floodlight on or off by hand is done with this ID
+pub const MSG_ID_FLOODLIGHT_MANUAL: u32 = 288;
+/// Setting when the floodlight turns on by itself is done with this ID
+pub const MSG_ID_SET_FLOODLIGHT_TASK: u32 = 290;
+/// Getting when the floodlight turns on by itself is done with this ID
+pub const MSG_ID_GET_FLOODLIGHT_TASK: u32 = 438;
+/// Getting the privacy mode is done with this ID
+pub const MSG_ID_GET_SLEEP: u32 = 574;
+/// Setting the privacy mode is done with this ID
+pub const MSG_ID_SET_SLEEP: u32 = 575;
 
 /// An empty password in legacy format
 pub const EMPTY_LEGACY_PASSWORD: &str =
@@ -181,6 +223,9 @@ pub(crate) struct BcContext {
     // Arc<Mutex<EncryptionProtocol>> because it is shared between context
     // and connection for deserialisation and serialistion respectivly
     pub(super) encryption_protocol: Arc<Mutex<EncryptionProtocol>>,
+    // When set the header and decrypted body of each message are kept in `captured`
+    pub(crate) capture: bool,
+    pub(crate) captured: Option<Vec<u8>>,
 }
 
 impl Bc {
@@ -221,6 +266,8 @@ impl BcContext {
         BcContext {
             in_bin_mode: HashSet::new(),
             encryption_protocol,
+            capture: false,
+            captured: None,
         }
     }
 
@@ -292,7 +339,7 @@ impl BcHeader {
     }
 }
 
-pub(super) fn has_payload_offset(class: u16) -> bool {
+pub(crate) fn has_payload_offset(class: u16) -> bool {
     // See BcHeader::is_modern() for a description of which packets have the bin offset
     class == 0x6414 || class == 0x0000
 }
@@ -72,6 +72,39 @@ pub struct BcXml {
     /// Received when motion is detected
     #[yaserde(rename = "AlarmEventList")]
     pub alarm_event_list: Option<AlarmEventList>,
+    /// Received as part of the storage info request
+    #[yaserde(rename = "HddInfoList")]
+    pub hdd_info_list: Option<HddInfoList>,
+    /// Sent and received to search for and play back recordings on the storage
+    #[yaserde(rename = "FileInfoList")]
+    pub file_info_list: Option<FileInfoList>,
+    /// Sent and received to get/set the SD recording settings such as overwrite
+    #[yaserde(rename = "RecordCfg")]
+    pub record_cfg: Option<RecordCfg>,
+    /// Sent and received to get/set the recording schedule
+    #[yaserde(rename = "Record")]
+    pub record: Option<Record>,
+    /// Sent to turn the floodlight on or off by hand
+    #[yaserde(rename = "FloodlightManual")]
+    pub floodlight_manual: Option<FloodlightManual>,
+    /// Sent and received to get/set when the floodlight turns on by itself
+    #[yaserde(rename = "FloodlightTask")]
+    pub floodlight_task: Option<FloodlightTask>,
+    /// Sent to sound or silence the siren
+    #[yaserde(rename = "audioPlayInfo")]
+    pub audio_play_info: Option<AudioPlayInfo>,
+    /// Describes a file such as a settings backup that is sent to or from the camera
+    #[yaserde(rename = "ConfigFileInfo")]
+    pub config_file_info: Option<ConfigFileInfo>,
+    /// Sent and received to get/set the encoder settings of the streams
+    #[yaserde(rename = "Compression")]
+    pub compression: Option<Compression>,
+    /// Received with the encoder settings that the streams support
+    #[yaserde(rename = "StreamInfoList")]
+    pub stream_info_list: Option<StreamInfoList>,
+    /// Sent and received to get/set the privacy mode
+    #[yaserde(rename = "sleep")]
+    pub sleep: Option<Sleep>,
 }
 
 impl BcXml {
@@ -442,6 +475,399 @@ pub struct AlarmEvent {
     /// The timestamp associated with the recording. `0` if not recording
     #[yaserde(rename = "timeStamp")]
     pub timeStamp: i32,
+    /// What the AI detection of the camera saw e.g. `"people,vehicle"`.
+    /// Not sent by cameras without AI detection
+    #[yaserde(rename = "AItype")]
+    pub ai_type: Option<String>,
+}
+
+/// An XML with the encoder settings of each stream
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct Compression {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The channel whose settings these are
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Unknown, sent by the camera but not when setting them. Known values `1`
+    #[yaserde(rename = "isNoTranslateFrame")]
+    pub is_no_translate_frame: Option<u8>,
+    /// The settings of the main stream
+    #[yaserde(rename = "mainStream")]
+    pub main_stream: StreamCompression,
+    /// The settings of the sub stream
+    #[yaserde(rename = "subStream")]
+    pub sub_stream: StreamCompression,
+    /// The settings of the extern stream, if the camera has one
+    #[yaserde(rename = "thirdStream")]
+    pub third_stream: Option<StreamCompression>,
+}
+
+/// The encoder settings of one stream
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct StreamCompression {
+    /// Whether the stream has audio. Known values `0` or `1`
+    pub audio: u8,
+    /// The resolution as `"width*height"` e.g. `"2304*1296"`
+    #[yaserde(rename = "resolutionName")]
+    pub resolution_name: String,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Known values `"cbr"` or `"vbr"`
+    #[yaserde(rename = "encoderType")]
+    pub encoder_type: String,
+    /// Frames per second
+    pub frame: u32,
+    /// The bit rate in kbps
+    #[yaserde(rename = "bitRate")]
+    pub bit_rate: u32,
+    /// Known values `"high"`, `"main"`, `"base"` or `"default"`
+    #[yaserde(rename = "encoderProfile")]
+    pub encoder_profile: String,
+}
+
+/// An XML with the encoder settings that the streams support
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct StreamInfoList {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// One entry per group of channels
+    #[yaserde(rename = "StreamInfo")]
+    pub stream_infos: Vec<StreamInfo>,
+}
+
+/// The supported encoder settings of a group of channels
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct StreamInfo {
+    /// A bit for each channel that these are for
+    #[yaserde(rename = "channelBits")]
+    pub channel_bits: u32,
+    /// One entry per stream and resolution
+    #[yaserde(rename = "encodeTable")]
+    pub encode_tables: Vec<EncodeTable>,
+}
+
+/// The frame rates and bit rates a stream supports at one resolution
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct EncodeTable {
+    /// The stream e.g. `"mainStream"` or `"subStream"`
+    #[yaserde(rename = "type")]
+    pub stream: String,
+    /// The resolution these are for
+    pub resolution: EncodeResolution,
+    /// The frame rate used when none is set
+    #[yaserde(rename = "defaultFramerate")]
+    pub default_framerate: u32,
+    /// The bit rate in kbps used when none is set
+    #[yaserde(rename = "defaultBitrate")]
+    pub default_bitrate: u32,
+    /// The supported frame rates e.g. `"15,12,10"`
+    #[yaserde(rename = "framerateTable")]
+    pub framerate_table: String,
+    /// The supported bit rates in kbps e.g. `"1024,1536,2048"`
+    #[yaserde(rename = "bitrateTable")]
+    pub bitrate_table: String,
+}
+
+impl EncodeTable {
+    /// The supported frame rates
+    pub fn framerates(&self) -> Vec<u32> {
+        parse_table(&self.framerate_table)
+    }
+
+    /// The supported bit rates in kbps
+    pub fn bitrates(&self) -> Vec<u32> {
+        parse_table(&self.bitrate_table)
+    }
+}
+
+fn parse_table(table: &str) -> Vec<u32> {
+    table
+        .split(',')
+        .filter_map(|value| value.trim().parse().ok())
+        .collect()
+}
+
+/// The resolution of an [EncodeTable]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct EncodeResolution {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+}
+
+/// An XML that describes the storage of the camera such as its SD card
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct HddInfoList {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// One entry per storage device. Empty when the camera has none
+    #[yaserde(rename = "HddInfo")]
+    pub hdd_info: Vec<HddInfo>,
+}
+
+/// The details of one storage device
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct HddInfo {
+    /// The number of the device used to format it
+    pub number: u8,
+    /// Total capacity in MB
+    pub capacity: u64,
+    /// Free space in MB
+    #[yaserde(rename = "remainSize")]
+    pub remain_size: u64,
+    /// Whether the device is mounted. Known values `0` or `1`
+    pub mount: u8,
+    /// Whether the device is formatted. Known values `0` or `1`
+    pub format: u8,
+    /// The kind of device e.g. `"sd"`
+    #[yaserde(rename = "storageType")]
+    pub storage_type: Option<String>,
+}
+
+/// An XML that lists recordings on the storage or selects one to play back
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct FileInfoList {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The recordings
+    #[yaserde(rename = "FileInfo")]
+    pub file_info: Vec<FileInfo>,
+}
+
+/// A recording on the storage, or the search for them
+///
+/// Only some of the fields are used in each message
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct FileInfo {
+    /// Unknown, the official client sends `0`
+    pub uid: Option<u32>,
+    /// The file name of the recording on the camera
+    pub name: Option<String>,
+    /// The channel the recording is from. Usually zero unless from an NVR
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Whether the sub stream is also recorded. Known values `0` or `1`
+    #[yaserde(rename = "supportSub")]
+    pub support_sub: Option<u8>,
+    /// The playback speed, `1` is normal speed
+    #[yaserde(rename = "playSpeed")]
+    pub play_speed: Option<u32>,
+    /// The stream recorded e.g. `"mainStream"`
+    #[yaserde(rename = "streamType")]
+    pub stream_type: Option<String>,
+    /// The reasons to search for e.g. `"manual, sched, md, pir"`
+    #[yaserde(rename = "recordType")]
+    pub record_type: Option<String>,
+    /// Identifies the search in the follow up messages
+    pub handle: Option<u32>,
+    /// The start of the recording or search in the camera's local time
+    #[yaserde(rename = "startTime")]
+    pub start_time: Option<RecordTime>,
+    /// The end of the recording or search in the camera's local time
+    #[yaserde(rename = "endTime")]
+    pub end_time: Option<RecordTime>,
+    /// The size of the recording in bytes
+    pub size: Option<u64>,
+}
+
+/// A time in the camera's local time used by [FileInfo]
+#[derive(PartialEq, Eq, Default, Debug, Clone, Copy, YaDeserialize, YaSerialize)]
+pub struct RecordTime {
+    /// The full year e.g. `2021`
+    pub year: i32,
+    /// The month from `1` to `12`
+    pub month: u8,
+    /// The day of the month from `1`
+    pub day: u8,
+    /// The hour from `0` to `23`
+    pub hour: u8,
+    /// The minute
+    pub minute: u8,
+    /// The second
+    pub second: u8,
+}
+
+/// An XML with the settings of the recordings on the storage
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct RecordCfg {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The channel whose settings these are. Not sent when setting them
+    #[yaserde(rename = "channelId")]
+    pub channel_id: Option<u8>,
+    /// Whether the oldest recordings are overwritten when the storage is full.
+    /// Known values `0` or `1`
+    pub cycle: u8,
+    /// How many seconds to keep recording after an alarm ends
+    #[yaserde(rename = "recordDelayTime")]
+    pub record_delay_time: Option<u32>,
+    /// How many seconds before an alarm are included in its recording
+    #[yaserde(rename = "preRecordTime")]
+    pub pre_record_time: Option<u32>,
+    /// The length of each recording in minutes
+    #[yaserde(rename = "packageTime")]
+    pub package_time: Option<u32>,
+}
+
+/// An XML with the schedule of when the camera records
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct Record {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The channel whose schedule this is
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Whether recording is enabled at all. Known values `0` or `1`
+    pub enable: u8,
+    /// The schedules of each kind of recording
+    #[yaserde(rename = "ScheduleList")]
+    pub schedule_list: ScheduleList,
+}
+
+/// The list of schedules in [Record]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct ScheduleList {
+    /// One schedule per kind of recording
+    #[yaserde(rename = "Schedule")]
+    pub schedule: Vec<Schedule>,
+}
+
+/// When one kind of recording happens
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct Schedule {
+    /// The kind of recording e.g. `"MD"` for motion detection
+    #[yaserde(rename = "alarmType")]
+    pub alarm_type: String,
+    /// The hours of each week day
+    #[yaserde(rename = "timeBlockList")]
+    pub time_block_list: TimeBlockList,
+}
+
+/// The list of time blocks in a [Schedule]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct TimeBlockList {
+    /// Usually one block per week day
+    #[yaserde(rename = "timeBlock")]
+    pub time_block: Vec<TimeBlock>,
+}
+
+/// The hours of a week day that are recorded
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+#[yaserde(rename = "timeBlock")]
+pub struct TimeBlock {
+    /// Whether this block is recorded. Known values `0` or `1`
+    pub enable: u8,
+    /// The day in english e.g. `"Sunday"`
+    #[yaserde(rename = "weekDay")]
+    pub week_day: String,
+    /// The first hour recorded from `0` to `23`
+    #[yaserde(rename = "beginHour")]
+    pub begin_hour: u8,
+    /// The last hour recorded from `0` to `23`
+    #[yaserde(rename = "endHour")]
+    pub end_hour: u8,
+}
+
+/// An XML that turns the floodlight on or off by hand
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct FloodlightManual {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The channel of the floodlight
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// `1` to turn it on or `0` to turn it off
+    pub status: u8,
+    /// How many seconds to keep it on for
+    pub duration: Option<u16>,
+}
+
+/// An XML with when the floodlight turns on by itself, such as on motion at night
+///
+/// Only the fields that are given are changed when it is sent
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct FloodlightTask {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The channel of the floodlight, sent when setting the task
+    #[yaserde(rename = "channelId")]
+    pub channel_id: Option<u8>,
+    /// The channel of the floodlight, received when getting the task
+    pub channel: Option<u8>,
+    /// Whether the floodlight turns on by itself at all. Known values `0` or `1`
+    pub enable: Option<u8>,
+    /// What turns the light on by itself e.g. `1` for motion
+    #[yaserde(rename = "alarmMode")]
+    pub alarm_mode: Option<u8>,
+    /// The brightness in percent
+    pub brightness_cur: Option<u8>,
+    /// The highest brightness the light has
+    pub brightness_max: Option<u8>,
+    /// The lowest brightness the light has
+    pub brightness_min: Option<u8>,
+    /// How many seconds the light stays on, at least after motion
+    pub duration: Option<u32>,
+}
+
+/// An XML that sounds or silences the siren
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct AudioPlayInfo {
+    /// The channel of the siren
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// How the play is ended. `0` after `play_times` plays or `2` when
+    /// manually turned off with `on_off`
+    #[yaserde(rename = "playMode")]
+    pub play_mode: u32,
+    /// Unknown, the official client sends `0`
+    #[yaserde(rename = "playDuration")]
+    pub play_duration: u32,
+    /// How many times to play the alarm with play mode `0`
+    #[yaserde(rename = "playTimes")]
+    pub play_times: u32,
+    /// `1` to sound the siren or `0` to silence it with play mode `2`
+    #[yaserde(rename = "onOff")]
+    pub on_off: u32,
+}
+
+/// An XML with the privacy mode of the camera, which turns its sensor off
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct Sleep {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// `1` when the privacy mode is on or `0` when it is off
+    pub sleep: u8,
+}
+
+/// An XML that describes a file sent to or from the camera in the binary
+/// messages that follow it, such as a settings backup or firmware
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct ConfigFileInfo {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The name of the file
+    #[yaserde(rename = "fileName")]
+    pub file_name: Option<String>,
+    /// The size of the file in bytes
+    #[yaserde(rename = "fileSize")]
+    pub file_size: Option<u64>,
+    /// For firmware, `1` also restores the factory settings
+    #[yaserde(rename = "updateParameter")]
+    pub update_parameter: Option<u8>,
 }
 
 /// Convience function to return the xml version used throughout the library
@@ -593,6 +1019,319 @@ fn test_deviceinfo_partial_deser() {
     }
 }
 
+#[test]
+fn test_hddinfo_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <HddInfoList version="1.1">
+        <HddInfo>
+        <number>0</number>
+        <capacity>30436</capacity>
+        <remainSize>1024</remainSize>
+        <mount>1</mount>
+        <format>1</format>
+        <storageType>sd</storageType>
+        </HddInfo>
+        </HddInfoList>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            hdd_info_list: Some(HddInfoList { hdd_info, .. }),
+            ..
+        } => {
+            assert_eq!(hdd_info.len(), 1);
+            assert_eq!(hdd_info[0].capacity, 30436);
+            assert_eq!(hdd_info[0].remain_size, 1024);
+            assert_eq!(hdd_info[0].storage_type.as_deref(), Some("sd"));
+        }
+        _ => panic!(),
+    }
+
+    let empty = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <HddInfoList version="1.1">
+        </HddInfoList>
+        </body>"#
+    );
+    match BcXml::try_parse(empty.as_bytes()).unwrap() {
+        BcXml {
+            hdd_info_list: Some(HddInfoList { hdd_info, .. }),
+            ..
+        } => assert!(hdd_info.is_empty()),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_fileinfo_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <FileInfoList version="1.1">
+        <FileInfo>
+        <name>Mp4Record/2021-09-01/RecM01_20210901_120000_120500_6D28808_1A2B3C.mp4</name>
+        <channelId>0</channelId>
+        <streamType>mainStream</streamType>
+        <recordType>md</recordType>
+        <startTime>
+        <year>2021</year>
+        <month>9</month>
+        <day>1</day>
+        <hour>12</hour>
+        <minute>0</minute>
+        <second>0</second>
+        </startTime>
+        <endTime>
+        <year>2021</year>
+        <month>9</month>
+        <day>1</day>
+        <hour>12</hour>
+        <minute>5</minute>
+        <second>0</second>
+        </endTime>
+        <size>27429128</size>
+        </FileInfo>
+        </FileInfoList>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let file_info = &b.file_info_list.as_ref().unwrap().file_info;
+    assert_eq!(file_info.len(), 1);
+    assert_eq!(file_info[0].size, Some(27429128));
+    assert_eq!(
+        file_info[0].end_time,
+        Some(RecordTime {
+            year: 2021,
+            month: 9,
+            day: 1,
+            hour: 12,
+            minute: 5,
+            second: 0,
+        })
+    );
+
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_record_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Record version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <ScheduleList>
+        <Schedule>
+        <alarmType>MD</alarmType>
+        <timeBlockList>
+        <timeBlock>
+        <enable>1</enable>
+        <weekDay>Sunday</weekDay>
+        <beginHour>0</beginHour>
+        <endHour>23</endHour>
+        </timeBlock>
+        <timeBlock>
+        <enable>0</enable>
+        <weekDay>Monday</weekDay>
+        <beginHour>8</beginHour>
+        <endHour>17</endHour>
+        </timeBlock>
+        </timeBlockList>
+        </Schedule>
+        </ScheduleList>
+        </Record>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let record = b.record.as_ref().unwrap();
+    assert_eq!(record.enable, 1);
+    let schedule = &record.schedule_list.schedule;
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(schedule[0].alarm_type, "MD");
+    let blocks = &schedule[0].time_block_list.time_block;
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[1].week_day, "Monday");
+    assert_eq!((blocks[1].begin_hour, blocks[1].end_hour), (8, 17));
+
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_alarm_event_ai_type_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <AlarmEventList version="1.1">
+        <AlarmEvent version="1.1">
+        <channelId>0</channelId>
+        <status>MD</status>
+        <AItype>people,vehicle</AItype>
+        <recording>0</recording>
+        <timeStamp>0</timeStamp>
+        </AlarmEvent>
+        </AlarmEventList>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let events = &b.alarm_event_list.as_ref().unwrap().alarm_events;
+    assert_eq!(events[0].status, "MD");
+    assert_eq!(events[0].ai_type.as_deref(), Some("people,vehicle"));
+}
+
+#[test]
+fn test_compression_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Compression version="1.1">
+        <channelId>0</channelId>
+        <isNoTranslateFrame>1</isNoTranslateFrame>
+        <mainStream>
+        <audio>1</audio>
+        <resolutionName>2304*1296</resolutionName>
+        <width>2304</width>
+        <height>1296</height>
+        <encoderType>cbr</encoderType>
+        <frame>15</frame>
+        <bitRate>2560</bitRate>
+        <encoderProfile>high</encoderProfile>
+        </mainStream>
+        <subStream>
+        <audio>1</audio>
+        <resolutionName>896*512</resolutionName>
+        <width>896</width>
+        <height>512</height>
+        <encoderType>cbr</encoderType>
+        <frame>15</frame>
+        <bitRate>512</bitRate>
+        <encoderProfile>high</encoderProfile>
+        </subStream>
+        </Compression>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let compression = b.compression.as_ref().unwrap();
+    assert_eq!(compression.main_stream.resolution_name, "2304*1296");
+    assert_eq!(compression.main_stream.bit_rate, 2560);
+    assert_eq!(compression.sub_stream.frame, 15);
+    assert!(compression.third_stream.is_none());
+
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_stream_info_list_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <StreamInfoList version="1.1">
+        <StreamInfo>
+        <channelBits>1</channelBits>
+        <encodeTable>
+        <type>mainStream</type>
+        <resolution>
+        <width>2304</width>
+        <height>1296</height>
+        </resolution>
+        <defaultFramerate>15</defaultFramerate>
+        <defaultBitrate>2560</defaultBitrate>
+        <framerateTable>15,12,10,8,6,4,2</framerateTable>
+        <bitrateTable>1024,1536,2048,2560,3072</bitrateTable>
+        </encodeTable>
+        <encodeTable>
+        <type>subStream</type>
+        <resolution>
+        <width>896</width>
+        <height>512</height>
+        </resolution>
+        <defaultFramerate>15</defaultFramerate>
+        <defaultBitrate>512</defaultBitrate>
+        <framerateTable>15,12,10,8,6,4,2</framerateTable>
+        <bitrateTable>128,256,384,512,768,1024</bitrateTable>
+        </encodeTable>
+        </StreamInfo>
+        </StreamInfoList>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let infos = &b.stream_info_list.as_ref().unwrap().stream_infos;
+    assert_eq!(infos.len(), 1);
+    let tables = &infos[0].encode_tables;
+    assert_eq!(tables.len(), 2);
+    assert_eq!(tables[1].stream, "subStream");
+    assert_eq!(
+        (tables[1].resolution.width, tables[1].resolution.height),
+        (896, 512)
+    );
+    assert_eq!(tables[0].framerates(), vec![15, 12, 10, 8, 6, 4, 2]);
+    assert_eq!(tables[0].bitrates(), vec![1024, 1536, 2048, 2560, 3072]);
+}
+
+#[test]
+fn test_floodlight_task_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <FloodlightTask version="1.1">
+        <channel>0</channel>
+        <alarmMode>1</alarmMode>
+        <enable>1</enable>
+        <lastAlarmMode>2</lastAlarmMode>
+        <preview_auto>0</preview_auto>
+        <duration>300</duration>
+        <brightness_cur>92</brightness_cur>
+        <brightness_max>100</brightness_max>
+        <brightness_min>1</brightness_min>
+        </FloodlightTask>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let task = b.floodlight_task.unwrap();
+    assert_eq!(task.enable, Some(1));
+    assert_eq!(task.brightness_cur, Some(92));
+    assert_eq!(task.brightness_max, Some(100));
+    assert_eq!(task.duration, Some(300));
+}
+
+#[test]
+fn test_sleep_deser() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <sleep version="1.1">
+        <sleep>1</sleep>
+        </sleep>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    assert_eq!(b.sleep.unwrap().sleep, 1);
+}
+
 #[test]
 fn test_binary_deser() {
     let _ = env_logger::builder().is_test(true).try_init();
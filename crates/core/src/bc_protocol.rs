@@ -1,21 +1,33 @@
 use crate::{bc, bcmedia};
 use log::*;
 use std::convert::TryInto;
-use std::net::ToSocketAddrs;
-use std::sync::atomic::{AtomicU16, Ordering};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::Arc;
 
 use Md5Trunc::*;
 
+mod compression;
+mod config_file;
 mod connection;
 mod errors;
+mod fanout;
+mod floodlight;
 mod ledstate;
 mod login;
 mod logout;
 mod motion;
 mod ping;
 mod pirstate;
+mod playback;
+mod privacy;
+mod queue;
 mod reboot;
+mod record;
+mod replay;
 mod resolution;
+mod siren;
+mod storage;
 mod stream;
 mod talk;
 mod time;
@@ -23,16 +35,45 @@ mod version;
 
 use super::RX_TIMEOUT;
 use bc::model::*;
+use bc::xml::{BcXml, Extension};
 pub(crate) use connection::*;
+pub use connection::{BcDump, TcpOptions};
 pub use errors::Error;
+pub use fanout::StreamFanOut;
 pub use ledstate::LightState;
+pub use motion::{AiType, MotionOutput, MotionOutputError, MotionStatus};
 pub use pirstate::PirState;
+pub use queue::{StreamQueue, StreamQueueReceiver};
+pub use replay::replay_media;
 pub use resolution::*;
-pub use motion::{MotionOutput, MotionOutputError, MotionStatus};
-pub use stream::{Stream, StreamOutput, StreamOutputError};
+pub use stream::{Stream, StreamOutput, StreamOutputError, StreamOutputStatus};
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// The response code the camera sends to the commands that it rejects
+///
+/// What it means depends on the message: a login with the wrong credentials,
+/// a read of settings that the model does not have or a set of values that the
+/// camera does not take, so each message maps it to its own error
+const RESPONSE_CODE_BAD_REQUEST: u16 = 400;
+
+/// Check the response code of the camera's reply to a command
+///
+/// Returns [`Error::Refused`] with the response code and `why` if it failed.
+/// A rejected command is only taken as [`Error::Unsupported`] by the requests
+/// that read what the camera has, as a camera also rejects the values it is
+/// sent with that code
+fn check_reply(reply: Bc, why: &'static str) -> Result<()> {
+    match reply.meta.response_code {
+        200 => Ok(()),
+        response_code => Err(Error::Refused {
+            msg_id: reply.meta.msg_id,
+            response_code,
+            why,
+        }),
+    }
+}
+
 impl<'a> From<std::sync::mpsc::RecvTimeoutError> for Error {
     fn from(k: std::sync::mpsc::RecvTimeoutError) -> Self {
         match k {
@@ -52,6 +93,8 @@ pub struct BcCamera {
     message_num: AtomicU16,
     // Certain commands such as logout require the username/pass in plain text.... why....???
     credentials: Option<Credentials>,
+    // Counts the changes to the camera that were refused in read only mode
+    read_only: Option<Arc<AtomicU64>>,
 }
 
 // Used for caching the credentials
@@ -89,12 +132,36 @@ impl BcCamera {
     /// returns either an error or the camera
     ///
     pub fn new_with_addr<T: ToSocketAddrs>(host: T, channel_id: u8) -> Result<Self> {
+        Self::new_with_addr_from(host, channel_id, None)
+    }
+
+    ///
+    /// Create a new camera interface with this address and channel ID that
+    /// connects from a local address
+    ///
+    /// # Parameters
+    ///
+    /// * `host` - The address of the camera either ip address or hostname string
+    ///
+    /// * `channel_id` - The channel ID this is usually zero unless using a NVR
+    ///
+    /// * `source` - The local address to connect from or `None` to let the OS pick
+    ///
+    /// # Returns
+    ///
+    /// returns either an error or the camera
+    ///
+    pub fn new_with_addr_from<T: ToSocketAddrs>(
+        host: T,
+        channel_id: u8,
+        source: Option<IpAddr>,
+    ) -> Result<Self> {
         let addr_iter = match host.to_socket_addrs() {
             Ok(iter) => iter,
             Err(_) => return Err(Error::AddrResolutionError),
         };
         for addr in addr_iter {
-            if let Ok(cam) = Self::new(SocketAddrOrUid::SocketAddr(addr), channel_id) {
+            if let Ok(cam) = Self::new_from(SocketAddrOrUid::SocketAddr(addr), channel_id, source) {
                 return Ok(cam);
             }
         }
@@ -116,7 +183,27 @@ impl BcCamera {
     /// returns either an error or the camera
     ///
     pub fn new_with_uid(uid: &str, channel_id: u8) -> Result<Self> {
-        Self::new(SocketAddrOrUid::Uid(uid.to_string()), channel_id)
+        Self::new_with_uid_from(uid, channel_id, None)
+    }
+
+    ///
+    /// Create a new camera interface with this uid and channel ID that
+    /// connects from a local address
+    ///
+    /// # Parameters
+    ///
+    /// * `uid` - The uid of the camera
+    ///
+    /// * `channel_id` - The channel ID this is usually zero unless using a NVR
+    ///
+    /// * `source` - The local address to connect from or `None` to let the OS pick
+    ///
+    /// # Returns
+    ///
+    /// returns either an error or the camera
+    ///
+    pub fn new_with_uid_from(uid: &str, channel_id: u8, source: Option<IpAddr>) -> Result<Self> {
+        Self::new_from(SocketAddrOrUid::Uid(uid.to_string()), channel_id, source)
     }
 
     ///
@@ -167,14 +254,37 @@ impl BcCamera {
     /// returns either an error or the camera
     ///
     pub fn new(addr: SocketAddrOrUid, channel_id: u8) -> Result<Self> {
+        Self::new_from(addr, channel_id, None)
+    }
+
+    ///
+    /// Create a new camera interface with this address/uid and channel ID
+    /// that connects from a local address
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - An enum of [`SocketAddrOrUid`] that contains the address
+    ///
+    /// * `channel_id` - The channel ID this is usually zero unless using a NVR
+    ///
+    /// * `from` - The local address to connect from or `None` to let the OS pick
+    ///
+    /// # Returns
+    ///
+    /// returns either an error or the camera
+    ///
+    pub fn new_from(addr: SocketAddrOrUid, channel_id: u8, from: Option<IpAddr>) -> Result<Self> {
+        if let Some(from) = from {
+            debug!("Connecting from {}", from);
+        }
         let source = match addr {
             SocketAddrOrUid::SocketAddr(addr) => {
                 debug!("Trying address {}", addr);
-                BcSource::new_tcp(addr, RX_TIMEOUT)?
+                BcSource::new_tcp(addr, from, RX_TIMEOUT)?
             }
             SocketAddrOrUid::Uid(uid) => {
                 debug!("Trying uid {}", uid);
-                BcSource::new_udp(&uid, RX_TIMEOUT)?
+                BcSource::new_udp(&uid, from, RX_TIMEOUT)?
             }
         };
 
@@ -187,6 +297,7 @@ impl BcCamera {
             channel_id,
             logged_in: false,
             credentials: None,
+            read_only: None,
         };
 
         if let Some(conn) = &me.connection {
@@ -216,6 +327,50 @@ impl BcCamera {
         self.message_num.fetch_add(1, Ordering::Relaxed)
     }
 
+    /// Set the keepalive and nodelay options of the tcp connection to the camera
+    ///
+    /// Returns the options that are in effect afterwards, or `None` if the
+    /// camera is connected over udp which has no such options
+    pub fn set_tcp_options(&self, options: &TcpOptions) -> Result<Option<TcpOptions>> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to set the tcp options");
+        Ok(connection.set_tcp_options(options)?)
+    }
+
+    /// Write the bc frames to and from the camera to `dump` from now on, as `name`
+    ///
+    /// This is for debugging the protocol. See [`BcDump`] for what is redacted
+    pub fn set_dump(&self, dump: Arc<BcDump>, name: &str) {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to dump the frames");
+        connection.set_dump(dump, name);
+    }
+
+    /// Refuse the commands that would change the camera from now on
+    ///
+    /// Each refused command is logged with what it would have done, counted
+    /// in `suppressed` and returns `Ok` without being sent. Reading from the
+    /// camera and streaming still work
+    pub fn set_read_only(&mut self, suppressed: Arc<AtomicU64>) {
+        self.read_only = Some(suppressed);
+    }
+
+    /// Whether the change described by `what` is refused, logging and counting it if so
+    fn is_read_only(&self, what: &str) -> bool {
+        match &self.read_only {
+            Some(suppressed) => {
+                warn!("Read only, would {} on channel {}", what, self.channel_id);
+                suppressed.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// This will drop the connection. It will try to send the logout request to the camera
     /// first
     pub fn disconnect(&mut self) {
@@ -232,6 +387,73 @@ impl BcCamera {
         self.connection = None;
     }
 
+    /// Ask for the settings of this channel with `msg_id`
+    ///
+    /// Returns [`Error::Unsupported`] if the camera rejects the request
+    fn get_channel_xml(&self, msg_id: u32) -> Result<Bc> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to get the settings");
+        let sub_get = connection.subscribe(msg_id)?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num: self.new_message_num(),
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get)?;
+        let msg = sub_get.rx.recv_timeout(RX_TIMEOUT)?;
+        if msg.meta.response_code == RESPONSE_CODE_BAD_REQUEST {
+            return Err(Error::Unsupported { msg_id });
+        }
+        Ok(msg)
+    }
+
+    /// Send the settings in `xml` for this channel with `msg_id`
+    ///
+    /// Returns [`Error::Refused`] if the camera rejects the request
+    fn set_channel_xml(&self, msg_id: u32, xml: BcXml, why: &'static str) -> Result<()> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to set the settings");
+        let sub_set = connection.subscribe(msg_id)?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num: self.new_message_num(),
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(xml)),
+            }),
+        };
+
+        sub_set.send(set)?;
+        let msg = sub_set.rx.recv_timeout(RX_TIMEOUT)?;
+        check_reply(msg, why)
+    }
+
     // Certains commands like logout need the username and password
     // this command will return it as a tuple of (Username, Option<Password>)
     // This will only work after login
@@ -290,3 +512,35 @@ fn test_md5_string() {
         "21232F297A57A5A743894A0E4A801FC\0"
     );
 }
+
+#[test]
+fn test_check_reply() {
+    let reply = |response_code| Bc {
+        meta: BcMeta {
+            msg_id: MSG_ID_SET_LED_STATUS,
+            channel_id: 0,
+            msg_num: 0,
+            response_code,
+            stream_type: 0,
+            class: 0x0000,
+        },
+        body: BcBody::ModernMsg(ModernMsg::default()),
+    };
+    assert!(check_reply(reply(200), "").is_ok());
+    assert!(matches!(
+        check_reply(reply(RESPONSE_CODE_BAD_REQUEST), ""),
+        Err(Error::Refused {
+            msg_id: MSG_ID_SET_LED_STATUS,
+            response_code: RESPONSE_CODE_BAD_REQUEST,
+            ..
+        })
+    ));
+    assert!(matches!(
+        check_reply(reply(401), ""),
+        Err(Error::Refused {
+            msg_id: MSG_ID_SET_LED_STATUS,
+            response_code: 401,
+            ..
+        })
+    ));
+}
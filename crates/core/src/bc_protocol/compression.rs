@@ -0,0 +1,88 @@
+use super::{BcCamera, Error, Result, RESPONSE_CODE_BAD_REQUEST, RX_TIMEOUT};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [Compression] xml which contains the encoder settings of the streams
+    ///
+    /// Returns [`Error::Unsupported`] if the camera has no such settings
+    pub fn get_compression(&self) -> Result<Compression> {
+        let msg = self.get_channel_xml(MSG_ID_GET_COMPRESSION)?;
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        compression: Some(compression),
+                        ..
+                    })),
+                ..
+            }) => Ok(compression),
+            _ => Err(Error::UnintelligibleReply {
+                reply: msg,
+                why: "Expected Compression xml but it was not recieved",
+            }),
+        }
+    }
+
+    /// Set the encoder settings of the streams using the [Compression] xml
+    pub fn set_compression(&self, mut compression: Compression) -> Result<()> {
+        if self.is_read_only("change the encoder settings") {
+            return Ok(());
+        }
+        // Only the camera sends this
+        compression.is_no_translate_frame = None;
+        self.set_channel_xml(
+            MSG_ID_SET_COMPRESSION,
+            BcXml {
+                compression: Some(compression),
+                ..Default::default()
+            },
+            "The camera did not accept the Compression xml",
+        )
+    }
+
+    /// Get the [StreamInfoList] xml with the encoder settings that the streams support
+    ///
+    /// Returns [`Error::Unsupported`] if the camera does not list them
+    pub fn get_stream_info_list(&self) -> Result<StreamInfoList> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to get the stream info");
+        let sub_get = connection.subscribe(MSG_ID_STREAM_INFO_LIST)?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_STREAM_INFO_LIST,
+                channel_id: self.channel_id,
+                msg_num: self.new_message_num(),
+                stream_type: 0,
+                response_code: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                ..Default::default()
+            }),
+        };
+
+        sub_get.send(get)?;
+        let msg = sub_get.rx.recv_timeout(RX_TIMEOUT)?;
+        if msg.meta.response_code == RESPONSE_CODE_BAD_REQUEST {
+            return Err(Error::Unsupported {
+                msg_id: MSG_ID_STREAM_INFO_LIST,
+            });
+        }
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        stream_info_list: Some(stream_info_list),
+                        ..
+                    })),
+                ..
+            }) => Ok(stream_info_list),
+            _ => Err(Error::UnintelligibleReply {
+                reply: msg,
+                why: "Expected StreamInfoList xml but it was not recieved",
+            }),
+        }
+    }
+}
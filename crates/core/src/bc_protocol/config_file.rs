@@ -0,0 +1,60 @@
+use super::{BcCamera, BinarySubscriber, Error, Result, RESPONSE_CODE_BAD_REQUEST, RX_TIMEOUT};
+use crate::bc::{model::*, xml::*};
+use std::io::Read;
+
+impl BcCamera {
+    /// Download a backup of all the camera settings
+    ///
+    /// The backup is in the camera's own format. Returns [`Error::Unsupported`] if the camera
+    /// cannot export its settings
+    pub fn export_config(&self) -> Result<Vec<u8>> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to export the settings");
+        let sub = connection.subscribe(MSG_ID_CONFIG_EXPORT)?;
+        sub.send(Bc::new_from_meta(self.file_meta(MSG_ID_CONFIG_EXPORT)))?;
+
+        // The camera first says how big the backup is then sends it as binary
+        let msg = sub.rx.recv_timeout(RX_TIMEOUT)?;
+        if msg.meta.response_code == RESPONSE_CODE_BAD_REQUEST {
+            return Err(Error::Unsupported {
+                msg_id: MSG_ID_CONFIG_EXPORT,
+            });
+        }
+        let file_size =
+            match &msg.body {
+                BcBody::ModernMsg(ModernMsg {
+                    payload:
+                        Some(BcPayloads::BcXml(BcXml {
+                            config_file_info:
+                                Some(ConfigFileInfo {
+                                    file_size: Some(file_size),
+                                    ..
+                                }),
+                            ..
+                        })),
+                    ..
+                }) => *file_size,
+                _ => return Err(Error::UnintelligibleReply {
+                    reply: msg,
+                    why: "Expected ConfigFileInfo xml with the backup size but it was not recieved",
+                }),
+            };
+
+        let mut backup = vec![0; file_size as usize];
+        BinarySubscriber::from_bc_sub(&sub).read_exact(&mut backup)?;
+        Ok(backup)
+    }
+
+    fn file_meta(&self, msg_id: u32) -> BcMeta {
+        BcMeta {
+            msg_id,
+            channel_id: self.channel_id,
+            msg_num: self.new_message_num(),
+            response_code: 0,
+            stream_type: 0,
+            class: 0x6414,
+        }
+    }
+}
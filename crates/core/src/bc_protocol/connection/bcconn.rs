@@ -1,4 +1,4 @@
-use super::{BcSource, BcSubscription, Error, Result, TcpSource};
+use super::{BcDump, BcSource, BcSubscription, Error, Result, TcpOptions, TcpSource};
 use crate::bc;
 use crate::bc::model::*;
 use log::*;
@@ -13,6 +13,9 @@ use std::sync::{atomic::AtomicBool, atomic::Ordering, Arc, Mutex};
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
+// Where the frames are dumped and the name of the camera in the dump
+type DumpTo = Arc<Mutex<Option<(Arc<BcDump>, String)>>>;
+
 /// A shareable connection to a camera.  Handles serialization of messages.  To send/receive, call
 /// .[subscribe()] with a message ID.  You can use the BcSubscription to send or receive only
 /// messages with that ID; each incoming message is routed to its appropriate subscriber.
@@ -27,6 +30,7 @@ pub struct BcConnection {
     encryption_protocol: Arc<Mutex<EncryptionProtocol>>,
     poll_abort: Arc<AtomicBool>,
     keep_alive_msg: Arc<Mutex<Option<Bc>>>,
+    dump: DumpTo,
 }
 
 impl BcConnection {
@@ -42,6 +46,8 @@ impl BcConnection {
         let mut conn = source.try_clone()?;
         let keep_alive_msg: Arc<Mutex<Option<Bc>>> = Arc::new(Mutex::new(None));
         let connections_keep_alive_msg = keep_alive_msg.clone();
+        let dump: DumpTo = Default::default();
+        let connections_dump = dump.clone();
         let rx_thread = std::thread::spawn(move || {
             let keep_alive_encryption_protocol = connections_encryption_protocol.clone();
             let mut context = BcContext::new(connections_encryption_protocol);
@@ -49,7 +55,13 @@ impl BcConnection {
             let mut last_keep_alive = Instant::now();
             let keep_alive_time = Duration::from_millis(500);
             loop {
-                result = Self::poll(&mut context, &conn, &mut subs, &connections_keep_alive_msg);
+                result = Self::poll(
+                    &mut context,
+                    &conn,
+                    &mut subs,
+                    &connections_keep_alive_msg,
+                    &connections_dump,
+                );
                 if poll_abort_rx.load(Ordering::Relaxed) {
                     break; // Poll has been aborted by request usally during disconnect
                 }
@@ -83,6 +95,7 @@ impl BcConnection {
             encryption_protocol,
             poll_abort,
             keep_alive_msg,
+            dump,
         })
     }
 
@@ -90,7 +103,11 @@ impl BcConnection {
         self.poll_abort.store(true, Ordering::Relaxed);
     }
 
-    pub(super) fn send(&self, bc: Bc) -> Result<()> {
+    pub(crate) fn send(&self, bc: Bc) -> Result<()> {
+        if let Some((dump, name)) = self.dump.lock().unwrap().as_ref() {
+            let frame = bc.serialize(vec![], &EncryptionProtocol::Unencrypted)?;
+            dump.write(name, true, &frame);
+        }
         bc.serialize(&*self.sink.lock().unwrap(), &self.get_encrypted())?;
         let _ = self.sink.lock().unwrap().flush();
         Ok(())
@@ -114,6 +131,11 @@ impl BcConnection {
         *self.keep_alive_msg.lock().unwrap() = Some(msg);
     }
 
+    /// Write the frames sent and received from now on to `dump` under `name`
+    pub fn set_dump(&self, dump: Arc<BcDump>, name: &str) {
+        *self.dump.lock().unwrap() = Some((dump, name.to_string()));
+    }
+
     pub fn set_encrypted(&self, value: EncryptionProtocol) {
         *(self.encryption_protocol.lock().unwrap()) = value;
     }
@@ -122,6 +144,10 @@ impl BcConnection {
         (*self.encryption_protocol.lock().unwrap()).clone()
     }
 
+    pub fn set_tcp_options(&self, options: &TcpOptions) -> Result<Option<TcpOptions>> {
+        self.sink.lock().unwrap().set_tcp_options(options)
+    }
+
     pub fn is_udp(&self) -> bool {
         self.sink.lock().unwrap().is_udp()
     }
@@ -131,7 +157,9 @@ impl BcConnection {
         connection: &BcSource,
         subscribers: &mut Arc<Mutex<BTreeMap<u32, Sender<Bc>>>>,
         connections_keep_alive_msg: &Arc<Mutex<Option<Bc>>>,
+        dump: &DumpTo,
     ) -> Result<()> {
+        context.capture = dump.lock().unwrap().is_some();
         // Don't hold the lock during deserialization so we don't poison the subscribers mutex if
         // something goes wrong
         let response = Bc::deserialize(context, connection).map_err(|err| {
@@ -139,6 +167,11 @@ impl BcConnection {
             subscribers.lock().unwrap().clear();
             err
         })?;
+        if let (Some((dump, name)), Some(frame)) =
+            (dump.lock().unwrap().as_ref(), context.captured.take())
+        {
+            dump.write(name, false, &frame);
+        }
         let msg_id = response.meta.msg_id;
 
         let mut locked_subs = subscribers.lock().unwrap();
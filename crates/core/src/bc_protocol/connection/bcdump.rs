@@ -0,0 +1,138 @@
+use crate::bc::model::*;
+use log::*;
+use std::fs::File;
+use std::io::{Result, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The xml elements whose text is replaced, the login sends hashes of the credentials in them
+const REDACTED_ELEMENTS: &[&str] = &["userName", "password", "token"];
+
+// The legacy login has the hashed username and password in the first 64 bytes of its body
+const LEGACY_CREDENTIALS_LEN: usize = 64;
+
+/// Writes the bc frames sent to and received from the cameras to a file
+///
+/// Each frame is one line of the time since the epoch in seconds, the name of
+/// the camera, `sent` or `recv`, the length of the frame and its bytes in hex.
+/// The frames are written as if they were unencrypted so that they can be read
+/// and their credentials redacted: the hashed username and password of the
+/// logins are replaced by `*`, and so are the bodies of the config exports
+/// which hold the users of the camera. The lengths are kept.
+pub struct BcDump {
+    file: Mutex<File>,
+}
+
+impl BcDump {
+    /// Create the dump at `path`, replacing any file that is there
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(BcDump {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    pub(crate) fn write(&self, name: &str, sent: bool, frame: &[u8]) {
+        let mut frame = frame.to_vec();
+        redact(&mut frame);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let hex: String = frame.iter().map(|b| format!("{:02x}", b)).collect();
+        let line = format!(
+            "{}.{:03} {} {} {} {}\n",
+            now.as_secs(),
+            now.subsec_millis(),
+            name,
+            if sent { "sent" } else { "recv" },
+            frame.len(),
+            hex
+        );
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            warn!("Unable to write to the bc dump: {}", e);
+        }
+    }
+}
+
+// Replace the credentials and config files of an unencrypted `frame` with `*`
+fn redact(frame: &mut [u8]) {
+    if frame.len() < 20 {
+        return;
+    }
+    let msg_id = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+    let class = u16::from_le_bytes([frame[18], frame[19]]);
+    let header_len = if has_payload_offset(class) { 24 } else { 20 };
+    let body_start = header_len.min(frame.len());
+    match msg_id {
+        MSG_ID_CONFIG_EXPORT => frame[body_start..].fill(b'*'),
+        MSG_ID_LOGIN if class == 0x6514 => {
+            let end = (body_start + LEGACY_CREDENTIALS_LEN).min(frame.len());
+            frame[body_start..end].fill(b'*');
+        }
+        _ => {}
+    }
+    for element in REDACTED_ELEMENTS {
+        let open = format!("<{}>", element);
+        let close = format!("</{}>", element);
+        let mut from = 0;
+        while let Some(start) = find(&frame[from..], open.as_bytes()) {
+            let text = from + start + open.len();
+            let end = match find(&frame[text..], close.as_bytes()) {
+                Some(end) => text + end,
+                None => break,
+            };
+            frame[text..end].fill(b'*');
+            from = end + close.len();
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_modern_login() {
+        let xml = b"<body><LoginUser version=\"1.1\"><userName>9F07915E819A076E2E14169830769D6</userName>\
+                    <password>8EFECD610524A98390F118D2789BE3B</password><userVer>1</userVer>\
+                    </LoginUser></body>";
+        let mut frame = vec![
+            0xf0, 0xde, 0xbc, 0x0a, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x14, 0x64, 0x00, 0x00, 0x00, 0x00,
+        ];
+        frame.extend_from_slice(xml);
+        let len = frame.len();
+        redact(&mut frame);
+        assert_eq!(frame.len(), len);
+        let text = String::from_utf8(frame[24..].to_vec()).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "<body><LoginUser version=\"1.1\"><userName>{}</userName>\
+                 <password>{}</password><userVer>1</userVer></LoginUser></body>",
+                "*".repeat(31),
+                "*".repeat(31)
+            )
+        );
+    }
+
+    #[test]
+    fn test_redact_legacy_login() {
+        let mut frame = vec![
+            0xf0, 0xde, 0xbc, 0x0a, 0x01, 0x00, 0x00, 0x00, 0x2c, 0x07, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x14, 0x65,
+        ];
+        frame.extend_from_slice(&[b'A'; 64]);
+        frame.extend_from_slice(&[0; 1772]);
+        redact(&mut frame);
+        assert!(frame[20..84].iter().all(|b| *b == b'*'));
+        assert!(frame[84..].iter().all(|b| *b == 0));
+        assert_eq!(frame[4], 0x01);
+    }
+}
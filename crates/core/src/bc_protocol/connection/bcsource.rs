@@ -1,6 +1,6 @@
-use super::{Result, TcpSource, UdpSource};
+use super::{Result, TcpOptions, TcpSource, UdpSource};
 use std::io::{Error as IoError, ErrorKind, Read, Write};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -16,13 +16,21 @@ impl BcSource {
         matches!(self, BcSource::Udp(_))
     }
 
-    pub fn new_tcp(addr: SocketAddr, timeout: Duration) -> Result<Self> {
-        let source = TcpSource::new(addr, timeout)?;
+    pub fn new_tcp(addr: SocketAddr, from: Option<IpAddr>, timeout: Duration) -> Result<Self> {
+        let source = TcpSource::new(addr, from, timeout)?;
         Ok(BcSource::Tcp(Mutex::new(source)))
     }
 
-    pub fn new_udp(uid: &str, timeout: Duration) -> Result<Self> {
-        let source = UdpSource::new(uid, timeout)?;
+    /// Set the options of a tcp connection, a udp one has none so gives `None`
+    pub fn set_tcp_options(&self, options: &TcpOptions) -> Result<Option<TcpOptions>> {
+        match self {
+            BcSource::Tcp(source) => Ok(Some(source.lock().unwrap().set_options(options)?)),
+            BcSource::Udp(_) => Ok(None),
+        }
+    }
+
+    pub fn new_udp(uid: &str, from: Option<IpAddr>, timeout: Duration) -> Result<Self> {
+        let source = UdpSource::new(uid, from, timeout)?;
         Ok(BcSource::Udp(Mutex::new(source)))
     }
 
@@ -22,6 +22,7 @@ use std::thread::JoinHandle;
 use std::time::Duration;
 
 mod bcconn;
+mod bcdump;
 mod bcsource;
 mod bcsub;
 mod binarysub;
@@ -29,6 +30,8 @@ mod filesub;
 mod tcpconn;
 mod udpconn;
 
+pub use self::bcdump::BcDump;
+pub use self::tcpconn::TcpOptions;
 pub(crate) use self::{
     bcconn::BcConnection, bcsource::BcSource, bcsub::BcSubscription, binarysub::BinarySubscriber,
     filesub::FileSubscriber, tcpconn::TcpSource, udpconn::UdpSource,
@@ -7,7 +7,7 @@ use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::error::Error as StdErr; // Just need the traits
 use std::io::{Error as IoError, Read, Write};
-use std::net::{SocketAddr, TcpStream};
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{atomic::AtomicBool, atomic::Ordering, Arc, Mutex};
 use std::thread::JoinHandle;
@@ -19,9 +19,22 @@ pub struct TcpSource {
     stream: TcpStream,
 }
 
+/// The keepalive and nodelay options of the tcp connection to a camera
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpOptions {
+    /// How long the connection is idle before the first keepalive probe, `None` keeps it as is
+    pub keepalive_idle: Option<Duration>,
+    /// The time between the keepalive probes, only set on Linux and macOS
+    pub keepalive_interval: Option<Duration>,
+    /// How many probes go unanswered before the connection is dropped, only set on Linux and macOS
+    pub keepalive_count: Option<u32>,
+    /// Send small packets straight away instead of coalescing them
+    pub nodelay: bool,
+}
+
 impl TcpSource {
-    pub fn new(addr: SocketAddr, timeout: Duration) -> Result<TcpSource> {
-        let tcp_conn = connect_to(addr, timeout)?;
+    pub fn new(addr: SocketAddr, source: Option<IpAddr>, timeout: Duration) -> Result<TcpSource> {
+        let tcp_conn = connect_to(addr, source, timeout)?;
 
         Ok(Self { stream: tcp_conn })
     }
@@ -31,6 +44,82 @@ impl TcpSource {
             stream: self.stream.try_clone()?,
         })
     }
+
+    /// Set the `options` of the socket and return those that are in effect afterwards
+    pub fn set_options(&self, options: &TcpOptions) -> Result<TcpOptions> {
+        // The clone shares the socket so the options apply to the connection
+        let socket = Socket::from(self.stream.try_clone()?);
+        if let Some(idle) = options.keepalive_idle {
+            socket.set_keepalive(Some(idle))?;
+        }
+        socket.set_nodelay(options.nodelay)?;
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            use std::os::unix::io::AsRawFd;
+            let fd = socket.as_raw_fd();
+            if let Some(interval) = options.keepalive_interval {
+                set_tcp_int(fd, libc::TCP_KEEPINTVL, interval.as_secs() as libc::c_int)?;
+            }
+            if let Some(count) = options.keepalive_count {
+                set_tcp_int(fd, libc::TCP_KEEPCNT, count as libc::c_int)?;
+            }
+        }
+
+        let mut effective = TcpOptions {
+            keepalive_idle: socket.keepalive()?,
+            nodelay: socket.nodelay()?,
+            ..Default::default()
+        };
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            use std::os::unix::io::AsRawFd;
+            let fd = socket.as_raw_fd();
+            effective.keepalive_interval = Some(Duration::from_secs(get_tcp_int(
+                fd,
+                libc::TCP_KEEPINTVL,
+            )? as u64));
+            effective.keepalive_count = Some(get_tcp_int(fd, libc::TCP_KEEPCNT)? as u32);
+        }
+        Ok(effective)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn set_tcp_int(fd: libc::c_int, option: libc::c_int, value: libc::c_int) -> IoResult<()> {
+    // Safety: the value is a c_int that lives for the call, as the option expects
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(IoError::last_os_error()),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn get_tcp_int(fd: libc::c_int, option: libc::c_int) -> IoResult<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // Safety: value and len are valid for writes of the size given in len
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            option,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    match result {
+        0 => Ok(value),
+        _ => Err(IoError::last_os_error()),
+    }
 }
 
 impl Read for TcpSource {
@@ -49,8 +138,8 @@ impl Write for TcpSource {
     }
 }
 
-/// Helper to create a TcpStream with a connect timeout
-fn connect_to(addr: SocketAddr, timeout: Duration) -> Result<TcpStream> {
+/// Helper to create a TcpStream with a connect timeout, from the source address if given
+fn connect_to(addr: SocketAddr, source: Option<IpAddr>, timeout: Duration) -> Result<TcpStream> {
     let socket = match addr {
         SocketAddr::V4(_) => Socket::new(Domain::ipv4(), Type::stream(), None)?,
         SocketAddr::V6(_) => {
@@ -60,6 +149,9 @@ fn connect_to(addr: SocketAddr, timeout: Duration) -> Result<TcpStream> {
         }
     };
 
+    if let Some(source) = source {
+        socket.bind(&SocketAddr::new(source, 0).into())?;
+    }
     socket.set_keepalive(Some(timeout))?;
     socket.set_read_timeout(Some(timeout))?;
     socket.set_write_timeout(Some(timeout))?;
@@ -15,7 +15,7 @@ use log::*;
 use rand::{seq::SliceRandom, thread_rng, Rng};
 use std::{
     io::{BufRead, Error as IoError, ErrorKind, Read, Result as IoResult, Write},
-    net::{SocketAddr, UdpSocket},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
     sync::Arc,
     time::Duration,
 };
@@ -66,12 +66,12 @@ pub struct UdpSource {
 }
 
 impl UdpSource {
-    pub fn new(uid: &str, timeout: Duration) -> Result<Self> {
+    pub fn new(uid: &str, source: Option<IpAddr>, timeout: Duration) -> Result<Self> {
         let (outgoing, from_outgoing) = unbounded();
         let (to_incoming, incoming) = unbounded();
         let aborter = AbortHandle::new();
 
-        Self::start_polling(uid, timeout, &aborter, to_incoming, from_outgoing)?;
+        Self::start_polling(uid, source, timeout, &aborter, to_incoming, from_outgoing)?;
 
         Ok(Self {
             outgoing,
@@ -87,12 +87,13 @@ impl UdpSource {
 
     fn start_polling(
         uid: &str,
+        source: Option<IpAddr>,
         timeout: Duration,
         aborter: &AbortHandle,
         to_incoming: Sender<Vec<u8>>,
         from_outgoing: Receiver<Vec<u8>>,
     ) -> Result<()> {
-        let socket = Self::get_socket(SOCKET_WAIT_TIME)?;
+        let socket = Self::get_socket(source, SOCKET_WAIT_TIME)?;
         let allow_remote = true;
         let discovery_result = Arc::new(UdpDiscover::discover_from_uuid(
             &socket,
@@ -186,15 +187,18 @@ impl UdpSource {
         self.aborter.abort();
     }
 
-    fn get_socket(timeout: Duration) -> Result<UdpSocket> {
+    fn get_socket(source: Option<IpAddr>, timeout: Duration) -> Result<UdpSocket> {
         // Select a random port to bind to
         let mut ports: Vec<u16> = (53500..54000).into_iter().collect();
         let mut rng = thread_rng();
         ports.shuffle(&mut rng);
 
+        // A socket bound to a unicast source does not get the replies sent to a
+        // broadcast address, so the local discovery may not find the camera
+        let source = source.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
         let addrs: Vec<_> = ports
             .iter()
-            .map(|&port| SocketAddr::from(([0, 0, 0, 0], port)))
+            .map(|&port| SocketAddr::new(source, port))
             .collect();
         let socket = UdpSocket::bind(&addrs[..])?;
         socket.set_read_timeout(Some(timeout))?;
@@ -54,6 +54,40 @@ pub enum Error {
     #[error(display = "Credential error")]
     AuthFailed,
 
+    /// Raised when the camera refuses credentials that it accepted before, as it
+    /// does for a while after too many failed attempts. Retrying straight away
+    /// extends the lockout. The camera refuses this login just as it does
+    /// wrong credentials, so only a caller that knows they worked can tell
+    #[error(
+        display = "Login refused with credentials that worked before, the account is temporarily locked"
+    )]
+    LoginLocked,
+
+    /// Raised when the camera replies to a command with a response code other
+    /// than 200. The codes are not documented, so the camera may have refused
+    /// it for the rights of the account that logged in or for what was sent
+    #[error(
+        display = "{}, message {} got response code {}",
+        why,
+        msg_id,
+        response_code
+    )]
+    Refused {
+        /// The msg_id of the command that was refused
+        msg_id: u32,
+        /// The response code of the reply
+        response_code: u16,
+        /// The message attached to the error
+        why: &'static str,
+    },
+
+    /// Raised when the camera rejects a command that its model or firmware does not have
+    #[error(display = "The camera does not support message {}", msg_id)]
+    Unsupported {
+        /// The msg_id of the command that was rejected
+        msg_id: u32,
+    },
+
     /// Raised when the given camera url could not be resolved
     #[error(display = "Failed to translate camera address")]
     AddrResolutionError,
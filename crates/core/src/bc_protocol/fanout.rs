@@ -0,0 +1,259 @@
+use super::{StreamOutput, StreamOutputError, StreamOutputStatus};
+use crate::bcmedia::model::*;
+use log::*;
+use std::time::{Duration, Instant};
+
+/// A [`StreamOutput`] that sends the media of one
+/// [`BcCamera::start_video()`](super::BcCamera::start_video) to many outputs
+///
+/// This lets one camera session feed several consumers at once, such as an
+/// rtsp server and a recording, instead of logging in once for each.
+///
+/// An output is removed when it returns an error, asks to stop, or takes
+/// longer than the slow limit to accept a packet. The other outputs keep
+/// receiving the stream. The stream only stops when every output has been removed.
+///
+/// An output that asks to drop to the next keyframe only misses packets
+/// itself, the other outputs still receive them.
+pub struct StreamFanOut<'a> {
+    outputs: Vec<FanOutput<'a>>,
+    slow_limit: Duration,
+}
+
+struct FanOutput<'a> {
+    name: String,
+    output: Box<dyn StreamOutput + 'a>,
+    // Skipping packets until the next keyframe
+    dropping: bool,
+}
+
+impl<'a> Default for StreamFanOut<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> StreamFanOut<'a> {
+    /// Create a fan out without any outputs
+    pub fn new() -> Self {
+        StreamFanOut {
+            outputs: vec![],
+            slow_limit: Duration::from_secs(5),
+        }
+    }
+
+    /// Add an output, the name is used when logging that it was removed
+    pub fn add<T: StreamOutput + 'a>(&mut self, name: &str, output: T) {
+        self.outputs.push(FanOutput {
+            name: name.to_string(),
+            output: Box::new(output),
+            dropping: false,
+        });
+    }
+
+    /// Remove any output that takes longer than this to accept one packet
+    ///
+    /// Defaults to 5s
+    pub fn set_slow_limit(&mut self, slow_limit: Duration) {
+        self.slow_limit = slow_limit;
+    }
+
+    /// The number of outputs still receiving the stream
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// True if all outputs have been removed
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+}
+
+impl<'a> StreamOutput for StreamFanOut<'a> {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        let slow_limit = self.slow_limit;
+        let keyframe = matches!(media, BcMedia::Iframe(_));
+        self.outputs.retain_mut(|fan_output| {
+            let name = &fan_output.name;
+            if fan_output.dropping {
+                if !keyframe {
+                    return true;
+                }
+                fan_output.dropping = false;
+            }
+            let start = Instant::now();
+            let result = fan_output.output.stream_recv(media.clone());
+            if start.elapsed() > slow_limit {
+                warn!(
+                    "Stream output {} took {}ms to accept a packet, removing it",
+                    name,
+                    start.elapsed().as_millis()
+                );
+                return false;
+            }
+            match result {
+                Ok(StreamOutputStatus::Continue) => true,
+                Ok(StreamOutputStatus::DropToKeyframe) => {
+                    debug!(
+                        "Stream output {} is lagging, skipping to the next keyframe",
+                        name
+                    );
+                    fan_output.dropping = true;
+                    true
+                }
+                Ok(StreamOutputStatus::Stop) => {
+                    debug!("Stream output {} finished", name);
+                    false
+                }
+                Err(e) => {
+                    warn!("Stream output {} failed, removing it: {:?}", name, e);
+                    false
+                }
+            }
+        });
+        if self.outputs.is_empty() {
+            Ok(StreamOutputStatus::Stop)
+        } else {
+            Ok(StreamOutputStatus::Continue)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bc_protocol::Error;
+
+    struct Counter<'a> {
+        count: &'a mut usize,
+        // None makes the output fail
+        result: Option<StreamOutputStatus>,
+    }
+
+    impl<'a> StreamOutput for Counter<'a> {
+        fn stream_recv(&mut self, _media: BcMedia) -> StreamOutputError {
+            *self.count += 1;
+            self.result.ok_or(Error::Timeout)
+        }
+    }
+
+    fn packet() -> BcMedia {
+        BcMedia::Aac(BcMediaAac { data: vec![0; 8] })
+    }
+
+    fn keyframe() -> BcMedia {
+        BcMedia::Iframe(BcMediaIframe {
+            video_type: VideoType::H264,
+            microseconds: 0,
+            time: None,
+            data: vec![0; 8],
+        })
+    }
+
+    #[test]
+    fn test_fanout_all_receive() {
+        let (mut a, mut b) = (0, 0);
+        {
+            let mut fanout = StreamFanOut::new();
+            fanout.add(
+                "a",
+                Counter {
+                    count: &mut a,
+                    result: Some(StreamOutputStatus::Continue),
+                },
+            );
+            fanout.add(
+                "b",
+                Counter {
+                    count: &mut b,
+                    result: Some(StreamOutputStatus::Continue),
+                },
+            );
+            for _ in 0..3 {
+                assert!(matches!(
+                    fanout.stream_recv(packet()),
+                    Ok(StreamOutputStatus::Continue)
+                ));
+            }
+        }
+        assert_eq!((a, b), (3, 3));
+    }
+
+    #[test]
+    fn test_fanout_drops_failed() {
+        let (mut good, mut bad) = (0, 0);
+        {
+            let mut fanout = StreamFanOut::new();
+            fanout.add(
+                "good",
+                Counter {
+                    count: &mut good,
+                    result: Some(StreamOutputStatus::Continue),
+                },
+            );
+            fanout.add(
+                "bad",
+                Counter {
+                    count: &mut bad,
+                    result: None,
+                },
+            );
+            for _ in 0..3 {
+                assert!(matches!(
+                    fanout.stream_recv(packet()),
+                    Ok(StreamOutputStatus::Continue)
+                ));
+            }
+            assert_eq!(fanout.len(), 1);
+        }
+        assert_eq!((good, bad), (3, 1));
+    }
+
+    #[test]
+    fn test_fanout_stops_when_empty() {
+        let mut count = 0;
+        let mut fanout = StreamFanOut::new();
+        fanout.add(
+            "done",
+            Counter {
+                count: &mut count,
+                result: Some(StreamOutputStatus::Stop),
+            },
+        );
+        assert!(matches!(
+            fanout.stream_recv(packet()),
+            Ok(StreamOutputStatus::Stop)
+        ));
+        assert!(fanout.is_empty());
+    }
+
+    #[test]
+    fn test_fanout_drop_to_keyframe() {
+        let (mut lagging, mut other) = (0, 0);
+        {
+            let mut fanout = StreamFanOut::new();
+            fanout.add(
+                "lagging",
+                Counter {
+                    count: &mut lagging,
+                    result: Some(StreamOutputStatus::DropToKeyframe),
+                },
+            );
+            fanout.add(
+                "other",
+                Counter {
+                    count: &mut other,
+                    result: Some(StreamOutputStatus::Continue),
+                },
+            );
+            // The lagging output gets the first packet then waits for the keyframe
+            for media in [packet(), packet(), packet(), keyframe(), packet()] {
+                assert!(matches!(
+                    fanout.stream_recv(media),
+                    Ok(StreamOutputStatus::Continue)
+                ));
+            }
+        }
+        assert_eq!((lagging, other), (2, 5));
+    }
+}
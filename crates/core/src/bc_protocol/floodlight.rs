@@ -0,0 +1,71 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [FloodlightTask] xml which says when the floodlight turns on by itself
+    ///
+    /// Returns [`Error::Unsupported`] if the camera has no floodlight
+    pub fn get_floodlight_task(&self) -> Result<FloodlightTask> {
+        let msg = self.get_channel_xml(MSG_ID_GET_FLOODLIGHT_TASK)?;
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        floodlight_task: Some(floodlight_task),
+                        ..
+                    })),
+                ..
+            }) => Ok(floodlight_task),
+            _ => Err(Error::UnintelligibleReply {
+                reply: msg,
+                why: "Expected FloodlightTask xml but it was not recieved",
+            }),
+        }
+    }
+
+    /// Change when the floodlight turns on by itself using the [FloodlightTask] xml
+    ///
+    /// Only the fields that are set are changed
+    pub fn set_floodlight_task(&self, mut floodlight_task: FloodlightTask) -> Result<()> {
+        if self.is_read_only("change when the floodlight turns on") {
+            return Ok(());
+        }
+        // The reply names the channel differently to the request
+        floodlight_task.channel = None;
+        floodlight_task.channel_id = Some(self.channel_id);
+        self.set_channel_xml(
+            MSG_ID_SET_FLOODLIGHT_TASK,
+            BcXml {
+                floodlight_task: Some(floodlight_task),
+                ..Default::default()
+            },
+            "The camera did not accept the FloodlightTask xml",
+        )
+    }
+
+    /// Turn the floodlight on for `duration` seconds, or off
+    ///
+    /// Turning it off also hands it back to the [FloodlightTask] of the camera
+    pub fn set_floodlight_manual(&self, on: bool, duration: u16) -> Result<()> {
+        let what = match on {
+            true => "turn the floodlight on",
+            false => "turn the floodlight off",
+        };
+        if self.is_read_only(what) {
+            return Ok(());
+        }
+        self.set_channel_xml(
+            MSG_ID_FLOODLIGHT_MANUAL,
+            BcXml {
+                floodlight_manual: Some(FloodlightManual {
+                    version: xml_ver(),
+                    channel_id: self.channel_id,
+                    status: on as u8,
+                    duration: Some(duration),
+                }),
+                ..Default::default()
+            },
+            "The camera did not accept the FloodlightManual xml",
+        )
+    }
+}
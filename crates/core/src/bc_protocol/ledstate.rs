@@ -1,34 +1,12 @@
-use super::{BcCamera, Error, Result, RX_TIMEOUT};
+use super::{BcCamera, Error, Result};
 use crate::bc::{model::*, xml::*};
 
 impl BcCamera {
     /// Get the [LedState] xml which contains the LED status of the camera
+    ///
+    /// Returns [`Error::Unsupported`] if the camera has no status light
     pub fn get_ledstate(&self) -> Result<LedState> {
-        let connection = self
-            .connection
-            .as_ref()
-            .expect("Must be connected to get time");
-        let sub_get = connection.subscribe(MSG_ID_GET_LED_STATUS)?;
-        let get = Bc {
-            meta: BcMeta {
-                msg_id: MSG_ID_GET_LED_STATUS,
-                channel_id: self.channel_id,
-                msg_num: self.new_message_num(),
-                response_code: 0,
-                stream_type: 0,
-                class: 0x6414,
-            },
-            body: BcBody::ModernMsg(ModernMsg {
-                extension: Some(Extension {
-                    channel_id: Some(self.channel_id),
-                    ..Default::default()
-                }),
-                payload: None,
-            }),
-        };
-
-        sub_get.send(get)?;
-        let msg = sub_get.rx.recv_timeout(RX_TIMEOUT)?;
+        let msg = self.get_channel_xml(MSG_ID_GET_LED_STATUS)?;
 
         if let BcBody::ModernMsg(ModernMsg {
             payload:
@@ -50,50 +28,20 @@ impl BcCamera {
 
     /// Set the led lights using the [LedState] xml
     pub fn set_ledstate(&self, mut led_state: LedState) -> Result<()> {
-        let connection = self
-            .connection
-            .as_ref()
-            .expect("Must be connected to get time");
-        let sub_set = connection.subscribe(MSG_ID_SET_LED_STATUS)?;
-
+        if self.is_read_only("change the LED lights") {
+            return Ok(());
+        }
         // led_version is a field recieved from the camera but not sent
         // we set to None to ensure we don't send it to the camera
         led_state.led_version = None;
-        let get = Bc {
-            meta: BcMeta {
-                msg_id: MSG_ID_SET_LED_STATUS,
-                channel_id: self.channel_id,
-                msg_num: self.new_message_num(),
-                response_code: 0,
-                stream_type: 0,
-                class: 0x6414,
+        self.set_channel_xml(
+            MSG_ID_SET_LED_STATUS,
+            BcXml {
+                led_state: Some(led_state),
+                ..Default::default()
             },
-            body: BcBody::ModernMsg(ModernMsg {
-                extension: Some(Extension {
-                    channel_id: Some(self.channel_id),
-                    ..Default::default()
-                }),
-                payload: Some(BcPayloads::BcXml(BcXml {
-                    led_state: Some(led_state),
-                    ..Default::default()
-                })),
-            }),
-        };
-
-        sub_set.send(get)?;
-        let msg = sub_set.rx.recv_timeout(RX_TIMEOUT)?;
-
-        if let BcMeta {
-            response_code: 200, ..
-        } = msg.meta
-        {
-            Ok(())
-        } else {
-            Err(Error::UnintelligibleReply {
-                reply: msg,
-                why: "The camera did not except the LEDState xml",
-            })
-        }
+            "The camera did not except the LEDState xml",
+        )
     }
 
     /// This is a convience function to control the IR LED lights
@@ -118,6 +118,8 @@ impl BcCamera {
                     self.logged_in = true;
                     device_info = info;
                 }
+                // The camera refuses wrong credentials and an account it has
+                // locked for a while with the same reply
                 BcBody::ModernMsg(ModernMsg {
                     extension: None,
                     payload: None,
@@ -1,10 +1,49 @@
 use super::{BcCamera, Error, Result, RX_TIMEOUT};
 use crate::bc::{model::*, xml::*};
 
+/// What the AI detection of a camera can tell apart
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AiType {
+    /// A person, `people` to the camera
+    Person,
+    /// A vehicle
+    Vehicle,
+    /// An animal, `dog_cat` to the camera
+    Pet,
+}
+
+impl AiType {
+    /// The detection type called `name` by either the camera or neolink
+    pub fn from_name(name: &str) -> Option<AiType> {
+        match name.trim().to_lowercase().as_str() {
+            "people" | "person" => Some(AiType::Person),
+            "vehicle" => Some(AiType::Vehicle),
+            "dog_cat" | "pet" | "animal" => Some(AiType::Pet),
+            _ => None,
+        }
+    }
+
+    /// The detection types of the `AItype` of an alarm event such as
+    /// `"people,vehicle"`. Types neolink does not know are left out
+    pub fn list(ai_type: &str) -> Vec<AiType> {
+        ai_type.split(',').filter_map(AiType::from_name).collect()
+    }
+
+    /// The name neolink uses for this type
+    pub fn name(&self) -> &'static str {
+        match self {
+            AiType::Person => "person",
+            AiType::Vehicle => "vehicle",
+            AiType::Pet => "pet",
+        }
+    }
+}
+
 /// Motion Status that the callback can send
 pub enum MotionStatus {
-    /// Sent when motion is first detected
-    Start,
+    /// Sent when motion is first detected with what the AI detection of the
+    /// camera saw. This is empty for cameras without AI detection
+    Start(Vec<AiType>),
     /// Sent when motion stops
     Stop,
     /// Sent when an Alarm about something other than motion was received
@@ -104,7 +143,12 @@ impl BcCamera {
                         for alarm_event in &alarm_event_list.alarm_events {
                             if alarm_event.channel_id == self.channel_id {
                                 if alarm_event.status == "MD" {
-                                    result = MotionStatus::Start;
+                                    let ai_types = alarm_event
+                                        .ai_type
+                                        .as_deref()
+                                        .map(AiType::list)
+                                        .unwrap_or_default();
+                                    result = MotionStatus::Start(ai_types);
                                     break;
                                 } else if alarm_event.status == "none" {
                                     result = MotionStatus::Stop;
@@ -130,3 +174,18 @@ impl BcCamera {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ai_type_list() {
+        assert_eq!(
+            AiType::list("people,vehicle"),
+            vec![AiType::Person, AiType::Vehicle]
+        );
+        assert_eq!(AiType::list("dog_cat,face"), vec![AiType::Pet]);
+        assert!(AiType::list("none").is_empty());
+    }
+}
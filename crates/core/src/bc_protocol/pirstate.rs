@@ -1,34 +1,12 @@
-use super::{BcCamera, Error, Result, RX_TIMEOUT};
+use super::{BcCamera, Error, Result};
 use crate::bc::{model::*, xml::*};
 
 impl BcCamera {
     /// Get the [RfAlarmCfg] xml which contains the PIR status of the camera
+    ///
+    /// Returns [`Error::Unsupported`] if the camera has no PIR sensor
     pub fn get_pirstate(&mut self) -> Result<RfAlarmCfg> {
-        let connection = self
-            .connection
-            .as_ref()
-            .expect("Must be connected to get time");
-        let sub_get = connection.subscribe(MSG_ID_GET_PIR_ALARM)?;
-        let get = Bc {
-            meta: BcMeta {
-                msg_id: MSG_ID_GET_PIR_ALARM,
-                channel_id: self.channel_id,
-                msg_num: self.new_message_num(),
-                response_code: 0,
-                stream_type: 0,
-                class: 0x6414,
-            },
-            body: BcBody::ModernMsg(ModernMsg {
-                extension: Some(Extension {
-                    channel_id: Some(self.channel_id),
-                    ..Default::default()
-                }),
-                payload: None,
-            }),
-        };
-
-        sub_get.send(get)?;
-        let msg = sub_get.rx.recv_timeout(RX_TIMEOUT)?;
+        let msg = self.get_channel_xml(MSG_ID_GET_PIR_ALARM)?;
 
         if let BcBody::ModernMsg(ModernMsg {
             payload:
@@ -50,47 +28,17 @@ impl BcCamera {
 
     /// Set the PIR sensor using the [RfAlarmCfg] xml
     pub fn set_pirstate(&mut self, rf_alarm_cfg: RfAlarmCfg) -> Result<()> {
-        let connection = self
-            .connection
-            .as_ref()
-            .expect("Must be connected to get time");
-        let sub_set = connection.subscribe(MSG_ID_START_PIR_ALARM)?;
-
-        let get = Bc {
-            meta: BcMeta {
-                msg_id: MSG_ID_START_PIR_ALARM,
-                channel_id: self.channel_id,
-                msg_num: self.new_message_num(),
-                response_code: 0,
-                stream_type: 0,
-                class: 0x6414,
-            },
-            body: BcBody::ModernMsg(ModernMsg {
-                extension: Some(Extension {
-                    channel_id: Some(self.channel_id),
-                    ..Default::default()
-                }),
-                payload: Some(BcPayloads::BcXml(BcXml {
-                    rf_alarm_cfg: Some(rf_alarm_cfg),
-                    ..Default::default()
-                })),
-            }),
-        };
-
-        sub_set.send(get)?;
-        let msg = sub_set.rx.recv_timeout(RX_TIMEOUT)?;
-
-        if let BcMeta {
-            response_code: 200, ..
-        } = msg.meta
-        {
-            Ok(())
-        } else {
-            Err(Error::UnintelligibleReply {
-                reply: msg,
-                why: "The camera did not except the RfAlarmCfg xml",
-            })
+        if self.is_read_only("change the PIR sensor") {
+            return Ok(());
         }
+        self.set_channel_xml(
+            MSG_ID_START_PIR_ALARM,
+            BcXml {
+                rf_alarm_cfg: Some(rf_alarm_cfg),
+                ..Default::default()
+            },
+            "The camera did not except the RfAlarmCfg xml",
+        )
     }
 
     /// This is a convience function to control the PIR status
@@ -0,0 +1,238 @@
+use super::{
+    BcCamera, BinarySubscriber, Error, Result, Stream, StreamOutput, StreamOutputStatus,
+    RESPONSE_CODE_BAD_REQUEST, RX_TIMEOUT,
+};
+use crate::{
+    bc::{model::*, xml::*},
+    bcmedia::{self, model::*},
+};
+use std::io::ErrorKind;
+
+// Stop asking for more results if a search keeps returning them
+const MAX_SEARCH_PAGES: usize = 100;
+
+impl BcCamera {
+    ///
+    /// List the recordings from the storage between `start` and `end`
+    ///
+    /// # Parameters
+    ///
+    /// * `stream` - The [`Stream`] that was recorded
+    ///
+    /// * `start`, `end` - The times to search between in the camera's local time
+    ///
+    /// # Returns
+    ///
+    /// The recordings, each of which has a `name` that can be played with
+    /// [`BcCamera::start_playback()`], or [`Error::Unsupported`] if the camera
+    /// has no recordings to search
+    ///
+    pub fn list_recordings(
+        &self,
+        stream: Stream,
+        start: RecordTime,
+        end: RecordTime,
+    ) -> Result<Vec<FileInfo>> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to list the recordings");
+
+        let sub_open = connection.subscribe(MSG_ID_FILE_INFO_LIST_OPEN)?;
+        sub_open.send(self.file_info_request(
+            MSG_ID_FILE_INFO_LIST_OPEN,
+            FileInfo {
+                channel_id: self.channel_id,
+                stream_type: Some(stream_type(stream)),
+                record_type: Some("manual, sched, md, pir, io".to_string()),
+                start_time: Some(start),
+                end_time: Some(end),
+                ..Default::default()
+            },
+        ))?;
+        let msg = sub_open.rx.recv_timeout(RX_TIMEOUT)?;
+        if msg.meta.response_code == RESPONSE_CODE_BAD_REQUEST {
+            return Err(Error::Unsupported {
+                msg_id: MSG_ID_FILE_INFO_LIST_OPEN,
+            });
+        }
+        let handle = match file_info_reply(&msg)
+            .and_then(|files| files.first())
+            .and_then(|file| file.handle)
+        {
+            Some(handle) => handle,
+            None => {
+                return Err(Error::UnintelligibleReply {
+                    reply: msg,
+                    why: "Expected a FileInfoList with a search handle",
+                })
+            }
+        };
+
+        let search = || FileInfo {
+            channel_id: self.channel_id,
+            handle: Some(handle),
+            ..Default::default()
+        };
+        let mut recordings = vec![];
+        let sub_list = connection.subscribe(MSG_ID_FILE_INFO_LIST)?;
+        for _ in 0..MAX_SEARCH_PAGES {
+            sub_list.send(self.file_info_request(MSG_ID_FILE_INFO_LIST, search()))?;
+            let msg = sub_list.rx.recv_timeout(RX_TIMEOUT)?;
+            // The camera replies with no xml or with an error code once every recording has been sent
+            let page: Vec<_> = match msg.body {
+                BcBody::ModernMsg(ModernMsg {
+                    payload:
+                        Some(BcPayloads::BcXml(BcXml {
+                            file_info_list: Some(list),
+                            ..
+                        })),
+                    ..
+                }) if msg.meta.response_code == 200 => list
+                    .file_info
+                    .into_iter()
+                    .filter(|file| file.name.is_some())
+                    .collect(),
+                _ => vec![],
+            };
+            if page.is_empty() {
+                break;
+            }
+            recordings.extend(page);
+        }
+
+        let sub_close = connection.subscribe(MSG_ID_FILE_INFO_LIST_CLOSE)?;
+        sub_close.send(self.file_info_request(MSG_ID_FILE_INFO_LIST_CLOSE, search()))?;
+        let _ = sub_close.rx.recv_timeout(RX_TIMEOUT);
+
+        Ok(recordings)
+    }
+
+    ///
+    /// Play back a recording from the storage
+    ///
+    /// # Parameters
+    ///
+    /// * `data_outs` - This should be a struct that implements the [`StreamOutput`] trait
+    ///
+    /// * `name` - The `name` of the recording from [`BcCamera::list_recordings()`]
+    ///
+    /// * `stream` - The [`Stream`] that was recorded
+    ///
+    /// # Returns
+    ///
+    /// This blocks until the whole recording has been sent to the output, the
+    /// output asks to stop, or an error occurs
+    ///
+    pub fn start_playback<Outputs>(
+        &self,
+        data_outs: &mut Outputs,
+        name: &str,
+        stream: Stream,
+    ) -> Result<()>
+    where
+        Outputs: StreamOutput,
+    {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to play back a recording");
+        let sub_replay = connection.subscribe(MSG_ID_REPLAY)?;
+
+        let file = FileInfo {
+            uid: Some(0),
+            name: Some(name.to_string()),
+            channel_id: self.channel_id,
+            support_sub: Some(0),
+            play_speed: Some(1),
+            stream_type: Some(stream_type(stream)),
+            ..Default::default()
+        };
+        sub_replay.send(self.file_info_request(MSG_ID_REPLAY, file))?;
+
+        let mut media_sub = BinarySubscriber::from_bc_sub(&sub_replay);
+        let result = (|| {
+            let mut received = false;
+            let mut dropping = false;
+            loop {
+                let bc_media = match BcMedia::deserialize(&mut media_sub) {
+                    Ok(bc_media) => bc_media,
+                    // The camera goes quiet at the end of the recording
+                    Err(bcmedia::de::Error::IoError(e))
+                        if received && e.kind() == ErrorKind::TimedOut =>
+                    {
+                        return Ok(())
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                received = true;
+                if dropping {
+                    if let BcMedia::Iframe(_) = bc_media {
+                        dropping = false;
+                    } else {
+                        continue;
+                    }
+                }
+                match data_outs.stream_recv(bc_media) {
+                    Ok(StreamOutputStatus::Continue) => {}
+                    Ok(StreamOutputStatus::DropToKeyframe) => dropping = true,
+                    Ok(StreamOutputStatus::Stop) => return Ok(()),
+                    Err(e) => return Err(e),
+                };
+            }
+        })();
+
+        let stop = FileInfo {
+            uid: Some(0),
+            name: Some(name.to_string()),
+            channel_id: self.channel_id,
+            ..Default::default()
+        };
+        connection.send(self.file_info_request(MSG_ID_REPLAY_STOP, stop))?;
+
+        result
+    }
+
+    fn file_info_request(&self, msg_id: u32, file: FileInfo) -> Bc {
+        Bc::new_from_xml(
+            BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num: self.new_message_num(),
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            BcXml {
+                file_info_list: Some(FileInfoList {
+                    version: xml_ver(),
+                    file_info: vec![file],
+                }),
+                ..Default::default()
+            },
+        )
+    }
+}
+
+fn stream_type(stream: Stream) -> String {
+    match stream {
+        Stream::Main => "mainStream",
+        Stream::Sub => "subStream",
+        Stream::Extern => "externStream",
+    }
+    .to_string()
+}
+
+fn file_info_reply(msg: &Bc) -> Option<&Vec<FileInfo>> {
+    match &msg.body {
+        BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    file_info_list: Some(list),
+                    ..
+                })),
+            ..
+        }) => Some(&list.file_info),
+        _ => None,
+    }
+}
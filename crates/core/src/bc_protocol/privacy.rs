@@ -0,0 +1,50 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Whether the privacy mode is on, while it is the sensor is off and the camera
+    /// may send no video
+    ///
+    /// Returns [`Error::Unsupported`] if the camera has no privacy mode
+    pub fn get_privacy(&self) -> Result<bool> {
+        let msg = self.get_channel_xml(MSG_ID_GET_SLEEP)?;
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        sleep: Some(sleep), ..
+                    })),
+                ..
+            }) => Ok(sleep.sleep != 0),
+            _ => Err(Error::UnintelligibleReply {
+                reply: msg,
+                why: "Expected sleep xml but it was not recieved",
+            }),
+        }
+    }
+
+    /// Turn the privacy mode on or off
+    ///
+    /// Returns [`Error::Refused`] if the camera rejects it,
+    /// [`BcCamera::get_privacy`] tells if it has a privacy mode
+    pub fn set_privacy(&self, on: bool) -> Result<()> {
+        let what = match on {
+            true => "turn privacy mode on",
+            false => "turn privacy mode off",
+        };
+        if self.is_read_only(what) {
+            return Ok(());
+        }
+        self.set_channel_xml(
+            MSG_ID_SET_SLEEP,
+            BcXml {
+                sleep: Some(Sleep {
+                    version: xml_ver(),
+                    sleep: on as u8,
+                }),
+                ..Default::default()
+            },
+            "The camera did not accept the sleep xml",
+        )
+    }
+}
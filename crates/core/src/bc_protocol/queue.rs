@@ -0,0 +1,349 @@
+use super::{StreamOutput, StreamOutputError, StreamOutputStatus};
+use crate::bcmedia::model::*;
+use std::collections::VecDeque;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Condvar, Mutex,
+};
+
+/// A bounded queue between [`BcCamera::start_video()`](super::BcCamera::start_video)
+/// and an output that runs on another thread
+///
+/// Without it a consumer that stalls also stalls the reading of the camera,
+/// while the connection keeps buffering every message that arrives. Instead
+/// the queue holds at most `capacity` packets. When it is full the oldest
+/// packet that is not a keyframe is dropped so the camera is never blocked,
+/// along with the packets after it up to the next keyframe as the frames of
+/// the rest of that group of pictures can no longer be decoded.
+///
+/// The queue asks the stream to stop once the [`StreamQueueReceiver`] has
+/// finished, and the receiver finishes once the queue is dropped.
+pub struct StreamQueue {
+    shared: Arc<Shared>,
+}
+
+/// The consuming end of a [`StreamQueue`]
+pub struct StreamQueueReceiver {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    capacity: usize,
+    dropped: Mutex<Arc<AtomicU64>>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    media: VecDeque<BcMedia>,
+    // The sending end was dropped
+    closed: bool,
+    // The receiving end finished
+    stopped: bool,
+    // Packets are dropped until the next keyframe arrives
+    dropping: bool,
+}
+
+impl StreamQueue {
+    /// Create a queue that holds at most `capacity` packets
+    pub fn new(capacity: usize) -> (StreamQueue, StreamQueueReceiver) {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState::default()),
+            changed: Condvar::new(),
+            capacity: capacity.max(1),
+            dropped: Mutex::new(Default::default()),
+        });
+        (
+            StreamQueue {
+                shared: shared.clone(),
+            },
+            StreamQueueReceiver { shared },
+        )
+    }
+
+    /// Count the dropped packets in `counter` rather than in a counter of the queue's own
+    ///
+    /// This lets the count outlive the queue, such as across reconnects
+    pub fn set_dropped_counter(&mut self, counter: Arc<AtomicU64>) {
+        *self.shared.dropped.lock().unwrap() = counter;
+    }
+
+    /// The most packets that are held before they are dropped
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// The packets dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.lock().unwrap().load(Ordering::Relaxed)
+    }
+}
+
+impl StreamOutput for StreamQueue {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.stopped {
+            return Ok(StreamOutputStatus::Stop);
+        }
+        let is_key = |media: &BcMedia| matches!(media, BcMedia::Iframe(_));
+        let mut dropped = 0;
+        if state.dropping && is_key(&media) {
+            state.dropping = false;
+        }
+        if !state.dropping && state.media.len() >= self.shared.capacity {
+            dropped += match state.media.iter().position(|media| !is_key(media)) {
+                Some(start) => {
+                    let next_keyframe = state.media.iter().skip(start).position(is_key);
+                    match next_keyframe {
+                        Some(len) => state.media.drain(start..start + len).count(),
+                        None => {
+                            // The rest of the group of pictures is still to come
+                            state.dropping = true;
+                            state.media.drain(start..).count()
+                        }
+                    }
+                }
+                None => state.media.pop_front().map_or(0, |_| 1),
+            };
+        }
+        if state.dropping {
+            dropped += 1;
+        } else {
+            state.media.push_back(media);
+        }
+        if dropped > 0 {
+            self.shared
+                .dropped
+                .lock()
+                .unwrap()
+                .fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+        self.shared.changed.notify_all();
+        Ok(StreamOutputStatus::Continue)
+    }
+}
+
+impl Drop for StreamQueue {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl StreamQueueReceiver {
+    /// Pass the queued packets to `output` until the queue is dropped or the output stops
+    ///
+    /// If the output falls behind and asks to drop to the next keyframe the
+    /// packets before it are skipped
+    pub fn run<T: StreamOutput>(&self, output: &mut T) -> StreamOutputError {
+        let result = self.forward(output);
+        self.shared.state.lock().unwrap().stopped = true;
+        result
+    }
+
+    fn forward<T: StreamOutput>(&self, output: &mut T) -> StreamOutputError {
+        let mut dropping = false;
+        loop {
+            let media = {
+                let state = self.shared.state.lock().unwrap();
+                let mut state = self
+                    .shared
+                    .changed
+                    .wait_while(state, |state| state.media.is_empty() && !state.closed)
+                    .unwrap();
+                match state.media.pop_front() {
+                    Some(media) => media,
+                    None => return Ok(StreamOutputStatus::Stop),
+                }
+            };
+            if dropping {
+                if !matches!(media, BcMedia::Iframe(_)) {
+                    continue;
+                }
+                dropping = false;
+            }
+            match output.stream_recv(media)? {
+                StreamOutputStatus::Continue => {}
+                StreamOutputStatus::DropToKeyframe => dropping = true,
+                StreamOutputStatus::Stop => return Ok(StreamOutputStatus::Stop),
+            }
+        }
+    }
+
+    /// The packets waiting in the queue
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().media.len()
+    }
+
+    /// True if no packets are waiting in the queue
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter<'a> {
+        count: &'a mut usize,
+        result: StreamOutputStatus,
+    }
+
+    impl<'a> StreamOutput for Counter<'a> {
+        fn stream_recv(&mut self, _media: BcMedia) -> StreamOutputError {
+            *self.count += 1;
+            Ok(self.result)
+        }
+    }
+
+    // Counts the packets and the keyframes among them
+    struct Keyframes<'a>(&'a mut usize, &'a mut usize);
+
+    impl<'a> StreamOutput for Keyframes<'a> {
+        fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+            *self.0 += 1;
+            if let BcMedia::Iframe(_) = media {
+                *self.1 += 1;
+            }
+            Ok(StreamOutputStatus::Continue)
+        }
+    }
+
+    fn packet() -> BcMedia {
+        BcMedia::Aac(BcMediaAac { data: vec![0; 8] })
+    }
+
+    fn pframe(microseconds: u32) -> BcMedia {
+        BcMedia::Pframe(BcMediaPframe {
+            video_type: VideoType::H264,
+            microseconds,
+            data: vec![0; 8],
+        })
+    }
+
+    fn keyframe() -> BcMedia {
+        BcMedia::Iframe(BcMediaIframe {
+            video_type: VideoType::H264,
+            microseconds: 0,
+            time: None,
+            data: vec![0; 8],
+        })
+    }
+
+    #[test]
+    fn test_queue_slow_consumer_is_bounded() {
+        let (mut queue, receiver) = StreamQueue::new(10);
+        // Nothing is consumed while the camera sends many times the capacity
+        assert!(matches!(
+            queue.stream_recv(keyframe()),
+            Ok(StreamOutputStatus::Continue)
+        ));
+        for _ in 0..10_000 {
+            assert!(matches!(
+                queue.stream_recv(packet()),
+                Ok(StreamOutputStatus::Continue)
+            ));
+            assert!(receiver.len() <= queue.capacity());
+        }
+        assert_eq!(queue.dropped(), 10_000);
+
+        // The keyframe was kept and the packets after it were dropped
+        // until the next keyframe, which never came
+        drop(queue);
+        let mut count = 0;
+        let mut keyframes = 0;
+        assert!(matches!(
+            receiver.run(&mut Keyframes(&mut count, &mut keyframes)),
+            Ok(StreamOutputStatus::Stop)
+        ));
+        assert_eq!((count, keyframes), (1, 1));
+    }
+
+    #[test]
+    fn test_queue_drops_to_keyframe() {
+        let (mut queue, receiver) = StreamQueue::new(6);
+        for media in [
+            keyframe(),
+            pframe(1),
+            pframe(2),
+            keyframe(),
+            pframe(4),
+            pframe(5),
+        ] {
+            queue.stream_recv(media).unwrap();
+        }
+        // The whole first group of pictures after its keyframe goes
+        queue.stream_recv(pframe(6)).unwrap();
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(receiver.len(), 5);
+
+        // A full queue without a later keyframe drops up to the next one to arrive
+        queue.stream_recv(pframe(7)).unwrap();
+        queue.stream_recv(pframe(8)).unwrap();
+        queue.stream_recv(pframe(9)).unwrap();
+        assert_eq!(queue.dropped(), 2 + 6);
+        queue.stream_recv(keyframe()).unwrap();
+        queue.stream_recv(pframe(11)).unwrap();
+        assert_eq!(queue.dropped(), 2 + 6);
+
+        drop(queue);
+        let mut count = 0;
+        let mut keyframes = 0;
+        receiver
+            .run(&mut Keyframes(&mut count, &mut keyframes))
+            .unwrap();
+        assert_eq!((count, keyframes), (4, 3));
+    }
+
+    #[test]
+    fn test_queue_delivers_across_threads() {
+        let mut count = 0;
+        let (mut queue, receiver) = StreamQueue::new(10_000);
+        std::thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                receiver.run(&mut Counter {
+                    count: &mut count,
+                    result: StreamOutputStatus::Continue,
+                })
+            });
+            for _ in 0..1_000 {
+                queue.stream_recv(packet()).unwrap();
+            }
+            drop(queue);
+            assert!(consumer.join().unwrap().is_ok());
+        });
+        assert_eq!(count, 1_000);
+    }
+
+    #[test]
+    fn test_queue_stops_with_receiver() {
+        let mut count = 0;
+        let (mut queue, receiver) = StreamQueue::new(10);
+        queue.stream_recv(packet()).unwrap();
+        receiver
+            .run(&mut Counter {
+                count: &mut count,
+                result: StreamOutputStatus::Stop,
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+        assert!(matches!(
+            queue.stream_recv(packet()),
+            Ok(StreamOutputStatus::Stop)
+        ));
+    }
+
+    #[test]
+    fn test_queue_shared_dropped_counter() {
+        let counter = Arc::new(AtomicU64::new(5));
+        let (mut queue, _receiver) = StreamQueue::new(1);
+        queue.set_dropped_counter(counter.clone());
+        queue.stream_recv(packet()).unwrap();
+        queue.stream_recv(packet()).unwrap();
+        // Both go as there is no keyframe to drop to
+        assert_eq!(counter.load(Ordering::Relaxed), 7);
+        assert_eq!(queue.dropped(), 7);
+    }
+}
@@ -1,9 +1,12 @@
-use super::{BcCamera, Error, Result, RX_TIMEOUT};
+use super::{check_reply, BcCamera, Result, RX_TIMEOUT};
 use crate::bc::model::*;
 
 impl BcCamera {
     /// Reboot the camera
     pub fn reboot(&self) -> Result<()> {
+        if self.is_read_only("reboot the camera") {
+            return Ok(());
+        }
         let connection = self.connection.as_ref().expect("Must be connected to ping");
         let sub = connection.subscribe(MSG_ID_REBOOT)?;
 
@@ -24,16 +27,6 @@ impl BcCamera {
         sub.send(msg)?;
         let msg = sub.rx.recv_timeout(RX_TIMEOUT)?;
 
-        if let BcMeta {
-            response_code: 200, ..
-        } = msg.meta
-        {
-            Ok(())
-        } else {
-            Err(Error::UnintelligibleReply {
-                reply: msg,
-                why: "The camera did not accept the reboot command",
-            })
-        }
+        check_reply(msg, "The camera did not accept the reboot command")
     }
 }
@@ -0,0 +1,84 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [RecordCfg] xml which contains the SD recording settings such as overwrite
+    ///
+    /// Returns [`Error::Unsupported`] if the camera has no such settings
+    pub fn get_record_cfg(&self) -> Result<RecordCfg> {
+        let msg = self.get_channel_xml(MSG_ID_GET_RECORD_CFG)?;
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        record_cfg: Some(record_cfg),
+                        ..
+                    })),
+                ..
+            }) => Ok(record_cfg),
+            _ => Err(Error::UnintelligibleReply {
+                reply: msg,
+                why: "Expected RecordCfg xml but it was not recieved",
+            }),
+        }
+    }
+
+    /// Set the SD recording settings using the [RecordCfg] xml
+    ///
+    /// Returns [`Error::Refused`] if the camera rejects them,
+    /// [`BcCamera::get_record_cfg`] tells if it has such settings
+    pub fn set_record_cfg(&self, mut record_cfg: RecordCfg) -> Result<()> {
+        if self.is_read_only("change the SD card recording settings") {
+            return Ok(());
+        }
+        // The channel is given in the extension instead
+        record_cfg.channel_id = None;
+        self.set_channel_xml(
+            MSG_ID_SET_RECORD_CFG,
+            BcXml {
+                record_cfg: Some(record_cfg),
+                ..Default::default()
+            },
+            "The camera did not accept the RecordCfg xml",
+        )
+    }
+
+    /// Get the [Record] xml which contains the recording schedule
+    ///
+    /// Returns [`Error::Unsupported`] if the camera has no schedule
+    pub fn get_record(&self) -> Result<Record> {
+        let msg = self.get_channel_xml(MSG_ID_GET_RECORD)?;
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        record: Some(record),
+                        ..
+                    })),
+                ..
+            }) => Ok(record),
+            _ => Err(Error::UnintelligibleReply {
+                reply: msg,
+                why: "Expected Record xml but it was not recieved",
+            }),
+        }
+    }
+
+    /// Set the recording schedule using the [Record] xml
+    ///
+    /// Returns [`Error::Refused`] if the camera rejects it,
+    /// [`BcCamera::get_record`] tells if it has a schedule
+    pub fn set_record(&self, record: Record) -> Result<()> {
+        if self.is_read_only("change the recording schedule") {
+            return Ok(());
+        }
+        self.set_channel_xml(
+            MSG_ID_SET_RECORD,
+            BcXml {
+                record: Some(record),
+                ..Default::default()
+            },
+            "The camera did not accept the Record xml",
+        )
+    }
+}
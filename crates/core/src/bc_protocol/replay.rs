@@ -0,0 +1,156 @@
+use super::{stream::feed_media, Error, Result, StreamOutput};
+use crate::bcmedia;
+use std::io::{ErrorKind, Read};
+
+///
+/// Replays recorded media through a [`StreamOutput`] as if it came from a camera
+///
+/// This lets outputs be tested without a camera. The `recording` is the raw
+/// media of a video stream, as in the bcmedia samples, and is handled just like
+/// [`super::BcCamera::start_video()`] handles the media from the camera,
+/// including dropping to the next keyframe when the output asks.
+///
+/// # Parameters
+///
+/// * `recording` - The recorded media
+///
+/// * `data_outs` - The output to replay it through
+///
+/// # Returns
+///
+/// This returns when the recording ends or when the output asks to stop
+///
+pub fn replay_media<R, Outputs>(mut recording: R, data_outs: &mut Outputs) -> Result<()>
+where
+    R: Read,
+    Outputs: StreamOutput,
+{
+    match feed_media(&mut recording, data_outs) {
+        Err(Error::MediaDeserialization(bcmedia::de::Error::IoError(e)))
+            if e.kind() == ErrorKind::UnexpectedEof =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bc_protocol::{FileSubscriber, StreamOutputError, StreamOutputStatus};
+    use crate::bcmedia::model::*;
+    use std::path::PathBuf;
+
+    fn samples(names: Vec<String>) -> FileSubscriber {
+        let dir = std::env::current_dir().unwrap(); // This is crate root during cargo test
+        let dir = dir.join("src").join("bcmedia").join("samples");
+        FileSubscriber::from_files(
+            names
+                .iter()
+                .map(|name| dir.join(name))
+                .collect::<Vec<PathBuf>>(),
+        )
+    }
+
+    // The capture of an H264 camera with its stream info and ADPCM audio
+    fn h264_recording() -> FileSubscriber {
+        let mut names = vec!["info_v1.raw".to_string()];
+        names.extend((0..10).map(|i| format!("video_stream_swan_{:02}.raw", i)));
+        samples(names)
+    }
+
+    // The capture of an H265 camera with AAC audio
+    fn h265_recording() -> FileSubscriber {
+        let mut names: Vec<_> = (0..5).map(|i| format!("argus2_iframe_{}.raw", i)).collect();
+        names.extend((0..18).map(|i| format!("argus2_pframe_{}.raw", i)));
+        samples(names)
+    }
+
+    // Notes what an output would base its decisions on
+    #[derive(Default)]
+    struct Decisions {
+        resolution: Option<(u32, u32)>,
+        codecs: Vec<VideoType>,
+        frames: Vec<char>,
+        audio: Vec<&'static str>,
+        // Ask to drop to the next keyframe at this frame
+        drop_at: Option<usize>,
+        stop_at: Option<usize>,
+    }
+
+    impl StreamOutput for Decisions {
+        fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+            match media {
+                BcMedia::InfoV1(info) => {
+                    self.resolution = Some((info.video_width, info.video_height))
+                }
+                BcMedia::InfoV2(info) => {
+                    self.resolution = Some((info.video_width, info.video_height))
+                }
+                BcMedia::Iframe(frame) => {
+                    self.codecs.push(frame.video_type);
+                    self.frames.push('I');
+                }
+                BcMedia::Pframe(frame) => {
+                    self.codecs.push(frame.video_type);
+                    self.frames.push('P');
+                }
+                BcMedia::Aac(_) => self.audio.push("aac"),
+                BcMedia::Adpcm(_) => self.audio.push("adpcm"),
+            }
+            let frames = Some(self.frames.len());
+            if frames == self.stop_at {
+                Ok(StreamOutputStatus::Stop)
+            } else if frames == self.drop_at {
+                Ok(StreamOutputStatus::DropToKeyframe)
+            } else {
+                Ok(StreamOutputStatus::Continue)
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_h264() {
+        let mut decisions = Decisions::default();
+        replay_media(h264_recording(), &mut decisions).unwrap();
+        assert_eq!(decisions.resolution, Some((2560, 1440)));
+        assert!(decisions.codecs.iter().all(|c| *c == VideoType::H264));
+        assert_eq!(decisions.frames.first(), Some(&'I'));
+        assert!(decisions.frames.contains(&'P'));
+        assert!(decisions.audio.iter().all(|a| *a == "adpcm"));
+        assert!(!decisions.audio.is_empty());
+    }
+
+    #[test]
+    fn test_replay_h265() {
+        let mut decisions = Decisions::default();
+        replay_media(h265_recording(), &mut decisions).unwrap();
+        assert_eq!(decisions.resolution, None);
+        assert_eq!(decisions.codecs.first(), Some(&VideoType::H265));
+        assert!(decisions.codecs.iter().all(|c| *c == VideoType::H265));
+        assert_eq!(decisions.audio, vec!["aac"]);
+    }
+
+    #[test]
+    fn test_replay_drops_to_keyframe() {
+        // The H265 capture has a single keyframe so dropping at it skips the rest
+        let mut decisions = Decisions {
+            drop_at: Some(1),
+            ..Default::default()
+        };
+        replay_media(h265_recording(), &mut decisions).unwrap();
+        assert_eq!(decisions.frames, vec!['I']);
+        assert!(decisions.audio.is_empty());
+    }
+
+    #[test]
+    fn test_replay_stops() {
+        let mut decisions = Decisions {
+            stop_at: Some(2),
+            ..Default::default()
+        };
+        replay_media(h264_recording(), &mut decisions).unwrap();
+        assert_eq!(decisions.frames, vec!['I', 'P']);
+    }
+}
@@ -0,0 +1,64 @@
+use super::{BcCamera, Result};
+use crate::bc::{model::*, xml::*};
+
+/// The play mode that sounds the siren until it is turned off
+const PLAY_MODE_MANUAL: u32 = 2;
+
+impl BcCamera {
+    /// Sound the siren until it is turned off, or silence it
+    ///
+    /// Silencing also stops a siren that the camera sounded on its own such as on motion.
+    /// Returns [`Error::Refused`](super::Error::Refused) if the camera rejects it, as one
+    /// with no siren does
+    pub fn siren_set(&self, on: bool) -> Result<()> {
+        let what = match on {
+            true => "sound the siren",
+            false => "silence the siren",
+        };
+        if self.is_read_only(what) {
+            return Ok(());
+        }
+        self.set_channel_xml(
+            MSG_ID_PLAY_AUDIO,
+            siren_xml(self.channel_id, on),
+            "The camera did not accept the audioPlayInfo xml",
+        )
+    }
+}
+
+/// The xml that sounds the siren of `channel_id` until it is turned off, or silences it
+fn siren_xml(channel_id: u8, on: bool) -> BcXml {
+    BcXml {
+        audio_play_info: Some(AudioPlayInfo {
+            channel_id,
+            play_mode: PLAY_MODE_MANUAL,
+            play_duration: 0,
+            play_times: 0,
+            on_off: on as u32,
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_siren_xml() {
+        let xml = siren_xml(2, true).serialize(vec![]).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("<audioPlayInfo>"));
+        assert!(xml.contains("<channelId>2</channelId>"));
+        assert!(xml.contains("<playMode>2</playMode>"));
+        assert!(xml.contains("<playDuration>0</playDuration>"));
+        assert!(xml.contains("<playTimes>0</playTimes>"));
+        assert!(xml.contains("<onOff>1</onOff>"));
+
+        let silence = siren_xml(0, false);
+        assert_eq!(silence.audio_play_info.as_ref().unwrap().on_off, 0);
+        // It reads back as it was sent
+        let xml = silence.serialize(vec![]).unwrap();
+        assert_eq!(BcXml::try_parse(xml.as_slice()).unwrap(), silence);
+    }
+}
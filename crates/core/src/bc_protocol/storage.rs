@@ -0,0 +1,59 @@
+use super::{BcCamera, Error, Result, RESPONSE_CODE_BAD_REQUEST, RX_TIMEOUT};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [HddInfoList] xml which describes the SD card or other storage of the camera
+    ///
+    /// Cameras without any storage reply with an empty list, or
+    /// [`Error::Unsupported`] if they have no storage support at all
+    pub fn get_hdd_info(&self) -> Result<HddInfoList> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to get the storage info");
+        let sub_get = connection.subscribe(MSG_ID_HDD_INFO_LIST)?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_HDD_INFO_LIST,
+                channel_id: self.channel_id,
+                msg_num: self.new_message_num(),
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                ..Default::default()
+            }),
+        };
+
+        sub_get.send(get)?;
+        let msg = sub_get.rx.recv_timeout(RX_TIMEOUT)?;
+        if msg.meta.response_code == RESPONSE_CODE_BAD_REQUEST {
+            return Err(Error::Unsupported {
+                msg_id: MSG_ID_HDD_INFO_LIST,
+            });
+        }
+
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        hdd_info_list: Some(hdd_info_list),
+                        ..
+                    })),
+                ..
+            }) => Ok(hdd_info_list),
+            // Some cameras without storage send no xml at all
+            BcBody::ModernMsg(ModernMsg { payload: None, .. }) if msg.meta.response_code == 200 => {
+                Ok(HddInfoList {
+                    version: xml_ver(),
+                    hdd_info: vec![],
+                })
+            }
+            _ => Err(Error::UnintelligibleReply {
+                reply: msg,
+                why: "Expected HddInfoList xml but it was not recieved",
+            }),
+        }
+    }
+}
@@ -3,24 +3,48 @@ use crate::{
     bc::{model::*, xml::*},
     bcmedia::model::*,
 };
+use std::io::Read;
 
 /// Convience type for the error raised by the [StreamOutput] trait
-pub type StreamOutputError = Result<bool>;
+pub type StreamOutputError = Result<StreamOutputStatus>;
+
+/// What a [`StreamOutput`] wants to happen after it received a packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StreamOutputStatus {
+    /// Keep sending every packet
+    Continue,
+    /// The output is falling behind. The packets up to the next keyframe
+    /// are skipped so that it can catch up without corrupting the picture
+    DropToKeyframe,
+    /// Stop the stream
+    Stop,
+}
 
 /// The method [`BcCamera::start_video()`] requires a structure with this trait to pass the
 /// audio and video data back to
 pub trait StreamOutput {
     /// This is the callback raised a complete media packet is received
     ///
-    /// If result is `Ok(true)` more messages will be sent
+    /// If result is `Ok(StreamOutputStatus::Continue)` more messages will be sent
     ///
-    /// If result if `Ok(false)` then message will be stopped
+    /// If result is `Ok(StreamOutputStatus::DropToKeyframe)` messages will
+    /// resume from the next keyframe
+    ///
+    /// If result if `Ok(StreamOutputStatus::Stop)` then message will be stopped
     ///
     /// If result is `Err(E)` then messages be stopped
     /// and an error will be thrown
     fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError;
 }
 
+/// Lets an output be borrowed by a
+/// [`StreamFanOut`](super::StreamFanOut) or other wrapper
+impl<T: StreamOutput + ?Sized> StreamOutput for &mut T {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        (**self).stream_recv(media)
+    }
+}
+
 /// The stream names supported by BC
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Stream {
@@ -62,6 +86,56 @@ impl BcCamera {
             .expect("Must be connected to start video");
         let sub_video = connection.subscribe(MSG_ID_VIDEO)?;
 
+        sub_video.send(self.preview_request(MSG_ID_VIDEO, stream))?;
+
+        let mut media_sub = BinarySubscriber::from_bc_sub(&sub_video);
+
+        feed_media(&mut media_sub, data_outs)
+    }
+
+    /// Ask the camera to send a new keyframe on a running video stream
+    ///
+    /// There is no known message that asks for a keyframe. Instead this sends
+    /// the same preview start (`MSG_ID_VIDEO`) as [`BcCamera::start_video()`]
+    /// again on the running connection. The camera begins each preview with
+    /// an I-frame, so most cameras send a fresh keyframe without restarting
+    /// the stream and the media continues to arrive on the existing
+    /// [`StreamOutput`]. The reply to the second start is not waited for, and
+    /// a camera that does not take it this way may stall or restart the stream.
+    ///
+    /// # Parameters
+    ///
+    /// * `stream` - The [`Stream`] that was passed to [`BcCamera::start_video()`]
+    ///
+    pub fn request_keyframe(&self, stream: Stream) -> Result<()> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to request a keyframe");
+        connection.send(self.preview_request(MSG_ID_VIDEO, stream))?;
+        Ok(())
+    }
+
+    /// Ask the camera to stop sending a video stream
+    ///
+    /// The camera keeps sending the stream of a [`BcCamera::start_video()`]
+    /// whose output has stopped, call this to free the connection of it
+    /// when the connection is kept for something else.
+    ///
+    /// # Parameters
+    ///
+    /// * `stream` - The [`Stream`] that was passed to [`BcCamera::start_video()`]
+    ///
+    pub fn stop_video(&self, stream: Stream) -> Result<()> {
+        let connection = self
+            .connection
+            .as_ref()
+            .expect("Must be connected to stop video");
+        connection.send(self.preview_request(MSG_ID_VIDEO_STOP, stream))?;
+        Ok(())
+    }
+
+    fn preview_request(&self, msg_id: u32, stream: Stream) -> Bc {
         // On an E1 and swann cameras:
         //  - mainStream always has a value of 0
         //  - subStream always has a value of 1
@@ -98,9 +172,9 @@ impl BcCamera {
         }
         .to_string();
 
-        let start_video = Bc::new_from_xml(
+        Bc::new_from_xml(
             BcMeta {
-                msg_id: MSG_ID_VIDEO,
+                msg_id,
                 channel_id: self.channel_id,
                 msg_num: self.new_message_num(),
                 stream_type: stream_code,
@@ -116,20 +190,35 @@ impl BcCamera {
                 }),
                 ..Default::default()
             },
-        );
-
-        sub_video.send(start_video)?;
-
-        let mut media_sub = BinarySubscriber::from_bc_sub(&sub_video);
+        )
+    }
+}
 
-        loop {
-            let bc_media = BcMedia::deserialize(&mut media_sub)?;
-            // We now have a complete interesting packet. Send it to on the callback
-            match data_outs.stream_recv(bc_media) {
-                Ok(true) => {}
-                Ok(false) => return Ok(()),
-                Err(e) => return Err(e),
-            };
+/// Pass the media read from `media` to `data_outs` until it asks to stop
+///
+/// This is shared by [`BcCamera::start_video()`] and [`super::replay_media()`]
+/// so that replayed media is handled like media from a camera
+pub(super) fn feed_media<R, Outputs>(media: &mut R, data_outs: &mut Outputs) -> Result<()>
+where
+    R: Read,
+    Outputs: StreamOutput,
+{
+    let mut dropping = false;
+    loop {
+        let bc_media = BcMedia::deserialize(&mut *media)?;
+        if dropping {
+            if let BcMedia::Iframe(_) = bc_media {
+                dropping = false;
+            } else {
+                continue;
+            }
         }
+        // We now have a complete interesting packet. Send it to on the callback
+        match data_outs.stream_recv(bc_media) {
+            Ok(StreamOutputStatus::Continue) => {}
+            Ok(StreamOutputStatus::DropToKeyframe) => dropping = true,
+            Ok(StreamOutputStatus::Stop) => return Ok(()),
+            Err(e) => return Err(e),
+        };
     }
 }
@@ -57,31 +57,7 @@ impl BcCamera {
     /// Requests the [`TalkAbility`] xml
     ///
     pub fn talk_ability(&self) -> Result<TalkAbility> {
-        let connection = self
-            .connection
-            .as_ref()
-            .expect("Must be connected to get time");
-        let sub_get = connection.subscribe(MSG_ID_TALKABILITY)?;
-        let get = Bc {
-            meta: BcMeta {
-                msg_id: MSG_ID_TALKABILITY,
-                channel_id: self.channel_id,
-                msg_num: self.new_message_num(),
-                response_code: 0,
-                stream_type: 0,
-                class: 0x6414,
-            },
-            body: BcBody::ModernMsg(ModernMsg {
-                extension: Some(Extension {
-                    channel_id: Some(self.channel_id),
-                    ..Default::default()
-                }),
-                payload: None,
-            }),
-        };
-
-        sub_get.send(get)?;
-        let msg = sub_get.rx.recv_timeout(RX_TIMEOUT)?;
+        let msg = self.get_channel_xml(MSG_ID_TALKABILITY)?;
 
         if let BcBody::ModernMsg(ModernMsg {
             payload:
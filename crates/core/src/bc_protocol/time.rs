@@ -1,4 +1,4 @@
-use super::{BcCamera, Error, Result, RX_TIMEOUT};
+use super::{check_reply, BcCamera, Error, Result, RX_TIMEOUT};
 use crate::bc::{model::*, xml::*};
 use time::{date, Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
@@ -91,9 +91,12 @@ impl BcCamera {
     ///
     /// # Returns
     ///
-    /// returns Ok(()) or error
+    /// returns Ok(()) or error, [`Error::Refused`] if the camera does not accept the time
     ///
     pub fn set_time(&self, timestamp: OffsetDateTime) -> Result<()> {
+        if self.is_read_only("set the time") {
+            return Ok(());
+        }
         let connection = self
             .connection
             .as_ref()
@@ -128,9 +131,9 @@ impl BcCamera {
         );
 
         sub_set_general.send(set)?;
-        let _ = sub_set_general.rx.recv_timeout(RX_TIMEOUT)?;
+        let msg = sub_set_general.rx.recv_timeout(RX_TIMEOUT)?;
 
-        Ok(())
+        check_reply(msg, "The camera did not accept the new time")
     }
 }
 
@@ -4,3 +4,5 @@ pub mod de;
 pub mod model;
 /// Serlizer for BCMedia
 pub mod ser;
+/// Reads the picture size from the H264/H265 SPS
+pub mod sps;
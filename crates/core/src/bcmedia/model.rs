@@ -1,5 +1,5 @@
 /// Video streams encapsulate a stream of BcMedia
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum BcMedia {
     /// Holds info on the stream
     InfoV1(BcMediaInfoV1),
@@ -19,7 +19,7 @@ pub(super) const MAGIC_HEADER_BCMEDIA_INFO_V1: u32 = 0x31303031;
 
 /// The start of a BcMedia stream contains this message
 /// which describes the data to follow
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BcMediaInfoV1 {
     // This is the size of the header so it's actually a fixed value
     // The other messages have body size here so maybe that's why
@@ -63,7 +63,7 @@ pub(super) const MAGIC_HEADER_BCMEDIA_INFO_V2: u32 = 0x32303031;
 
 /// The start of a BcMedia stream contains this message
 /// which describes the data to follow
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BcMediaInfoV2 {
     // This is the size of the header so it's actually a fixed value
     // The other messages have body size here so maybe that's why
@@ -108,7 +108,7 @@ pub(super) const MAGIC_HEADER_BCMEDIA_IFRAME: u32 = 0x63643030;
 pub(super) const MAGIC_HEADER_BCMEDIA_IFRAME_LAST: u32 = 0x63643039;
 
 /// Video Types for I/PFrame
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VideoType {
     /// H264 video data
     H264,
@@ -117,7 +117,7 @@ pub enum VideoType {
 }
 
 /// This is a BcMedia video IFrame.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BcMediaIframe {
     /// "H264", or "H265"
     pub video_type: VideoType,
@@ -139,7 +139,7 @@ pub(super) const MAGIC_HEADER_BCMEDIA_PFRAME: u32 = 0x63643130;
 pub(super) const MAGIC_HEADER_BCMEDIA_PFRAME_LAST: u32 = 0x63643139;
 
 /// This is a BcMedia video PFrame.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BcMediaPframe {
     /// "H264", or "H265"
     pub video_type: VideoType,
@@ -156,7 +156,7 @@ pub struct BcMediaPframe {
 pub(super) const MAGIC_HEADER_BCMEDIA_AAC: u32 = 0x62773530;
 
 /// This contains BcMedia audio data in AAC format
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BcMediaAac {
     // Size of payload after header in bytes
     // pub payload_size: u16,
@@ -171,7 +171,7 @@ pub(super) const MAGIC_HEADER_BCMEDIA_ADPCM: u32 = 0x62773130;
 pub(super) const MAGIC_HEADER_BCMEDIA_ADPCM_DATA: u16 = 0x0100;
 
 /// This contains BcMedia audio data in ADPCM format
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BcMediaAdpcm {
     // Size of payload after header in bytes
     // pub payload_size: u16,
@@ -0,0 +1,325 @@
+//! Reads the picture size from the sequence parameter set (SPS) of a keyframe
+//!
+//! Some camera firmwares never send the `InfoV1`/`InfoV2` packets that
+//! normally describe the stream, so the size is taken from the bitstream
+//! instead. Only the fields needed to reach the size are parsed.
+use super::model::VideoType;
+
+/// The size of the pictures described by an SPS
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpsSize {
+    /// Width in pixels after cropping
+    pub width: u32,
+    /// Height in pixels after cropping
+    pub height: u32,
+}
+
+/// Find the SPS in an Annex B keyframe of `video_type` and read its picture size
+///
+/// Returns `None` if the data has no SPS or it could not be parsed
+pub fn parse_sps_size(video_type: VideoType, data: &[u8]) -> Option<SpsSize> {
+    nal_units(data).find_map(|nal| match video_type {
+        VideoType::H264 if nal.first().map(|b| b & 0x1F) == Some(7) => {
+            parse_h264_sps(&unescape(&nal[1..]))
+        }
+        VideoType::H265 if nal.first().map(|b| (b >> 1) & 0x3F) == Some(33) => {
+            parse_h265_sps(&unescape(nal.get(2..)?))
+        }
+        _ => None,
+    })
+}
+
+// Split Annex B data on its 00 00 01 start codes
+fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends: Vec<_> = starts
+        .iter()
+        .skip(1)
+        .map(|&start| start - 3)
+        .chain(std::iter::once(data.len()))
+        .collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(move |(start, end)| &data[start..end])
+}
+
+// Remove the emulation prevention bytes, 00 00 03 becomes 00 00
+fn unescape(nal: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit.into())
+    }
+
+    fn bits(&mut self, count: usize) -> Option<u32> {
+        (0..count).try_fold(0, |acc, _| Some((acc << 1) | self.bit()?))
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        if self.pos + count > self.data.len() * 8 {
+            return None;
+        }
+        self.pos += count;
+        Some(())
+    }
+
+    // Exp-Golomb unsigned
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << zeros) - 1 + self.bits(zeros)?)
+    }
+
+    // Exp-Golomb signed
+    fn se(&mut self) -> Option<i32> {
+        let value = self.ue()?;
+        Some(if value % 2 == 1 {
+            value.div_ceil(2) as i32
+        } else {
+            -((value / 2) as i32)
+        })
+    }
+}
+
+// The chroma subsampling of `chroma_format_idc` as (SubWidthC, SubHeightC)
+fn chroma_subsampling(chroma_format_idc: u32) -> (u32, u32) {
+    match chroma_format_idc {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    }
+}
+
+fn parse_h264_sps(rbsp: &[u8]) -> Option<SpsSize> {
+    let mut r = BitReader::new(rbsp);
+    let profile_idc = r.bits(8)?;
+    r.skip(16)?; // constraint flags and level_idc
+    r.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    if [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135].contains(&profile_idc) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 && r.bit()? == 1 {
+            // Each colour plane is coded on its own like monochrome
+            chroma_format_idc = 0;
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.skip(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.bit()? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.bit()? == 1 {
+                    skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.skip(1)?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.skip(1)?; // gaps_in_frame_num_value_allowed_flag
+    let width_in_mbs = r.ue()? + 1;
+    let height_in_map_units = r.ue()? + 1;
+    let frame_mbs_only = r.bit()?;
+    if frame_mbs_only == 0 {
+        r.skip(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.skip(1)?; // direct_8x8_inference_flag
+
+    let mut width = width_in_mbs * 16;
+    let mut height = (2 - frame_mbs_only) * height_in_map_units * 16;
+    if r.bit()? == 1 {
+        let (crop_x, crop_y) = match chroma_format_idc {
+            0 => (1, 2 - frame_mbs_only),
+            idc => {
+                let (sub_width, sub_height) = chroma_subsampling(idc);
+                (sub_width, sub_height * (2 - frame_mbs_only))
+            }
+        };
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        width = width.checked_sub(crop_x * (left + right))?;
+        height = height.checked_sub(crop_y * (top + bottom))?;
+    }
+    Some(SpsSize { width, height })
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Option<()> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            next_scale = (last_scale + r.se()? + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Some(())
+}
+
+fn parse_h265_sps(rbsp: &[u8]) -> Option<SpsSize> {
+    let mut r = BitReader::new(rbsp);
+    r.skip(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = r.bits(3)? as usize;
+    r.skip(1)?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level
+    r.skip(96)?; // The general profile, tier and level
+    let mut sub_layers = vec![];
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((r.bit()?, r.bit()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present == 1 {
+            r.skip(88)?;
+        }
+        if level_present == 1 {
+            r.skip(8)?;
+        }
+    }
+
+    r.ue()?; // sps_seq_parameter_set_id
+    let mut chroma_format_idc = r.ue()?;
+    if chroma_format_idc == 3 && r.bit()? == 1 {
+        chroma_format_idc = 0;
+    }
+    let mut width = r.ue()?;
+    let mut height = r.ue()?;
+    if r.bit()? == 1 {
+        let (sub_width, sub_height) = chroma_subsampling(chroma_format_idc);
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        width = width.checked_sub(sub_width * (left + right))?;
+        height = height.checked_sub(sub_height * (top + bottom))?;
+    }
+    Some(SpsSize { width, height })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str) -> Vec<u8> {
+        let dir = std::env::current_dir().unwrap(); // This is crate root during cargo test
+        std::fs::read(dir.join("src").join("bcmedia").join("samples").join(name)).unwrap()
+    }
+
+    #[test]
+    fn test_h264_sps() {
+        // The same camera reports 2560x1440 in its InfoV1
+        let data = sample("iframe_0.raw");
+        assert_eq!(
+            parse_sps_size(VideoType::H264, &data),
+            Some(SpsSize {
+                width: 2560,
+                height: 1440
+            })
+        );
+    }
+
+    #[test]
+    fn test_h265_sps() {
+        let data = sample("argus2_iframe_0.raw");
+        assert_eq!(
+            parse_sps_size(VideoType::H265, &data),
+            Some(SpsSize {
+                width: 3840,
+                height: 2160
+            })
+        );
+    }
+
+    #[test]
+    fn test_keyframe_without_sps() {
+        // Later keyframes of a stream only carry the slices
+        assert_eq!(
+            parse_sps_size(VideoType::H264, &sample("iframe_2.raw")),
+            None
+        );
+        assert_eq!(
+            parse_sps_size(VideoType::H265, &sample("argus2_iframe_1.raw")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_no_sps() {
+        assert_eq!(
+            parse_sps_size(VideoType::H264, &[0, 0, 1, 0x65, 0x88]),
+            None
+        );
+        assert_eq!(
+            parse_sps_size(VideoType::H265, &sample("iframe_0.raw")),
+            None
+        );
+        // Truncated inside the SPS
+        assert_eq!(
+            parse_sps_size(VideoType::H264, &[0, 0, 1, 0x67, 0x64, 0x00]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(
+            unescape(&[1, 0, 0, 3, 1, 0, 0, 3, 0]),
+            vec![1, 0, 0, 1, 0, 0, 0]
+        );
+    }
+}
@@ -24,6 +24,8 @@
 //! For further commands see the [`bc_protocol::BcCamera`] struct.
 //!
 
+/// Contains the ADPCM encoder used to prepare talk audio
+pub mod adpcm;
 /// Contains low level BC structures and formats
 pub mod bc;
 /// Contains high level interfaces for the camera
@@ -32,6 +34,8 @@ pub mod bc_protocol;
 pub mod bcmedia;
 ///  Contains low level structures and formats for the udpstream
 pub mod bcudp;
+/// Contains helpers for converting PCM audio
+pub mod pcm;
 
 /// This is the top level error structure of the library
 ///
@@ -0,0 +1,234 @@
+//! Helpers for preparing PCM audio before it is encoded for the camera
+
+/// Converts a stream of mono PCM from one sample rate to another
+///
+/// This uses linear interpolation which is good enough for the camera
+/// speakers. The resampler keeps its position between calls so audio can
+/// be fed to it in chunks of any size.
+pub struct Resampler {
+    from_rate: u64,
+    to_rate: u64,
+    // The position is kept as a multiple of `1 / to_rate` input samples
+    // so that it does not drift however the input is chunked
+    position: u64,
+    previous: i16,
+}
+
+impl Resampler {
+    /// Create a resampler from `from_rate` to `to_rate`
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        assert!(
+            from_rate > 0 && to_rate > 0,
+            "Sample rates must be greater than zero"
+        );
+        Self {
+            from_rate: from_rate as u64,
+            to_rate: to_rate as u64,
+            // Start one sample in so the first output is the first input
+            position: to_rate as u64,
+            previous: 0,
+        }
+    }
+
+    /// Resample the next chunk of audio
+    pub fn process(&mut self, input: &[i16]) -> Vec<i16> {
+        let len = input.len() as u64 * self.to_rate;
+        let mut output =
+            Vec::with_capacity((input.len() as u64 * self.to_rate / self.from_rate) as usize + 1);
+        // `position` is relative to `previous` which sits just before the start of `input`
+        while self.position < len {
+            let index = (self.position / self.to_rate) as usize;
+            let frac = (self.position % self.to_rate) as i64;
+            let before = if index == 0 {
+                self.previous
+            } else {
+                input[index - 1]
+            } as i64;
+            let after = input[index] as i64;
+            let sample = before + (after - before) * frac / self.to_rate as i64;
+            output.push(sample as i16);
+            self.position += self.from_rate;
+        }
+        self.position -= len;
+        if let Some(last) = input.last() {
+            self.previous = *last;
+        }
+        output
+    }
+}
+
+/// Mix interleaved audio with `channels` channels down to mono
+pub fn downmix(interleaved: &[i16], channels: u16) -> Vec<i16> {
+    let channels = channels.max(1) as usize;
+    if channels == 1 {
+        return interleaved.to_vec();
+    }
+    interleaved
+        .chunks_exact(channels)
+        .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / channels as i32) as i16)
+        .collect()
+}
+
+/// Convert an integer sample of `bits` bit depth to 16 bit
+pub fn int_to_i16(sample: i32, bits: u16) -> i16 {
+    match bits {
+        0..=16 => (sample << (16 - bits)) as i16,
+        _ => (sample >> (bits - 16)) as i16,
+    }
+}
+
+/// Convert a float sample in the range -1.0..1.0 to 16 bit
+pub fn float_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Convert a gain in decibels to a linear multiplier
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Scale the samples by a linear `gain`
+///
+/// Rather than hard clipping, samples above about 80% of full scale are
+/// passed through a soft limiter so that loud input does not distort badly
+pub fn apply_gain(samples: &mut [i16], gain: f32) {
+    if (gain - 1.0).abs() < f32::EPSILON {
+        return;
+    }
+    for sample in samples.iter_mut() {
+        let scaled = *sample as f32 / i16::MAX as f32 * gain;
+        *sample = (soft_limit(scaled) * i16::MAX as f32) as i16;
+    }
+}
+
+/// Soft limit a sample in float range so that it never exceeds -1.0..1.0
+fn soft_limit(sample: f32) -> f32 {
+    const THRESHOLD: f32 = 0.8;
+    const HEADROOM: f32 = 1.0 - THRESHOLD;
+    let magnitude = sample.abs();
+    if magnitude <= THRESHOLD {
+        sample
+    } else {
+        // Smoothly approach full scale above the threshold
+        let limited = THRESHOLD + HEADROOM * ((magnitude - THRESHOLD) / HEADROOM).tanh();
+        limited.copysign(sample)
+    }
+}
+
+/// Generates a sine wave at half of full scale, such as a test tone
+pub struct SineWave {
+    step: f64,
+    phase: f64,
+}
+
+impl SineWave {
+    /// A tone of `frequency` Hz sampled at `sample_rate`
+    pub fn new(frequency: f32, sample_rate: u32) -> Self {
+        SineWave {
+            step: std::f64::consts::TAU * frequency as f64 / sample_rate.max(1) as f64,
+            phase: 0.0,
+        }
+    }
+
+    /// The next `count` samples, continuing from the last call
+    pub fn samples(&mut self, count: usize) -> Vec<i16> {
+        (0..count)
+            .map(|_| {
+                let sample = self.phase.sin() * 0.5;
+                self.phase = (self.phase + self.step) % std::f64::consts::TAU;
+                float_to_i16(sample as f32)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo_second(rate: u32) -> Vec<i16> {
+        (0..rate)
+            .flat_map(|i| {
+                let s = ((i as f32 / 20.0).sin() * 10000.0) as i16;
+                vec![s, s / 2]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_downmix_resample_44k_stereo() {
+        let input = stereo_second(44100);
+        let mono = downmix(&input, 2);
+        assert_eq!(mono.len(), 44100);
+
+        for &target in &[8000, 16000] {
+            let mut resampler = Resampler::new(44100, target);
+            let output = resampler.process(&mono);
+            assert_eq!(output.len(), target as usize);
+        }
+    }
+
+    #[test]
+    fn test_chunked_resample() {
+        let mono = downmix(&stereo_second(44100), 2);
+
+        let mut resampler = Resampler::new(44100, 8000);
+        let whole = resampler.process(&mono);
+
+        let mut resampler = Resampler::new(44100, 8000);
+        let chunked: Vec<i16> = mono
+            .chunks(1000)
+            .flat_map(|chunk| resampler.process(chunk))
+            .collect();
+
+        assert_eq!(whole, chunked);
+    }
+
+    #[test]
+    fn test_bit_depth() {
+        assert_eq!(int_to_i16(127, 8), 127 << 8);
+        assert_eq!(int_to_i16(-8_388_608, 24), i16::MIN);
+        assert_eq!(int_to_i16(1000, 16), 1000);
+        assert_eq!(float_to_i16(1.0), i16::MAX);
+    }
+
+    #[test]
+    fn test_gain_6db() {
+        let mut samples = vec![1000i16, -1000, 5000, -5000];
+        apply_gain(&mut samples, db_to_linear(6.0));
+        for (&out, &input) in samples.iter().zip(&[1000i16, -1000, 5000, -5000]) {
+            let ratio = out as f32 / input as f32;
+            assert!((ratio - 2.0).abs() < 0.01, "{} -> {}", input, out);
+        }
+    }
+
+    #[test]
+    fn test_gain_limits() {
+        let mut samples = vec![i16::MAX, i16::MIN, 30000, -30000, 20000];
+        apply_gain(&mut samples, db_to_linear(6.0));
+        // Loud samples stay in range and keep their sign rather than wrapping
+        assert!(samples[0] > 30000 && samples[1] < -30000);
+        assert!(samples[2] > 0 && samples[3] < 0);
+        // and are still ordered
+        assert!(samples[4] < samples[2]);
+    }
+
+    #[test]
+    fn test_sine_wave() {
+        let mut tone = SineWave::new(1000.0, 8000);
+        let samples = tone.samples(8000);
+        // A 1kHz tone crosses zero upwards a thousand times a second
+        let rising = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0 && pair[1] >= 0)
+            .count();
+        assert!((999..=1000).contains(&rising), "{}", rising);
+        assert!(samples.iter().all(|s| s.abs() <= i16::MAX / 2 + 1));
+
+        // Chunks continue the wave where the last one ended
+        let mut whole = SineWave::new(440.0, 16000);
+        let mut chunked = SineWave::new(440.0, 16000);
+        let joined: Vec<i16> = (0..4).flat_map(|_| chunked.samples(250)).collect();
+        assert_eq!(whole.samples(1000), joined);
+    }
+}
@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// The audio-test command encodes a wav file to the camera's ADPCM and decodes it back
+#[derive(StructOpt, Debug, Clone)]
+pub struct Opt {
+    /// The wav file to encode
+    #[structopt(long = "in", parse(from_os_str))]
+    pub input: PathBuf,
+    /// Save the decoded audio to this wav file
+    #[structopt(long = "out", parse(from_os_str))]
+    pub output: PathBuf,
+    /// The size of the ADPCM blocks, including their 4 byte header
+    #[structopt(long, default_value = "512")]
+    pub block_size: u16,
+    /// Resample the input to this rate first, such as the camera's 8000 or 16000
+    #[structopt(long)]
+    pub sample_rate: Option<u32>,
+}
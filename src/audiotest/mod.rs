@@ -0,0 +1,176 @@
+///
+/// # Neolink Audio Test
+///
+/// This module handles the audio-test subcommand
+///
+/// The subcommand encodes a wav file to the DVI-4 ADPCM that the cameras
+/// talk in and decodes it back, without any camera. The decoded audio is
+/// saved as 16 bit mono wav to listen to, and the loss of the codec is
+/// reported as the signal to noise ratio and the RMS and largest error of
+/// the samples.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink audio-test --in=in.wav --out=out.wav
+/// # With the block size and rate of the camera's talk ability
+/// neolink audio-test --in=in.wav --out=out.wav --block-size=1024 --sample-rate=16000
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::{
+    adpcm::{decode_block, AdpcmEncoder, BLOCK_HEADER_SIZE},
+    pcm::{downmix, float_to_i16, int_to_i16, Resampler},
+};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+mod cmdline;
+
+pub(crate) use cmdline::Opt;
+
+/// How much the audio changed going through the codec
+#[derive(Debug, PartialEq)]
+struct Loss {
+    /// The signal to noise ratio in dB, infinite when nothing was lost
+    snr_db: f64,
+    rms_error: f64,
+    max_error: u16,
+}
+
+/// Entry point for the audio-test subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt) -> Result<()> {
+    if opt.block_size as usize <= BLOCK_HEADER_SIZE {
+        return Err(anyhow!(
+            "The block size must be larger than the {} byte block header",
+            BLOCK_HEADER_SIZE
+        ));
+    }
+    let (mut samples, mut sample_rate) = read_wav(&opt.input)?;
+    if let Some(rate) = opt.sample_rate.filter(|rate| *rate != sample_rate) {
+        samples = Resampler::new(sample_rate, rate).process(&samples);
+        sample_rate = rate;
+    }
+
+    let (blocks, decoded) = roundtrip(&samples, opt.block_size as usize);
+    write_wav(&opt.output, &decoded, sample_rate)?;
+
+    let loss = compare(&samples, &decoded);
+    println!(
+        "Encoded {} samples at {} Hz into {} blocks of {} bytes",
+        samples.len(),
+        sample_rate,
+        blocks,
+        opt.block_size
+    );
+    println!(
+        "SNR: {:.1} dB, RMS error: {:.1}, max error: {}",
+        loss.snr_db, loss.rms_error, loss.max_error
+    );
+    Ok(())
+}
+
+/// The samples of a wav file as mono 16 bit, and its sample rate
+fn read_wav(path: &Path) -> Result<(Vec<i16>, u32)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let reader = hound::WavReader::new(BufReader::new(file))
+        .with_context(|| format!("Failed to read the wav header of {:?}", path))?;
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Int => reader
+            .into_samples::<i32>()
+            .map(|sample| sample.map(|sample| int_to_i16(sample, spec.bits_per_sample)))
+            .collect::<std::result::Result<Vec<_>, _>>(),
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .map(|sample| sample.map(float_to_i16))
+            .collect::<std::result::Result<Vec<_>, _>>(),
+    }
+    .with_context(|| format!("Failed to read the samples of {:?}", path))?;
+    Ok((downmix(&samples, spec.channels), spec.sample_rate))
+}
+
+fn write_wav(path: &Path, samples: &[i16], sample_rate: u32) -> Result<()> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create {:?}", path))?;
+    for sample in samples {
+        writer.write_sample(*sample)?;
+    }
+    writer
+        .finalize()
+        .with_context(|| format!("Failed to finish {:?}", path))
+}
+
+/// Encode `samples` into blocks of `block_size` and decode them again
+///
+/// Returns the number of blocks and the decoded samples, without the silence
+/// that filled the last block
+fn roundtrip(samples: &[i16], block_size: usize) -> (usize, Vec<i16>) {
+    let mut encoder = AdpcmEncoder::new(block_size);
+    let blocks: Vec<Vec<u8>> = samples
+        .chunks(encoder.samples_per_block())
+        .map(|chunk| encoder.encode_block(chunk))
+        .collect();
+    let mut decoded: Vec<i16> = blocks
+        .iter()
+        .flat_map(|block| decode_block(block))
+        .collect();
+    decoded.truncate(samples.len());
+    (blocks.len(), decoded)
+}
+
+fn compare(original: &[i16], decoded: &[i16]) -> Loss {
+    let mut signal = 0.0;
+    let mut noise = 0.0;
+    let mut max_error = 0;
+    for (a, b) in original.iter().zip(decoded) {
+        let error = (*a as i32 - *b as i32).unsigned_abs();
+        signal += (*a as f64).powi(2);
+        noise += (error as f64).powi(2);
+        max_error = max_error.max(error);
+    }
+    let count = original.len().min(decoded.len()).max(1) as f64;
+    Loss {
+        snr_db: 10.0 * (signal / noise).log10(),
+        rms_error: (noise / count).sqrt(),
+        max_error: max_error.min(u16::MAX as u32) as u16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neolink_core::pcm::SineWave;
+
+    #[test]
+    fn test_compare() {
+        let samples = [100, -200, 300];
+        let loss = compare(&samples, &samples);
+        assert_eq!(loss.snr_db, f64::INFINITY);
+        assert_eq!(loss.max_error, 0);
+
+        let loss = compare(&[1000, 1000], &[990, 1000]);
+        assert_eq!(loss.max_error, 10);
+        assert!((loss.snr_db - 43.0).abs() < 0.1, "snr was {}", loss.snr_db);
+    }
+
+    // Guards the codec against changes that make it lose more of a test tone
+    #[test]
+    fn test_roundtrip_tone() {
+        let samples = SineWave::new(440.0, 8000).samples(8000);
+        let (blocks, decoded) = roundtrip(&samples, 512);
+        assert_eq!(blocks, 8);
+        assert_eq!(decoded.len(), samples.len());
+        let loss = compare(&samples, &decoded);
+        assert!(loss.snr_db > 25.0, "snr was {}", loss.snr_db);
+    }
+}
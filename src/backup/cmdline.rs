@@ -0,0 +1,12 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// The backup command saves all the settings of a camera to a file
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The file to save the settings in
+    #[structopt(long, parse(from_os_str))]
+    pub out: PathBuf,
+}
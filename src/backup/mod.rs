@@ -0,0 +1,92 @@
+///
+/// # Neolink Backup
+///
+/// This module handles the backup subcommand
+///
+/// The subcommand downloads a backup of all the settings of a camera into a
+/// file in the camera's own format. The model and firmware of the camera are
+/// saved next to it in `<file>.json` to tell which camera it came from.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink backup --config=config.toml CameraName --out=camera.cfg
+/// ```
+///
+use anyhow::{Context, Result};
+use log::*;
+use neolink_core::bc::xml::VersionInfo;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::find_and_connect;
+pub(crate) use cmdline::Opt;
+
+/// The camera a backup was taken from as saved next to it
+#[derive(Debug, Serialize)]
+struct BackupInfo {
+    /// The name of the camera in the config
+    camera: String,
+    /// The model e.g. `IPC_517SD5`
+    hardware_version: String,
+    /// The version of the settings format e.g. `v2.0.0.0`
+    cfg_version: String,
+    firmware_version: String,
+}
+
+impl BackupInfo {
+    fn new(camera: &str, version: &VersionInfo) -> Self {
+        BackupInfo {
+            camera: camera.to_string(),
+            hardware_version: version.hardwareVersion.clone(),
+            cfg_version: version.cfgVersion.clone(),
+            firmware_version: version.firmwareVersion.clone(),
+        }
+    }
+
+    /// Where the info of the backup in `backup` is saved
+    fn path(backup: &Path) -> PathBuf {
+        let mut path = backup.as_os_str().to_owned();
+        path.push(".json");
+        PathBuf::from(path)
+    }
+}
+
+/// Entry point for the backup subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera = find_and_connect(&config, &opt.camera)?;
+    let version = camera
+        .version()
+        .context("Unable to get the camera model to save with the backup")?;
+
+    let backup = match camera.export_config() {
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            return Err(anyhow::anyhow!(
+                "{}: The camera cannot export its settings",
+                opt.camera
+            ))
+        }
+        result => result.context("Unable to download the settings")?,
+    };
+    fs::write(&opt.out, &backup)
+        .with_context(|| format!("Unable to write the backup to {:?}", opt.out))?;
+
+    let info = BackupInfo::new(&opt.camera, &version);
+    let info_path = BackupInfo::path(&opt.out);
+    fs::write(&info_path, serde_json::to_string_pretty(&info)?)
+        .with_context(|| format!("Unable to write the backup info to {:?}", info_path))?;
+    info!(
+        "{}: Saved {} bytes of {} settings to {:?}",
+        opt.camera,
+        backup.len(),
+        info.hardware_version,
+        opt.out
+    );
+    Ok(())
+}
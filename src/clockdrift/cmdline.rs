@@ -0,0 +1,14 @@
+use structopt::StructOpt;
+
+/// The clock-drift command keeps printing how far the clock of a camera is from this host's
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Compare the clocks every this many seconds
+    #[structopt(long, default_value = "60")]
+    pub interval: u64,
+    /// Print the comparisons as csv
+    #[structopt(long)]
+    pub csv: bool,
+}
@@ -0,0 +1,184 @@
+///
+/// # Neolink Clock Drift
+///
+/// This module handles the clock-drift subcommand
+///
+/// The subcommand compares the clock of a camera with this host's every
+/// `--interval` seconds until Ctrl-C, over one connection that is only made
+/// again if it is lost. Each comparison prints the skew, how many seconds
+/// the camera is ahead, and the rate it drifts at in seconds per day. The
+/// camera only gives its time to the second so the rate is fitted to all the
+/// skews since the start, and is only worth reading after some hours. A clock
+/// that jumps, such as when it is set, starts the fit over.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink clock-drift --config=config.toml CameraName
+/// # Every five minutes as csv
+/// neolink clock-drift --config=config.toml --interval=300 --csv CameraName > drift.csv
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, SecondsFormat};
+use log::*;
+use neolink_core::bc_protocol::BcCamera;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+mod cmdline;
+
+use super::config::{CameraConfig, Config};
+use crate::utils::{connect_and_login, find_camera_by_name, CameraStop};
+pub(crate) use cmdline::Opt;
+
+// A skew that changes by more than this between two comparisons is a clock that was set
+const MAX_SKEW_STEP: i64 = 30;
+
+/// Entry point for the clock-drift subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    if opt.interval == 0 {
+        return Err(anyhow!("The interval must be at least a second"));
+    }
+    let camera_config = find_camera_by_name(&config, &opt.camera)?;
+    let stop = Arc::new(CameraStop::default());
+    let stop_handler = stop.clone();
+    ctrlc::set_handler(move || stop_handler.stop()).context("Unable to set the Ctrl-C handler")?;
+
+    if opt.csv {
+        println!("time,skew_seconds,drift_seconds_per_day");
+    }
+    let mut camera = None;
+    let mut drift = Drift::default();
+    loop {
+        match skew(&mut camera, camera_config) {
+            Ok((now, Some(skew))) => {
+                if drift.add(Instant::now(), skew) {
+                    info!("{}: The camera clock jumped, starting over", opt.camera);
+                }
+                let per_day = drift.per_day();
+                if opt.csv {
+                    let per_day = per_day.map(|d| format!("{:.2}", d)).unwrap_or_default();
+                    println!("{},{},{}", now, skew, per_day);
+                } else {
+                    let per_day = per_day
+                        .map(|d| format!("{:+.2}s/day over {:.1}h", d, drift.hours()))
+                        .unwrap_or_else(|| "-".to_string());
+                    println!("{}  Skew {:+}s  Drift {}", now, skew, per_day);
+                }
+            }
+            Ok((now, None)) => {
+                drift = Drift::default();
+                match opt.csv {
+                    true => println!("{},,", now),
+                    false => println!("{}  The camera clock is unset", now),
+                }
+            }
+            Err(e) => {
+                warn!("{}: Unable to compare the clocks: {:?}", opt.camera, e);
+                camera = None;
+            }
+        }
+        if stop.sleep(Duration::from_secs(opt.interval)) {
+            return Ok(());
+        }
+    }
+}
+
+/// The local time of this host and how many seconds the camera is ahead of it, if its clock is set
+///
+/// Connects to the camera first when `camera` is not connected
+fn skew(
+    camera: &mut Option<BcCamera>,
+    camera_config: &CameraConfig,
+) -> Result<(String, Option<i64>)> {
+    let camera = match camera {
+        Some(camera) => camera,
+        None => camera.insert(connect_and_login(camera_config)?),
+    };
+    let time = camera.get_time().context("Unable to get the camera time")?;
+    let skew = time.map(|time| (time - OffsetDateTime::now_utc()).whole_seconds());
+    let now = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+    Ok((now, skew))
+}
+
+/// Fits the rate of the drift to the skews of a clock
+#[derive(Default)]
+struct Drift {
+    start: Option<Instant>,
+    last_skew: i64,
+    last_hours: f64,
+    // The sums of the least squares fit of the skew to the hours since the start
+    count: f64,
+    sum_hours: f64,
+    sum_skew: f64,
+    sum_hours_squared: f64,
+    sum_hours_skew: f64,
+}
+
+impl Drift {
+    /// Note a `skew` at `now`, returning true if the clock jumped since the last one
+    fn add(&mut self, now: Instant, skew: i64) -> bool {
+        let start = *self.start.get_or_insert(now);
+        let jumped = self.count > 0.0 && (skew - self.last_skew).abs() > MAX_SKEW_STEP;
+        if jumped {
+            *self = Drift::default();
+            self.add(now, skew);
+            return true;
+        }
+        let hours = now.duration_since(start).as_secs_f64() / 3600.0;
+        let skew_f = skew as f64;
+        self.last_skew = skew;
+        self.last_hours = hours;
+        self.count += 1.0;
+        self.sum_hours += hours;
+        self.sum_skew += skew_f;
+        self.sum_hours_squared += hours * hours;
+        self.sum_hours_skew += hours * skew_f;
+        false
+    }
+
+    /// The hours the fit covers
+    fn hours(&self) -> f64 {
+        self.last_hours
+    }
+
+    /// The seconds a day the camera gains, once two skews at different times were noted
+    fn per_day(&self) -> Option<f64> {
+        let spread = self.count * self.sum_hours_squared - self.sum_hours * self.sum_hours;
+        if self.count < 2.0 || spread <= f64::EPSILON {
+            return None;
+        }
+        let per_hour = (self.count * self.sum_hours_skew - self.sum_hours * self.sum_skew) / spread;
+        Some(per_hour * 24.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drift() {
+        let start = Instant::now();
+        let at = |hours: u64| start + Duration::from_secs(hours * 3600);
+        let mut drift = Drift::default();
+        assert!(!drift.add(at(0), 2));
+        assert_eq!(drift.per_day(), None);
+        // A clock gaining a second every 4 hours
+        for (hours, skew) in [(4, 3), (8, 4), (12, 5)] {
+            assert!(!drift.add(at(hours), skew));
+        }
+        let per_day = drift.per_day().unwrap();
+        assert!((per_day - 6.0).abs() < 1e-9, "drift was {}", per_day);
+        assert_eq!(drift.hours(), 12.0);
+
+        // Setting the clock starts the fit over
+        assert!(drift.add(at(13), -120));
+        assert_eq!(drift.per_day(), None);
+        assert_eq!(drift.hours(), 0.0);
+    }
+}
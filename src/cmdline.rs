@@ -11,6 +11,22 @@ use structopt::{clap::AppSettings, StructOpt};
 pub struct Opt {
     #[structopt(short, long, global(true), parse(from_os_str))]
     pub config: Option<PathBuf>,
+    /// Load and validate the config file then exit without connecting to any camera
+    #[structopt(long, global(true))]
+    pub check_config: bool,
+    /// Connect and log in to each camera then exit without streaming or changing any camera setting
+    #[structopt(long, global(true))]
+    pub dry_run: bool,
+    /// Log the changes to the cameras, such as their time, lights or settings, instead of making them
+    #[structopt(long, global(true))]
+    pub read_only: bool,
+    /// Only use these cameras from the config file, given as a comma separated list of names
+    #[structopt(long, global(true), require_delimiter(true))]
+    pub cameras: Vec<String>,
+    /// Write the bc frames sent to and received from the cameras to this file, with the passwords redacted.
+    /// Every video frame is written in hex so the file grows very large while streaming
+    #[structopt(long, global(true), parse(from_os_str))]
+    pub bc_dump: Option<PathBuf>,
     #[structopt(subcommand)]
     pub cmd: Option<Command>,
 }
@@ -22,4 +38,48 @@ pub enum Command {
     Reboot(super::reboot::Opt),
     Pir(super::pir::Opt),
     Talk(super::talk::Opt),
+    Intercom(super::intercom::Opt),
+    Listen(super::listen::Opt),
+    Record(super::record::Opt),
+    Sdcard(super::sdcard::Opt),
+    Playback(super::playback::Opt),
+    VersionInfo(super::versioninfo::Opt),
+    Tap(super::tap::Opt),
+    RecSchedule(super::recschedule::Opt),
+    Discover(super::discover::Opt),
+    Light(super::light::Opt),
+    Siren(super::siren::Opt),
+    Backup(super::backup::Opt),
+    Diag(super::diag::Opt),
+    Encoder(super::encoder::Opt),
+    SyncTime(super::synctime::Opt),
+    LastMotion(super::lastmotion::Opt),
+    Keyframe(super::keyframe::Opt),
+    Privacy(super::privacy::Opt),
+    AudioTest(super::audiotest::Opt),
+    Timelapse(super::timelapse::Opt),
+    ClockDrift(super::clockdrift::Opt),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // clap checks that the names of the arguments are unique as it builds the parser
+    #[test]
+    fn test_record_cameras() {
+        let opt = Opt::from_iter(&["neolink", "record", "cam"]);
+        assert!(opt.cameras.is_empty());
+        match opt.cmd {
+            Some(Command::Record(record)) => assert_eq!(record.cameras, vec!["cam".to_string()]),
+            other => panic!("Expected the record command, got {:?}", other),
+        }
+
+        let opt = Opt::from_iter(&["neolink", "--cameras", "Garage,Door", "record", "cam"]);
+        assert_eq!(opt.cameras, vec!["Garage".to_string(), "Door".to_string()]);
+        match opt.cmd {
+            Some(Command::Record(record)) => assert_eq!(record.cameras, vec!["cam".to_string()]),
+            other => panic!("Expected the record command, got {:?}", other),
+        }
+    }
 }
@@ -1,18 +1,40 @@
+use anyhow::{anyhow, Context, Result as AnyResult};
+use chrono::NaiveTime;
 use lazy_static::lazy_static;
+use neolink_core::bc_protocol::{AiType, Stream, TcpOptions};
 use regex::Regex;
 use serde::Deserialize;
 use std::clone::Clone;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
 use std::time::Duration;
 use validator::{Validate, ValidationError};
 use validator_derive::Validate;
 
+use crate::quirks;
+use crate::utils::{split_host_port, AddressOrUid};
+
 lazy_static! {
     static ref RE_STREAM_SRC: Regex =
         Regex::new(r"^(mainStream|subStream|externStream|both|all)$").unwrap();
     static ref RE_TLS_CLIENT_AUTH: Regex = Regex::new(r"^(none|request|require)$").unwrap();
+    static ref RE_HEALTH_CHECK: Regex = Regex::new(r"^(all|any)$").unwrap();
+    static ref RE_RTSP_TRANSPORT: Regex = Regex::new(r"^(any|tcp|udp)$").unwrap();
+    static ref RE_RECORD_STREAM: Regex =
+        Regex::new(r"^(mainStream|subStream|externStream)$").unwrap();
+    static ref RE_RECORD_FORMAT: Regex = Regex::new(r"^(mkv|mp4|fmp4|ts)$").unwrap();
+    static ref RE_RECORD_MODE: Regex = Regex::new(r"^(continuous|motion|motion_only)$").unwrap();
+    static ref RE_HOST_NAME: Regex = Regex::new(
+        r"^[A-Za-z0-9]([A-Za-z0-9-]*[A-Za-z0-9])?(\.[A-Za-z0-9]([A-Za-z0-9-]*[A-Za-z0-9])?)*$"
+    )
+    .unwrap();
+    static ref RE_RESOLUTION: Regex = Regex::new(r"^[0-9]+[x*][0-9]+$").unwrap();
 }
 
 #[derive(Debug, Deserialize, Validate, Clone)]
+#[validate(schema(function = "validate_config"))]
 pub(crate) struct Config {
     #[validate]
     pub(crate) cameras: Vec<CameraConfig>,
@@ -20,10 +42,35 @@ pub(crate) struct Config {
     #[serde(rename = "bind", default = "default_bind_addr")]
     pub(crate) bind_addr: String,
 
+    /// The host the rtsp responses give to the clients in place of the address they reached
+    #[validate(custom = "validate_advertised_host")]
+    pub(crate) advertised_host: Option<String>,
+
     #[validate(range(min = 0, max = 65535, message = "Invalid port", code = "bind_port"))]
     #[serde(default = "default_bind_port")]
     pub(crate) bind_port: u16,
 
+    /// Serve jpeg snapshots and mjpeg streams of the cameras over http on this port
+    pub(crate) http_port: Option<u16>,
+
+    /// Whether `/healthz` needs `all` the cameras streaming or `any` of them
+    #[validate(regex(
+        path = "RE_HEALTH_CHECK",
+        message = "Incorrect health check",
+        code = "health_check"
+    ))]
+    #[serde(default = "default_health_check")]
+    pub(crate) health_check: String,
+
+    /// The transports the rtsp clients may use: `any`, `tcp` or `udp`
+    #[validate(regex(
+        path = "RE_RTSP_TRANSPORT",
+        message = "Incorrect rtsp transport",
+        code = "rtsp_transport"
+    ))]
+    #[serde(default = "default_rtsp_transport")]
+    pub(crate) rtsp_transport: String,
+
     #[serde(default = "default_certificate")]
     pub(crate) certificate: Option<String>,
 
@@ -38,22 +85,70 @@ pub(crate) struct Config {
     #[validate]
     #[serde(default)]
     pub(crate) users: Vec<UserConfig>,
+
+    #[validate]
+    pub(crate) record: Option<RecordConfig>,
+
+    /// Let at most this many cameras connect and log in at the same time
+    #[validate(range(
+        min = 1,
+        message = "Invalid max concurrent connects",
+        code = "max_concurrent_connects"
+    ))]
+    pub(crate) max_concurrent_connects: Option<usize>,
+
+    /// Start each camera this many seconds after the one before it
+    #[validate(
+        range(
+            min = 0.0,
+            max = 3600.0,
+            message = "Invalid stagger interval",
+            code = "stagger_interval"
+        ),
+        custom = "validate_seconds"
+    )]
+    #[serde(default)]
+    pub(crate) stagger_interval: f64,
+
+    /// Save the time each camera last saw motion to this file
+    pub(crate) motion_state_file: Option<String>,
+
+    /// Log the changes to the cameras instead of making them
+    #[serde(default)]
+    pub(crate) read_only: bool,
+
+    /// Alert when a camera has not streamed for too long, unless the camera has its own
+    #[validate]
+    pub(crate) stream_alert: Option<StreamAlertConfig>,
 }
 
-#[derive(Debug, Deserialize, Validate, Clone)]
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
 #[validate(schema(function = "validate_camera_config"))]
 pub(crate) struct CameraConfig {
+    #[validate(custom = "validate_camera_name")]
     pub(crate) name: String,
 
+    #[validate(custom = "validate_camera_address")]
     #[serde(rename = "address")]
     pub(crate) camera_addr: Option<String>,
 
+    #[validate(length(min = 1, message = "Camera uid cannot be empty", code = "uid"))]
     #[serde(rename = "uid")]
     pub(crate) camera_uid: Option<String>,
 
+    /// The port of the camera when the address doesn't give one, instead of 9000
+    #[serde(rename = "port")]
+    pub(crate) camera_port: Option<u16>,
+
     pub(crate) username: String,
     pub(crate) password: Option<String>,
 
+    /// Read the password from this file instead, such as a docker secret
+    pub(crate) password_file: Option<String>,
+
+    /// Or passwords to try in order, such as the old and new one while they are changed
+    pub(crate) passwords: Option<Vec<String>>,
+
     // no longer used, but still here so we can warn users:
     pub(crate) timeout: Option<Duration>,
 
@@ -62,17 +157,801 @@ pub(crate) struct CameraConfig {
 
     #[validate(regex(
         path = "RE_STREAM_SRC",
-        message = "Incorrect stream source",
+        message = "Unknown stream, use mainStream (main, clear), subStream (sub, fluent), externStream (extern, balanced), both or all",
         code = "stream"
     ))]
     #[serde(default = "default_stream")]
     pub(crate) stream: String,
 
+    /// Pull only this stream until an rtsp client plays one of the other enabled streams
+    pub(crate) default_stream: Option<String>,
+
     pub(crate) permitted_users: Option<Vec<String>>,
 
+    /// Forward only these kinds of media, `video` and `audio`, or everything if not set
+    pub(crate) media_filter: Option<Vec<String>>,
+
     #[validate(range(min = 0, max = 31, message = "Invalid channel", code = "channel_id"))]
     #[serde(default = "default_channel_id")]
     pub(crate) channel_id: u8,
+
+    /// Serve each of these channels of an NVR as its own camera named `<name>-ch<N>`
+    pub(crate) channel_ids: Option<ChannelsConfig>,
+
+    /// The name and rtsp paths of the channels of an NVR keyed by the channel e.g. `"3"`,
+    /// these channels are served if `channel_ids` is not set
+    pub(crate) channels: Option<HashMap<String, ChannelConfig>>,
+
+    #[validate]
+    pub(crate) led_schedule: Option<LedScheduleConfig>,
+
+    /// The SD card recording settings to give the camera when managing it
+    #[validate]
+    pub(crate) sd_recording: Option<SdRecordingConfig>,
+
+    /// Reboot the camera when it keeps reconnecting
+    #[validate]
+    pub(crate) auto_reboot: Option<AutoRebootConfig>,
+
+    /// Send a JPEG of the camera when it sees motion
+    #[validate]
+    pub(crate) motion_snapshot: Option<MotionSnapshotConfig>,
+
+    /// Feed the main stream's mount from the sub stream while the link can't keep up
+    #[validate]
+    pub(crate) adaptive_stream: Option<AdaptiveStreamConfig>,
+
+    /// Drop the video frames that are too broken to decode
+    #[validate]
+    pub(crate) frame_check: Option<FrameCheckConfig>,
+
+    /// Alert when this camera has not streamed for too long, instead of the top level one
+    #[validate]
+    pub(crate) stream_alert: Option<StreamAlertConfig>,
+
+    /// The encoder settings of the streams to give the camera when managing it
+    #[validate]
+    #[serde(default)]
+    pub(crate) encoder: Vec<EncoderConfig>,
+
+    /// Serve this camera's rtsp streams on their own port instead of the shared `bind_port`
+    pub(crate) bind_port: Option<u16>,
+
+    /// Template for the rtsp mount paths, either one for all streams or one per stream
+    pub(crate) rtsp_path: Option<RtspPathConfig>,
+
+    /// The quirks profile of the camera, `default` if not set
+    #[validate(custom = "validate_quirks")]
+    pub(crate) quirks: Option<String>,
+
+    /// Ask the camera for a keyframe when a new rtsp client starts playing, overrides the quirks
+    pub(crate) keyframe_on_connect: Option<bool>,
+
+    /// Drop video frames that change codec within a connection, overrides the quirks
+    pub(crate) fixed_codec: Option<bool>,
+
+    /// Manage the camera settings, such as its clock, from one of its streams
+    #[serde(default = "default_manage")]
+    pub(crate) manage: bool,
+
+    /// Set the camera clock when it reads as unset
+    #[serde(default = "default_manage_time")]
+    pub(crate) manage_time: bool,
+
+    /// Append the rtsp connection state transitions to this file as json lines
+    pub(crate) state_log: Option<String>,
+
+    /// Reconnect delays are randomly changed by up to this fraction
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "Invalid reconnect jitter",
+        code = "reconnect_jitter"
+    ))]
+    #[serde(default = "default_reconnect_jitter")]
+    pub(crate) reconnect_jitter: f64,
+
+    /// Stop reconnecting after this many attempts in a row that could not connect
+    #[validate(range(min = 1, message = "Invalid max reconnects", code = "max_reconnects"))]
+    pub(crate) max_reconnects: Option<u32>,
+
+    /// Stop reconnecting after the camera could not be connected to for this many seconds
+    #[validate(range(
+        min = 1,
+        message = "Invalid max retry duration",
+        code = "max_retry_duration"
+    ))]
+    pub(crate) max_retry_duration: Option<u64>,
+
+    /// Give up on a single connect and login attempt after this many seconds
+    #[validate(range(min = 1, message = "Invalid connect timeout", code = "connect_timeout"))]
+    #[serde(default = "default_connect_timeout")]
+    pub(crate) connect_timeout: u64,
+
+    /// Wait this many seconds after neolink starts before the first connect
+    #[validate(
+        range(
+            min = 0.0,
+            max = 3600.0,
+            message = "Invalid startup delay",
+            code = "startup_delay"
+        ),
+        custom = "validate_seconds"
+    )]
+    #[serde(default)]
+    pub(crate) startup_delay: f64,
+
+    /// The keepalive probes of the tcp connection, which notice a dead connection
+    #[validate]
+    #[serde(default)]
+    pub(crate) tcp_keepalive: TcpKeepaliveConfig,
+
+    /// Send small packets to the camera straight away instead of coalescing them
+    #[serde(default)]
+    pub(crate) tcp_nodelay: bool,
+
+    /// Cameras with a higher priority connect first and retry sooner when the connection is lost
+    #[serde(default)]
+    pub(crate) priority: i32,
+
+    /// Connect to the camera from this address of the host, to pick the interface it goes out of
+    #[validate(custom = "validate_source_ip")]
+    pub(crate) source_ip: Option<String>,
+
+    /// Reconnect if the camera sends no video frames for this many seconds
+    #[validate(range(min = 1, message = "Invalid stall timeout", code = "stall_timeout"))]
+    #[serde(default = "default_stall_timeout")]
+    pub(crate) stall_timeout: u64,
+
+    /// The seconds between the keyframes assumed until they are measured from
+    /// the stream, a stream without one for twice its interval is reported
+    #[validate(
+        range(
+            min = 0.1,
+            max = 600.0,
+            message = "Invalid keyframe interval",
+            code = "keyframe_interval"
+        ),
+        custom = "validate_seconds"
+    )]
+    #[serde(default = "default_keyframe_interval")]
+    pub(crate) keyframe_interval: f64,
+
+    /// Ask the camera for a keyframe when a stream is missing them
+    #[serde(default)]
+    pub(crate) keyframe_gap_request: bool,
+
+    /// Forward only the keyframes to the rtsp clients to save bandwidth
+    #[serde(default)]
+    pub(crate) iframe_only: bool,
+
+    /// Hold at most this many packets for an rtsp stream that is falling behind
+    #[validate(range(min = 1, message = "Invalid queue size", code = "queue_packets"))]
+    #[serde(default = "default_queue_packets")]
+    pub(crate) queue_packets: usize,
+
+    /// The transports the rtsp clients of this camera may use, instead of the global `rtsp_transport`
+    #[validate(regex(
+        path = "RE_RTSP_TRANSPORT",
+        message = "Incorrect rtsp transport",
+        code = "rtsp_transport"
+    ))]
+    pub(crate) rtsp_transport: Option<String>,
+
+    /// Disconnect a stream from the camera after this many seconds without rtsp clients
+    #[validate(range(min = 1, message = "Invalid idle timeout", code = "idle_timeout"))]
+    pub(crate) idle_timeout: Option<u64>,
+}
+
+/// The rtsp path templates can use the placeholders `{camera}` and `{stream}`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum RtspPathConfig {
+    /// One template used for every stream
+    All(String),
+    /// A template for each stream keyed by the stream name e.g. `mainStream`
+    PerStream(HashMap<String, String>),
+}
+
+impl RtspPathConfig {
+    /// Every template of the config
+    fn templates(&self) -> Vec<&String> {
+        match self {
+            RtspPathConfig::All(template) => vec![template],
+            RtspPathConfig::PerStream(templates) => templates.values().collect(),
+        }
+    }
+}
+
+/// The channels of an NVR as a list like `[0, 2]` or a range like `"0-3"`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum ChannelsConfig {
+    List(Vec<u8>),
+    Range(String),
+}
+
+impl ChannelsConfig {
+    fn ids(&self) -> AnyResult<Vec<u8>> {
+        match self {
+            ChannelsConfig::List(ids) => Ok(ids.clone()),
+            ChannelsConfig::Range(range) => {
+                let invalid =
+                    || anyhow!("Invalid channel range {:?}, expected like \"0-3\"", range);
+                let (first, last) = range.split_once('-').ok_or_else(invalid)?;
+                let first: u8 = first.trim().parse().map_err(|_| invalid())?;
+                let last: u8 = last.trim().parse().map_err(|_| invalid())?;
+                if first > last {
+                    return Err(invalid());
+                }
+                Ok((first..=last).collect())
+            }
+        }
+    }
+}
+
+/// How one channel of an NVR is served instead of the `<name>-ch<N>` camera
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub(crate) struct ChannelConfig {
+    /// The name of the camera of this channel
+    pub(crate) name: Option<String>,
+
+    /// The rtsp path templates of this channel instead of the camera's
+    pub(crate) rtsp_path: Option<RtspPathConfig>,
+}
+
+/// The names of the streams as used in the config
+pub(crate) static STREAM_NAMES: &[&str] = &["mainStream", "subStream", "externStream"];
+
+/// The other names that the streams can be given, such as those of the Reolink apps
+static STREAM_ALIASES: &[(&str, &[&str])] = &[
+    ("mainStream", &["main", "clear"]),
+    ("subStream", &["sub", "fluent"]),
+    ("externStream", &["extern", "balanced"]),
+];
+
+/// The name used in the config for the stream called `name` or one of its aliases
+pub(crate) fn stream_name(name: &str) -> Option<&'static str> {
+    STREAM_ALIASES
+        .iter()
+        .find(|(stream, aliases)| {
+            *stream == name || aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+        .map(|(stream, _)| *stream)
+}
+
+/// The stream called `name` or one of its aliases
+pub(crate) fn stream_from_name(name: &str) -> Option<Stream> {
+    match stream_name(name)? {
+        "mainStream" => Some(Stream::Main),
+        "subStream" => Some(Stream::Sub),
+        _ => Some(Stream::Extern),
+    }
+}
+
+/// Parse a stream given on the command line
+pub(crate) fn parse_stream(name: &str) -> Result<Stream, String> {
+    stream_from_name(name).ok_or_else(|| {
+        format!(
+            "Unknown stream {}, use mainStream (main, clear), subStream (sub, fluent) \
+            or externStream (extern, balanced)",
+            name
+        )
+    })
+}
+
+/// The kinds of media that can be given in `media_filter`
+static MEDIA_KINDS: &[&str] = &["video", "audio"];
+
+/// When the status light should be turned off
+///
+/// Either give fixed `off_at`/`on_at` times or a `latitude`/`longitude`
+/// to turn the light off between sunset and sunrise
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_led_schedule"))]
+pub(crate) struct LedScheduleConfig {
+    #[validate(custom = "validate_time_of_day")]
+    pub(crate) off_at: Option<String>,
+
+    #[validate(custom = "validate_time_of_day")]
+    pub(crate) on_at: Option<String>,
+
+    #[validate(range(min = -90.0, max = 90.0, message = "Invalid latitude", code = "latitude"))]
+    pub(crate) latitude: Option<f64>,
+
+    #[validate(range(
+        min = -180.0,
+        max = 180.0,
+        message = "Invalid longitude",
+        code = "longitude"
+    ))]
+    pub(crate) longitude: Option<f64>,
+}
+
+/// The tcp keepalive of a camera connection
+///
+/// A connection that is idle for `idle` seconds is probed every `interval`
+/// seconds and dropped after `count` probes go unanswered. The interval and
+/// count are only set on Linux and macOS
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+pub(crate) struct TcpKeepaliveConfig {
+    #[validate(range(min = 1, max = 7200, message = "Invalid keepalive idle", code = "idle"))]
+    #[serde(default = "default_keepalive_idle")]
+    pub(crate) idle: u64,
+
+    #[validate(range(
+        min = 1,
+        max = 600,
+        message = "Invalid keepalive interval",
+        code = "interval"
+    ))]
+    #[serde(default = "default_keepalive_interval")]
+    pub(crate) interval: u64,
+
+    #[validate(range(min = 1, max = 50, message = "Invalid keepalive count", code = "count"))]
+    #[serde(default = "default_keepalive_count")]
+    pub(crate) count: u32,
+}
+
+impl Default for TcpKeepaliveConfig {
+    fn default() -> Self {
+        TcpKeepaliveConfig {
+            idle: default_keepalive_idle(),
+            interval: default_keepalive_interval(),
+            count: default_keepalive_count(),
+        }
+    }
+}
+
+/// When a camera that keeps reconnecting is rebooted
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+pub(crate) struct AutoRebootConfig {
+    /// Reboot after this many reconnects within the window
+    #[validate(range(min = 1, message = "Invalid number of reconnects", code = "reconnects"))]
+    pub(crate) reconnects: usize,
+
+    #[validate(range(min = 1, message = "Invalid reconnect window", code = "window_minutes"))]
+    #[serde(default = "default_auto_reboot_window")]
+    pub(crate) window_minutes: u64,
+
+    /// Reboot at most once in this many minutes
+    #[serde(default = "default_auto_reboot_cooldown")]
+    pub(crate) cooldown_minutes: u64,
+}
+
+/// Where the JPEGs of a camera that saw motion are sent
+///
+/// Motion within `cooldown_seconds` of the last motion belongs to the same
+/// episode, and only the first `images` keyframes of an episode are sent
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_motion_snapshot"))]
+pub(crate) struct MotionSnapshotConfig {
+    /// POST each JPEG to this http url
+    pub(crate) url: Option<String>,
+
+    /// Save each JPEG in a directory for its camera and day below this one
+    pub(crate) path: Option<String>,
+
+    #[validate(range(
+        min = 1,
+        max = 10,
+        message = "Invalid number of images",
+        code = "images"
+    ))]
+    #[serde(default = "default_motion_snapshot_images")]
+    pub(crate) images: u32,
+
+    #[serde(default = "default_motion_snapshot_cooldown")]
+    pub(crate) cooldown_seconds: u64,
+}
+
+/// When the main stream's mount switches to the sub stream and back
+///
+/// The main stream is tried again after `upgrade_after` seconds, twice as
+/// long each time it falls behind again soon after, up to `max_upgrade_after`
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+pub(crate) struct AdaptiveStreamConfig {
+    /// Switch to the sub stream when the main stream is this many seconds behind
+    #[validate(
+        range(min = 0.5, max = 600.0, message = "Invalid max lag", code = "max_lag"),
+        custom = "validate_seconds"
+    )]
+    #[serde(default = "default_adaptive_max_lag")]
+    pub(crate) max_lag: f64,
+
+    #[validate(range(min = 10, message = "Invalid upgrade after", code = "upgrade_after"))]
+    #[serde(default = "default_adaptive_upgrade_after")]
+    pub(crate) upgrade_after: u64,
+
+    #[serde(default = "default_adaptive_max_upgrade_after")]
+    pub(crate) max_upgrade_after: u64,
+}
+
+/// Which video frames are dropped as broken
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+pub(crate) struct FrameCheckConfig {
+    /// Drop the frames with fewer bytes than this
+    #[validate(range(min = 1, message = "Invalid min bytes", code = "min_bytes"))]
+    #[serde(default = "default_frame_check_min_bytes")]
+    pub(crate) min_bytes: usize,
+}
+
+/// When and where to alert about a camera that keeps failing to stream
+///
+/// The alert is always logged, and also posted as json to the `url` if set
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+pub(crate) struct StreamAlertConfig {
+    /// Alert once the camera has been failing for this many seconds without streaming
+    #[validate(range(min = 1, message = "Invalid alert delay", code = "after_seconds"))]
+    #[serde(default = "default_stream_alert_after")]
+    pub(crate) after_seconds: u64,
+
+    /// POST the alert and the recovery to this http url
+    #[validate(custom = "validate_http_url")]
+    pub(crate) url: Option<String>,
+}
+
+/// The encoder settings of one stream of the camera
+///
+/// The settings that are not given are left as the camera has them
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+pub(crate) struct EncoderConfig {
+    #[validate(regex(
+        path = "RE_RECORD_STREAM",
+        message = "Unknown encoder stream, use mainStream (main, clear), subStream (sub, fluent) or externStream (extern, balanced)",
+        code = "stream"
+    ))]
+    #[serde(default = "default_encoder_stream")]
+    pub(crate) stream: String,
+
+    /// The width and height of the pictures e.g. `2304x1296`
+    #[validate(regex(
+        path = "RE_RESOLUTION",
+        message = "Invalid encoder resolution, give the width and height e.g. 2304x1296",
+        code = "resolution"
+    ))]
+    pub(crate) resolution: Option<String>,
+
+    /// The bit rate in kbps
+    pub(crate) bitrate: Option<u32>,
+
+    /// Frames per second
+    pub(crate) fps: Option<u32>,
+}
+
+/// The recording the camera does on its own SD card
+///
+/// The settings that are not given are left as the camera has them
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+pub(crate) struct SdRecordingConfig {
+    /// Record to the SD card at all
+    pub(crate) enable: Option<bool>,
+
+    /// Overwrite the oldest recordings when the card is full
+    pub(crate) overwrite: Option<bool>,
+
+    #[validate]
+    pub(crate) schedule: Option<SdScheduleConfig>,
+}
+
+/// The hours of the week when one kind of SD recording happens
+#[derive(Debug, Deserialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_sd_schedule"))]
+pub(crate) struct SdScheduleConfig {
+    /// The kind of recording as the camera names it e.g. `MD` for motion
+    #[serde(default = "default_alarm_type")]
+    pub(crate) alarm_type: String,
+
+    /// The week days to record on, every day if not given
+    pub(crate) days: Option<Vec<String>>,
+
+    #[validate(range(max = 23, message = "Invalid begin hour", code = "begin_hour"))]
+    #[serde(default)]
+    pub(crate) begin_hour: u8,
+
+    #[validate(range(max = 23, message = "Invalid end hour", code = "end_hour"))]
+    #[serde(default = "default_end_hour")]
+    pub(crate) end_hour: u8,
+}
+
+/// The week days as the camera names them
+pub(crate) static WEEK_DAYS: &[&str] = &[
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Where and how the `record` subcommand saves the cameras
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub(crate) struct RecordConfig {
+    /// Each camera is recorded into its own directory below this one
+    pub(crate) path: String,
+
+    #[validate(range(min = 1, message = "Invalid segment length", code = "segment_minutes"))]
+    #[serde(default = "default_segment_minutes")]
+    pub(crate) segment_minutes: u64,
+
+    /// Segments older than this are deleted, they are kept forever if not set
+    #[validate(range(min = 1, message = "Invalid retention", code = "retention_days"))]
+    pub(crate) retention_days: Option<u64>,
+
+    /// The container of the segments
+    #[validate(regex(
+        path = "RE_RECORD_FORMAT",
+        message = "Incorrect record format, use ts (the default, copes best with H265 and \
+                   resolution changes mid stream), mkv (keeps what was written if neolink stops), \
+                   mp4 (plays most widely but only once the segment is closed) or \
+                   fmp4 (mp4 that plays while it is written, such as over http)",
+        code = "format"
+    ))]
+    #[serde(default = "default_record_format")]
+    pub(crate) format: String,
+
+    #[validate(regex(
+        path = "RE_RECORD_STREAM",
+        message = "Unknown record stream, use mainStream (main, clear), subStream (sub, fluent) or externStream (extern, balanced)",
+        code = "stream"
+    ))]
+    #[serde(default = "default_record_stream")]
+    pub(crate) stream: String,
+
+    #[validate(regex(
+        path = "RE_RECORD_MODE",
+        message = "Incorrect record mode",
+        code = "mode"
+    ))]
+    #[serde(default = "default_record_mode")]
+    pub(crate) mode: String,
+
+    /// In motion mode the seconds before the motion to include in the clip
+    #[serde(default = "default_pre_roll_seconds")]
+    pub(crate) pre_roll_seconds: u64,
+
+    /// In motion mode the seconds to keep recording once the motion stops
+    #[serde(default = "default_post_roll_seconds")]
+    pub(crate) post_roll_seconds: u64,
+
+    /// In motion mode only record the motion that the AI detection of the
+    /// camera saw one of these in, all motion is recorded if empty
+    #[validate(custom = "validate_ai_types")]
+    #[serde(default)]
+    pub(crate) ai_types: Vec<String>,
+}
+
+impl Config {
+    /// How long the camera at `position` in the order they start waits before it connects
+    pub(crate) fn startup_delay(&self, camera: &CameraConfig, position: usize) -> Duration {
+        Duration::try_from_secs_f64(self.stagger_interval * position as f64 + camera.startup_delay)
+            .unwrap_or_default()
+    }
+
+    /// Keep only the named cameras, failing if any of them are not in the config
+    pub(crate) fn retain_cameras(&mut self, names: &[String]) -> AnyResult<()> {
+        if let Some(missing) = names
+            .iter()
+            .find(|name| !self.cameras.iter().any(|c| &&c.name == name))
+        {
+            return Err(anyhow!("Camera {} not found in the config file", missing));
+        }
+        self.cameras.retain(|c| names.contains(&c.name));
+        Ok(())
+    }
+
+    /// Replace each camera with `channel_ids` or `channels` by one camera for each channel
+    fn expand_channels(&mut self) -> AnyResult<()> {
+        let mut cameras = Vec::with_capacity(self.cameras.len());
+        for camera in self.cameras.drain(..) {
+            let mut named = HashMap::new();
+            for (id, channel) in camera.channels.iter().flatten() {
+                let id: u8 = id.trim().parse().map_err(|_| {
+                    anyhow!("{}: Invalid channel {:?} in channels", camera.name, id)
+                })?;
+                named.insert(id, channel);
+            }
+            let ids = match &camera.channel_ids {
+                Some(channels) => {
+                    let ids = channels
+                        .ids()
+                        .with_context(|| format!("{}: Invalid channel_ids", camera.name))?;
+                    if let Some(id) = named.keys().find(|id| !ids.contains(id)) {
+                        return Err(anyhow!(
+                            "{}: Channel {} is in channels but not in channel_ids",
+                            camera.name,
+                            id
+                        ));
+                    }
+                    ids
+                }
+                None if !named.is_empty() => {
+                    let mut ids: Vec<u8> = named.keys().copied().collect();
+                    ids.sort_unstable();
+                    ids
+                }
+                None => {
+                    cameras.push(camera);
+                    continue;
+                }
+            };
+            // The channels that keep the path of the camera must not all get the same one
+            let shared_path = ids
+                .iter()
+                .filter(|id| named.get(id).is_none_or(|c| c.rtsp_path.is_none()))
+                .count()
+                > 1;
+            if let Some(template) = camera
+                .rtsp_path
+                .iter()
+                .flat_map(RtspPathConfig::templates)
+                .find(|t| shared_path && !t.contains("{channel}") && !t.contains("{camera}"))
+            {
+                return Err(anyhow!(
+                    "{}: The rtsp_path {} would be the same for every channel, add {{channel}} to it",
+                    camera.name,
+                    template
+                ));
+            }
+            for (position, id) in ids.into_iter().enumerate() {
+                let mut channel = camera.clone();
+                channel.name = format!("{}-ch{}", camera.name, id);
+                channel.channel_id = id;
+                channel.channel_ids = None;
+                channel.channels = None;
+                // Each channel takes the next port after the camera's
+                if let Some(port) = camera.bind_port {
+                    let next = usize::from(port) + position;
+                    if next > usize::from(u16::MAX) {
+                        return Err(anyhow!(
+                            "{}: Too many channels for the bind_port {}",
+                            camera.name,
+                            port
+                        ));
+                    }
+                    channel.bind_port = Some(next as u16);
+                }
+                if let Some(named) = named.get(&id) {
+                    if let Some(name) = &named.name {
+                        channel.name = name.clone();
+                    }
+                    if let Some(rtsp_path) = &named.rtsp_path {
+                        channel.rtsp_path = Some(rtsp_path.clone());
+                    }
+                }
+                cameras.push(channel);
+            }
+        }
+        self.cameras = cameras;
+        Ok(())
+    }
+
+    /// Replace the aliases of the streams with their names, unknown names are
+    /// left for the validation to report
+    fn resolve_stream_aliases(&mut self) {
+        let resolve = |name: &mut String| {
+            if let Some(stream) = stream_name(name) {
+                *name = stream.to_string();
+            }
+        };
+        for camera in &mut self.cameras {
+            resolve(&mut camera.stream);
+            if let Some(default_stream) = &mut camera.default_stream {
+                resolve(default_stream);
+            }
+            for encoder in &mut camera.encoder {
+                resolve(&mut encoder.stream);
+            }
+            if let Some(RtspPathConfig::PerStream(templates)) = &mut camera.rtsp_path {
+                *templates = templates
+                    .drain()
+                    .map(|(mut stream, template)| {
+                        resolve(&mut stream);
+                        (stream, template)
+                    })
+                    .collect();
+            }
+        }
+        if let Some(record) = &mut self.record {
+            resolve(&mut record.stream);
+        }
+    }
+}
+
+impl CameraConfig {
+    /// The streams that are enabled by the `stream` option
+    pub(crate) fn enabled_streams(&self) -> Vec<&'static str> {
+        let enabled = |name: &str| match name {
+            "mainStream" => ["all", "both", "mainStream"].contains(&self.stream.as_str()),
+            "subStream" => ["all", "both", "subStream"].contains(&self.stream.as_str()),
+            _ => ["all", "externStream"].contains(&self.stream.as_str()),
+        };
+        STREAM_NAMES
+            .iter()
+            .copied()
+            .filter(|n| enabled(n))
+            .collect()
+    }
+
+    /// The shortest and longest delay before reconnecting
+    ///
+    /// Cameras with a priority above the default back off less
+    pub(crate) fn reconnect_backoff(&self) -> (Duration, Duration) {
+        match self.priority > 0 {
+            true => (Duration::from_millis(250), Duration::from_secs(5)),
+            false => (Duration::from_secs(1), Duration::from_secs(15)),
+        }
+    }
+
+    /// The options of the tcp connection to the camera
+    pub(crate) fn tcp_options(&self) -> TcpOptions {
+        TcpOptions {
+            keepalive_idle: Some(Duration::from_secs(self.tcp_keepalive.idle)),
+            keepalive_interval: Some(Duration::from_secs(self.tcp_keepalive.interval)),
+            keepalive_count: Some(self.tcp_keepalive.count),
+            nodelay: self.tcp_nodelay,
+        }
+    }
+
+    /// The local address to connect to the camera from, if one is set
+    pub(crate) fn source_ip(&self) -> Option<IpAddr> {
+        self.source_ip.as_ref().and_then(|ip| ip.parse().ok())
+    }
+
+    /// Whether the `media_filter` lets this kind of media through
+    pub(crate) fn forwards_media(&self, kind: &str) -> bool {
+        self.media_filter
+            .as_ref()
+            .is_none_or(|filter| filter.iter().any(|allowed| allowed == kind))
+    }
+
+    /// The rtsp mount paths for the stream named `stream`
+    ///
+    /// Without an `rtsp_path` the main stream is at `/{camera}` and
+    /// `/{camera}/mainStream` and the others at `/{camera}/{stream}`
+    pub(crate) fn rtsp_paths(&self, stream: &str) -> Vec<String> {
+        let template = match &self.rtsp_path {
+            Some(RtspPathConfig::All(template)) => Some(template),
+            Some(RtspPathConfig::PerStream(templates)) => templates.get(stream),
+            None => None,
+        };
+        match template {
+            Some(template) => vec![template
+                .replace("{camera}", &self.name)
+                .replace("{stream}", stream)
+                .replace("{channel}", &self.channel_id.to_string())],
+            None if stream == "mainStream" => vec![
+                format!("/{}", self.name),
+                format!("/{}/{}", self.name, stream),
+            ],
+            None => vec![format!("/{}/{}", self.name, stream)],
+        }
+    }
+
+    /// The password to log in with
+    ///
+    /// If `password_file` is set the file is read each time this is called so that
+    /// a rotated secret is picked up on the next reconnect
+    pub(crate) fn password(&self) -> AnyResult<Option<String>> {
+        match &self.password_file {
+            Some(path) => {
+                let contents = fs::read_to_string(path).with_context(|| {
+                    format!("{}: Failed to read password_file {}", self.name, path)
+                })?;
+                Ok(Some(contents.trim().to_string()))
+            }
+            None => Ok(self.password.clone()),
+        }
+    }
+
+    /// The passwords to try logging in with in order
+    pub(crate) fn password_candidates(&self) -> AnyResult<Vec<Option<String>>> {
+        match &self.passwords {
+            Some(passwords) => Ok(passwords.iter().cloned().map(Some).collect()),
+            None => Ok(vec![self.password()?]),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Validate, Clone)]
@@ -105,6 +984,126 @@ fn default_tls_client_auth() -> String {
     "none".to_string()
 }
 
+fn default_alarm_type() -> String {
+    "MD".to_string()
+}
+
+fn default_end_hour() -> u8 {
+    23
+}
+
+fn default_health_check() -> String {
+    "all".to_string()
+}
+
+fn default_rtsp_transport() -> String {
+    "any".to_string()
+}
+
+fn default_segment_minutes() -> u64 {
+    60
+}
+
+fn default_record_format() -> String {
+    "ts".to_string()
+}
+
+fn default_encoder_stream() -> String {
+    "mainStream".to_string()
+}
+
+fn default_auto_reboot_window() -> u64 {
+    60
+}
+
+fn default_auto_reboot_cooldown() -> u64 {
+    360
+}
+
+fn default_record_stream() -> String {
+    "mainStream".to_string()
+}
+
+fn default_record_mode() -> String {
+    "continuous".to_string()
+}
+
+fn default_pre_roll_seconds() -> u64 {
+    5
+}
+
+fn default_post_roll_seconds() -> u64 {
+    10
+}
+
+fn default_reconnect_jitter() -> f64 {
+    0.25
+}
+
+fn default_connect_timeout() -> u64 {
+    20
+}
+
+fn default_keepalive_idle() -> u64 {
+    5
+}
+
+fn default_keepalive_interval() -> u64 {
+    5
+}
+
+fn default_keepalive_count() -> u32 {
+    3
+}
+
+fn default_motion_snapshot_images() -> u32 {
+    1
+}
+
+fn default_motion_snapshot_cooldown() -> u64 {
+    30
+}
+
+fn default_frame_check_min_bytes() -> usize {
+    16
+}
+
+fn default_adaptive_max_lag() -> f64 {
+    3.0
+}
+
+fn default_adaptive_upgrade_after() -> u64 {
+    300
+}
+
+fn default_adaptive_max_upgrade_after() -> u64 {
+    3600
+}
+
+fn default_stream_alert_after() -> u64 {
+    600
+}
+
+fn default_stall_timeout() -> u64 {
+    20
+}
+
+fn default_keyframe_interval() -> f64 {
+    2.0
+}
+
+fn default_queue_packets() -> usize {
+    500
+}
+
+fn default_manage() -> bool {
+    true
+}
+
+fn default_manage_time() -> bool {
+    true
+}
+
 fn default_channel_id() -> u8 {
     0
 }
@@ -120,7 +1119,100 @@ fn validate_username(name: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+fn validate_rtsp_path(template: &str) -> Result<(), ValidationError> {
+    if !template.starts_with('/') {
+        return Err(ValidationError::new("rtsp_path must start with a /"));
+    }
+    let remaining = template
+        .replace("{camera}", "")
+        .replace("{stream}", "")
+        .replace("{channel}", "");
+    if remaining.contains('{') || remaining.contains('}') {
+        return Err(ValidationError::new(
+            "rtsp_path only supports the {camera}, {stream} and {channel} placeholders",
+        ));
+    }
+    Ok(())
+}
+
 fn validate_camera_config(camera_config: &CameraConfig) -> Result<(), ValidationError> {
+    match &camera_config.rtsp_path {
+        Some(RtspPathConfig::All(template)) => validate_rtsp_path(template)?,
+        Some(RtspPathConfig::PerStream(templates)) => {
+            for (stream, template) in templates {
+                if !STREAM_NAMES.contains(&stream.as_str()) {
+                    return Err(ValidationError::new(
+                        "rtsp_path keys must be mainStream (main, clear), subStream (sub, fluent) or externStream (extern, balanced)",
+                    ));
+                }
+                validate_rtsp_path(template)?;
+            }
+        }
+        None => {}
+    }
+
+    if let Some(default_stream) = &camera_config.default_stream {
+        if !STREAM_NAMES.contains(&default_stream.as_str()) {
+            return Err(ValidationError::new(
+                "default_stream must be mainStream (main, clear), subStream (sub, fluent) or externStream (extern, balanced)",
+            ));
+        }
+        if !camera_config
+            .enabled_streams()
+            .contains(&default_stream.as_str())
+        {
+            return Err(ValidationError::new(
+                "default_stream must be one of the streams enabled by stream",
+            ));
+        }
+    }
+
+    for (i, encoder) in camera_config.encoder.iter().enumerate() {
+        if camera_config.encoder[..i]
+            .iter()
+            .any(|other| other.stream == encoder.stream)
+        {
+            return Err(ValidationError::new(
+                "Only one encoder can be given for each stream",
+            ));
+        }
+    }
+
+    // A port that disagrees with the address or that is given with a uid
+    if camera_config.camera_port.is_some() {
+        if let Err(e) = AddressOrUid::for_camera(camera_config) {
+            let mut error = ValidationError::new("port");
+            error.message = Some(e.to_string().into());
+            return Err(error);
+        }
+    }
+
+    if let Some(media_filter) = &camera_config.media_filter {
+        if media_filter
+            .iter()
+            .any(|kind| !MEDIA_KINDS.contains(&kind.as_str()))
+        {
+            return Err(ValidationError::new(
+                "media_filter can only contain video and audio",
+            ));
+        }
+    }
+
+    let password_sources = [
+        camera_config.password.is_some(),
+        camera_config.password_file.is_some(),
+        camera_config.passwords.is_some(),
+    ];
+    if password_sources.iter().filter(|given| **given).count() > 1 {
+        return Err(ValidationError::new(
+            "Must provide only one of password, password_file or passwords",
+        ));
+    }
+    if let Some(passwords) = &camera_config.passwords {
+        if passwords.is_empty() {
+            return Err(ValidationError::new("passwords cannot be empty"));
+        }
+    }
     match (&camera_config.camera_addr, &camera_config.camera_uid) {
         (None, None) => Err(ValidationError::new(
             "Either camera address or uid must be given",
@@ -131,3 +1223,657 @@ fn validate_camera_config(camera_config: &CameraConfig) -> Result<(), Validation
         _ => Ok(()),
     }
 }
+
+fn validate_camera_name(name: &str) -> Result<(), ValidationError> {
+    if name.trim().is_empty() {
+        return Err(ValidationError::new("Camera name cannot be empty"));
+    }
+    if name.contains('/') {
+        return Err(ValidationError::new("Camera name cannot contain a /"));
+    }
+    Ok(())
+}
+
+fn validate_quirks(name: &str) -> Result<(), ValidationError> {
+    if quirks::profile(name).is_none() {
+        return Err(ValidationError::new("Unknown quirks profile"));
+    }
+    Ok(())
+}
+
+fn validate_ai_types(ai_types: &[String]) -> Result<(), ValidationError> {
+    if ai_types
+        .iter()
+        .any(|name| AiType::from_name(name).is_none())
+    {
+        return Err(ValidationError::new(
+            "Unknown AI detection type, use person, vehicle or pet",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_camera_address(addr: &str) -> Result<(), ValidationError> {
+    // Addresses are either `host` or `host:port`, with IPv6 hosts in brackets when a port is given
+    if split_host_port(addr).is_err() {
+        return Err(ValidationError::new(
+            "Camera address is not a valid host and port",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_advertised_host(host: &str) -> Result<(), ValidationError> {
+    if host.parse::<IpAddr>().is_err() && !RE_HOST_NAME.is_match(host) {
+        return Err(ValidationError::new(
+            "Advertised host is not a valid host name or IP address",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_seconds(secs: f64) -> Result<(), ValidationError> {
+    // The range of a validation lets a NaN through
+    if !secs.is_finite() {
+        return Err(ValidationError::new("Seconds must be a finite number"));
+    }
+    Ok(())
+}
+
+fn validate_source_ip(ip: &str) -> Result<(), ValidationError> {
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|_| ValidationError::new("Source IP is not a valid IP address"))?;
+    // Sockets can only be bound to the addresses of this host
+    if UdpSocket::bind((ip, 0)).is_err() {
+        return Err(ValidationError::new(
+            "Source IP is not an address of this host",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_config(config: &Config) -> Result<(), ValidationError> {
+    // The rtsp and http servers resolve a host name themselves
+    let resolves = (config.bind_addr.as_str(), config.bind_port)
+        .to_socket_addrs()
+        .is_ok_and(|mut addrs| addrs.next().is_some());
+    if !resolves {
+        return Err(ValidationError::new(
+            "Bind address is not an IP address or a host name that resolves",
+        ));
+    }
+
+    let mut camera_names = HashSet::new();
+    for camera in &config.cameras {
+        if !camera_names.insert(&camera.name) {
+            return Err(ValidationError::new(
+                "Camera names, including those of the NVR channels, must be unique",
+            ));
+        }
+    }
+
+    for camera in &config.cameras {
+        if let Some(source) = camera.source_ip() {
+            // A host name is resolved when connecting and only its addresses
+            // of the source's family are tried
+            let camera_ip = camera
+                .camera_addr
+                .as_deref()
+                .and_then(|addr| split_host_port(addr).ok())
+                .and_then(|(host, _)| host.split('%').next()?.parse::<IpAddr>().ok());
+            let mismatched = match camera_ip {
+                Some(ip) => ip.is_ipv6() != source.is_ipv6(),
+                // The uid is looked up and connected to over IPv4
+                None => camera.camera_uid.is_some() && source.is_ipv6(),
+            };
+            if mismatched {
+                return Err(ValidationError::new(
+                    "Camera source_ip must be IPv4 for an IPv4 address or uid and IPv6 for an IPv6 address",
+                ));
+            }
+        }
+    }
+
+    let mut ports = HashSet::new();
+    for port in config.cameras.iter().filter_map(|c| c.bind_port) {
+        if port == config.bind_port || !ports.insert(port) {
+            return Err(ValidationError::new(
+                "Camera bind_port must be unique and differ from the global bind_port",
+            ));
+        }
+    }
+    if let Some(http_port) = config.http_port {
+        if http_port == config.bind_port || ports.contains(&http_port) {
+            return Err(ValidationError::new(
+                "The http_port must differ from the rtsp bind_ports",
+            ));
+        }
+    }
+
+    // Paths must be unique on each port once the templates are expanded
+    let mut paths = HashSet::new();
+    for camera in &config.cameras {
+        let port = camera.bind_port.unwrap_or(config.bind_port);
+        for stream in camera.enabled_streams() {
+            for path in camera.rtsp_paths(stream) {
+                if !paths.insert((port, path)) {
+                    return Err(ValidationError::new(
+                        "rtsp_path templates give the same path to more than one stream",
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut user_names = HashSet::new();
+    for user in &config.users {
+        if !user_names.insert(&user.name) {
+            return Err(ValidationError::new("User names must be unique"));
+        }
+    }
+
+    for camera in &config.cameras {
+        for permitted_user in camera.permitted_users.iter().flatten() {
+            if !RESERVED_NAMES.contains(&permitted_user.as_str())
+                && !user_names.contains(permitted_user)
+            {
+                return Err(ValidationError::new(
+                    "Camera permitted_users must be defined in the [[users]] section",
+                ));
+            }
+        }
+    }
+
+    if let Some(cert_path) = &config.certificate {
+        match fs::read_to_string(cert_path) {
+            Ok(contents) if contents.contains("-----BEGIN CERTIFICATE-----") => {}
+            Ok(_) => {
+                return Err(ValidationError::new(
+                    "Certificate file does not contain a PEM certificate",
+                ))
+            }
+            Err(_) => return Err(ValidationError::new("Certificate file cannot be read")),
+        }
+    }
+
+    Ok(())
+}
+
+/// Read, expand and validate the config file at `conf_path`
+///
+/// If `cameras` is not empty only the cameras with those names are kept
+pub(crate) fn load_config(conf_path: &Path, cameras: &[String]) -> AnyResult<Config> {
+    let raw_config: toml::Value = toml::from_str(
+        &fs::read_to_string(conf_path)
+            .with_context(|| format!("Failed to read {:?}", conf_path))?,
+    )
+    .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
+    let mut config: Config = expand_env_vars(raw_config)
+        .with_context(|| format!("Failed to expand the {:?} config file", conf_path))?
+        .try_into()
+        .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
+    config
+        .expand_channels()
+        .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
+    config.resolve_stream_aliases();
+
+    config
+        .validate()
+        .with_context(|| format!("Failed to validate the {:?} config file", conf_path))?;
+
+    if !cameras.is_empty() {
+        config.retain_cameras(cameras)?;
+    }
+    Ok(config)
+}
+
+/// Expand `${VAR}` references to environment variables in every string of the config
+///
+/// A literal `$` can be written as `$$`. Referencing a variable that is not set is an error
+pub(crate) fn expand_env_vars(value: toml::Value) -> AnyResult<toml::Value> {
+    Ok(match value {
+        toml::Value::String(s) => toml::Value::String(expand_env_str(&s)?),
+        toml::Value::Array(values) => toml::Value::Array(
+            values
+                .into_iter()
+                .map(expand_env_vars)
+                .collect::<AnyResult<_>>()?,
+        ),
+        toml::Value::Table(table) => toml::Value::Table(
+            table
+                .into_iter()
+                .map(|(k, v)| Ok((k, expand_env_vars(v)?)))
+                .collect::<AnyResult<_>>()?,
+        ),
+        other => other,
+    })
+}
+
+fn expand_env_str(input: &str) -> AnyResult<String> {
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(idx) = rest.find('$') {
+        result.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            result.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| anyhow!("Unterminated ${{ in config value {:?}", input))?;
+            let name = &after[..end];
+            let var = std::env::var(name).map_err(|_| {
+                anyhow!(
+                    "Environment variable {:?} used in the config is not set",
+                    name
+                )
+            })?;
+            result.push_str(&var);
+            rest = &after[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn validate_time_of_day(time: &str) -> Result<(), ValidationError> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map(|_| ())
+        .map_err(|_| ValidationError::new("Times must be given as HH:MM"))
+}
+
+fn validate_sd_schedule(schedule: &SdScheduleConfig) -> Result<(), ValidationError> {
+    if schedule.begin_hour > schedule.end_hour {
+        return Err(ValidationError::new(
+            "The sd_recording schedule cannot end before it begins",
+        ));
+    }
+    if let Some(days) = &schedule.days {
+        if days.iter().any(|day| !WEEK_DAYS.contains(&day.as_str())) {
+            return Err(ValidationError::new(
+                "The sd_recording days must be week days e.g. Monday",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_motion_snapshot(snapshot: &MotionSnapshotConfig) -> Result<(), ValidationError> {
+    if snapshot.url.is_none() && snapshot.path.is_none() {
+        return Err(ValidationError::new(
+            "motion_snapshot needs a url or a path to send the images to",
+        ));
+    }
+    if let Some(url) = &snapshot.url {
+        if !url.starts_with("http://") {
+            return Err(ValidationError::new(
+                "motion_snapshot url must be an http:// url",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_http_url(url: &str) -> Result<(), ValidationError> {
+    if !url.starts_with("http://") {
+        return Err(ValidationError::new("The url must be an http:// url"));
+    }
+    Ok(())
+}
+
+fn validate_led_schedule(schedule: &LedScheduleConfig) -> Result<(), ValidationError> {
+    let fixed = (&schedule.off_at, &schedule.on_at);
+    let sun = (&schedule.latitude, &schedule.longitude);
+    match (fixed, sun) {
+        ((Some(_), Some(_)), (None, None)) | ((None, None), (Some(_), Some(_))) => Ok(()),
+        _ => Err(ValidationError::new(
+            "led_schedule needs either off_at and on_at or latitude and longitude",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const CAMERA: &str = r#"
+[[cameras]]
+name = "Garage"
+username = "admin"
+address = "192.168.1.10"
+"#;
+
+    // Load `toml` as a config file, each test has its own
+    fn load(toml: &str) -> AnyResult<Config> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "neolink-config-{}-{}.toml",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, toml).unwrap();
+        let config = load_config(&path, &[]);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_startup_delay() {
+        let config = load(&format!(
+            "stagger_interval = 1.5\n{}startup_delay = 2.0",
+            CAMERA
+        ))
+        .unwrap();
+        let camera = &config.cameras[0];
+        assert_eq!(config.startup_delay(camera, 0), Duration::from_secs(2));
+        assert_eq!(config.startup_delay(camera, 2), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_delays_must_be_finite() {
+        assert!(load(&format!(
+            "{}keyframe_interval = 4.0\n[cameras.adaptive_stream]\nmax_lag = 3.0",
+            CAMERA
+        ))
+        .is_ok());
+        for delay in &["nan", "inf", "-1.0", "1e300"] {
+            assert!(load(&format!("{}startup_delay = {}", CAMERA, delay)).is_err());
+            assert!(load(&format!("stagger_interval = {}\n{}", delay, CAMERA)).is_err());
+            assert!(load(&format!("{}keyframe_interval = {}", CAMERA, delay)).is_err());
+            assert!(load(&format!(
+                "{}[cameras.adaptive_stream]\nmax_lag = {}",
+                CAMERA, delay
+            ))
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_channel_ids() {
+        let ids = |channels: ChannelsConfig| channels.ids();
+        assert_eq!(ids(ChannelsConfig::List(vec![0, 2])).unwrap(), vec![0, 2]);
+        assert_eq!(
+            ids(ChannelsConfig::Range("0-3".to_string())).unwrap(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(
+            ids(ChannelsConfig::Range(" 5 - 5 ".to_string())).unwrap(),
+            vec![5]
+        );
+        for range in &["3-0", "0", "0-", "a-3", "0-300"] {
+            assert!(ids(ChannelsConfig::Range(range.to_string())).is_err());
+        }
+    }
+
+    #[test]
+    fn test_expand_channels() {
+        let config = load(&format!(
+            "{}channel_ids = \"1-3\"\nbind_port = 9000",
+            CAMERA
+        ))
+        .unwrap();
+        let cameras: Vec<_> = config
+            .cameras
+            .iter()
+            .map(|c| (c.name.as_str(), c.channel_id, c.bind_port))
+            .collect();
+        assert_eq!(
+            cameras,
+            [
+                ("Garage-ch1", 1, Some(9000)),
+                ("Garage-ch2", 2, Some(9001)),
+                ("Garage-ch3", 3, Some(9002)),
+            ]
+        );
+        assert!(config.cameras.iter().all(|c| c.channel_ids.is_none()));
+
+        let config = load(&format!(
+            "{}channel_ids = [0, 4]\nrtsp_path = \"/nvr/{{channel}}/{{stream}}\"",
+            CAMERA
+        ))
+        .unwrap();
+        assert_eq!(
+            config.cameras[1].rtsp_paths("subStream"),
+            ["/nvr/4/subStream"]
+        );
+
+        // Every channel would be at the same path
+        assert!(load(&format!(
+            "{}channel_ids = [0, 4]\nrtsp_path = \"/nvr/{{stream}}\"",
+            CAMERA
+        ))
+        .is_err());
+        assert!(load(&format!(
+            "{}channel_ids = [0, 1]\nbind_port = 65535",
+            CAMERA
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_named_channels() {
+        let config = load(&format!(
+            "{}bind_port = 9000\n\
+             [cameras.channels.3]\nname = \"driveway\"\nrtsp_path = \"/nvr/driveway/{{stream}}\"\n\
+             [cameras.channels.0]\nname = \"porch\"",
+            CAMERA
+        ))
+        .unwrap();
+        let cameras: Vec<_> = config
+            .cameras
+            .iter()
+            .map(|c| (c.name.as_str(), c.channel_id, c.bind_port))
+            .collect();
+        assert_eq!(
+            cameras,
+            [("porch", 0, Some(9000)), ("driveway", 3, Some(9001))]
+        );
+        assert_eq!(
+            config.cameras[0].rtsp_paths("subStream"),
+            ["/porch/subStream"]
+        );
+        assert_eq!(
+            config.cameras[1].rtsp_paths("subStream"),
+            ["/nvr/driveway/subStream"]
+        );
+
+        // Channels without a table keep the generated name
+        let config = load(&format!(
+            "{}channel_ids = [0, 1]\n[cameras.channels.1]\nname = \"yard\"",
+            CAMERA
+        ))
+        .unwrap();
+        let names: Vec<_> = config.cameras.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Garage-ch0", "yard"]);
+    }
+
+    #[test]
+    fn test_named_channels_are_checked() {
+        let err = load(&format!(
+            "{}channel_ids = [0, 1]\n[cameras.channels.2]\nname = \"yard\"",
+            CAMERA
+        ))
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("Channel 2 is in channels but not in channel_ids"));
+        assert!(load(&format!("{}[cameras.channels.x]\nname = \"yard\"", CAMERA)).is_err());
+        // A channel named like another camera
+        assert!(load(&format!(
+            "{}[cameras.channels.0]\nname = \"Door\"\n\
+             [[cameras]]\nname = \"Door\"\nusername = \"admin\"\naddress = \"192.168.1.11\"",
+            CAMERA
+        ))
+        .is_err());
+        // Two channels at the same path
+        assert!(load(&format!(
+            "{}[cameras.channels.0]\nrtsp_path = \"/nvr/{{stream}}\"\n\
+             [cameras.channels.1]\nrtsp_path = \"/nvr/{{stream}}\"",
+            CAMERA
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_expand_env_str() {
+        std::env::set_var("NEOLINK_TEST_PASSWORD", "s3cret");
+        assert_eq!(
+            expand_env_str("${NEOLINK_TEST_PASSWORD}").unwrap(),
+            "s3cret"
+        );
+        assert_eq!(
+            expand_env_str("pre-${NEOLINK_TEST_PASSWORD}-${NEOLINK_TEST_PASSWORD}").unwrap(),
+            "pre-s3cret-s3cret"
+        );
+        // `$$` is a literal `$` and a `$` without braces is left alone
+        assert_eq!(expand_env_str("$$5 or $5").unwrap(), "$5 or $5");
+        assert_eq!(
+            expand_env_str("$${NEOLINK_TEST_PASSWORD}").unwrap(),
+            "${NEOLINK_TEST_PASSWORD}"
+        );
+        assert_eq!(expand_env_str("ends with $").unwrap(), "ends with $");
+        assert!(expand_env_str("${NEOLINK_TEST_UNSET}").is_err());
+        assert!(expand_env_str("${NEOLINK_TEST_PASSWORD").is_err());
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        std::env::set_var("NEOLINK_TEST_USER", "viewer");
+        let config = load(&format!(
+            "{}[[users]]\nname = \"${{NEOLINK_TEST_USER}}\"\npass = \"secret\"\n",
+            CAMERA
+        ))
+        .unwrap();
+        assert_eq!(config.users[0].name, "viewer");
+        assert!(load(&format!("{}password = \"${{NEOLINK_TEST_UNSET}}\"", CAMERA)).is_err());
+    }
+
+    #[test]
+    fn test_bind_address() {
+        for bind in &["0.0.0.0", "::", "127.0.0.1", "localhost"] {
+            assert!(
+                load(&format!("bind = \"{}\"\n{}", bind, CAMERA)).is_ok(),
+                "{}",
+                bind
+            );
+        }
+        for bind in &["", "not a host"] {
+            assert!(load(&format!("bind = \"{}\"\n{}", bind, CAMERA)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_source_ip() {
+        assert!(validate_source_ip("127.0.0.1").is_ok());
+        assert!(validate_source_ip("localhost").is_err());
+        // Not an address of this host
+        assert!(validate_source_ip("192.0.2.1").is_err());
+
+        assert!(load(&format!("{}source_ip = \"127.0.0.1\"", CAMERA)).is_ok());
+        let ipv6_camera = CAMERA.replace("192.168.1.10", "[2001:db8::1]:9000");
+        let err = load(&format!("{}source_ip = \"127.0.0.1\"", ipv6_camera)).unwrap_err();
+        assert!(format!("{:?}", err).contains("source_ip must be IPv4"));
+        // The family of a host name is picked when it is resolved
+        let named_camera = CAMERA.replace("192.168.1.10", "camera.local");
+        assert!(load(&format!("{}source_ip = \"127.0.0.1\"", named_camera)).is_ok());
+    }
+
+    #[test]
+    fn test_bind_ports_are_unique() {
+        let door = CAMERA.replace("Garage", "Door");
+        assert!(load(&format!(
+            "{}bind_port = 8555\n{}bind_port = 8556",
+            CAMERA, door
+        ))
+        .is_ok());
+        assert!(load(&format!(
+            "{}bind_port = 8555\n{}bind_port = 8555",
+            CAMERA, door
+        ))
+        .is_err());
+        assert!(load(&format!(
+            "bind_port = 8555\n{}{}bind_port = 8555",
+            CAMERA, door
+        ))
+        .is_err());
+        assert!(load(&format!("http_port = 8554\n{}", CAMERA)).is_err());
+        assert!(load(&format!("http_port = 8555\n{}bind_port = 8555", CAMERA)).is_err());
+        assert!(load(&format!("http_port = 8080\n{}bind_port = 8555", CAMERA)).is_ok());
+    }
+
+    #[test]
+    fn test_rtsp_paths_are_unique() {
+        let door = CAMERA.replace("Garage", "Door");
+        let with_path = |camera: &str, path: &str| format!("{}rtsp_path = \"{}\"\n", camera, path);
+
+        assert!(load(&format!(
+            "{}{}",
+            with_path(CAMERA, "/{camera}/{stream}"),
+            with_path(&door, "/{camera}/{stream}")
+        ))
+        .is_ok());
+        assert!(load(&format!(
+            "{}{}",
+            with_path(CAMERA, "/{stream}"),
+            with_path(&door, "/{stream}")
+        ))
+        .is_err());
+        // The main and sub streams of one camera
+        assert!(load(&with_path(CAMERA, "/live")).is_err());
+        assert!(load(&format!(
+            "{}stream = \"mainStream\"",
+            with_path(CAMERA, "/live")
+        ))
+        .is_ok());
+        // A template that lands on the default path of another camera
+        assert!(load(&format!(
+            "{}{}",
+            CAMERA,
+            with_path(&door, "/Garage/{stream}")
+        ))
+        .is_err());
+        // The same path on different ports
+        assert!(load(&format!(
+            "{}{}bind_port = 8555\n",
+            with_path(CAMERA, "/{stream}"),
+            with_path(&door, "/{stream}")
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn test_record_format() {
+        let record = "[record]\npath = \"/var/lib/neolink\"\n";
+        let config = load(&format!("{}{}", record, CAMERA)).unwrap();
+        assert_eq!(config.record.unwrap().format, "ts");
+        let config = load(&format!("{}format = \"mkv\"\n{}", record, CAMERA)).unwrap();
+        assert_eq!(config.record.unwrap().format, "mkv");
+        let err = load(&format!("{}format = \"avi\"\n{}", record, CAMERA)).unwrap_err();
+        assert!(format!("{:?}", err).contains("use ts (the default"));
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(load(CAMERA).is_ok());
+        // Two cameras with the same name
+        assert!(load(&format!("{}{}", CAMERA, CAMERA)).is_err());
+        // A camera without an address or uid
+        assert!(load("[[cameras]]\nname = \"Garage\"\nusername = \"admin\"").is_err());
+        assert!(load(&format!("{}stream = \"fourthStream\"", CAMERA)).is_err());
+        assert!(load(&format!("bind_port = 70000\n{}", CAMERA)).is_err());
+        assert!(load(&format!("{}bind_port = 8554", CAMERA)).is_err());
+        assert!(load(&format!("{}permitted_users = [\"nobody\"]", CAMERA)).is_err());
+        assert!(load(&format!("{}permitted_users = [\"anyone\"]", CAMERA)).is_ok());
+        assert!(load(&format!(
+            "certificate = \"/nonexistent/neolink.pem\"\n{}",
+            CAMERA
+        ))
+        .is_err());
+        let users = "[[users]]\nname = \"viewer\"\npass = \"secret\"\n";
+        assert!(load(&format!("{}{}{}", users, users, CAMERA)).is_err());
+    }
+}
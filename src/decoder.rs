@@ -0,0 +1,194 @@
+//! Decodes the camera's audio to PCM for the features that play it locally
+//!
+//! Older firmwares send ADPCM and newer ones AAC. The codec is told apart by
+//! the kind of audio packet and the decoder for it is made when the first
+//! one arrives. Only `listen` and `intercom` need this, the rtsp and record
+//! outputs pass the AAC through to their muxers as it is.
+use anyhow::{anyhow, Context, Result};
+use gstreamer::{parse_launch, prelude::*, Buffer, FlowError, FlowSuccess, Pipeline, State};
+use gstreamer_app::{AppSink, AppSinkCallbacks, AppSrc};
+use log::*;
+use neolink_core::{
+    adpcm::{decode_block, SAMPLE_RATE},
+    bcmedia::model::*,
+    pcm::Resampler,
+};
+use std::sync::mpsc::{channel, Receiver};
+
+/// Decodes the audio packets of a camera to mono 16 bit PCM at one rate
+/// whichever codec the camera uses
+pub(crate) struct AudioDecoder {
+    rate: u32,
+    adpcm: Option<Resampler>,
+    aac: Option<AacDecoder>,
+}
+
+impl AudioDecoder {
+    /// A decoder whose output is at `rate`
+    pub(crate) fn new(rate: u32) -> Self {
+        AudioDecoder {
+            rate,
+            adpcm: None,
+            aac: None,
+        }
+    }
+
+    /// The samples of an audio packet, or `None` if the media is not audio
+    ///
+    /// AAC is decoded with a short delay so a packet may give no samples,
+    /// or the samples of the packets before it
+    pub(crate) fn decode(&mut self, media: &BcMedia) -> Result<Option<Vec<i16>>> {
+        match media {
+            BcMedia::Adpcm(payload) => {
+                let rate = self.rate;
+                let resampler = self.adpcm.get_or_insert_with(|| {
+                    debug!("Decoding ADPCM audio");
+                    Resampler::new(SAMPLE_RATE, rate)
+                });
+                Ok(Some(resampler.process(&decode_block(&payload.data))))
+            }
+            BcMedia::Aac(payload) => {
+                if self.aac.is_none() {
+                    debug!("Decoding AAC audio");
+                    self.aac = Some(AacDecoder::new(self.rate)?);
+                }
+                let aac = self.aac.as_mut().expect("The AAC decoder was just made");
+                aac.decode(&payload.data).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Decodes the ADTS framed AAC of the camera with gstreamer
+struct AacDecoder {
+    pipeline: Pipeline,
+    src: AppSrc,
+    decoded: Receiver<Vec<i16>>,
+}
+
+impl AacDecoder {
+    fn new(rate: u32) -> Result<Self> {
+        gstreamer::init()
+            .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+        let launch = format!(
+            "appsrc name=src is-live=true caps=audio/mpeg,mpegversion=4,stream-format=adts \
+            ! aacparse \
+            ! decodebin \
+            ! audioconvert \
+            ! audioresample \
+            ! audio/x-raw,format=S16LE,layout=interleaved,rate={},channels=1 \
+            ! appsink name=sink sync=false",
+            rate
+        );
+        let pipeline = parse_launch(&launch)
+            .context("Unable to load the gstreamer AAC decoder ensure all gstramer plugins are installed")?
+            .dynamic_cast::<Pipeline>()
+            .map_err(|_| anyhow!("Unable to create the gstreamer AAC decoder"))?;
+        let src = pipeline
+            .by_name("src")
+            .and_then(|e| e.dynamic_cast::<AppSrc>().ok())
+            .ok_or_else(|| anyhow!("AAC decoder has no appsrc"))?;
+        let sink = pipeline
+            .by_name("sink")
+            .and_then(|e| e.dynamic_cast::<AppSink>().ok())
+            .ok_or_else(|| anyhow!("AAC decoder has no appsink"))?;
+        let (tx, decoded) = channel();
+        sink.set_callbacks(
+            AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(FlowError::Error)?;
+                    let map = buffer.map_readable().map_err(|_| FlowError::Error)?;
+                    let samples = map
+                        .as_slice()
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                        .collect();
+                    tx.send(samples).map_err(|_| FlowError::Eos)?;
+                    Ok(FlowSuccess::Ok)
+                })
+                .build(),
+        );
+        pipeline
+            .set_state(State::Playing)
+            .context("Unable to start the gstreamer AAC decoder")?;
+        Ok(AacDecoder {
+            pipeline,
+            src,
+            decoded,
+        })
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<i16>> {
+        self.src
+            .push_buffer(Buffer::from_slice(data.to_vec()))
+            .map_err(|e| anyhow!("Failed to pass the AAC audio to gstreamer: {:?}", e))?;
+        Ok(self.decoded.try_iter().flatten().collect())
+    }
+}
+
+impl Drop for AacDecoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(State::Null);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neolink_core::adpcm::AdpcmEncoder;
+
+    fn adpcm(samples: &[i16]) -> BcMedia {
+        let mut encoder = AdpcmEncoder::new(164);
+        BcMedia::Adpcm(BcMediaAdpcm {
+            data: encoder.encode_block(samples),
+        })
+    }
+
+    #[test]
+    fn test_decode_adpcm() {
+        let samples: Vec<i16> = (0..321)
+            .map(|i| ((i as f32 / 5.0).sin() * 4000.0) as i16)
+            .collect();
+        let media = adpcm(&samples);
+
+        // At the camera's rate the blocks are decoded as they are, the
+        // resampler holds back the last sample until the next block
+        let mut decoder = AudioDecoder::new(SAMPLE_RATE);
+        let decoded = decoder.decode(&media).unwrap().unwrap();
+        let BcMedia::Adpcm(payload) = &media else {
+            unreachable!()
+        };
+        let block = decode_block(&payload.data);
+        assert_eq!(block.len(), 321);
+        assert_eq!(decoded, block[..320]);
+        let next = decoder.decode(&media).unwrap().unwrap();
+        assert_eq!(next[0], block[320]);
+        assert_eq!(next.len(), 321);
+
+        // And resampled to another rate
+        let mut decoder = AudioDecoder::new(SAMPLE_RATE * 2);
+        let mut resampled = decoder.decode(&media).unwrap().unwrap();
+        resampled.extend(decoder.decode(&media).unwrap().unwrap());
+        assert!(
+            (1280..=1284).contains(&resampled.len()),
+            "{}",
+            resampled.len()
+        );
+    }
+
+    #[test]
+    fn test_decode_ignores_video() {
+        let mut decoder = AudioDecoder::new(SAMPLE_RATE);
+        let frame = BcMedia::Iframe(BcMediaIframe {
+            video_type: VideoType::H264,
+            microseconds: 0,
+            time: None,
+            data: vec![0; 8],
+        });
+        assert!(decoder.decode(&frame).unwrap().is_none());
+        // No decoder is made until audio arrives
+        assert!(decoder.adpcm.is_none() && decoder.aac.is_none());
+    }
+}
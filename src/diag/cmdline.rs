@@ -0,0 +1,11 @@
+use structopt::StructOpt;
+
+/// The diag command gathers what is needed to diagnose a camera into one report
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Print the report as json
+    #[structopt(long)]
+    pub json: bool,
+}
@@ -0,0 +1,309 @@
+///
+/// # Neolink Diag
+///
+/// This module handles the diag subcommand
+///
+/// The subcommand checks one camera for a support ticket: how long it takes
+/// to connect and log in, its ping, firmware, clock, the codec and size of
+/// each enabled stream and how long it takes to its first keyframe, its SD
+/// card and which of the talk, floodlight, PIR and status light commands it
+/// has. A check that fails is reported in the `problems` and the others carry
+/// on.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink diag --config=config.toml CameraName
+/// # To attach to a ticket
+/// neolink diag --config=config.toml CameraName --json > diag.json
+/// ```
+///
+use anyhow::{anyhow, Result};
+use neolink_core::{
+    bc_protocol::{BcCamera, StreamOutput, StreamOutputError, StreamOutputStatus},
+    bcmedia::model::*,
+};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+mod cmdline;
+
+use super::config::{stream_from_name, CameraConfig, Config};
+use crate::sdcard;
+use crate::utils::{connect_and_login, find_camera_by_name, AddressOrUid, FirstKeyframe};
+pub(crate) use cmdline::Opt;
+
+// A stream that sends no keyframe within this long is reported as a problem
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// What was found out about the camera, `None` where it could not be checked
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    camera: String,
+    address: String,
+    /// The time to connect and log in
+    connect_ms: Option<u128>,
+    logged_in: bool,
+    ping_ms: Option<u128>,
+    hardware_version: Option<String>,
+    firmware_version: Option<String>,
+    /// How far the camera clock is ahead of this host
+    time_skew_seconds: Option<i64>,
+    streams: Vec<StreamReport>,
+    storage: Option<Vec<String>>,
+    capabilities: Capabilities,
+    problems: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct StreamReport {
+    stream: &'static str,
+    codec: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: Option<u8>,
+    /// The time from starting the video to its first keyframe
+    first_keyframe_ms: Option<u128>,
+    #[serde(skip)]
+    first_keyframe: Option<FirstKeyframe>,
+    // When the sample gives up waiting for a keyframe
+    #[serde(skip)]
+    deadline: Option<Instant>,
+}
+
+/// Whether the camera answers each command, `None` if that was not found out
+#[derive(Debug, Default, Serialize)]
+struct Capabilities {
+    talk: Option<bool>,
+    floodlight: Option<bool>,
+    pir: Option<bool>,
+    status_light: Option<bool>,
+}
+
+/// Entry point for the diag subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera_config = find_camera_by_name(&config, &opt.camera)?;
+    let report = diagnose(camera_config);
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    if !report.logged_in {
+        return Err(anyhow!("Unable to log in to {}", report.camera));
+    }
+    Ok(())
+}
+
+fn diagnose(camera_config: &CameraConfig) -> Report {
+    let mut report = Report {
+        camera: camera_config.name.clone(),
+        address: AddressOrUid::for_camera(camera_config)
+            .map(|addr| addr.to_string())
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let mut camera = match connect_and_login(camera_config) {
+        Ok(camera) => camera,
+        Err(e) => {
+            report.problems.push(format!("connect: {:#}", e));
+            return report;
+        }
+    };
+    report.connect_ms = Some(start.elapsed().as_millis());
+    report.logged_in = true;
+
+    let start = Instant::now();
+    if check(&mut report, "ping", camera.ping()).is_some() {
+        report.ping_ms = Some(start.elapsed().as_millis());
+    }
+
+    if let Some(version) = check(&mut report, "version", camera.version()) {
+        report.hardware_version = Some(version.hardwareVersion);
+        report.firmware_version = Some(version.firmwareVersion);
+    }
+
+    match check(&mut report, "time", camera.get_time()) {
+        Some(Some(time)) => {
+            report.time_skew_seconds = Some((time - OffsetDateTime::now_utc()).whole_seconds())
+        }
+        Some(None) => report
+            .problems
+            .push("time: The camera clock is not set".to_string()),
+        None => {}
+    }
+
+    report.storage = check(&mut report, "storage", camera.get_hdd_info())
+        .map(|storage| storage.hdd_info.iter().map(sdcard::describe).collect());
+
+    report.capabilities = Capabilities {
+        talk: capability(&mut report, "talk", camera.talk_ability()),
+        floodlight: capability(&mut report, "floodlight", camera.get_floodlight_task()),
+        pir: capability(&mut report, "pir", camera.get_pirstate()),
+        status_light: capability(&mut report, "status light", camera.get_ledstate()),
+    };
+    drop(camera);
+
+    // Each stream needs its own connection as one only carries a single video stream
+    for stream in camera_config.enabled_streams() {
+        let result =
+            connect_and_login(camera_config).and_then(|camera| sample_stream(&camera, stream));
+        if let Some(stream_report) = check(&mut report, stream, result) {
+            report.streams.push(stream_report);
+        }
+    }
+    report
+}
+
+/// The value of a check that succeeded, or `None` with the error added to the problems
+fn check<T, E: Into<anyhow::Error>>(
+    report: &mut Report,
+    what: &str,
+    result: std::result::Result<T, E>,
+) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            report.problems.push(format!("{}: {:#}", what, e.into()));
+            None
+        }
+    }
+}
+
+/// Whether the camera has the command that gave `result`
+fn capability<T>(
+    report: &mut Report,
+    what: &str,
+    result: std::result::Result<T, neolink_core::Error>,
+) -> Option<bool> {
+    match result {
+        Ok(_) => Some(true),
+        Err(neolink_core::Error::Unsupported { .. }) => Some(false),
+        Err(e) => {
+            report.problems.push(format!("{}: {}", what, e));
+            None
+        }
+    }
+}
+
+/// Stream from the camera until its first keyframe, by when its size is usually known
+///
+/// Gives up after `SAMPLE_TIMEOUT` without a keyframe
+fn sample_stream(camera: &BcCamera, stream: &'static str) -> Result<StreamReport> {
+    let mut output = StreamReport {
+        stream,
+        first_keyframe: Some(FirstKeyframe::start()),
+        deadline: Some(Instant::now() + SAMPLE_TIMEOUT),
+        ..Default::default()
+    };
+    let stream_name =
+        stream_from_name(stream).ok_or_else(|| anyhow!("Unknown stream {}", stream))?;
+    camera.start_video(&mut output, stream_name)?;
+    if output.codec.is_none() {
+        return Err(anyhow!("No keyframe within {}s", SAMPLE_TIMEOUT.as_secs()));
+    }
+    Ok(output)
+}
+
+impl StreamOutput for StreamReport {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        match media {
+            BcMedia::InfoV1(info) => {
+                self.width = Some(info.video_width);
+                self.height = Some(info.video_height);
+                self.fps = Some(info.fps);
+            }
+            BcMedia::InfoV2(info) => {
+                self.width = Some(info.video_width);
+                self.height = Some(info.video_height);
+                self.fps = Some(info.fps);
+            }
+            BcMedia::Iframe(frame) => {
+                self.codec = Some(format!("{:?}", frame.video_type));
+                if let Some(latency) = self.first_keyframe.as_mut().and_then(|f| f.keyframe()) {
+                    self.first_keyframe_ms = Some(latency.as_millis());
+                }
+            }
+            _ => {}
+        }
+        let timed_out = self.deadline.is_some_and(|d| Instant::now() >= d);
+        if self.codec.is_some() || timed_out {
+            Ok(StreamOutputStatus::Stop)
+        } else {
+            Ok(StreamOutputStatus::Continue)
+        }
+    }
+}
+
+fn print_report(report: &Report) {
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+    println!("Camera:      {} at {}", report.camera, report.address);
+    println!(
+        "Login:       {}",
+        match report.connect_ms {
+            Some(ms) => format!("ok in {} ms", ms),
+            None => "failed".to_string(),
+        }
+    );
+    println!(
+        "Ping:        {}",
+        or_unknown(report.ping_ms.map(|ms| format!("{} ms", ms)))
+    );
+    println!(
+        "Hardware:    {}",
+        or_unknown(report.hardware_version.clone())
+    );
+    println!(
+        "Firmware:    {}",
+        or_unknown(report.firmware_version.clone())
+    );
+    println!(
+        "Clock:       {}",
+        or_unknown(
+            report
+                .time_skew_seconds
+                .map(|skew| format!("{:+} s from this host", skew))
+        )
+    );
+    for stream in &report.streams {
+        println!(
+            "Stream:      {} {} {}x{} at {} fps, first keyframe after {}",
+            stream.stream,
+            or_unknown(stream.codec.clone()),
+            stream.width.unwrap_or(0),
+            stream.height.unwrap_or(0),
+            stream.fps.unwrap_or(0),
+            or_unknown(stream.first_keyframe_ms.map(|ms| format!("{} ms", ms)))
+        );
+    }
+    match &report.storage {
+        Some(storage) if storage.is_empty() => println!("Storage:     none"),
+        Some(storage) => {
+            for hdd in storage {
+                println!("Storage:     {}", hdd);
+            }
+        }
+        None => println!("Storage:     unknown"),
+    }
+    let capabilities = &report.capabilities;
+    let has = |capability: Option<bool>| match capability {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "unknown",
+    };
+    println!(
+        "Supports:    talk {}, floodlight {}, pir {}, status light {}",
+        has(capabilities.talk),
+        has(capabilities.floodlight),
+        has(capabilities.pir),
+        has(capabilities.status_light)
+    );
+    for problem in &report.problems {
+        println!("Problem:     {}", problem);
+    }
+}
@@ -0,0 +1,12 @@
+use structopt::StructOpt;
+
+/// The discover command finds cameras on the local network with ONVIF
+#[derive(StructOpt, Debug, Clone)]
+pub struct Opt {
+    /// How many seconds to wait for the cameras to answer
+    #[structopt(long, default_value = "3")]
+    pub timeout: u64,
+    /// Print a `[[cameras]]` block for each camera to start a config.toml with
+    #[structopt(long)]
+    pub config_stubs: bool,
+}
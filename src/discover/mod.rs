@@ -0,0 +1,264 @@
+///
+/// # Neolink Discover
+///
+/// This module handles the discover subcommand
+///
+/// The subcommand sends an ONVIF WS-Discovery probe to the local network and
+/// lists the devices that answer with their address, model and ONVIF service
+/// URL. It can also print a `[[cameras]]` block for each of them with the
+/// address filled in, ready to be completed with the credentials. No config
+/// file is needed.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink discover
+/// # To start a config file
+/// neolink discover --config-stubs >> config.toml
+/// ```
+///
+use anyhow::{Context, Result};
+use log::*;
+use rand::Rng;
+use std::collections::HashSet;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use xml::reader::{EventReader, XmlEvent};
+
+mod cmdline;
+
+use crate::utils::percent_decode;
+pub(crate) use cmdline::Opt;
+
+/// The multicast group and port of WS-Discovery
+const DISCOVERY_ADDR: &str = "239.255.255.250:3702";
+
+/// A device that answered the probe
+#[derive(Debug, PartialEq, Eq)]
+struct Device {
+    /// The address it answered from
+    ip: String,
+    /// The model from its `hardware` scope
+    model: Option<String>,
+    /// The name from its `name` scope
+    name: Option<String>,
+    /// The ONVIF device service URLs
+    service_urls: Vec<String>,
+}
+
+/// Entry point for the discover subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt) -> Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0").context("Unable to open a UDP socket")?;
+    let probe = probe_message(&format!("uuid:{}", random_uuid()));
+    socket
+        .send_to(probe.as_bytes(), DISCOVERY_ADDR)
+        .context("Unable to send the ONVIF probe")?;
+    info!("Waiting {}s for cameras to answer", opt.timeout);
+
+    let deadline = Instant::now() + Duration::from_secs(opt.timeout);
+    let mut devices: Vec<Device> = vec![];
+    let mut seen = HashSet::new();
+    let mut buffer = vec![0; 65536];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining == Duration::from_secs(0) {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(e) => return Err(e).context("Unable to receive the ONVIF answers"),
+        };
+        match parse_probe_matches(&buffer[..len], from) {
+            Ok(found) => {
+                for device in found {
+                    if seen.insert(device.ip.clone()) {
+                        devices.push(device);
+                    }
+                }
+            }
+            Err(e) => debug!(
+                "Ignoring an answer from {} that is not understood: {}",
+                from, e
+            ),
+        }
+    }
+
+    if devices.is_empty() {
+        println!("No cameras answered");
+        return Ok(());
+    }
+    devices.sort_by(|a, b| a.ip.cmp(&b.ip));
+    if opt.config_stubs {
+        print!("{}", config_stubs(&devices));
+    } else {
+        for device in &devices {
+            println!(
+                "{}\t{}\t{}",
+                device.ip,
+                device.model.as_deref().unwrap_or("unknown model"),
+                device.service_urls.join(" ")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn random_uuid() -> String {
+    let mut rng = rand::thread_rng();
+    format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        rng.gen::<u32>(),
+        rng.gen::<u16>(),
+        rng.gen::<u16>() & 0x0fff,
+        (rng.gen::<u16>() & 0x3fff) | 0x8000,
+        rng.gen::<u64>() & 0xffff_ffff_ffff
+    )
+}
+
+fn probe_message(message_id: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<e:Envelope xmlns:e="http://www.w3.org/2003/05/soap-envelope" xmlns:w="http://schemas.xmlsoap.org/ws/2004/08/addressing" xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery" xmlns:dn="http://www.onvif.org/ver10/network/wsdl">
+<e:Header>
+<w:MessageID>{}</w:MessageID>
+<w:To e:mustUnderstand="true">urn:schemas-xmlsoap-org:ws:2005:04:discovery</w:To>
+<w:Action e:mustUnderstand="true">http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</w:Action>
+</e:Header>
+<e:Body>
+<d:Probe>
+<d:Types>dn:NetworkVideoTransmitter</d:Types>
+</d:Probe>
+</e:Body>
+</e:Envelope>"#,
+        message_id
+    )
+}
+
+/// The devices in a ProbeMatches answer
+fn parse_probe_matches(answer: &[u8], from: SocketAddr) -> Result<Vec<Device>> {
+    let mut devices = vec![];
+    let mut current: Option<Device> = None;
+    let mut element = String::new();
+    for event in EventReader::new(answer) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                if name.local_name == "ProbeMatch" {
+                    current = Some(Device {
+                        ip: from.ip().to_string(),
+                        model: None,
+                        name: None,
+                        service_urls: vec![],
+                    });
+                }
+                element = name.local_name;
+            }
+            XmlEvent::Characters(text) => {
+                if let Some(device) = current.as_mut() {
+                    match element.as_str() {
+                        "XAddrs" => device
+                            .service_urls
+                            .extend(text.split_whitespace().map(String::from)),
+                        "Scopes" => {
+                            for scope in text.split_whitespace() {
+                                let value = |kind: &str| {
+                                    scope
+                                        .strip_prefix("onvif://www.onvif.org/")
+                                        .and_then(|s| s.strip_prefix(kind))
+                                        .map(percent_decode)
+                                };
+                                if let Some(model) = value("hardware/") {
+                                    device.model = Some(model);
+                                }
+                                if let Some(name) = value("name/") {
+                                    device.name = Some(name);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "ProbeMatch" {
+                    devices.extend(current.take());
+                }
+                element.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(devices)
+}
+
+/// A `[[cameras]]` block for each device with unique names
+fn config_stubs(devices: &[Device]) -> String {
+    let mut names = HashSet::new();
+    let mut stubs = String::new();
+    for (i, device) in devices.iter().enumerate() {
+        let base: String = device
+            .name
+            .as_deref()
+            .or(device.model.as_deref())
+            .unwrap_or("camera")
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        let mut name = base.clone();
+        if !names.insert(name.clone()) {
+            name = format!("{}_{}", base, i + 1);
+            names.insert(name.clone());
+        }
+        stubs.push_str(&format!(
+            "[[cameras]]\nname = \"{}\"\nusername = \"\"\npassword = \"\"\naddress = \"{}\"\n\n",
+            name, device.ip
+        ));
+    }
+    stubs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_matches() {
+        let answer = r#"<?xml version="1.0" encoding="UTF-8"?>
+<SOAP-ENV:Envelope xmlns:SOAP-ENV="http://www.w3.org/2003/05/soap-envelope" xmlns:wsdd="http://schemas.xmlsoap.org/ws/2005/04/discovery">
+<SOAP-ENV:Body>
+<wsdd:ProbeMatches>
+<wsdd:ProbeMatch>
+<wsdd:Types>dn:NetworkVideoTransmitter</wsdd:Types>
+<wsdd:Scopes>onvif://www.onvif.org/type/video_encoder onvif://www.onvif.org/hardware/RLC-410 onvif://www.onvif.org/name/Front%20Door</wsdd:Scopes>
+<wsdd:XAddrs>http://192.168.1.10:8000/onvif/device_service</wsdd:XAddrs>
+</wsdd:ProbeMatch>
+</wsdd:ProbeMatches>
+</SOAP-ENV:Body>
+</SOAP-ENV:Envelope>"#;
+        let from = "192.168.1.10:3702".parse().unwrap();
+        let devices = parse_probe_matches(answer.as_bytes(), from).unwrap();
+        assert_eq!(
+            devices,
+            vec![Device {
+                ip: "192.168.1.10".to_string(),
+                model: Some("RLC-410".to_string()),
+                name: Some("Front Door".to_string()),
+                service_urls: vec!["http://192.168.1.10:8000/onvif/device_service".to_string()],
+            }]
+        );
+
+        let stubs = config_stubs(&devices);
+        assert!(stubs.contains("name = \"Front_Door\""));
+        assert!(stubs.contains("address = \"192.168.1.10\""));
+    }
+}
@@ -0,0 +1,86 @@
+///
+/// # Neolink Dry Run
+///
+/// This module handles the global `--dry-run` option
+///
+/// Each camera is connected, logged in and asked for its version and then
+/// disconnected again. Nothing is streamed and no camera setting is changed,
+/// so it can be used to check the credentials and reachability of all the
+/// cameras. The exit status is non-zero if any camera fails.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink rtsp --config=config.toml --dry-run
+/// ```
+///
+use anyhow::{anyhow, Result};
+use log::*;
+
+use super::config::{CameraConfig, Config};
+use crate::utils::connect_and_login;
+
+pub(crate) fn main(config: Config) -> Result<()> {
+    let results: Vec<(&CameraConfig, Result<String>)> = crossbeam::scope(|s| {
+        let handles: Vec<_> = config
+            .cameras
+            .iter()
+            .map(|camera_config| (camera_config, s.spawn(move |_| check_camera(camera_config))))
+            .collect();
+        handles
+            .into_iter()
+            .map(|(camera_config, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("The check panicked")));
+                (camera_config, result)
+            })
+            .collect()
+    })
+    .unwrap();
+
+    let name_width = results
+        .iter()
+        .map(|(camera_config, _)| camera_config.name.len())
+        .chain(std::iter::once("Camera".len()))
+        .max()
+        .unwrap_or_default();
+    println!("{:<width$}  Status  Details", "Camera", width = name_width);
+    for (camera_config, result) in &results {
+        match result {
+            Ok(firmware) => println!(
+                "{:<width$}  OK      Firmware {}",
+                camera_config.name,
+                firmware,
+                width = name_width
+            ),
+            Err(e) => println!(
+                "{:<width$}  FAIL    {:#}",
+                camera_config.name,
+                e,
+                width = name_width
+            ),
+        }
+    }
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed > 0 {
+        Err(anyhow!(
+            "{} of {} cameras failed the dry run",
+            failed,
+            results.len()
+        ))
+    } else {
+        info!("All {} cameras passed the dry run", results.len());
+        Ok(())
+    }
+}
+
+/// Connects and logs in then returns the firmware version
+fn check_camera(camera_config: &CameraConfig) -> Result<String> {
+    let camera = connect_and_login(camera_config)?;
+    let version = camera
+        .version()
+        .map_err(|e| anyhow!("Could not fetch version information: {}", e))?;
+    Ok(version.firmwareVersion)
+}
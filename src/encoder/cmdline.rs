@@ -0,0 +1,23 @@
+use structopt::StructOpt;
+
+/// The encoder command shows and sets the resolution, frame rate and bit rate of the streams
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Give the camera the `encoder` settings of its config
+    #[structopt(long)]
+    pub apply: bool,
+    /// The stream to change [mainStream, subStream, externStream]
+    #[structopt(long, default_value = "mainStream")]
+    pub stream: String,
+    /// The width and height of the pictures e.g. 2304x1296
+    #[structopt(long)]
+    pub resolution: Option<String>,
+    /// The bit rate in kbps
+    #[structopt(long)]
+    pub bitrate: Option<u32>,
+    /// Frames per second
+    #[structopt(long)]
+    pub fps: Option<u32>,
+}
@@ -0,0 +1,393 @@
+///
+/// # Neolink Encoder
+///
+/// This module handles the encoder subcommand
+///
+/// The subcommand shows the resolution, frame rate and bit rate of each
+/// stream and the ones the camera supports. It can also set them, either
+/// from the command line or from the camera's `encoder` config. The same
+/// config is given to the camera by `neolink rtsp` when it manages the
+/// camera. Settings the camera does not list as supported are refused.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink encoder --config=config.toml CameraName
+/// # To apply the encoder sections of the config
+/// neolink encoder --config=config.toml --apply CameraName
+/// # To lower the frame rate and bit rate of the sub stream
+/// neolink encoder --config=config.toml --stream=subStream --fps=10 --bitrate=256 CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use neolink_core::bc::xml::{Compression, EncodeTable, StreamCompression, StreamInfoList};
+use neolink_core::bc_protocol::BcCamera;
+
+mod cmdline;
+
+use super::config::{stream_name, CameraConfig, Config, EncoderConfig, STREAM_NAMES};
+use crate::utils::{connect_and_login, find_camera_by_name};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the encoder subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera_config = find_camera_by_name(&config, &opt.camera)?;
+    let stream = stream_name(&opt.stream).ok_or_else(|| {
+        anyhow!(
+            "Unknown stream {}, use mainStream, subStream or externStream",
+            opt.stream
+        )
+    })?;
+    let wanted = match opt.apply {
+        true if camera_config.encoder.is_empty() => {
+            return Err(anyhow!(
+                "{}: There is no encoder config to apply",
+                opt.camera
+            ))
+        }
+        true => camera_config.encoder.clone(),
+        false if opt.resolution.is_none() && opt.bitrate.is_none() && opt.fps.is_none() => {
+            vec![]
+        }
+        false => vec![EncoderConfig {
+            stream: stream.to_string(),
+            resolution: opt.resolution.clone(),
+            bitrate: opt.bitrate,
+            fps: opt.fps,
+        }],
+    };
+    let camera = connect_and_login(camera_config)?;
+    apply(&camera, camera_config, &wanted)?;
+
+    let compression = camera
+        .get_compression()
+        .context("Unable to get the encoder settings")?;
+    for stream in STREAM_NAMES {
+        if let Some(settings) = stream_compression(&compression, stream) {
+            println!("{}: {} {}", opt.camera, stream, describe(settings));
+        }
+    }
+    match camera.get_stream_info_list() {
+        Ok(supported) => {
+            for table in supported_tables(&supported, camera_config.channel_id, None) {
+                println!("{}: {}", opt.camera, describe_table(table));
+            }
+        }
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            println!("{}: The supported settings are not reported", opt.camera)
+        }
+        Err(e) => return Err(e).context("Unable to get the supported encoder settings"),
+    }
+    Ok(())
+}
+
+/// Give the camera the `encoder` settings of its config, if it has any
+pub(crate) fn manage(camera: &BcCamera, camera_config: &CameraConfig) -> Result<()> {
+    apply(camera, camera_config, &camera_config.encoder)
+}
+
+fn apply(camera: &BcCamera, camera_config: &CameraConfig, wanted: &[EncoderConfig]) -> Result<()> {
+    if wanted.is_empty() {
+        return Ok(());
+    }
+    let name = &camera_config.name;
+    let mut compression = match camera.get_compression() {
+        Ok(compression) => compression,
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            warn!("{}: The camera does not support encoder settings", name);
+            return Ok(());
+        }
+        Err(e) => return Err(e).context("Unable to get the encoder settings"),
+    };
+    let supported = camera
+        .get_stream_info_list()
+        .context("Unable to get the encoder settings the camera supports")?;
+
+    let mut changed = false;
+    for wanted in wanted {
+        changed |= change(
+            &mut compression,
+            &supported,
+            camera_config.channel_id,
+            wanted,
+        )
+        .with_context(|| format!("{}: Unable to set the {} encoder", name, wanted.stream))?;
+    }
+    if changed {
+        info!("{}: Setting the encoder settings", name);
+        camera
+            .set_compression(compression)
+            .context("Unable to set the encoder settings")?;
+    }
+    Ok(())
+}
+
+/// Change the settings of one stream in `compression` to those `wanted`
+///
+/// Fails if the camera does not list them as supported. Returns whether
+/// anything changed
+fn change(
+    compression: &mut Compression,
+    supported: &StreamInfoList,
+    channel_id: u8,
+    wanted: &EncoderConfig,
+) -> Result<bool> {
+    let stream = wanted.stream.as_str();
+    let settings = match stream {
+        "mainStream" => &mut compression.main_stream,
+        "subStream" => &mut compression.sub_stream,
+        _ => compression
+            .third_stream
+            .as_mut()
+            .ok_or_else(|| anyhow!("The camera has no externStream"))?,
+    };
+    let tables = supported_tables(supported, channel_id, Some(stream));
+    if tables.is_empty() {
+        return Err(anyhow!(
+            "The camera reports no supported settings for the {}",
+            stream
+        ));
+    }
+
+    let (width, height) = match &wanted.resolution {
+        Some(resolution) => parse_resolution(resolution)
+            .ok_or_else(|| anyhow!("Invalid resolution {}", resolution))?,
+        None => (settings.width, settings.height),
+    };
+    let table = tables
+        .iter()
+        .find(|t| (t.resolution.width, t.resolution.height) == (width, height))
+        .ok_or_else(|| {
+            let resolutions: Vec<_> = tables
+                .iter()
+                .map(|t| format!("{}x{}", t.resolution.width, t.resolution.height))
+                .collect();
+            anyhow!(
+                "The {} supports the resolutions {}, not {}x{}",
+                stream,
+                resolutions.join(", "),
+                width,
+                height
+            )
+        })?;
+
+    // The settings that are not given are kept if the resolution supports them
+    let fps = pick(
+        wanted.fps,
+        settings.frame,
+        table.framerates(),
+        table.default_framerate,
+    )
+    .map_err(|rates| {
+        anyhow!(
+            "The {} at {}x{} supports the frame rates {}, not {}",
+            stream,
+            width,
+            height,
+            rates,
+            wanted.fps.unwrap_or_default()
+        )
+    })?;
+    let bitrate = pick(
+        wanted.bitrate,
+        settings.bit_rate,
+        table.bitrates(),
+        table.default_bitrate,
+    )
+    .map_err(|rates| {
+        anyhow!(
+            "The {} at {}x{} supports the bit rates {}, not {}",
+            stream,
+            width,
+            height,
+            rates,
+            wanted.bitrate.unwrap_or_default()
+        )
+    })?;
+
+    let before = describe(settings);
+    settings.width = width;
+    settings.height = height;
+    settings.resolution_name = format!("{}*{}", width, height);
+    settings.frame = fps;
+    settings.bit_rate = bitrate;
+    Ok(describe(settings) != before)
+}
+
+/// The `wanted` value if it is one of the `allowed`, otherwise the `current`
+/// one or the `default` if it is no longer allowed
+///
+/// Fails with the allowed values if the wanted one is not among them
+fn pick(wanted: Option<u32>, current: u32, allowed: Vec<u32>, default: u32) -> Result<u32, String> {
+    match wanted {
+        Some(wanted) if allowed.contains(&wanted) => Ok(wanted),
+        Some(_) => Err(allowed
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")),
+        None if allowed.contains(&current) => Ok(current),
+        None => Ok(default),
+    }
+}
+
+/// The encode tables of the channel, only those of the `stream` if given
+fn supported_tables<'a>(
+    supported: &'a StreamInfoList,
+    channel_id: u8,
+    stream: Option<&str>,
+) -> Vec<&'a EncodeTable> {
+    supported
+        .stream_infos
+        .iter()
+        .filter(|info| info.channel_bits == 0 || info.channel_bits & (1 << channel_id) != 0)
+        .flat_map(|info| info.encode_tables.iter())
+        .filter(|table| stream.is_none_or(|stream| table.stream == stream))
+        .collect()
+}
+
+fn stream_compression<'a>(
+    compression: &'a Compression,
+    stream: &str,
+) -> Option<&'a StreamCompression> {
+    match stream {
+        "mainStream" => Some(&compression.main_stream),
+        "subStream" => Some(&compression.sub_stream),
+        _ => compression.third_stream.as_ref().filter(|s| s.width > 0),
+    }
+}
+
+/// The width and height of a resolution such as `2304x1296` or `2304*1296`
+fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let (width, height) = resolution.split_once(['x', '*'])?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+fn describe(settings: &StreamCompression) -> String {
+    format!(
+        "{}x{} {}fps {}kbps {}",
+        settings.width, settings.height, settings.frame, settings.bit_rate, settings.encoder_type
+    )
+}
+
+fn describe_table(table: &EncodeTable) -> String {
+    format!(
+        "{} supports {}x{} at {} fps and {} kbps",
+        table.stream,
+        table.resolution.width,
+        table.resolution.height,
+        table.framerate_table,
+        table.bitrate_table
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neolink_core::bc::xml::{EncodeResolution, StreamInfo};
+
+    fn supported() -> StreamInfoList {
+        let table = |stream: &str, width, height, framerates: &str, bitrates: &str| EncodeTable {
+            stream: stream.to_string(),
+            resolution: EncodeResolution { width, height },
+            default_framerate: 15,
+            default_bitrate: 1024,
+            framerate_table: framerates.to_string(),
+            bitrate_table: bitrates.to_string(),
+        };
+        StreamInfoList {
+            stream_infos: vec![StreamInfo {
+                channel_bits: 1,
+                encode_tables: vec![
+                    table("mainStream", 2304, 1296, "15,12,10", "1024,2048,2560"),
+                    table("mainStream", 1920, 1080, "15,10", "1024,1536"),
+                    table("subStream", 896, 512, "15,10", "256,512"),
+                ],
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn compression() -> Compression {
+        Compression {
+            main_stream: StreamCompression {
+                width: 2304,
+                height: 1296,
+                frame: 12,
+                bit_rate: 2560,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn wanted(resolution: Option<&str>, fps: Option<u32>, bitrate: Option<u32>) -> EncoderConfig {
+        EncoderConfig {
+            stream: "mainStream".to_string(),
+            resolution: resolution.map(str::to_string),
+            bitrate,
+            fps,
+        }
+    }
+
+    #[test]
+    fn test_change_supported() {
+        let mut compression = compression();
+        let changed = change(
+            &mut compression,
+            &supported(),
+            0,
+            &wanted(None, Some(10), Some(2048)),
+        )
+        .unwrap();
+        assert!(changed);
+        assert_eq!(compression.main_stream.frame, 10);
+        assert_eq!(compression.main_stream.bit_rate, 2048);
+        assert!(!change(
+            &mut compression,
+            &supported(),
+            0,
+            &wanted(None, Some(10), None)
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_change_resolution_keeps_supported_settings() {
+        let mut compression = compression();
+        change(
+            &mut compression,
+            &supported(),
+            0,
+            &wanted(Some("1920x1080"), None, None),
+        )
+        .unwrap();
+        let main = &compression.main_stream;
+        assert_eq!(main.resolution_name, "1920*1080");
+        // 12 fps and 2560 kbps are not supported at 1080p
+        assert_eq!((main.frame, main.bit_rate), (15, 1024));
+    }
+
+    #[test]
+    fn test_change_unsupported_is_refused() {
+        let mut compression = compression();
+        for wanted in &[
+            wanted(None, Some(25), None),
+            wanted(None, None, Some(4096)),
+            wanted(Some("1280x720"), None, None),
+        ] {
+            assert!(change(&mut compression, &supported(), 0, wanted).is_err());
+        }
+        assert_eq!(compression, self::compression());
+    }
+
+    #[test]
+    fn test_parse_resolution() {
+        assert_eq!(parse_resolution("2304x1296"), Some((2304, 1296)));
+        assert_eq!(parse_resolution("896*512"), Some((896, 512)));
+        assert_eq!(parse_resolution("896"), None);
+    }
+}
@@ -0,0 +1,108 @@
+//! Local microphone capture and speaker playback using cpal
+use anyhow::{anyhow, Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Sample, SampleFormat, Stream, StreamConfig};
+use log::*;
+use neolink_core::pcm::{downmix, Resampler};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+
+/// Start capturing from the default microphone
+///
+/// Returns the stream, which must be kept alive, the sample rate and a channel of mono
+/// samples. Samples are only sent while `enabled` is true
+pub(super) fn microphone(enabled: Arc<AtomicBool>) -> Result<(Stream, u32, Receiver<Vec<i16>>)> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| anyhow!("No microphone was found"))?;
+    let supported = device
+        .default_input_config()
+        .context("Unable to get the microphone config")?;
+    let format = supported.sample_format();
+    let config: StreamConfig = supported.into();
+    let rate = config.sample_rate.0;
+    let channels = config.channels;
+    debug!("Microphone: {} channels at {}Hz", channels, rate);
+
+    let (tx, rx) = sync_channel(30);
+    let stream = match format {
+        SampleFormat::I16 => build_input::<i16>(&device, &config, tx, enabled),
+        SampleFormat::U16 => build_input::<u16>(&device, &config, tx, enabled),
+        SampleFormat::F32 => build_input::<f32>(&device, &config, tx, enabled),
+    }?;
+    stream.play().context("Unable to start the microphone")?;
+    Ok((stream, rate, rx))
+}
+
+fn build_input<T: Sample>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    tx: SyncSender<Vec<i16>>,
+    enabled: Arc<AtomicBool>,
+) -> Result<Stream> {
+    let channels = config.channels;
+    Ok(device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            if enabled.load(Ordering::Relaxed) {
+                let samples: Vec<i16> = data.iter().map(|s| s.to_i16()).collect();
+                // Never block the audio thread, drop audio if the encoder is behind
+                let _ = tx.try_send(downmix(&samples, channels));
+            }
+        },
+        |e| error!("Microphone error: {}", e),
+    )?)
+}
+
+/// Start playing mono samples at `input_rate` on the default speaker
+pub(super) fn speaker(input_rate: u32, rx: Receiver<Vec<i16>>) -> Result<Stream> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| anyhow!("No speaker was found"))?;
+    let supported = device
+        .default_output_config()
+        .context("Unable to get the speaker config")?;
+    let format = supported.sample_format();
+    let config: StreamConfig = supported.into();
+    debug!(
+        "Speaker: {} channels at {}Hz",
+        config.channels, config.sample_rate.0
+    );
+
+    let resampler = Resampler::new(input_rate, config.sample_rate.0);
+    let stream = match format {
+        SampleFormat::I16 => build_output::<i16>(&device, &config, rx, resampler),
+        SampleFormat::U16 => build_output::<u16>(&device, &config, rx, resampler),
+        SampleFormat::F32 => build_output::<f32>(&device, &config, rx, resampler),
+    }?;
+    stream.play().context("Unable to start the speaker")?;
+    Ok(stream)
+}
+
+fn build_output<T: Sample>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    rx: Receiver<Vec<i16>>,
+    mut resampler: Resampler,
+) -> Result<Stream> {
+    let channels = config.channels as usize;
+    let mut buffer: VecDeque<i16> = VecDeque::new();
+    Ok(device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            while let Ok(samples) = rx.try_recv() {
+                buffer.extend(resampler.process(&samples));
+            }
+            for frame in data.chunks_mut(channels) {
+                // Play silence when the camera audio has not arrived yet
+                let sample = buffer.pop_front().unwrap_or(0);
+                for out in frame.iter_mut() {
+                    *out = T::from(&sample);
+                }
+            }
+        },
+        |e| error!("Speaker error: {}", e),
+    )?)
+}
@@ -0,0 +1,16 @@
+use structopt::StructOpt;
+
+/// The intercom command will send the microphone to the camera
+/// and play the camera's audio on the speakers
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera to talk through. Must be a name in the config
+    pub camera: String,
+    /// Push to talk: press enter to start talking and enter again to listen.
+    /// The camera audio is muted while talking so that it does not feed back
+    #[structopt(long)]
+    pub ptt: bool,
+    /// Use to change the volume of the microphone in decibels
+    #[structopt(long, default_value = "0.0", allow_hyphen_values = true)]
+    pub gain: f32,
+}
@@ -0,0 +1,134 @@
+///
+/// # Neolink Intercom
+///
+/// This module handles the intercom subcommand
+///
+/// The subcommand sends the local microphone to the camera's speaker
+/// and plays the camera's microphone on the local speakers
+///
+/// With `--ptt` it is half duplex: press enter to start talking and
+/// enter again to go back to listening. The camera audio is muted
+/// while talking so that the two do not feed back into each other.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink intercom --config=config.toml CameraName
+/// # Or with push to talk
+/// neolink intercom --config=config.toml --ptt CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+use log::*;
+use neolink_core::{
+    adpcm::SAMPLE_RATE,
+    bc_protocol::{Stream, StreamOutput, StreamOutputError, StreamOutputStatus},
+    bcmedia::model::*,
+    pcm::db_to_linear,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Arc;
+
+mod audio;
+mod cmdline;
+
+use super::config::Config;
+use crate::decoder::AudioDecoder;
+use crate::talk::{pcm::start_encoder, talk_setup};
+use crate::utils::{connect_and_login, find_camera_by_name};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the intercom subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera_config = find_camera_by_name(&config, &opt.camera)?;
+    let camera = connect_and_login(camera_config)?;
+
+    let (talk_config, camera_audio) = talk_setup(&camera, camera_config, db_to_linear(opt.gain))?;
+
+    // Without push to talk we are always talking
+    let talking = Arc::new(AtomicBool::new(!opt.ptt));
+
+    let (_mic_stream, mic_rate, mic_rx) = audio::microphone(talking.clone())?;
+    let adpcm_rx = start_encoder(mic_rx, mic_rate, camera_audio);
+
+    let (speaker_tx, speaker_rx) = sync_channel(30);
+    let _speaker_stream = audio::speaker(SAMPLE_RATE, speaker_rx)?;
+    let mut speaker = SpeakerOutput {
+        tx: speaker_tx,
+        talking: talking.clone(),
+        ptt: opt.ptt,
+        decoder: Some(AudioDecoder::new(SAMPLE_RATE)),
+    };
+
+    if opt.ptt {
+        let talking = talking.clone();
+        std::thread::spawn(move || push_to_talk(talking));
+    } else {
+        info!("Intercom started, press Ctrl-C to stop");
+    }
+
+    let camera = &camera;
+    crossbeam::scope(|s| {
+        let talk = s.spawn(move |_| {
+            camera
+                .talk_stream(adpcm_rx, talk_config)
+                .context("Talk stream ended early")
+        });
+        camera
+            .start_video(&mut speaker, Stream::Sub)
+            .context("Error while listening to the camera")?;
+        talk.join().expect("Talk thread panicked")?;
+        Ok(())
+    })
+    .expect("Intercom thread panicked")
+}
+
+/// Toggle between talking and listening each time enter is pressed
+fn push_to_talk(talking: Arc<AtomicBool>) {
+    info!("Listening. Press enter to talk");
+    let mut line = String::new();
+    while matches!(std::io::stdin().read_line(&mut line), Ok(n) if n > 0) {
+        line.clear();
+        let now_talking = !talking.load(Ordering::SeqCst);
+        talking.store(now_talking, Ordering::SeqCst);
+        if now_talking {
+            info!("Talking. Press enter to listen");
+        } else {
+            info!("Listening. Press enter to talk");
+        }
+    }
+}
+
+/// Decodes the camera's audio and passes it to the speaker
+struct SpeakerOutput {
+    tx: SyncSender<Vec<i16>>,
+    talking: Arc<AtomicBool>,
+    ptt: bool,
+    // Dropped if the audio cannot be decoded
+    decoder: Option<AudioDecoder>,
+}
+
+impl StreamOutput for SpeakerOutput {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        // Half duplex, don't play the camera while we are talking
+        if self.ptt && self.talking.load(Ordering::Relaxed) {
+            return Ok(StreamOutputStatus::Continue);
+        }
+        if let Some(decoder) = &mut self.decoder {
+            match decoder.decode(&media) {
+                Ok(Some(samples)) if !samples.is_empty() => {
+                    let _ = self.tx.try_send(samples);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Unable to play the camera audio: {:?}", e);
+                    self.decoder = None;
+                }
+            }
+        }
+        Ok(StreamOutputStatus::Continue)
+    }
+}
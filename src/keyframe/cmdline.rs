@@ -0,0 +1,22 @@
+use crate::config::parse_stream;
+use neolink_core::bc_protocol::Stream;
+use structopt::StructOpt;
+
+/// The keyframe command asks a camera for keyframes and times how long they take to arrive
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The stream to ask: mainStream (main, clear), subStream (sub, fluent) or externStream (extern, balanced)
+    #[structopt(long, default_value = "mainStream", parse(try_from_str = parse_stream))]
+    pub stream: Stream,
+    /// How many keyframes to ask for
+    #[structopt(long, default_value = "1")]
+    pub count: u32,
+    /// Wait this many seconds after a keyframe before asking for the next
+    #[structopt(long, default_value = "1")]
+    pub delay: f64,
+    /// Give up if a keyframe takes longer than this many seconds to arrive
+    #[structopt(long, default_value = "10")]
+    pub timeout: u64,
+}
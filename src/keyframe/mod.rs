@@ -0,0 +1,206 @@
+///
+/// # Neolink Keyframe
+///
+/// This module handles the keyframe subcommand
+///
+/// The subcommand starts a stream of the camera and, once its first keyframe
+/// has arrived and the `--delay` has passed, asks the camera for a new one in
+/// the way the rtsp server does when a client connects. The time until the
+/// next I-frame arrives is printed along with the time since the keyframe
+/// before it, so that a keyframe the camera would have sent anyway can be
+/// told apart. It is meant for checking that a camera honours the request.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink keyframe --config=config.toml CameraName
+/// # Ask the sub stream five times, two seconds apart
+/// neolink keyframe --config=config.toml --stream=subStream --count=5 --delay=2 CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use neolink_core::{
+    bc_protocol::{BcCamera, Stream, StreamOutput, StreamOutputError, StreamOutputStatus},
+    bcmedia::model::*,
+};
+use std::time::{Duration, Instant};
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::find_and_connect;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the keyframe subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    if !opt.delay.is_finite() || opt.delay < 0.0 {
+        return Err(anyhow!("The delay must be a number of seconds"));
+    }
+    let camera = find_and_connect(&config, &opt.camera)?;
+    info!(
+        "Asking {} for {} keyframes on the {:?} stream",
+        opt.camera, opt.count, opt.stream
+    );
+    let timer = KeyframeTimer::new(
+        Duration::from_secs_f64(opt.delay),
+        Duration::from_secs(opt.timeout),
+    );
+    let mut output = KeyframeOutput {
+        camera: &camera,
+        stream: opt.stream,
+        timer,
+        wanted: opt.count,
+        arrived: 0,
+        timed_out: false,
+    };
+    if opt.count > 0 {
+        camera
+            .start_video(&mut output, opt.stream)
+            .context("Error while streaming from the camera")?;
+    }
+    if output.timed_out {
+        return Err(anyhow!(
+            "{}: No keyframe arrived within {}s of the request",
+            opt.camera,
+            opt.timeout
+        ));
+    }
+    Ok(())
+}
+
+/// What a packet of the stream means for the keyframe being timed
+#[derive(Debug, PartialEq)]
+enum Event {
+    Nothing,
+    /// Time to ask for a keyframe
+    Request,
+    /// The keyframe that was asked for arrived
+    Arrived {
+        after: Duration,
+        since_last: Option<Duration>,
+    },
+    TimedOut,
+}
+
+/// Times a keyframe request from the packets of the stream
+struct KeyframeTimer {
+    delay: Duration,
+    timeout: Duration,
+    last_keyframe: Option<Instant>,
+    requested: Option<Instant>,
+}
+
+impl KeyframeTimer {
+    fn new(delay: Duration, timeout: Duration) -> Self {
+        KeyframeTimer {
+            delay,
+            timeout,
+            last_keyframe: None,
+            requested: None,
+        }
+    }
+
+    fn packet(&mut self, keyframe: bool, now: Instant) -> Event {
+        match self.requested {
+            Some(at) if keyframe => {
+                let since_last = self.last_keyframe.map(|last| now - last);
+                self.requested = None;
+                self.last_keyframe = Some(now);
+                Event::Arrived {
+                    after: now - at,
+                    since_last,
+                }
+            }
+            Some(at) if now - at >= self.timeout => Event::TimedOut,
+            Some(_) => Event::Nothing,
+            None => {
+                if keyframe {
+                    self.last_keyframe = Some(now);
+                }
+                match self.last_keyframe {
+                    Some(last) if now - last >= self.delay => {
+                        self.requested = Some(now);
+                        Event::Request
+                    }
+                    _ => Event::Nothing,
+                }
+            }
+        }
+    }
+}
+
+/// Asks for the keyframes on the stream it receives and prints how long they took
+struct KeyframeOutput<'a> {
+    camera: &'a BcCamera,
+    stream: Stream,
+    timer: KeyframeTimer,
+    wanted: u32,
+    arrived: u32,
+    timed_out: bool,
+}
+
+impl<'a> StreamOutput for KeyframeOutput<'a> {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        let keyframe = matches!(media, BcMedia::Iframe(_));
+        match self.timer.packet(keyframe, Instant::now()) {
+            Event::Nothing => {}
+            Event::Request => {
+                debug!("Requesting a keyframe");
+                self.camera.request_keyframe(self.stream)?;
+            }
+            Event::Arrived { after, since_last } => {
+                self.arrived += 1;
+                let since_last = since_last
+                    .map(|since| {
+                        format!(", {:.2}s after the keyframe before it", since.as_secs_f64())
+                    })
+                    .unwrap_or_default();
+                println!(
+                    "Keyframe {}: {:.3}s after the request{}",
+                    self.arrived,
+                    after.as_secs_f64(),
+                    since_last
+                );
+                if self.arrived >= self.wanted {
+                    return Ok(StreamOutputStatus::Stop);
+                }
+            }
+            Event::TimedOut => {
+                self.timed_out = true;
+                return Ok(StreamOutputStatus::Stop);
+            }
+        }
+        Ok(StreamOutputStatus::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframe_timer() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut timer = KeyframeTimer::new(Duration::from_secs(1), Duration::from_secs(5));
+        // Nothing is asked for before the first keyframe and the delay after it
+        assert_eq!(timer.packet(false, at(0)), Event::Nothing);
+        assert_eq!(timer.packet(true, at(100)), Event::Nothing);
+        assert_eq!(timer.packet(false, at(600)), Event::Nothing);
+        assert_eq!(timer.packet(false, at(1100)), Event::Request);
+        assert_eq!(timer.packet(false, at(1200)), Event::Nothing);
+        assert_eq!(
+            timer.packet(true, at(1350)),
+            Event::Arrived {
+                after: Duration::from_millis(250),
+                since_last: Some(Duration::from_millis(1250)),
+            }
+        );
+        // The next request waits for the delay again and can time out
+        assert_eq!(timer.packet(false, at(2400)), Event::Request);
+        assert_eq!(timer.packet(false, at(7400)), Event::TimedOut);
+    }
+}
@@ -0,0 +1,11 @@
+use structopt::StructOpt;
+
+/// The last-motion command prints when a camera last saw motion
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Print the time as unix seconds
+    #[structopt(long)]
+    pub unix: bool,
+}
@@ -0,0 +1,95 @@
+///
+/// # Neolink Last Motion
+///
+/// This module handles the last-motion subcommand and keeps the time that
+/// each camera last saw motion
+///
+/// The rtsp and record subcommands note the motion of their cameras. With
+/// `motion_state_file` in the config the times are saved to that file, which
+/// this subcommand reads. It prints the local time of the last motion and how
+/// long ago it was, or `never` if the camera has not seen any.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink last-motion --config=config.toml CameraName
+/// # For scripts
+/// neolink last-motion --config=config.toml CameraName --unix
+/// ```
+///
+use anyhow::{anyhow, Result};
+use chrono::{Local, TimeZone};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod cmdline;
+mod state;
+
+use super::config::Config;
+use crate::utils::find_camera_by_name;
+pub(crate) use cmdline::Opt;
+pub(crate) use state::{last_motion, note_motion, persist_to, track_motion, wait_for_motion};
+
+/// Entry point for the last-motion subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    find_camera_by_name(&config, &opt.camera)?;
+    let file = config.motion_state_file.as_ref().ok_or_else(|| {
+        anyhow!("last-motion reads the `motion_state_file` which is not in the config")
+    })?;
+    let file = Path::new(file);
+    let times = match file.exists() {
+        true => state::read_state_file(file)?,
+        false => Default::default(),
+    };
+
+    match times.get(&opt.camera) {
+        None => println!("never"),
+        Some(time) if opt.unix => println!("{}", time),
+        Some(&time) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or_default();
+            let local = Local
+                .timestamp_opt(time as i64, 0)
+                .single()
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| time.to_string());
+            println!("{} ({} ago)", local, ago(now.saturating_sub(time)));
+        }
+    }
+    Ok(())
+}
+
+// The two largest units of `seconds`, like `3m 12s`
+fn ago(seconds: u64) -> String {
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    let parts: Vec<String> = units
+        .iter()
+        .scan(seconds, |left, &(unit, size)| {
+            let count = *left / size;
+            *left %= size;
+            Some((count, unit))
+        })
+        .skip_while(|&(count, unit)| count == 0 && unit != "s")
+        .take(2)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect();
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ago() {
+        assert_eq!(ago(0), "0s");
+        assert_eq!(ago(45), "45s");
+        assert_eq!(ago(192), "3m 12s");
+        assert_eq!(ago(7500), "2h 5m");
+        assert_eq!(ago(3 * 86400 + 60), "3d 0h");
+    }
+}
@@ -0,0 +1,152 @@
+//! The time each camera last saw motion
+//!
+//! The motion listeners note every motion the cameras report here. The times
+//! are kept for as long as neolink runs, across reconnects, and with a
+//! `motion_state_file` they are also written to that file so that they
+//! survive a restart and can be read by `neolink last-motion`.
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use log::*;
+use neolink_core::bc_protocol::{BcCamera, MotionOutput, MotionOutputError, MotionStatus};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Condvar, Mutex,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The unix time in seconds of the last motion of each camera
+pub(crate) type MotionTimes = BTreeMap<String, u64>;
+
+lazy_static! {
+    static ref LAST_MOTION: Mutex<LastMotion> = Default::default();
+    static ref MOTION_NOTED: Condvar = Condvar::new();
+}
+
+#[derive(Default)]
+struct LastMotion {
+    times: MotionTimes,
+    file: Option<PathBuf>,
+}
+
+/// Keep the last motion times in `file` too, starting from those already in it
+pub(crate) fn persist_to(file: Option<&str>) {
+    let mut last = LAST_MOTION.lock().unwrap();
+    last.file = file.map(PathBuf::from);
+    if let Some(file) = last.file.clone().filter(|file| file.exists()) {
+        match read_state_file(&file) {
+            Ok(times) => last.times = times,
+            Err(e) => warn!("Starting without the saved last motion: {:?}", e),
+        }
+    }
+}
+
+/// Note that `camera` saw motion just now
+pub(crate) fn note_motion(camera: &str) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let mut last = LAST_MOTION.lock().unwrap();
+    last.times.insert(camera.to_string(), now);
+    if let Some(file) = &last.file {
+        if let Err(e) = write_state_file(file, &last.times) {
+            warn!("{}: Failed to save the last motion: {:?}", camera, e);
+        }
+    }
+    MOTION_NOTED.notify_all();
+}
+
+/// Wait up to `timeout` for `camera` to see motion at another time than `since`
+///
+/// Returns the time of the new motion, or `None` if there was none
+pub(crate) fn wait_for_motion(camera: &str, since: Option<u64>, timeout: Duration) -> Option<u64> {
+    let last = LAST_MOTION.lock().unwrap();
+    let (last, _) = MOTION_NOTED
+        .wait_timeout_while(last, timeout, |last| {
+            last.times.get(camera).copied() == since
+        })
+        .unwrap();
+    last.times
+        .get(camera)
+        .copied()
+        .filter(|time| Some(*time) != since)
+}
+
+/// The unix time in seconds that `camera` last saw motion
+pub(crate) fn last_motion(camera: &str) -> Option<u64> {
+    LAST_MOTION.lock().unwrap().times.get(camera).copied()
+}
+
+/// Read the last motion times saved in `file`
+pub(crate) fn read_state_file(file: &Path) -> Result<MotionTimes> {
+    let json = std::fs::read_to_string(file)
+        .with_context(|| format!("Unable to read {}", file.display()))?;
+    parse_state(&json).with_context(|| format!("{} is not a motion state file", file.display()))
+}
+
+fn parse_state(json: &str) -> Result<MotionTimes> {
+    Ok(serde_json::from_str(json)?)
+}
+
+fn write_state_file(file: &Path, times: &MotionTimes) -> Result<()> {
+    // Replace the file in one step so that a reader never sees half of it
+    let partial = file.with_extension("partial");
+    std::fs::write(&partial, serde_json::to_string_pretty(times)?)
+        .with_context(|| format!("Unable to write {}", partial.display()))?;
+    std::fs::rename(&partial, file)
+        .with_context(|| format!("Unable to replace {}", file.display()))?;
+    Ok(())
+}
+
+/// Note the motion of `camera` while `work` runs
+///
+/// The motion is listened to on its own thread, which ends soon after `work`
+/// does. A camera that doesn't report motion only logs why.
+pub(crate) fn track_motion<T>(camera: &BcCamera, camera_name: &str, work: impl FnOnce() -> T) -> T {
+    let running = AtomicBool::new(true);
+    crossbeam::scope(|s| {
+        s.spawn(|_| {
+            let mut tracker = MotionTracker {
+                camera_name,
+                running: &running,
+            };
+            if let Err(e) = camera.listen_on_motion(&mut tracker) {
+                debug!("{}: Not tracking motion: {:?}", camera_name, e);
+            }
+        });
+        let result = work();
+        running.store(false, Ordering::Relaxed);
+        result
+    })
+    .expect("Motion thread panicked")
+}
+
+// Notes the motion of one camera until `running` is cleared
+struct MotionTracker<'a> {
+    camera_name: &'a str,
+    running: &'a AtomicBool,
+}
+
+impl<'a> MotionOutput for MotionTracker<'a> {
+    fn motion_recv(&mut self, motion_status: MotionStatus) -> MotionOutputError {
+        if let MotionStatus::Start(_) = motion_status {
+            note_motion(self.camera_name);
+        }
+        Ok(self.running.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_state() {
+        let times = parse_state(r#"{"Garage": 1760000000, "Porch": 1760000123}"#).unwrap();
+        assert_eq!(times.get("Porch"), Some(&1760000123));
+        assert_eq!(times.len(), 2);
+        assert!(parse_state(r#"{"Garage": "yesterday"}"#).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use anyhow::{anyhow, Result};
+use structopt::StructOpt;
+
+/// What to do with the floodlight
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LightMode {
+    /// Turn the light on
+    On,
+    /// Turn the light off, the camera may still turn it on by itself
+    Off,
+    /// Let the camera turn the light on by itself
+    Auto,
+}
+
+fn mode_parse(src: &str) -> Result<LightMode> {
+    match src {
+        "true" | "on" | "yes" => Ok(LightMode::On),
+        "false" | "off" | "no" => Ok(LightMode::Off),
+        "auto" => Ok(LightMode::Auto),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off, yes/no or auto",
+            src
+        )),
+    }
+}
+
+/// The light command will control the white floodlight/spotlight of the camera
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera to change the light of. Must be a name in the config
+    pub camera: String,
+    /// Whether to turn the light on or off, or let the camera turn it on by itself
+    #[structopt(parse(try_from_str = mode_parse), name = "on|off|auto")]
+    pub mode: LightMode,
+    /// Also set the brightness in percent
+    #[structopt(long)]
+    pub brightness: Option<u8>,
+    /// How many seconds to keep the light on for
+    #[structopt(long, default_value = "180")]
+    pub duration: u16,
+}
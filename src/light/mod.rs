@@ -0,0 +1,86 @@
+///
+/// # Neolink Light
+///
+/// This module handles the controls of the white floodlight, or spotlight,
+/// of the floodlight cameras
+///
+/// The subcommand attepts to set the floodlight not the IR lights or the
+/// status light. `off` turns the light off and leaves when the camera turns
+/// it on by itself alone, `auto` lets the camera do so with the motion and
+/// night settings it has.
+///
+/// # Usage
+///
+/// ```bash
+/// # To turn the light on for 10 minutes at half brightness
+/// neolink light --config=config.toml CameraName on --duration=600 --brightness=50
+/// # Or off
+/// neolink light --config=config.toml CameraName off
+/// # Or hand it back to the camera
+/// neolink light --config=config.toml CameraName auto
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::find_and_connect;
+use cmdline::LightMode;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the light subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera = find_and_connect(&config, &opt.camera)?;
+
+    let mut task = match camera.get_floodlight_task() {
+        Ok(task) => task,
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            return Err(anyhow!("{}: The camera has no floodlight", opt.camera))
+        }
+        Err(e) => return Err(e).context("Unable to get the floodlight settings"),
+    };
+
+    // The task that was read is sent back with the changes so that the
+    // camera keeps the rest of its settings
+    let mut changed = false;
+    if let Some(brightness) = opt.brightness {
+        let min = task.brightness_min.unwrap_or(1);
+        let max = task.brightness_max.unwrap_or(100);
+        if brightness < min || brightness > max {
+            return Err(anyhow!("The brightness must be from {} to {}", min, max));
+        }
+        changed |= task.brightness_cur != Some(brightness);
+        task.brightness_cur = Some(brightness);
+    }
+    if opt.mode == LightMode::Auto && task.enable != Some(1) {
+        changed = true;
+        task.enable = Some(1);
+    }
+    if changed {
+        camera
+            .set_floodlight_task(task)
+            .context("Unable to change the floodlight settings")?;
+    }
+
+    match opt.mode {
+        LightMode::On => {
+            info!(
+                "{}: Turning the floodlight on for {}s",
+                opt.camera, opt.duration
+            );
+            camera
+                .set_floodlight_manual(true, opt.duration)
+                .context("Unable to turn the floodlight on")?;
+        }
+        LightMode::Off | LightMode::Auto => {
+            camera
+                .set_floodlight_manual(false, 0)
+                .context("Unable to turn the floodlight off")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// The listen command will save the audio of the camera to a wav file
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera to listen to. Must be a name in the config
+    pub camera: String,
+    /// Save the audio to this wav file, use - to stream it to stdout
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: PathBuf,
+}
@@ -0,0 +1,242 @@
+///
+/// # Neolink Listen
+///
+/// This module handles the listen subcommand
+///
+/// The subcommand decodes the camera's microphone, ADPCM or AAC, and saves
+/// it as 16 bit mono wav until Ctrl-C is pressed. The wav header is then
+/// finished with the length of the audio.
+///
+/// When streaming to stdout the length is not known, so the header gives
+/// the largest length instead which most players treat as "until the end".
+///
+/// # Usage
+///
+/// ```bash
+/// neolink listen --config=config.toml CameraName --output=audio.wav
+/// # Or to stdout
+/// neolink listen --config=config.toml CameraName --output=- | aplay
+/// ```
+///
+use anyhow::{Context, Result};
+use log::*;
+use neolink_core::{
+    adpcm::SAMPLE_RATE,
+    bc_protocol::{Stream, StreamOutput, StreamOutputError, StreamOutputStatus},
+    bcmedia::model::*,
+};
+use std::io::{stdout, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+mod cmdline;
+
+use super::config::Config;
+use crate::decoder::AudioDecoder;
+use crate::utils::{connect_and_login, find_camera_by_name};
+pub(crate) use cmdline::Opt;
+
+// Give up if this many video keyframes arrive without any audio
+const KEYFRAMES_WITHOUT_AUDIO: usize = 3;
+
+/// Entry point for the listen subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera_config = find_camera_by_name(&config, &opt.camera)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_handler = stop.clone();
+    ctrlc::set_handler(move || stop_handler.store(true, Ordering::SeqCst))
+        .context("Unable to set the Ctrl-C handler")?;
+
+    // The wav is only made once the camera is there so a failed login leaves no empty file
+    let camera = connect_and_login(camera_config)?;
+    let to_stdout = opt.output == Path::new("-");
+    let wav: Box<dyn WavSink> = if to_stdout {
+        Box::new(StreamingWav::new(BufWriter::new(stdout()))?)
+    } else {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        Box::new(
+            hound::WavWriter::create(&opt.output, spec)
+                .with_context(|| format!("Unable to create {}", opt.output.display()))?,
+        )
+    };
+
+    let mut output = AudioOutput {
+        wav,
+        decoder: AudioDecoder::new(SAMPLE_RATE),
+        stop,
+        samples: 0,
+        keyframes: 0,
+        result: Ok(()),
+    };
+    info!("Listening to {}, press Ctrl-C to stop", camera_config.name);
+    let streamed = camera
+        .start_video(&mut output, Stream::Sub)
+        .context("Error while listening to the camera");
+
+    let AudioOutput {
+        wav,
+        samples,
+        result,
+        ..
+    } = output;
+    let finished = wav.finish().context("Unable to finish the wav file");
+    if !to_stdout {
+        info!(
+            "Saved {:.1}s of audio to {}",
+            samples as f64 / SAMPLE_RATE as f64,
+            opt.output.display()
+        );
+    }
+    result?;
+    finished?;
+    streamed
+}
+
+/// Decodes the camera's audio into the wav until stopped
+struct AudioOutput {
+    wav: Box<dyn WavSink>,
+    decoder: AudioDecoder,
+    stop: Arc<AtomicBool>,
+    samples: u64,
+    // Video keyframes seen before the first audio
+    keyframes: usize,
+    // The reason the audio stopped early
+    result: Result<()>,
+}
+
+impl StreamOutput for AudioOutput {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        if self.stop.load(Ordering::SeqCst) {
+            return Ok(StreamOutputStatus::Stop);
+        }
+        match media {
+            BcMedia::Adpcm(_) | BcMedia::Aac(_) => {
+                let samples = match self.decoder.decode(&media) {
+                    Ok(samples) => samples.unwrap_or_default(),
+                    Err(e) => {
+                        self.result = Err(e).context("Unable to decode the audio");
+                        return Ok(StreamOutputStatus::Stop);
+                    }
+                };
+                if let Err(e) = self.wav.write_samples(&samples) {
+                    self.result = Err(e).context("Unable to write the audio");
+                    return Ok(StreamOutputStatus::Stop);
+                }
+                self.samples += samples.len() as u64;
+            }
+            BcMedia::Iframe(_) if self.samples == 0 => {
+                self.keyframes += 1;
+                if self.keyframes >= KEYFRAMES_WITHOUT_AUDIO {
+                    warn!("The camera is not sending audio, check that its audio is enabled");
+                    return Ok(StreamOutputStatus::Stop);
+                }
+            }
+            _ => {}
+        }
+        Ok(StreamOutputStatus::Continue)
+    }
+}
+
+/// Where the samples are written, the wav header is finished by `finish`
+trait WavSink {
+    fn write_samples(&mut self, samples: &[i16]) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+impl<W: Write + std::io::Seek> WavSink for hound::WavWriter<W> {
+    fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        for &sample in samples {
+            self.write_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.finalize()?;
+        Ok(())
+    }
+}
+
+/// A wav that cannot seek back to write its length, such as stdout
+struct StreamingWav<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> StreamingWav<W> {
+    fn new(mut writer: W) -> Result<Self> {
+        let byte_rate = SAMPLE_RATE * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&u32::MAX.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&1u16.to_le_bytes())?; // Mono
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // Block align
+        writer.write_all(&16u16.to_le_bytes())?; // Bits per sample
+        writer.write_all(b"data")?;
+        // The largest whole number of samples, some readers refuse an odd length
+        writer.write_all(&(u32::MAX - 1).to_le_bytes())?;
+        Ok(StreamingWav { writer })
+    }
+}
+
+impl<W: Write> WavSink for StreamingWav<W> {
+    fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_wav() {
+        let mut bytes = vec![];
+        let mut wav = Box::new(StreamingWav::new(&mut bytes).unwrap());
+        wav.write_samples(&[0, 1, -1, i16::MAX]).unwrap();
+        wav.write_samples(&[i16::MIN]).unwrap();
+        wav.finish().unwrap();
+        assert_eq!(bytes.len(), 44 + 5 * 2);
+        assert_eq!(&bytes[4..8], &u32::MAX.to_le_bytes());
+        assert_eq!(&bytes[40..44], &(u32::MAX - 1).to_le_bytes());
+        assert_eq!(&bytes[44..46], &0i16.to_le_bytes());
+        assert_eq!(&bytes[52..54], &i16::MIN.to_le_bytes());
+
+        // Players read the header as 8kHz 16 bit mono and the samples after it
+        let reader = hound::WavReader::new(std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(
+            reader.spec(),
+            hound::WavSpec {
+                channels: 1,
+                sample_rate: SAMPLE_RATE,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            }
+        );
+        let samples: Vec<i16> = reader
+            .into_samples::<i16>()
+            .take(5)
+            .map(|sample| sample.unwrap())
+            .collect();
+        assert_eq!(samples, [0, 1, -1, i16::MAX, i16::MIN]);
+    }
+}
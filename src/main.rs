@@ -7,24 +7,45 @@
 //! It contains sub commands for running an rtsp proxy which can be used on Reolink cameras
 //! that do not nativly support RTSP.
 //!
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use env_logger::Env;
 use log::*;
-use std::fs;
 use structopt::StructOpt;
-use validator::Validate;
 
+mod audiotest;
+mod backup;
+mod clockdrift;
 mod cmdline;
 mod config;
+mod decoder;
+mod diag;
+mod discover;
+mod dryrun;
+mod encoder;
+mod intercom;
+mod keyframe;
+mod lastmotion;
+mod light;
+mod listen;
 mod pir;
+mod playback;
+mod privacy;
+mod quirks;
 mod reboot;
+mod record;
+mod recschedule;
 mod rtsp;
+mod sdcard;
+mod siren;
 mod statusled;
+mod synctime;
 mod talk;
+mod tap;
+mod timelapse;
 mod utils;
+mod versioninfo;
 
 use cmdline::{Command, Opt};
-use config::Config;
 
 fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
@@ -37,16 +58,43 @@ fn main() -> Result<()> {
 
     let opt = Opt::from_args();
 
+    // Discovery finds the cameras to put in a config so it runs without one
+    if let Some(Command::Discover(opts)) = &opt.cmd {
+        return discover::main(opts.clone());
+    }
+    // The audio test needs no camera
+    if let Some(Command::AudioTest(opts)) = &opt.cmd {
+        return audiotest::main(opts.clone());
+    }
+
     let conf_path = opt.config.context("Must supply --config file")?;
-    let config: Config = toml::from_str(
-        &fs::read_to_string(&conf_path)
-            .with_context(|| format!("Failed to read {:?}", conf_path))?,
-    )
-    .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
+    let cameras = opt.cameras.clone();
+    let config = config::load_config(&conf_path, &cameras)?;
+    // The long running subcommands read the config again on SIGHUP
+    let reload = || config::load_config(&conf_path, &cameras);
+
+    // Checking the config touches nothing else
+    if opt.check_config {
+        info!("The {:?} config file is valid", conf_path);
+        return Ok(());
+    }
+
+    utils::limit_concurrent_connects(config.max_concurrent_connects);
+    utils::set_read_only(opt.read_only || config.read_only);
+    lastmotion::persist_to(config.motion_state_file.as_deref());
+
+    if let Some(path) = &opt.bc_dump {
+        utils::dump_bc_to(path)?;
+    }
 
-    config
-        .validate()
-        .with_context(|| format!("Failed to validate the {:?} config file", conf_path))?;
+    if opt.dry_run {
+        // Sync time reports the clocks it would set instead
+        if let Some(Command::SyncTime(mut opts)) = opt.cmd {
+            opts.dry_run = true;
+            return synctime::main(opts, config);
+        }
+        return dryrun::main(config);
+    }
 
     match opt.cmd {
         None => {
@@ -54,10 +102,19 @@ fn main() -> Result<()> {
                 "Deprecated command line option. Please use: `neolink rtsp --config={:?}`",
                 config
             );
-            rtsp::main(rtsp::Opt {}, config)?;
+            rtsp::main(
+                rtsp::Opt {
+                    record: false,
+                    print_urls: false,
+                    show_secrets: false,
+                    tui: false,
+                },
+                config,
+                &reload,
+            )?;
         }
         Some(Command::Rtsp(opts)) => {
-            rtsp::main(opts, config)?;
+            rtsp::main(opts, config, &reload)?;
         }
         Some(Command::StatusLight(opts)) => {
             statusled::main(opts, config)?;
@@ -71,7 +128,74 @@ fn main() -> Result<()> {
         Some(Command::Talk(opts)) => {
             talk::main(opts, config)?;
         }
+        Some(Command::Record(opts)) => {
+            record::main(opts, config)?;
+        }
+        Some(Command::Playback(opts)) => {
+            playback::main(opts, config)?;
+        }
+        Some(Command::Sdcard(opts)) => {
+            sdcard::main(opts, config)?;
+        }
+        Some(Command::Intercom(opts)) => {
+            intercom::main(opts, config)?;
+        }
+        Some(Command::Listen(opts)) => {
+            listen::main(opts, config)?;
+        }
+        Some(Command::VersionInfo(opts)) => {
+            versioninfo::main(opts, config)?;
+        }
+        Some(Command::Tap(opts)) => {
+            tap::main(opts, config)?;
+        }
+        Some(Command::RecSchedule(opts)) => {
+            recschedule::main(opts, config)?;
+        }
+        Some(Command::Light(opts)) => {
+            light::main(opts, config)?;
+        }
+        Some(Command::Siren(opts)) => {
+            siren::main(opts, config)?;
+        }
+        Some(Command::Backup(opts)) => {
+            backup::main(opts, config)?;
+        }
+        Some(Command::Diag(opts)) => {
+            diag::main(opts, config)?;
+        }
+        Some(Command::Encoder(opts)) => {
+            encoder::main(opts, config)?;
+        }
+        Some(Command::SyncTime(opts)) => {
+            synctime::main(opts, config)?;
+        }
+        Some(Command::LastMotion(opts)) => {
+            lastmotion::main(opts, config)?;
+        }
+        Some(Command::Keyframe(opts)) => {
+            keyframe::main(opts, config)?;
+        }
+        Some(Command::Privacy(opts)) => {
+            privacy::main(opts, config)?;
+        }
+        Some(Command::Timelapse(opts)) => {
+            timelapse::main(opts, config)?;
+        }
+        Some(Command::ClockDrift(opts)) => {
+            clockdrift::main(opts, config)?;
+        }
+        Some(Command::Discover(_)) | Some(Command::AudioTest(_)) => {
+            unreachable!("Discovery and the audio test run before the config is loaded")
+        }
     }
 
+    let suppressed = utils::suppressed_changes();
+    if suppressed > 0 {
+        return Err(anyhow!(
+            "{} changes to the cameras were only logged in read only mode",
+            suppressed
+        ));
+    }
     Ok(())
 }
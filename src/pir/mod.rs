@@ -13,7 +13,7 @@
 /// neolink pir --config=config.toml CameraName off
 /// ```
 ///
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 
 mod cmdline;
 
@@ -27,8 +27,11 @@ pub(crate) use cmdline::Opt;
 pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
     let mut camera = find_and_connect(&config, &opt.camera)?;
 
-    camera
-        .pir_set(opt.on)
-        .context("Unable to set camera PIR state")?;
+    match camera.pir_set(opt.on) {
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            return Err(anyhow!("{}: The camera has no PIR sensor", opt.camera))
+        }
+        result => result.context("Unable to set camera PIR state")?,
+    }
     Ok(())
 }
@@ -0,0 +1,40 @@
+use crate::config::parse_stream;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use neolink_core::bc_protocol::Stream;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+fn time_parse(src: &str) -> Result<NaiveDateTime> {
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(src, format).ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not understand the time {}, it should be like \"2021-09-01 12:00:00\"",
+                src
+            )
+        })
+}
+
+/// The playback command will save recordings from the camera's SD card
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The start of the footage in local time e.g. "2021-09-01 12:00:00"
+    #[structopt(long, parse(try_from_str = time_parse))]
+    pub start: NaiveDateTime,
+    /// The end of the footage in local time
+    #[structopt(long, parse(try_from_str = time_parse))]
+    pub end: NaiveDateTime,
+    /// List the recordings in the time range instead of saving them
+    #[structopt(long)]
+    pub list: bool,
+    /// Save the video to this file as a raw H264 or H265 stream
+    #[structopt(short, long, parse(from_os_str), required_unless = "list")]
+    pub output: Option<PathBuf>,
+    /// The recorded stream to use, mainStream (main, clear) or subStream (sub, fluent)
+    #[structopt(long, default_value = "mainStream", parse(try_from_str = parse_stream))]
+    pub stream: Stream,
+}
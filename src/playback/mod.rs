@@ -0,0 +1,185 @@
+///
+/// # Neolink Playback
+///
+/// This module handles the playback subcommand
+///
+/// The subcommand lists or saves the recordings on the camera's SD card
+/// between two times. When the time range covers several recordings they
+/// are joined at their keyframes into a single video file.
+///
+/// # Usage
+///
+/// ```bash
+/// # To list the recordings
+/// neolink playback --config=config.toml --start="2021-09-01 12:00" --end="2021-09-01 13:00" --list CameraName
+/// # Or save them
+/// neolink playback --config=config.toml --start="2021-09-01 12:00" --end="2021-09-01 13:00" --output=clip.h264 CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Local, NaiveDateTime, TimeZone, Timelike};
+use log::*;
+use neolink_core::{
+    bc::xml::RecordTime,
+    bc_protocol::{Stream, StreamOutput, StreamOutputError, StreamOutputStatus},
+    bcmedia::model::*,
+};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::find_and_connect;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the playback subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    if opt.end <= opt.start {
+        return Err(anyhow!("The --end must be after the --start"));
+    }
+    let stream = opt.stream;
+    if stream == Stream::Extern {
+        return Err(anyhow!("Only the main and sub streams are recorded"));
+    }
+
+    let camera = find_and_connect(&config, &opt.camera)?;
+    let mut recordings =
+        match camera.list_recordings(stream, record_time(&opt.start), record_time(&opt.end)) {
+            Err(neolink_core::Error::Unsupported { .. }) => {
+                return Err(anyhow!(
+                    "{}: The camera has no recordings to play back",
+                    opt.camera
+                ))
+            }
+            result => result.context("Unable to list the recordings on the camera")?,
+        };
+    recordings.sort_by_key(|file| file.start_time.map(|t| naive_time(&t)));
+
+    if recordings.is_empty() {
+        return Err(anyhow!(
+            "{}: There are no recordings between {} and {}",
+            opt.camera,
+            opt.start,
+            opt.end
+        ));
+    }
+
+    if opt.list {
+        for file in &recordings {
+            let show = |time: Option<RecordTime>| {
+                time.and_then(|t| naive_time(&t))
+                    .map_or("unknown".to_string(), |t| t.to_string())
+            };
+            println!(
+                "{}  {}  {:>6.1} MB  {}",
+                show(file.start_time),
+                show(file.end_time),
+                file.size.unwrap_or(0) as f64 / 1_048_576.0,
+                file.name.as_deref().unwrap_or_default()
+            );
+        }
+        return Ok(());
+    }
+
+    let path = opt
+        .output
+        .as_ref()
+        .expect("Output is required without --list");
+    let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+    let mut output = PlaybackOutput {
+        writer: BufWriter::new(file),
+        start: local_timestamp(&opt.start)?,
+        end: local_timestamp(&opt.end)?,
+        started: false,
+        finished: false,
+    };
+
+    for file in &recordings {
+        let name = file.name.as_deref().unwrap_or_default();
+        info!("{}: Playing back {}", opt.camera, name);
+        camera
+            .start_playback(&mut output, name, stream)
+            .with_context(|| format!("Error while playing back {}", name))?;
+        if output.finished {
+            break;
+        }
+    }
+    output.writer.flush()?;
+    if !output.started {
+        return Err(anyhow!(
+            "{}: The recordings had no video in the time range",
+            opt.camera
+        ));
+    }
+    info!("{}: Saved the video to {:?}", opt.camera, path);
+    Ok(())
+}
+
+/// Writes the video between `start` and `end`
+///
+/// The video starts and ends at keyframes so that it can be decoded
+/// and so that recordings can be joined together
+struct PlaybackOutput {
+    writer: BufWriter<File>,
+    // POSIX times
+    start: i64,
+    end: i64,
+    started: bool,
+    finished: bool,
+}
+
+impl StreamOutput for PlaybackOutput {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        match media {
+            BcMedia::Iframe(payload) => {
+                if let Some(time) = payload.time.map(i64::from) {
+                    if time > self.end {
+                        self.finished = true;
+                        return Ok(StreamOutputStatus::Stop);
+                    }
+                    if time >= self.start {
+                        self.started = true;
+                    }
+                }
+                if self.started {
+                    self.writer.write_all(&payload.data)?;
+                }
+            }
+            BcMedia::Pframe(payload) if self.started => {
+                self.writer.write_all(&payload.data)?;
+            }
+            _ => {}
+        }
+        Ok(StreamOutputStatus::Continue)
+    }
+}
+
+fn record_time(time: &NaiveDateTime) -> RecordTime {
+    RecordTime {
+        year: time.year(),
+        month: time.month() as u8,
+        day: time.day() as u8,
+        hour: time.hour() as u8,
+        minute: time.minute() as u8,
+        second: time.second() as u8,
+    }
+}
+
+fn naive_time(time: &RecordTime) -> Option<NaiveDateTime> {
+    chrono::NaiveDate::from_ymd_opt(time.year, time.month.into(), time.day.into())?.and_hms_opt(
+        time.hour.into(),
+        time.minute.into(),
+        time.second.into(),
+    )
+}
+
+fn local_timestamp(time: &NaiveDateTime) -> Result<i64> {
+    Local
+        .from_local_datetime(time)
+        .earliest()
+        .map(|time| time.timestamp())
+        .ok_or_else(|| anyhow!("The time {} does not exist in the local time zone", time))
+}
@@ -0,0 +1,23 @@
+use anyhow::{anyhow, Result};
+use structopt::StructOpt;
+
+fn on_off_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The privacy command will turn the privacy mode of the camera on or off, or show it
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Whether to turn the privacy mode on or off. Shows it when not given
+    #[structopt(parse(try_from_str = on_off_parse), name = "on|off")]
+    pub on: Option<bool>,
+}
@@ -0,0 +1,67 @@
+///
+/// # Neolink Privacy
+///
+/// This module handles the privacy subcommand
+///
+/// The subcommand turns the privacy mode of the camera on or off, or shows
+/// whether it is on. While it is on the sensor of the camera is off and it
+/// may send no video.
+///
+/// # Usage
+///
+/// ```bash
+/// # To turn the privacy mode on
+/// neolink privacy --config=config.toml CameraName on
+/// # Or off
+/// neolink privacy --config=config.toml CameraName off
+/// # Or show it
+/// neolink privacy --config=config.toml CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc_protocol::BcCamera;
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::find_and_connect;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the privacy subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera = find_and_connect(&config, &opt.camera)?;
+    let unsupported = || anyhow!("{}: The camera has no privacy mode", opt.camera);
+
+    // Reading the privacy mode first tells if the camera has one
+    let mut on = match camera.get_privacy() {
+        Ok(on) => on,
+        Err(neolink_core::Error::Unsupported { .. }) => return Err(unsupported()),
+        Err(e) => return Err(e).context("Unable to get the privacy mode"),
+    };
+    if let Some(wanted) = opt.on {
+        camera
+            .set_privacy(wanted)
+            .context("Unable to set the privacy mode")?;
+        on = camera
+            .get_privacy()
+            .context("Unable to get the privacy mode")?;
+    }
+    println!("{}: Privacy mode {}", opt.camera, on_off(on));
+    Ok(())
+}
+
+/// Whether the privacy mode of `camera` is on
+///
+/// A camera without a privacy mode, or that can't tell, is taken to be off
+pub(crate) fn is_on(camera: &BcCamera) -> bool {
+    camera.get_privacy().unwrap_or(false)
+}
+
+fn on_off(on: bool) -> &'static str {
+    match on {
+        true => "on",
+        false => "off",
+    }
+}
@@ -0,0 +1,95 @@
+//! Handling that differs between camera models
+//!
+//! A quirks profile is a named set of behaviours. The profile of a camera is
+//! chosen with `quirks` in its `[[cameras]]` section, and the single options
+//! such as `keyframe_on_connect` override it.
+use log::*;
+
+use super::config::CameraConfig;
+
+/// The behaviours that can be changed for a camera model
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Quirks {
+    /// Ask the camera for a keyframe when an rtsp client starts playing
+    pub(crate) keyframe_on_connect: bool,
+    /// Keep the codec of the first video frame of a connection and drop the
+    /// frames that claim another codec while the camera switches over
+    pub(crate) fixed_codec: bool,
+}
+
+// Asking for a keyframe re-sends the preview start, which not every camera
+// takes well, so it is opted into with `keyframe_on_connect`
+const DEFAULT_QUIRKS: Quirks = Quirks {
+    keyframe_on_connect: false,
+    fixed_codec: false,
+};
+
+/// The profiles that can be named by `quirks` in the config
+static PROFILES: &[(&str, Quirks)] = &[
+    ("default", DEFAULT_QUIRKS),
+    (
+        "fixed_codec",
+        Quirks {
+            fixed_codec: true,
+            ..DEFAULT_QUIRKS
+        },
+    ),
+];
+
+/// The profile called `name`
+pub(crate) fn profile(name: &str) -> Option<Quirks> {
+    PROFILES
+        .iter()
+        .find(|(profile, _)| *profile == name)
+        .map(|(_, quirks)| *quirks)
+}
+
+impl Quirks {
+    /// The quirks of a camera from its config
+    pub(crate) fn for_camera(camera_config: &CameraConfig) -> Self {
+        let name = camera_config.quirks.as_deref();
+        if let Some(name) = name {
+            debug!("{}: Using the {} quirks profile", camera_config.name, name);
+        }
+        Self::resolve(
+            name,
+            camera_config.keyframe_on_connect,
+            camera_config.fixed_codec,
+        )
+    }
+
+    fn resolve(
+        name: Option<&str>,
+        keyframe_on_connect: Option<bool>,
+        fixed_codec: Option<bool>,
+    ) -> Self {
+        let quirks = name.and_then(profile).unwrap_or(DEFAULT_QUIRKS);
+        Quirks {
+            keyframe_on_connect: keyframe_on_connect.unwrap_or(quirks.keyframe_on_connect),
+            fixed_codec: fixed_codec.unwrap_or(quirks.fixed_codec),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_defaults() {
+        assert_eq!(Quirks::resolve(None, None, None), DEFAULT_QUIRKS);
+        assert_eq!(Quirks::resolve(Some("default"), None, None), DEFAULT_QUIRKS);
+    }
+
+    #[test]
+    fn test_config_overrides_profile() {
+        let quirks = Quirks::resolve(Some("fixed_codec"), Some(true), None);
+        assert_eq!(
+            quirks,
+            Quirks {
+                keyframe_on_connect: true,
+                fixed_codec: true
+            }
+        );
+    }
+}
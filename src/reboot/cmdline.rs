@@ -1,3 +1,4 @@
+use cron::Schedule;
 use structopt::StructOpt;
 
 /// The reboot command will reboot the camera
@@ -5,4 +6,11 @@ use structopt::StructOpt;
 pub struct Opt {
     /// The name of the camera to change the lights of. Must be a name in the config
     pub camera: String,
+    /// Keep running and reboot the camera on this cron schedule.
+    /// The schedule includes seconds e.g. "0 0 3 * * *" for 3am every day
+    #[structopt(long)]
+    pub schedule: Option<Schedule>,
+    /// How many seconds to wait for the camera to come back after the reboot
+    #[structopt(long, default_value = "120")]
+    pub wait: u64,
 }
@@ -4,6 +4,8 @@
 /// This module handles the reboot subcommand
 ///
 /// The subcommand attepts to reboot the camera.
+/// It then waits for the camera to drop the connection and
+/// come back, only succeeding once it can log in again.
 ///
 /// # Usage
 ///
@@ -11,22 +13,126 @@
 /// neolink reboot --config=config.toml CameraName
 /// ```
 ///
-use anyhow::{Context, Result};
+/// To reboot the camera every night at 3am keep neolink running with
+///
+/// ```bash
+/// neolink reboot --config=config.toml --schedule="0 0 3 * * *" CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use cron::Schedule;
+use log::*;
+use std::time::{Duration, Instant};
 
 mod cmdline;
 
-use super::config::Config;
-use crate::utils::find_and_connect;
+use super::config::{CameraConfig, Config};
+use crate::utils::{connect_and_login, find_camera_by_name, CameraStop};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the reboot subcommand
 ///
 /// Opt is the command line options
 pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
-    let camera = find_and_connect(&config, &opt.camera)?;
+    if let Some(schedule) = &opt.schedule {
+        return scheduled_reboot(&opt.camera, schedule, opt.wait, &config);
+    }
+
+    reboot(&opt.camera, opt.wait, &config)
+}
+
+fn reboot(camera_name: &str, wait: u64, config: &Config) -> Result<()> {
+    reboot_camera(find_camera_by_name(config, camera_name)?, wait, None)
+}
+
+/// Reboot the camera and wait up to `wait` seconds until it can log in again
+///
+/// The wait ends early, without an error, once `stop` is stopped
+pub(crate) fn reboot_camera(
+    camera_config: &CameraConfig,
+    wait: u64,
+    stop: Option<&CameraStop>,
+) -> Result<()> {
+    let camera_name = &camera_config.name;
+    let camera = connect_and_login(camera_config)?;
 
     camera
         .reboot()
         .context("Could not send reboot command to the camera")?;
+
+    let deadline = Instant::now() + Duration::from_secs(wait);
+    let poll_interval = Duration::from_secs(1);
+    // True once the camera is stopped
+    let pause = || match stop {
+        Some(stop) => stop.sleep(poll_interval),
+        None => {
+            std::thread::sleep(poll_interval);
+            false
+        }
+    };
+
+    // The camera ACKs the reboot before restarting, so first wait for it to go away
+    info!("{}: Waiting for the camera to restart", camera_name);
+    while camera.ping().is_ok() {
+        if Instant::now() >= deadline {
+            return Err(anyhow!(
+                "{}: Camera accepted the reboot but did not restart within {}s",
+                camera_name,
+                wait
+            ));
+        }
+        if pause() {
+            return Ok(());
+        }
+    }
+    drop(camera);
+
+    // Then wait for a fresh login to succeed
+    loop {
+        match connect_and_login(camera_config) {
+            Ok(_) => {
+                info!("{}: Camera rebooted successfully", camera_name);
+                return Ok(());
+            }
+            Err(e) if Instant::now() >= deadline => {
+                return Err(e.context(format!(
+                    "{}: Camera did not come back within {}s of the reboot",
+                    camera_name, wait
+                )));
+            }
+            Err(e) => debug!("{}: Camera is not back yet: {:?}", camera_name, e),
+        }
+        if pause() {
+            return Ok(());
+        }
+    }
+}
+
+fn scheduled_reboot(
+    camera_name: &str,
+    schedule: &Schedule,
+    wait: u64,
+    config: &Config,
+) -> Result<()> {
+    // Fail early on a bad camera name rather than at the first scheduled time
+    find_camera_by_name(config, camera_name)?;
+
+    for next in schedule.upcoming(Local) {
+        info!("{}: Next scheduled reboot at {}", camera_name, next);
+        if let Ok(wait) = (next - Local::now()).to_std() {
+            std::thread::sleep(wait);
+        }
+
+        info!("{}: Rebooting on schedule", camera_name);
+        if let Err(e) = reboot(camera_name, wait, config) {
+            warn!(
+                "{}: Scheduled reboot failed, will try again at the next time: {:?}",
+                camera_name, e
+            );
+        }
+    }
+
+    warn!("{}: The reboot schedule has no more times", camera_name);
     Ok(())
 }
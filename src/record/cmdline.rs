@@ -0,0 +1,9 @@
+use structopt::StructOpt;
+
+/// The record command will save the cameras to disk in segmented files
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The names of the cameras to record. All cameras in the config are recorded if none are given
+    #[structopt(name = "camera")]
+    pub cameras: Vec<String>,
+}
@@ -0,0 +1,278 @@
+///
+/// # Neolink Record
+///
+/// This module handles the record subcommand
+///
+/// Each camera is saved to disk in segmented ts, mkv or mp4 files without
+/// needing any rtsp client to be watching. The `[record]` section of the
+/// config controls where the files go, how long each segment is and how
+/// long they are kept.
+///
+/// Segments always begin on a keyframe so each file can be played on its own.
+///
+/// With `mode = "motion"` only clips around the camera's motion events are
+/// saved. The last `pre_roll_seconds` are kept in memory so each clip
+/// includes the moments before the motion, and the clip continues for
+/// `post_roll_seconds` after the motion stops. Cameras with AI detection
+/// can be limited to the motion of a person, vehicle or pet with `ai_types`.
+///
+/// With `mode = "motion_only"` the video is not even pulled from the camera
+/// between the clips. The connection only listens for the motion and starts
+/// the stream when it begins, which makes the camera send a keyframe first.
+/// There is no pre-roll, and the fraction of a second the camera takes to
+/// start the stream is missed from the start of each clip. The stream stops
+/// at the end of each clip. When the rtsp server records, its stream can't
+/// pause and `motion_only` records like `motion`.
+///
+/// # Usage
+///
+/// ```bash
+/// # Record all cameras
+/// neolink record --config=config.toml
+/// # Or only some of them
+/// neolink record --config=config.toml CameraName OtherCamera
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use neolink_core::bc_protocol::{AiType, BcCamera, Stream, StreamFanOut};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How often the motion is checked while the stream is paused
+const MOTION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+mod cmdline;
+mod output;
+
+use super::config::{stream_from_name, CameraConfig, Config, RecordConfig};
+use crate::utils::{
+    connect_and_login, find_camera_by_name, is_auth_failed, is_login_locked, live_output,
+    wait_for_login_lockout, CameraStop, GiveUp, Jitter,
+};
+pub(crate) use cmdline::Opt;
+use output::{MotionListener, MotionState, RecordOutput};
+
+/// Entry point for the record subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let record_config = config
+        .record
+        .as_ref()
+        .ok_or_else(|| anyhow!("The config file needs a [record] section to record"))?;
+
+    let cameras: Vec<&CameraConfig> = if opt.cameras.is_empty() {
+        config.cameras.iter().collect()
+    } else {
+        opt.cameras
+            .iter()
+            .map(|name| find_camera_by_name(&config, name))
+            .collect::<Result<_>>()?
+    };
+
+    gstreamer::init().context("Unable to start gstreamer")?;
+
+    // Each camera is only left once it gives up reconnecting or its login is refused
+    crossbeam::scope(|s| {
+        for (position, camera_config) in cameras.into_iter().enumerate() {
+            let delay = config.startup_delay(camera_config, position);
+            s.spawn(move |_| record_loop(camera_config, record_config, delay));
+        }
+    })
+    .unwrap();
+
+    Err(anyhow!("Every camera stopped recording"))
+}
+
+fn record_loop(camera_config: &CameraConfig, record_config: &RecordConfig, delay: Duration) {
+    if !delay.is_zero() {
+        info!(
+            "{}: Waiting {:.1}s before connecting",
+            camera_config.name,
+            delay.as_secs_f64()
+        );
+        std::thread::sleep(delay);
+    }
+    let min_backoff = Duration::from_secs(1);
+    let max_backoff = Duration::from_secs(15);
+    let mut current_backoff = min_backoff;
+    let mut jitter = Jitter::for_camera(camera_config, "record");
+    let mut give_up = GiveUp::for_camera(camera_config);
+
+    loop {
+        let mut connected = false;
+        let result = connect_and_login(camera_config).and_then(|camera| {
+            current_backoff = min_backoff;
+            connected = true;
+            record_video(
+                &camera,
+                camera_config,
+                record_config,
+                StreamFanOut::new(),
+                None,
+            )
+        });
+
+        if let Err(e) = &result {
+            if is_login_locked(e) {
+                wait_for_login_lockout(camera_config, &mut jitter, None);
+                continue;
+            }
+            // Like the rtsp streams a refused login is not retried, rather
+            // than counted towards the `max_reconnects`
+            if is_auth_failed(e) {
+                error!(
+                    "Authentication failed to camera {}, not recording: {:?}",
+                    camera_config.name, e
+                );
+                return;
+            }
+        }
+        if connected {
+            give_up.connected();
+        } else if give_up.failed(Instant::now()) {
+            let (attempts, duration) = give_up.failing(Instant::now());
+            error!(
+                "{}: Giving up on recording after {} attempts over {}s that could not connect: {:?}",
+                camera_config.name,
+                attempts,
+                duration.as_secs(),
+                result.err()
+            );
+            return;
+        }
+        let delay = jitter.apply(current_backoff);
+        if let Err(e) = result {
+            error!(
+                "Error recording camera {}, will retry in {:.1}s: {:?}",
+                camera_config.name,
+                delay.as_secs_f32(),
+                e
+            );
+        }
+        std::thread::sleep(delay);
+        current_backoff = std::cmp::min(max_backoff, current_backoff * 2);
+    }
+}
+
+/// The stream that the `[record]` config asks to record
+pub(crate) fn record_stream(record_config: &RecordConfig) -> Stream {
+    stream_from_name(&record_config.stream).expect("The record stream is validated")
+}
+
+/// Record the camera while also sending its video to the `outputs`
+///
+/// This blocks until the camera connection is dropped, `stop` is stopped or
+/// every output, including the recording, has stopped
+pub(crate) fn record_video(
+    camera: &BcCamera,
+    camera_config: &CameraConfig,
+    record_config: &RecordConfig,
+    mut outputs: StreamFanOut,
+    stop: Option<&CameraStop>,
+) -> Result<()> {
+    let stream = record_stream(record_config);
+    let dir = Path::new(&record_config.path).join(&camera_config.name);
+    let mut recording = RecordOutput::new(&camera_config.name, &dir, record_config);
+    info!("{}: Recording to {}", camera_config.name, dir.display());
+
+    // The rtsp clients of a shared stream need the video between the clips
+    let pauses = record_config.mode == "motion_only" && outputs.is_empty();
+    if record_config.mode == "motion_only" && !pauses {
+        warn!(
+            "{}: The stream is shared with rtsp so it is recorded as in motion mode",
+            camera_config.name
+        );
+    }
+
+    let result = if record_config.mode == "continuous" {
+        outputs.add("recording", recording);
+        camera
+            .start_video(
+                &mut live_output(camera_config, stream, stop, &mut outputs),
+                stream,
+            )
+            .with_context(|| format!("Error while recording {}", camera_config.name))
+    } else {
+        let motion = Arc::new(MotionState::default());
+        let ai_types: Vec<_> = record_config
+            .ai_types
+            .iter()
+            .filter_map(|name| AiType::from_name(name))
+            .collect();
+        recording.set_motion(
+            motion.clone(),
+            Duration::from_secs(record_config.pre_roll_seconds),
+            Duration::from_secs(record_config.post_roll_seconds),
+        );
+        recording.set_pause_between_clips(pauses);
+        crossbeam::scope(|s| {
+            let listener = s.spawn(|_| {
+                let result = camera
+                    .listen_on_motion(&mut MotionListener::new(
+                        motion.clone(),
+                        &camera_config.name,
+                        ai_types.clone(),
+                    ))
+                    .with_context(|| {
+                        format!("Error listening for motion on {}", camera_config.name)
+                    });
+                // Without motion events there is nothing to record
+                motion.stop();
+                result
+            });
+            let result = if pauses {
+                record_on_motion(camera, camera_config, stream, &motion, recording, stop)
+            } else {
+                outputs.add("recording", recording);
+                camera
+                    .start_video(
+                        &mut live_output(camera_config, stream, stop, &mut outputs),
+                        stream,
+                    )
+                    .map_err(anyhow::Error::from)
+            }
+            .with_context(|| format!("Error while recording {}", camera_config.name));
+            motion.stop();
+            listener.join().expect("Motion thread panicked")?;
+            result
+        })
+        .expect("Record thread panicked")
+    };
+
+    // Dropping the recording closes the current segment so that it can be played
+    result
+}
+
+/// Pull the video of the camera only while `motion` needs it for a clip
+fn record_on_motion(
+    camera: &BcCamera,
+    camera_config: &CameraConfig,
+    stream: Stream,
+    motion: &MotionState,
+    mut recording: RecordOutput,
+    stop: Option<&CameraStop>,
+) -> Result<()> {
+    let stopped = || motion.is_stopped() || stop.is_some_and(CameraStop::is_stopped);
+    loop {
+        while !motion.is_detected() {
+            if stopped() {
+                return Ok(());
+            }
+            std::thread::sleep(MOTION_POLL_INTERVAL);
+        }
+        debug!("{}: Starting the {:?} stream", camera_config.name, stream);
+        recording.resume();
+        camera.start_video(
+            &mut live_output(camera_config, stream, stop, &mut recording),
+            stream,
+        )?;
+        if stopped() {
+            return Ok(());
+        }
+        debug!("{}: Pausing the {:?} stream", camera_config.name, stream);
+        camera.stop_video(stream)?;
+    }
+}
@@ -0,0 +1,682 @@
+//! A [`StreamOutput`] that saves the camera's media to segmented files
+//!
+//! The media is muxed with gstreamer's splitmuxsink which starts a new file
+//! on the first keyframe after each segment length and interleaves the audio
+//! with the video.
+//!
+//! In motion mode the recent GOPs are kept in a pre-roll buffer and only
+//! written when the camera reports motion, so each clip begins a little
+//! before the motion and continues for the post-roll after it stops.
+use crate::config::RecordConfig;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use gstreamer::{
+    glib::ToValue, parse_launch, prelude::*, Buffer, ClockTime, ElementFactory, MessageView,
+    Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use lazy_static::lazy_static;
+use log::*;
+use neolink_core::{
+    adpcm::SAMPLE_RATE,
+    bc_protocol::{
+        AiType, MotionOutput, MotionOutputError, MotionStatus, StreamOutput, StreamOutputError,
+        StreamOutputStatus,
+    },
+    bcmedia::model::*,
+};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant, SystemTime};
+
+// Upper bound on the pre-roll buffer so a high bitrate stream can't use all the memory
+const PRE_ROLL_MAX_BYTES: usize = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AudioFormat {
+    Aac,
+    // The block size of the DVI-4 data
+    Adpcm(usize),
+}
+
+// Fragments of a fragmented mp4 are this many milliseconds long
+const MP4_FRAGMENT_MS: u32 = 1000;
+
+/// The container the segments are muxed into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Matroska,
+    Mp4,
+    // An mp4 written in fragments, which can be read before it is closed
+    FragmentedMp4,
+    MpegTs,
+}
+
+impl Container {
+    fn from_format(format: &str) -> Self {
+        match format {
+            "mkv" => Container::Matroska,
+            "mp4" => Container::Mp4,
+            "fmp4" => Container::FragmentedMp4,
+            _ => Container::MpegTs,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Container::Matroska => "mkv",
+            Container::Mp4 | Container::FragmentedMp4 => "mp4",
+            Container::MpegTs => "ts",
+        }
+    }
+
+    fn muxer(self) -> &'static str {
+        match self {
+            Container::Matroska => "matroskamux",
+            Container::Mp4 | Container::FragmentedMp4 => "mp4mux",
+            Container::MpegTs => "mpegtsmux",
+        }
+    }
+}
+
+struct Recording {
+    pipeline: Pipeline,
+    vidsrc: AppSrc,
+    audsrc: Option<AppSrc>,
+    // The arrival time of the first frame, buffers are timestamped from here
+    base: Instant,
+}
+
+enum Frame {
+    Video(Instant, Vec<u8>),
+    Audio(Instant, Vec<u8>),
+}
+
+impl Frame {
+    fn at(&self) -> Instant {
+        match self {
+            Frame::Video(at, _) | Frame::Audio(at, _) => *at,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Frame::Video(_, data) | Frame::Audio(_, data) => data.len(),
+        }
+    }
+}
+
+/// Shared between the motion listener and the recording
+#[derive(Default)]
+pub(crate) struct MotionState {
+    detected: AtomicBool,
+    stopped: AtomicBool,
+}
+
+impl MotionState {
+    /// Ask both the motion listener and the recording to stop
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Whether the camera reports motion that is recorded right now
+    pub(crate) fn is_detected(&self) -> bool {
+        self.detected.load(Ordering::Relaxed)
+    }
+}
+
+// Motion events without a detection type before it is taken that the camera has no AI detection
+const UNTYPED_MOTION_WARNING: u32 = 3;
+
+lazy_static! {
+    // The cameras already warned of filtering on AI types that they never report
+    static ref WARNED_UNTYPED: Mutex<HashSet<String>> = Default::default();
+}
+
+/// Passes the camera's motion events to the [`MotionState`]
+pub(crate) struct MotionListener {
+    state: Arc<MotionState>,
+    camera_name: String,
+    ai_types: Vec<AiType>,
+    // The motion events without any detection type, until one has one
+    untyped: Option<u32>,
+}
+
+impl MotionListener {
+    /// Only the motion in which the camera saw one of `ai_types` is passed
+    /// on, or all of it if they are empty
+    pub(crate) fn new(state: Arc<MotionState>, camera_name: &str, ai_types: Vec<AiType>) -> Self {
+        MotionListener {
+            state,
+            camera_name: camera_name.to_string(),
+            ai_types,
+            untyped: Some(0),
+        }
+    }
+
+    fn wanted(&self, detected: &[AiType]) -> bool {
+        self.ai_types.is_empty() || detected.iter().any(|t| self.ai_types.contains(t))
+    }
+
+    /// Whether the camera looks like it has no AI detection, after it sent
+    /// enough motion events that had no detection type in them
+    fn lacks_ai_detection(&mut self, detected: &[AiType]) -> bool {
+        if !detected.is_empty() {
+            self.untyped = None;
+        }
+        match &mut self.untyped {
+            Some(untyped) => {
+                *untyped += 1;
+                *untyped == UNTYPED_MOTION_WARNING
+            }
+            None => false,
+        }
+    }
+}
+
+impl MotionOutput for MotionListener {
+    fn motion_recv(&mut self, motion_status: MotionStatus) -> MotionOutputError {
+        if let MotionStatus::Start(detected) = &motion_status {
+            crate::lastmotion::note_motion(&self.camera_name);
+            if !self.ai_types.is_empty()
+                && self.lacks_ai_detection(detected)
+                && WARNED_UNTYPED
+                    .lock()
+                    .unwrap()
+                    .insert(self.camera_name.clone())
+            {
+                warn!(
+                    "{}: The camera has sent no AI detection type with its motion, \
+                     ai_types skips all of it unless the camera has AI detection",
+                    self.camera_name
+                );
+            }
+        }
+        match motion_status {
+            MotionStatus::Start(detected) if self.wanted(&detected) => {
+                debug!("{}: Motion of {:?}", self.camera_name, detected);
+                self.state.detected.store(true, Ordering::Relaxed)
+            }
+            MotionStatus::Start(detected) => {
+                debug!(
+                    "{}: Ignoring the motion of {:?}",
+                    self.camera_name, detected
+                );
+            }
+            MotionStatus::Stop => self.state.detected.store(false, Ordering::Relaxed),
+            MotionStatus::NoChange => {}
+        }
+        Ok(!self.state.stopped.load(Ordering::Relaxed))
+    }
+}
+
+struct MotionTrigger {
+    state: Arc<MotionState>,
+    pre_roll: Duration,
+    post_roll: Duration,
+    // Each entry is one GOP so that clips always start on a keyframe
+    buffer: VecDeque<Vec<Frame>>,
+    buffered_bytes: usize,
+    last_motion: Option<Instant>,
+}
+
+impl MotionTrigger {
+    fn active(&mut self) -> bool {
+        if self.state.detected.load(Ordering::Relaxed) {
+            self.last_motion = Some(Instant::now());
+        }
+        matches!(self.last_motion, Some(last) if last.elapsed() < self.post_roll)
+    }
+
+    fn buffer(&mut self, frame: Frame, keyframe: bool) {
+        if keyframe {
+            self.buffer.push_back(vec![]);
+        }
+        // Frames before the first keyframe can't be decoded in a clip
+        if let Some(gop) = self.buffer.back_mut() {
+            self.buffered_bytes += frame.len();
+            gop.push(frame);
+        }
+
+        // Keep just enough whole GOPs to cover the pre-roll. The GOP being
+        // received is always kept, even one larger than the most bytes held,
+        // so that a clip can start from its keyframe
+        let now = Instant::now();
+        while self.buffer.len() > 1 {
+            let next_start = self.buffer[1][0].at();
+            if now.duration_since(next_start) < self.pre_roll
+                && self.buffered_bytes <= PRE_ROLL_MAX_BYTES
+            {
+                break;
+            }
+            let dropped = self.buffer.pop_front().unwrap();
+            self.buffered_bytes -= dropped.iter().map(Frame::len).sum::<usize>();
+        }
+    }
+
+    fn take(&mut self) -> Vec<Frame> {
+        self.buffered_bytes = 0;
+        self.buffer.drain(..).flatten().collect()
+    }
+}
+
+pub(crate) struct RecordOutput {
+    camera_name: String,
+    dir: PathBuf,
+    segment: Duration,
+    retention: Option<Duration>,
+    container: Container,
+    // The formats seen while waiting for the first full GOP
+    video_type: Option<VideoType>,
+    audio_format: Option<AudioFormat>,
+    keyframes_seen: usize,
+    recording: Option<Recording>,
+    motion: Option<MotionTrigger>,
+    // Set to stop the stream at the end of each clip until it is resumed
+    pause_between_clips: bool,
+    paused: bool,
+}
+
+impl RecordOutput {
+    pub(crate) fn new(camera_name: &str, dir: &Path, config: &RecordConfig) -> Self {
+        RecordOutput {
+            camera_name: camera_name.to_string(),
+            dir: dir.to_path_buf(),
+            segment: Duration::from_secs(config.segment_minutes * 60),
+            retention: config
+                .retention_days
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+            container: Container::from_format(&config.format),
+            video_type: None,
+            audio_format: None,
+            keyframes_seen: 0,
+            recording: None,
+            motion: None,
+            pause_between_clips: false,
+            paused: false,
+        }
+    }
+
+    /// Only record around the motion reported to `state`
+    pub(crate) fn set_motion(
+        &mut self,
+        state: Arc<MotionState>,
+        pre_roll: Duration,
+        post_roll: Duration,
+    ) {
+        self.motion = Some(MotionTrigger {
+            state,
+            pre_roll,
+            post_roll,
+            buffer: Default::default(),
+            buffered_bytes: 0,
+            last_motion: None,
+        });
+    }
+
+    /// Stop the stream once each motion clip has finished
+    pub(crate) fn set_pause_between_clips(&mut self, pause: bool) {
+        self.pause_between_clips = pause;
+    }
+
+    /// Take the stream again after a pause, dropping what was buffered before it
+    pub(crate) fn resume(&mut self) {
+        self.paused = false;
+        if let Some(motion) = &mut self.motion {
+            motion.take();
+            // It resumes for the motion, which may have stopped again by the first frame
+            motion.last_motion = Some(Instant::now());
+        }
+    }
+
+    /// Close the current segment and stop recording
+    ///
+    /// The muxer must see the end of the stream to write a playable file
+    pub(crate) fn finish(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            let _ = recording.vidsrc.end_of_stream();
+            if let Some(audsrc) = &recording.audsrc {
+                let _ = audsrc.end_of_stream();
+            }
+            if let Some(bus) = recording.pipeline.bus() {
+                let _ = bus.timed_pop_filtered(
+                    ClockTime::from_seconds(5),
+                    &[gstreamer::MessageType::Eos, gstreamer::MessageType::Error],
+                );
+            }
+            let _ = recording.pipeline.set_state(State::Null);
+        }
+    }
+
+    fn start(&mut self, video_type: VideoType, base: Instant) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Unable to create {}", self.dir.display()))?;
+
+        let (video_caps, video_parse) = match video_type {
+            VideoType::H264 => ("video/x-h264", "h264parse"),
+            VideoType::H265 => ("video/x-h265", "h265parse"),
+        };
+        let mut launch = format!(
+            "splitmuxsink name=mux muxer-factory={} max-size-time={} \
+             appsrc name=vidsrc is-live=true block=true do-timestamp=false format=time caps={},stream-format=byte-stream \
+             ! {} ! queue ! mux.video",
+            self.container.muxer(),
+            self.segment.as_nanos(),
+            video_caps,
+            video_parse,
+        );
+        match self.audio_format {
+            Some(AudioFormat::Aac) => launch.push_str(
+                " appsrc name=audsrc is-live=true block=true do-timestamp=false format=time caps=audio/mpeg,mpegversion=4 \
+                 ! aacparse ! queue ! mux.audio_0",
+            ),
+            Some(AudioFormat::Adpcm(block_size)) => launch.push_str(&format!(
+                " appsrc name=audsrc is-live=true block=true do-timestamp=false format=time \
+                 caps=audio/x-adpcm,layout=dvi,block_align={},channels=1,rate={} \
+                 ! adpcmdec ! audioconvert ! audioresample ! avenc_aac ! aacparse ! queue ! mux.audio_0",
+                block_size, SAMPLE_RATE
+            )),
+            None => {}
+        }
+
+        let pipeline = parse_launch(&launch)
+            .context("Unable to load gstreamer recording pipeline ensure all gstramer plugins are installed")?
+            .dynamic_cast::<Pipeline>()
+            .map_err(|_| anyhow!("Unable to create gstreamer recording pipeline"))?;
+        let app_src = |name: &str| {
+            pipeline
+                .by_name(name)
+                .and_then(|e| e.dynamic_cast::<AppSrc>().ok())
+        };
+        let vidsrc =
+            app_src("vidsrc").ok_or_else(|| anyhow!("Recording pipeline has no appsrc"))?;
+        let audsrc = app_src("audsrc");
+
+        let mux = pipeline
+            .by_name("mux")
+            .ok_or_else(|| anyhow!("Recording pipeline has no muxer"))?;
+        if self.container == Container::FragmentedMp4 {
+            // The muxer of splitmuxsink can only be configured by handing it one
+            let muxer = ElementFactory::make(self.container.muxer(), None)
+                .context("Unable to create the mp4 muxer")?;
+            muxer
+                .set_property("fragment-duration", MP4_FRAGMENT_MS)
+                .context("Unable to fragment the mp4 muxer")?;
+            mux.set_property("muxer", &muxer)
+                .context("Unable to set the mp4 muxer")?;
+        }
+        let camera_name = self.camera_name.clone();
+        let dir = self.dir.clone();
+        let extension = self.container.extension();
+        let retention = self.retention;
+        mux.connect("format-location", false, move |_| {
+            if let Some(retention) = retention {
+                remove_old_segments(&dir, extension, retention);
+            }
+            let file_name = format!(
+                "{}_{}.{}",
+                camera_name,
+                Local::now().format("%Y-%m-%d_%H-%M-%S"),
+                extension
+            );
+            let location = dir.join(file_name);
+            info!("{}: Recording to {}", camera_name, location.display());
+            Some(location.to_string_lossy().to_value())
+        })
+        .context("Unable to name the recording segments")?;
+
+        pipeline
+            .set_state(State::Playing)
+            .context("Unable to start the recording pipeline")?;
+        self.recording = Some(Recording {
+            pipeline,
+            vidsrc,
+            audsrc,
+            base,
+        });
+        Ok(())
+    }
+
+    fn write(&self, frame: Frame) {
+        let recording = match &self.recording {
+            Some(recording) => recording,
+            None => return,
+        };
+        let pts = ClockTime::from_nseconds(
+            frame
+                .at()
+                .saturating_duration_since(recording.base)
+                .as_nanos() as u64,
+        );
+        let (app_src, data) = match frame {
+            Frame::Video(_, data) => (Some(&recording.vidsrc), data),
+            Frame::Audio(_, data) => (recording.audsrc.as_ref(), data),
+        };
+        if let Some(app_src) = app_src {
+            let mut buffer = Buffer::from_slice(data);
+            buffer
+                .get_mut()
+                .expect("A new buffer is writable")
+                .set_pts(pts);
+            // The pipeline reports its own errors on the bus
+            let _ = app_src.push_buffer(buffer);
+        }
+    }
+
+    fn handle(&mut self, frame: Frame, keyframe: bool) -> Result<()> {
+        // We must see one whole GOP to know the formats of the stream
+        let formats_known = self.keyframes_seen >= 2;
+        let wanted = match &mut self.motion {
+            Some(motion) => motion.active(),
+            None => true,
+        };
+
+        if self.recording.is_some() && !wanted && keyframe {
+            info!("{}: Motion clip finished", self.camera_name);
+            self.finish();
+        }
+        // Nothing more to record until the next motion
+        if self.pause_between_clips && self.recording.is_none() && !wanted {
+            self.paused = true;
+            return Ok(());
+        }
+
+        if self.recording.is_none() && wanted && formats_known {
+            let pre_roll = self.motion.as_mut().map(|m| m.take()).unwrap_or_default();
+            // Without a pre-roll the recording must wait for a keyframe
+            if !pre_roll.is_empty() || keyframe {
+                if self.motion.is_some() {
+                    info!("{}: Motion detected, recording a clip", self.camera_name);
+                }
+                let base = pre_roll.first().map_or_else(|| frame.at(), Frame::at);
+                self.start(self.video_type.expect("Formats are known"), base)?;
+                for buffered in pre_roll {
+                    self.write(buffered);
+                }
+            }
+        }
+
+        if self.recording.is_some() {
+            self.check_bus()?;
+            self.write(frame);
+        } else if let Some(motion) = &mut self.motion {
+            motion.buffer(frame, keyframe);
+        }
+        Ok(())
+    }
+
+    fn check_bus(&self) -> Result<()> {
+        if let Some(bus) = self.recording.as_ref().and_then(|r| r.pipeline.bus()) {
+            while let Some(msg) = bus.pop() {
+                if let MessageView::Error(err) = msg.view() {
+                    return Err(anyhow!("Recording failed: {}", err.error()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StreamOutput for RecordOutput {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        if matches!(&self.motion, Some(m) if m.state.stopped.load(Ordering::Relaxed)) {
+            return Ok(StreamOutputStatus::Stop);
+        }
+        let now = Instant::now();
+        let result = match media {
+            BcMedia::Iframe(payload) => {
+                self.keyframes_seen += 1;
+                self.video_type.get_or_insert(payload.video_type);
+                self.handle(Frame::Video(now, payload.data), true)
+            }
+            BcMedia::Pframe(payload) => self.handle(Frame::Video(now, payload.data), false),
+            BcMedia::Aac(payload) => {
+                self.audio_format.get_or_insert(AudioFormat::Aac);
+                self.handle(Frame::Audio(now, payload.data), false)
+            }
+            BcMedia::Adpcm(payload) => {
+                self.audio_format
+                    .get_or_insert(AudioFormat::Adpcm(payload.data.len()));
+                self.handle(Frame::Audio(now, payload.data), false)
+            }
+            _ => {
+                //Ignore other BcMedia like InfoV1 and InfoV2
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            error!("{}: {:?}", self.camera_name, e);
+            return Ok(StreamOutputStatus::Stop);
+        }
+        if self.paused {
+            return Ok(StreamOutputStatus::Stop);
+        }
+        Ok(StreamOutputStatus::Continue)
+    }
+}
+
+impl Drop for RecordOutput {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn remove_old_segments(dir: &Path, extension: &str, retention: Duration) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Unable to list old recordings in {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(extension) {
+            continue;
+        }
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map(|age| age > retention);
+        if expired == Some(true) {
+            info!("Removing old recording {}", path.display());
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Unable to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lacks_ai_detection() {
+        let listener = || MotionListener::new(Default::default(), "Garage", vec![AiType::Person]);
+        let mut untyped = listener();
+        let answers: Vec<bool> = (0..5).map(|_| untyped.lacks_ai_detection(&[])).collect();
+        assert_eq!(answers, [false, false, true, false, false]);
+
+        // One event with a type is enough to show that the camera has AI detection
+        let mut typed = listener();
+        assert!(!typed.lacks_ai_detection(&[]));
+        assert!(!typed.lacks_ai_detection(&[AiType::Vehicle]));
+        assert!((0..5).all(|_| !typed.lacks_ai_detection(&[])));
+    }
+
+    fn trigger(pre_roll: u64) -> MotionTrigger {
+        MotionTrigger {
+            state: Default::default(),
+            pre_roll: Duration::from_secs(pre_roll),
+            post_roll: Duration::from_secs(10),
+            buffer: Default::default(),
+            buffered_bytes: 0,
+            last_motion: None,
+        }
+    }
+
+    // A video frame of `len` bytes received `ago` seconds before now
+    fn frame(ago: u64, len: usize) -> Frame {
+        Frame::Video(Instant::now() - Duration::from_secs(ago), vec![0; len])
+    }
+
+    fn ages(frames: &[Frame]) -> Vec<u64> {
+        frames
+            .iter()
+            .map(|frame| frame.at().elapsed().as_secs())
+            .collect()
+    }
+
+    #[test]
+    fn test_pre_roll_keeps_whole_gops() {
+        let mut trigger = trigger(5);
+        // Frames before the first keyframe can't start a clip
+        trigger.buffer(frame(13, 10), false);
+        for (ago, keyframe) in [
+            (12, true),
+            (11, false),
+            (8, true),
+            (7, false),
+            (3, true),
+            (2, false),
+        ] {
+            trigger.buffer(frame(ago, 10), keyframe);
+        }
+        // The GOP from 8s ago is the latest start that still covers the 5s
+        assert_eq!(trigger.buffered_bytes, 40);
+        let frames = trigger.take();
+        assert_eq!(ages(&frames), [8, 7, 3, 2]);
+        assert_eq!(trigger.buffered_bytes, 0);
+        assert!(trigger.take().is_empty());
+    }
+
+    #[test]
+    fn test_pre_roll_keeps_the_last_gop_over_the_limit() {
+        let mut trigger = trigger(60);
+        let half = PRE_ROLL_MAX_BYTES / 2;
+        trigger.buffer(frame(3, half), true);
+        trigger.buffer(frame(2, half), false);
+        trigger.buffer(frame(1, 10), true);
+        // Over the limit the older GOPs go even within the pre-roll
+        assert_eq!(trigger.buffer.len(), 1);
+        assert_eq!(ages(&trigger.buffer[0]), [1]);
+
+        // A GOP that alone is over the limit is still kept whole
+        trigger.buffer(frame(0, PRE_ROLL_MAX_BYTES), false);
+        assert_eq!(trigger.buffer.len(), 1);
+        assert_eq!(trigger.buffered_bytes, PRE_ROLL_MAX_BYTES + 10);
+        assert_eq!(trigger.take().len(), 2);
+    }
+}
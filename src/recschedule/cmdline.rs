@@ -0,0 +1,17 @@
+use structopt::StructOpt;
+
+/// The rec-schedule command shows and sets the camera's SD card recording settings
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Give the camera the `sd_recording` settings of its config
+    #[structopt(long)]
+    pub apply: bool,
+    /// Turn recording to the SD card on or off [true, false]
+    #[structopt(long)]
+    pub enable: Option<bool>,
+    /// Overwrite the oldest recordings when the card is full [true, false]
+    #[structopt(long)]
+    pub overwrite: Option<bool>,
+}
@@ -0,0 +1,235 @@
+///
+/// # Neolink Rec Schedule
+///
+/// This module handles the rec-schedule subcommand
+///
+/// The subcommand shows whether the camera records to its SD card, if it
+/// overwrites the oldest recordings and the hours it records. It can also
+/// set them, either from the command line or from the camera's
+/// `sd_recording` config. The same config is given to the camera by
+/// `neolink rtsp` when it manages the camera.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink rec-schedule --config=config.toml CameraName
+/// # To apply the sd_recording section of the config
+/// neolink rec-schedule --config=config.toml --apply CameraName
+/// # To stop overwriting old recordings
+/// neolink rec-schedule --config=config.toml --overwrite=false CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+use log::*;
+use neolink_core::bc::xml::{xml_ver, Record, RecordCfg, Schedule, TimeBlock};
+use neolink_core::bc_protocol::BcCamera;
+
+mod cmdline;
+
+use super::config::{CameraConfig, Config, SdRecordingConfig, SdScheduleConfig, WEEK_DAYS};
+use crate::utils::{connect_and_login, find_camera_by_name};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the rec-schedule subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera_config = find_camera_by_name(&config, &opt.camera)?;
+    let camera = connect_and_login(camera_config)?;
+
+    let mut wanted = match opt.apply {
+        true => camera_config
+            .sd_recording
+            .clone()
+            .with_context(|| format!("{}: There is no sd_recording config to apply", opt.camera))?,
+        false => SdRecordingConfig {
+            enable: None,
+            overwrite: None,
+            schedule: None,
+        },
+    };
+    wanted.enable = opt.enable.or(wanted.enable);
+    wanted.overwrite = opt.overwrite.or(wanted.overwrite);
+    apply(&camera, &camera_config.name, &wanted)?;
+
+    match camera.get_record_cfg() {
+        Ok(record_cfg) => println!("{}: {}", opt.camera, describe_cfg(&record_cfg)),
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            println!("{}: Overwrite settings are unsupported", opt.camera)
+        }
+        Err(e) => return Err(e).context("Unable to get the recording settings"),
+    }
+    match camera.get_record() {
+        Ok(record) => {
+            for line in describe_record(&record) {
+                println!("{}: {}", opt.camera, line);
+            }
+        }
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            println!("{}: Recording schedules are unsupported", opt.camera)
+        }
+        Err(e) => return Err(e).context("Unable to get the recording schedule"),
+    }
+    Ok(())
+}
+
+/// Give the camera the `sd_recording` settings of its config, if it has any
+///
+/// A camera that has no such settings is reported as unsupported instead of failing
+pub(crate) fn manage(camera: &BcCamera, camera_config: &CameraConfig) -> Result<()> {
+    match &camera_config.sd_recording {
+        Some(wanted) => apply(camera, &camera_config.name, wanted),
+        None => Ok(()),
+    }
+}
+
+fn apply(camera: &BcCamera, name: &str, wanted: &SdRecordingConfig) -> Result<()> {
+    if let Some(overwrite) = wanted.overwrite {
+        match camera.get_record_cfg() {
+            Ok(mut record_cfg) => {
+                let cycle = overwrite as u8;
+                if record_cfg.cycle != cycle {
+                    info!("{}: Setting SD card overwrite to {}", name, overwrite);
+                    record_cfg.cycle = cycle;
+                    camera
+                        .set_record_cfg(record_cfg)
+                        .context("Unable to set the SD card overwrite")?;
+                }
+            }
+            Err(neolink_core::Error::Unsupported { .. }) => {
+                warn!(
+                    "{}: The camera does not support SD card overwrite settings",
+                    name
+                )
+            }
+            Err(e) => return Err(e).context("Unable to get the recording settings"),
+        }
+    }
+
+    if wanted.enable.is_some() || wanted.schedule.is_some() {
+        match camera.get_record() {
+            Ok(mut record) => {
+                let before = describe_record(&record);
+                if let Some(enable) = wanted.enable {
+                    record.enable = enable as u8;
+                }
+                if let Some(schedule) = &wanted.schedule {
+                    apply_schedule(&mut record, schedule);
+                }
+                if describe_record(&record) != before {
+                    info!("{}: Setting the SD card recording schedule", name);
+                    camera
+                        .set_record(record)
+                        .context("Unable to set the recording schedule")?;
+                }
+            }
+            Err(neolink_core::Error::Unsupported { .. }) => {
+                warn!("{}: The camera does not support recording schedules", name)
+            }
+            Err(e) => return Err(e).context("Unable to get the recording schedule"),
+        }
+    }
+    Ok(())
+}
+
+/// Replace the schedule of the `alarm_type` in `record` with one week day block per day
+fn apply_schedule(record: &mut Record, wanted: &SdScheduleConfig) {
+    let time_block = WEEK_DAYS
+        .iter()
+        .map(|day| TimeBlock {
+            enable: match &wanted.days {
+                Some(days) => days.iter().any(|d| d == day) as u8,
+                None => 1,
+            },
+            week_day: day.to_string(),
+            begin_hour: wanted.begin_hour,
+            end_hour: wanted.end_hour,
+        })
+        .collect();
+    let schedules = &mut record.schedule_list.schedule;
+    match schedules
+        .iter_mut()
+        .find(|s| s.alarm_type == wanted.alarm_type)
+    {
+        Some(schedule) => schedule.time_block_list.time_block = time_block,
+        None => {
+            let mut schedule = Schedule {
+                alarm_type: wanted.alarm_type.clone(),
+                ..Default::default()
+            };
+            schedule.time_block_list.time_block = time_block;
+            schedules.push(schedule);
+        }
+    }
+    if record.version.is_empty() {
+        record.version = xml_ver();
+    }
+}
+
+fn describe_cfg(record_cfg: &RecordCfg) -> String {
+    format!(
+        "overwrite {}",
+        match record_cfg.cycle {
+            0 => "off",
+            _ => "on",
+        }
+    )
+}
+
+fn describe_record(record: &Record) -> Vec<String> {
+    let mut lines = vec![format!(
+        "recording {}",
+        match record.enable {
+            0 => "disabled",
+            _ => "enabled",
+        }
+    )];
+    for schedule in &record.schedule_list.schedule {
+        let days: Vec<String> = schedule
+            .time_block_list
+            .time_block
+            .iter()
+            .filter(|block| block.enable != 0)
+            .map(|block| {
+                format!(
+                    "{} {:02}:00-{:02}:59",
+                    block.week_day, block.begin_hour, block.end_hour
+                )
+            })
+            .collect();
+        lines.push(match days.is_empty() {
+            true => format!("{} never", schedule.alarm_type),
+            false => format!("{} {}", schedule.alarm_type, days.join(", ")),
+        });
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_schedule() {
+        let mut record = Record::default();
+        let wanted = SdScheduleConfig {
+            alarm_type: "MD".to_string(),
+            days: Some(vec!["Saturday".to_string(), "Sunday".to_string()]),
+            begin_hour: 8,
+            end_hour: 17,
+        };
+        apply_schedule(&mut record, &wanted);
+        apply_schedule(&mut record, &wanted);
+
+        assert_eq!(record.schedule_list.schedule.len(), 1);
+        let blocks = &record.schedule_list.schedule[0].time_block_list.time_block;
+        assert_eq!(blocks.len(), 7);
+        let enabled: Vec<&str> = blocks
+            .iter()
+            .filter(|b| b.enable == 1)
+            .map(|b| b.week_day.as_str())
+            .collect();
+        assert_eq!(enabled, vec!["Sunday", "Saturday"]);
+        assert_eq!((blocks[0].begin_hour, blocks[0].end_hour), (8, 17));
+    }
+}
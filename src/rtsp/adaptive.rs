@@ -0,0 +1,304 @@
+//! Drops the main stream of a camera to the sub stream when the link can't keep up
+//!
+//! The times the camera stamps on its video frames are compared with the
+//! time they arrive. When the main stream falls more than `max_lag` seconds
+//! behind, or stalls, its mount is fed from the sub stream of the camera
+//! instead. The main stream is tried again after `upgrade_after` seconds.
+//! Each time it falls behind again soon after, the wait doubles up to
+//! `max_upgrade_after` seconds, so that a link that only fits the sub stream
+//! doesn't flap between them. `BcCamera` cannot change the stream of a
+//! running preview so each switch reconnects to the camera.
+//!
+//! The clients of the mount can't follow a change of codec, so if the sub
+//! stream turns out to use another codec than the main stream it goes back to
+//! the main stream and does not switch again.
+use log::*;
+use neolink_core::bc_protocol::Stream;
+use neolink_core::bcmedia::model::VideoType;
+use std::time::{Duration, Instant};
+
+use crate::config::AdaptiveStreamConfig;
+
+// How long the earliest arrival of the frames is remembered. Shorter than the
+// drift of the camera clock could add up to, longer than a link takes to fall behind
+const LAG_WINDOW: Duration = Duration::from_secs(30);
+
+// A jump in the frame times larger than this is a new clock, not a lag
+const MAX_FRAME_STEP: Duration = Duration::from_secs(10);
+
+/// How far the frames of one session arrive behind the times the camera gave them
+#[derive(Default)]
+struct LagMeter {
+    // When the first frame arrived and the media time since then
+    start: Option<Instant>,
+    media: Duration,
+    last_micros: u32,
+    // The least that the frames were behind in the last two windows
+    window_start: Option<Instant>,
+    least: Option<Duration>,
+    least_before: Option<Duration>,
+}
+
+impl LagMeter {
+    /// Note a frame stamped `micros` that arrived at `now`, returning its lag
+    fn frame(&mut self, micros: u32, now: Instant) -> Duration {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.start = Some(now);
+                self.last_micros = micros;
+                self.window_start = Some(now);
+                return Duration::ZERO;
+            }
+        };
+        let step = Duration::from_micros(u64::from(micros.wrapping_sub(self.last_micros)));
+        self.last_micros = micros;
+        if step > MAX_FRAME_STEP {
+            *self = LagMeter::default();
+            return self.frame(micros, now);
+        }
+        self.media += step;
+
+        let behind = now.duration_since(start).saturating_sub(self.media);
+        if matches!(self.window_start, Some(window_start) if now.duration_since(window_start) >= LAG_WINDOW)
+        {
+            self.least_before = self.least.take();
+            self.window_start = Some(now);
+        }
+        self.least = Some(self.least.map_or(behind, |least| least.min(behind)));
+        let least = match self.least_before {
+            Some(before) => before.min(self.least.unwrap_or(before)),
+            None => Duration::ZERO,
+        };
+        behind.saturating_sub(least)
+    }
+}
+
+/// Picks whether the main stream mount pulls the main or the sub stream
+pub(crate) struct AdaptiveStream {
+    camera: String,
+    max_lag: Duration,
+    min_upgrade_after: Duration,
+    max_upgrade_after: Duration,
+    upgrade_after: Duration,
+    // The stream to pull, and the one the current session pulls
+    wanted: Stream,
+    pulling: Stream,
+    // When the sub stream was switched to, and when main was last tried again
+    downgraded_at: Instant,
+    upgraded_at: Option<Instant>,
+    lag: LagMeter,
+    // The codec of the main stream, once a frame of it arrived
+    main_codec: Option<VideoType>,
+    // The sub stream has another codec so the main stream is kept
+    refused: bool,
+}
+
+impl AdaptiveStream {
+    pub(crate) fn new(camera: &str, config: &AdaptiveStreamConfig) -> Self {
+        let upgrade_after = Duration::from_secs(config.upgrade_after);
+        AdaptiveStream {
+            camera: camera.to_string(),
+            max_lag: Duration::try_from_secs_f64(config.max_lag).unwrap_or_default(),
+            min_upgrade_after: upgrade_after,
+            max_upgrade_after: Duration::from_secs(config.max_upgrade_after).max(upgrade_after),
+            upgrade_after,
+            wanted: Stream::Main,
+            pulling: Stream::Main,
+            downgraded_at: Instant::now(),
+            upgraded_at: None,
+            lag: LagMeter::default(),
+            main_codec: None,
+            refused: false,
+        }
+    }
+
+    /// The stream of the camera the next session should pull
+    pub(crate) fn wanted(&self) -> Stream {
+        self.wanted
+    }
+
+    /// Note that a session pulling `stream` is starting
+    pub(crate) fn session_started(&mut self, stream: Stream) {
+        self.pulling = stream;
+        self.lag = LagMeter::default();
+    }
+
+    /// Whether the current session pulls another stream than the one wanted
+    pub(crate) fn needs_switch(&self) -> bool {
+        self.wanted != self.pulling
+    }
+
+    /// Note a video frame in `codec` stamped `micros` arriving at `now`
+    ///
+    /// Returns true if the session should end to switch streams, the frame
+    /// is then not forwarded
+    pub(crate) fn frame(&mut self, codec: VideoType, micros: u32, now: Instant) -> bool {
+        match (self.pulling, self.main_codec) {
+            (Stream::Main, _) => self.main_codec = Some(codec),
+            (Stream::Sub, Some(main_codec)) if main_codec != codec => {
+                warn!(
+                    "{}: The sub stream is {:?} while the main stream is {:?}, staying on the \
+                     main stream as the rtsp clients can't change codec",
+                    self.camera, codec, main_codec
+                );
+                self.refused = true;
+                self.wanted = Stream::Main;
+            }
+            _ => {}
+        }
+        if self.needs_switch() {
+            return true;
+        }
+        let lag = self.lag.frame(micros, now);
+        match self.pulling {
+            Stream::Main if lag > self.max_lag && !self.refused => {
+                warn!(
+                    "{}: The main stream is {:.1}s behind the camera, switching to the sub stream",
+                    self.camera,
+                    lag.as_secs_f32()
+                );
+                self.downgrade(now);
+            }
+            Stream::Sub if now.duration_since(self.downgraded_at) >= self.upgrade_after => {
+                info!(
+                    "{}: Trying the main stream again after {}s on the sub stream",
+                    self.camera,
+                    self.upgrade_after.as_secs()
+                );
+                self.wanted = Stream::Main;
+                self.upgraded_at = Some(now);
+            }
+            _ => {}
+        }
+        self.needs_switch()
+    }
+
+    /// Note that the session pulling the main stream stalled or timed out at `now`
+    pub(crate) fn stalled(&mut self, now: Instant) {
+        if self.pulling == Stream::Main && self.wanted == Stream::Main && !self.refused {
+            warn!(
+                "{}: The main stream stalled, switching to the sub stream",
+                self.camera
+            );
+            self.downgrade(now);
+        }
+    }
+
+    fn downgrade(&mut self, now: Instant) {
+        // Falling behind soon after the main stream came back means the link still
+        // can't carry it, so it is given longer before the next try
+        self.upgrade_after = match self.upgraded_at {
+            Some(upgraded_at) if now.duration_since(upgraded_at) < self.upgrade_after => {
+                (self.upgrade_after * 2).min(self.max_upgrade_after)
+            }
+            _ => self.min_upgrade_after,
+        };
+        self.wanted = Stream::Sub;
+        self.downgraded_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AdaptiveStreamConfig {
+        AdaptiveStreamConfig {
+            max_lag: 3.0,
+            upgrade_after: 300,
+            max_upgrade_after: 1200,
+        }
+    }
+
+    // Feed 25 fps frames that arrive `slowdown` times slower than real time
+    fn feed(adaptive: &mut AdaptiveStream, start: Instant, seconds: u64, slowdown: f64) -> bool {
+        let frames = seconds * 25;
+        (0..frames).any(|n| {
+            let micros = (n * 40_000) as u32;
+            let arrival = start + Duration::from_secs_f64(n as f64 * 0.04 * slowdown);
+            adaptive.frame(VideoType::H264, micros, arrival)
+        })
+    }
+
+    #[test]
+    fn test_lag_meter_ignores_clock_drift() {
+        let start = Instant::now();
+        let mut meter = LagMeter::default();
+        let mut lag = Duration::ZERO;
+        // The camera clock runs 0.1% slow for an hour
+        for n in 0..(3600 * 25) {
+            let arrival = start + Duration::from_secs_f64(n as f64 * 0.04 * 1.001);
+            lag = meter.frame((n as u32).wrapping_mul(40_000), arrival);
+        }
+        assert!(lag < Duration::from_millis(100), "lag was {:?}", lag);
+    }
+
+    #[test]
+    fn test_switches_down_and_back_up() {
+        let start = Instant::now();
+        let mut adaptive = AdaptiveStream::new("Garage", &config());
+        adaptive.session_started(Stream::Main);
+        assert!(!feed(&mut adaptive, start, 60, 1.0));
+
+        // A link that carries 80% of the main stream falls 3s behind within 15s
+        assert!(feed(&mut adaptive, start, 30, 1.25));
+        assert_eq!(adaptive.wanted(), Stream::Sub);
+
+        adaptive.session_started(Stream::Sub);
+        let later = Instant::now();
+        assert!(!feed(&mut adaptive, later, 60, 1.0));
+        assert!(adaptive.frame(VideoType::H264, 0, later + Duration::from_secs(400)));
+        assert_eq!(adaptive.wanted(), Stream::Main);
+    }
+
+    #[test]
+    fn test_backs_off_when_main_falls_behind_again() {
+        let mut now = Instant::now();
+        let mut adaptive = AdaptiveStream::new("Garage", &config());
+        adaptive.session_started(Stream::Main);
+        adaptive.stalled(now);
+        assert_eq!(adaptive.upgrade_after, Duration::from_secs(300));
+
+        // Each retry of main that stalls straight away doubles the wait, up to the max
+        for wait in [600, 1200, 1200] {
+            adaptive.session_started(Stream::Sub);
+            now += adaptive.upgrade_after;
+            assert!(adaptive.frame(VideoType::H264, 0, now));
+            adaptive.session_started(Stream::Main);
+            now += Duration::from_secs(10);
+            adaptive.stalled(now);
+            assert_eq!(adaptive.upgrade_after, Duration::from_secs(wait));
+        }
+
+        // A main stream that held up starts the wait over
+        adaptive.session_started(Stream::Sub);
+        now += adaptive.upgrade_after;
+        assert!(adaptive.frame(VideoType::H264, 0, now));
+        adaptive.session_started(Stream::Main);
+        now += Duration::from_secs(5000);
+        adaptive.stalled(now);
+        assert_eq!(adaptive.upgrade_after, Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_refuses_a_sub_stream_of_another_codec() {
+        let start = Instant::now();
+        let mut adaptive = AdaptiveStream::new("Garage", &config());
+        adaptive.session_started(Stream::Main);
+        assert!(!adaptive.frame(VideoType::H265, 0, start));
+        adaptive.stalled(start);
+        assert_eq!(adaptive.wanted(), Stream::Sub);
+
+        // The first frame of the sub stream ends the session before it is forwarded
+        adaptive.session_started(Stream::Sub);
+        assert!(adaptive.frame(VideoType::H264, 0, start));
+        assert_eq!(adaptive.wanted(), Stream::Main);
+
+        // And the main stream is kept from then on
+        adaptive.session_started(Stream::Main);
+        adaptive.stalled(start);
+        assert!(!feed(&mut adaptive, start, 30, 1.25));
+        assert_eq!(adaptive.wanted(), Stream::Main);
+    }
+}
@@ -0,0 +1,146 @@
+//! Rewrites the rtsp responses to give the clients an advertised host
+//!
+//! Behind NAT, docker or a reverse proxy the address the server was reached
+//! at is not one the clients can follow. With `advertised_host` the host of
+//! each rtsp url in the `Content-Base`, `Content-Location` and `RTP-Info`
+//! headers of the responses is replaced, along with the unicast addresses of
+//! the SDP, just before they are sent. The ports and paths are kept.
+use std::net::IpAddr;
+
+/// The headers of a response that hold the urls of the stream
+pub(crate) const URL_HEADERS: &[&str] = &["Content-Base", "Content-Location", "RTP-Info"];
+
+/// Replace the host of every rtsp url in `text` with `host`
+pub(crate) fn advertise_urls(text: &str, host: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("://") {
+        let (before, after) = rest.split_at(start + 3);
+        out.push_str(before);
+        rest = after;
+        if !before.ends_with("rtsp://") && !before.ends_with("rtsps://") {
+            continue;
+        }
+        let end = rest
+            .find(&['/', ';', ',', ' ', '\r', '\n'][..])
+            .unwrap_or(rest.len());
+        let (authority, after) = rest.split_at(end);
+        let (user, host_port) = match authority.rfind('@') {
+            Some(at) => authority.split_at(at + 1),
+            None => ("", authority),
+        };
+        // The port follows the brackets of an IPv6 host
+        let port = match host_port.rfind(']') {
+            Some(bracket) => &host_port[bracket + 1..],
+            None => host_port.rfind(':').map_or("", |colon| &host_port[colon..]),
+        };
+        out.push_str(user);
+        out.push_str(&url_host(host));
+        out.push_str(port);
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Replace the unicast addresses of the origin and connections of `sdp` with `host`
+///
+/// The unspecified and multicast addresses are left as they are, the clients
+/// need those as the server sent them
+pub(crate) fn advertise_sdp(sdp: &str, host: &str) -> String {
+    let address_type = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => Some("IP6"),
+        Ok(IpAddr::V4(_)) => Some("IP4"),
+        Err(_) => None,
+    };
+    let lines: Vec<String> = sdp
+        .split_inclusive('\n')
+        .map(|line| {
+            if !line.starts_with("o=") && !line.starts_with("c=") {
+                return line.to_string();
+            }
+            let content = line.trim_end_matches(&['\r', '\n'][..]);
+            let ending = &line[content.len()..];
+            let mut fields: Vec<&str> = content.split(' ').collect();
+            if fields.len() < 3 {
+                return line.to_string();
+            }
+            let address = fields[fields.len() - 1];
+            let unicast = match address.parse::<IpAddr>() {
+                Ok(ip) => !ip.is_unspecified() && !ip.is_multicast(),
+                // A multicast address has its ttl after a slash
+                Err(_) => !address.contains('/'),
+            };
+            if !unicast {
+                return line.to_string();
+            }
+            let last = fields.len() - 1;
+            fields[last] = host;
+            if let Some(address_type) = address_type {
+                fields[last - 1] = address_type;
+            }
+            format!("{}{}", fields.join(" "), ending)
+        })
+        .collect();
+    advertise_urls(&lines.concat(), host)
+}
+
+// The host as it goes in a url, with an IPv6 address in brackets
+fn url_host(host: &str) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+        _ => host.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertise_urls() {
+        assert_eq!(
+            advertise_urls("rtsp://172.17.0.2:8554/Garage/", "nvr.example.com"),
+            "rtsp://nvr.example.com:8554/Garage/"
+        );
+        assert_eq!(
+            advertise_urls(
+                "url=rtsp://172.17.0.2:8554/Garage/stream=0;seq=1;rtptime=2,\
+                 url=rtsps://admin@[fd00::2]:8554/Garage/stream=1;seq=3",
+                "fd00::1"
+            ),
+            "url=rtsp://[fd00::1]:8554/Garage/stream=0;seq=1;rtptime=2,\
+             url=rtsps://admin@[fd00::1]:8554/Garage/stream=1;seq=3"
+        );
+        assert_eq!(
+            advertise_urls("http://172.17.0.2/", "nvr.example.com"),
+            "http://172.17.0.2/"
+        );
+    }
+
+    #[test]
+    fn test_advertise_sdp() {
+        let sdp = "v=0\r\n\
+                   o=- 1188340656180883 1 IN IP4 172.17.0.2\r\n\
+                   s=Session streamed with GStreamer\r\n\
+                   m=video 0 RTP/AVP 96\r\n\
+                   c=IN IP4 0.0.0.0\r\n\
+                   a=control:rtsp://172.17.0.2:8554/Garage/stream=0\r\n\
+                   m=audio 5000 RTP/AVP 97\r\n\
+                   c=IN IP4 224.1.2.3/15\r\n";
+        assert_eq!(
+            advertise_sdp(sdp, "192.168.1.10"),
+            "v=0\r\n\
+             o=- 1188340656180883 1 IN IP4 192.168.1.10\r\n\
+             s=Session streamed with GStreamer\r\n\
+             m=video 0 RTP/AVP 96\r\n\
+             c=IN IP4 0.0.0.0\r\n\
+             a=control:rtsp://192.168.1.10:8554/Garage/stream=0\r\n\
+             m=audio 5000 RTP/AVP 97\r\n\
+             c=IN IP4 224.1.2.3/15\r\n"
+        );
+        assert!(advertise_sdp(sdp, "fd00::1").contains("o=- 1188340656180883 1 IN IP6 fd00::1\r\n"));
+        assert!(advertise_sdp(sdp, "nvr.example.com")
+            .contains("o=- 1188340656180883 1 IN IP4 nvr.example.com\r\n"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Alerts when a camera has not streamed for too long
+//!
+//! A camera whose login fails stops retrying and one that can't be reached
+//! keeps reconnecting, neither of which is noticed without reading the logs.
+//! Once the streams of a camera have been failing for the `after_seconds` of
+//! its `stream_alert` without any of them streaming, an error with the
+//! `NEOLINK ALERT` marker is logged and, with a `url`, posted to it as json.
+//! The camera streaming again is reported the same way.
+use chrono::Local;
+use log::*;
+use serde::Serialize;
+use std::time::Duration;
+
+use super::metrics::Metrics;
+use super::motion_snapshot::http_post;
+use crate::config::StreamAlertConfig;
+use crate::utils::CameraStop;
+
+// How often the streams of the camera are checked
+const ALERT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Starts the log lines of the alerts so they can be matched on
+const ALERT_MARKER: &str = "NEOLINK ALERT";
+
+#[derive(Serialize)]
+struct Alert<'a> {
+    camera: &'a str,
+    streaming: bool,
+    failing_seconds: u64,
+    last_error: Option<&'a str>,
+    time: String,
+}
+
+/// Alert about `camera` whenever it has not streamed for too long until it is stopped
+pub(crate) fn watch(
+    camera: &str,
+    config: &StreamAlertConfig,
+    metrics: &Metrics,
+    stop: &CameraStop,
+) {
+    let mut outage = Outage::new(Duration::from_secs(config.after_seconds));
+    while !stop.sleep(ALERT_POLL_INTERVAL) {
+        let failing = metrics.outage(camera);
+        let streaming = match outage.check(failing.as_ref().map(|(since, _)| *since)) {
+            Some(streaming) => streaming,
+            None => continue,
+        };
+        let (failing_seconds, last_error) = match &failing {
+            Some((since, last_error)) => (since.as_secs(), last_error.as_deref()),
+            None => (0, None),
+        };
+        if streaming {
+            info!("{}: {}: Streaming again", ALERT_MARKER, camera);
+        } else {
+            error!(
+                "{}: {}: Not streamed for {}s: {}",
+                ALERT_MARKER,
+                camera,
+                failing_seconds,
+                last_error.unwrap_or("no error")
+            );
+        }
+        if let Some(url) = &config.url {
+            let alert = Alert {
+                camera,
+                streaming,
+                failing_seconds,
+                last_error,
+                time: Local::now().to_rfc3339(),
+            };
+            let body = serde_json::to_vec(&alert).expect("The alert is always valid json");
+            if let Err(e) = http_post(url, "application/json", &[], &body) {
+                warn!("{}: Failed to post the stream alert: {:?}", camera, e);
+            }
+        }
+    }
+}
+
+/// Decides when an outage of a camera is alerted and when it is over
+struct Outage {
+    after: Duration,
+    alerted: bool,
+}
+
+impl Outage {
+    fn new(after: Duration) -> Self {
+        Outage {
+            after,
+            alerted: false,
+        }
+    }
+
+    /// Note how long the camera has been `failing`, if it is
+    ///
+    /// Returns `Some(false)` when it should be alerted and `Some(true)` when it
+    /// streams again after an alert
+    fn check(&mut self, failing: Option<Duration>) -> Option<bool> {
+        match (failing, self.alerted) {
+            (Some(failing), false) if failing >= self.after => {
+                self.alerted = true;
+                Some(false)
+            }
+            (None, true) => {
+                self.alerted = false;
+                Some(true)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outage() {
+        let mut outage = Outage::new(Duration::from_secs(600));
+        assert_eq!(outage.check(None), None);
+        assert_eq!(outage.check(Some(Duration::from_secs(300))), None);
+        assert_eq!(outage.check(Some(Duration::from_secs(600))), Some(false));
+        // Alerted once for each outage
+        assert_eq!(outage.check(Some(Duration::from_secs(900))), None);
+        assert_eq!(outage.check(None), Some(true));
+        assert_eq!(outage.check(None), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! Reboots a camera that keeps reconnecting
+//!
+//! A camera that has reconnected many times in a short while is usually
+//! wedged and only a reboot fixes it. The full reconnects of the stream that
+//! manages the camera are counted, and once `reconnects` of them fall within
+//! the window the camera is rebooted, at most once per cooldown. Sessions the
+//! camera closed itself and that were resumed are not counted.
+use crate::config::AutoRebootConfig;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub(crate) struct AutoReboot {
+    reconnects: usize,
+    window: Duration,
+    cooldown: Duration,
+    recent: VecDeque<Instant>,
+    last_reboot: Option<Instant>,
+}
+
+impl AutoReboot {
+    pub(crate) fn new(config: &AutoRebootConfig) -> Self {
+        AutoReboot {
+            reconnects: config.reconnects,
+            window: Duration::from_secs(config.window_minutes * 60),
+            cooldown: Duration::from_secs(config.cooldown_minutes * 60),
+            recent: VecDeque::new(),
+            last_reboot: None,
+        }
+    }
+
+    /// Count a reconnect at `now`, true if the camera should be rebooted for it
+    pub(crate) fn reconnect(&mut self, now: Instant) -> bool {
+        self.recent.push_back(now);
+        while matches!(self.recent.front(), Some(&first) if now.duration_since(first) > self.window)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() < self.reconnects {
+            return false;
+        }
+        if matches!(self.last_reboot, Some(last) if now.duration_since(last) < self.cooldown) {
+            return false;
+        }
+        self.last_reboot = Some(now);
+        // The reconnects after the reboot start a new count
+        self.recent.clear();
+        true
+    }
+
+    /// How many reconnects and minutes reboot the camera, for the log
+    pub(crate) fn limit(&self) -> (usize, u64) {
+        (self.reconnects, self.window.as_secs() / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auto_reboot() -> AutoReboot {
+        AutoReboot::new(&AutoRebootConfig {
+            reconnects: 3,
+            window_minutes: 10,
+            cooldown_minutes: 60,
+        })
+    }
+
+    #[test]
+    fn test_reboots_after_reconnects_in_window() {
+        let mut auto_reboot = auto_reboot();
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+        assert!(!auto_reboot.reconnect(minutes(0)));
+        assert!(!auto_reboot.reconnect(minutes(8)));
+        // The first reconnect has left the window
+        assert!(!auto_reboot.reconnect(minutes(12)));
+        assert!(auto_reboot.reconnect(minutes(13)));
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let mut auto_reboot = auto_reboot();
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+        assert!(!auto_reboot.reconnect(minutes(0)));
+        assert!(!auto_reboot.reconnect(minutes(1)));
+        assert!(auto_reboot.reconnect(minutes(2)));
+        for m in 3..6 {
+            assert!(!auto_reboot.reconnect(minutes(m)));
+        }
+        // Once the cooldown is over a new run of reconnects reboots again
+        assert!(!auto_reboot.reconnect(minutes(61)));
+        assert!(!auto_reboot.reconnect(minutes(62)));
+        assert!(auto_reboot.reconnect(minutes(63)));
+    }
+}
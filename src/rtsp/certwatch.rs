@@ -0,0 +1,88 @@
+//! Reloads the TLS certificate of the rtsp server whenever
+//! the certificate file changes on disk
+//!
+//! This allows certificate renewals (such as Let's Encrypt) to
+//! take effect on the next handshake without restarting neolink
+//! and dropping the existing streams.
+use super::gst::RtspServer;
+use crate::utils::CameraStop;
+use log::*;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+// How often the watch checks whether the server is shutting down
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watch the certificate at `cert_path` and reload it into the `rtsp` servers when it is changed
+///
+/// The parent directory is watched rather than the file itself because
+/// renewal tools commonly replace the file (or the symlink to it) instead of
+/// writing to it in place. The watch ends once `shutdown` is stopped.
+pub(crate) fn watch_certificate(cert_path: &str, rtsp: &[&RtspServer], shutdown: &CameraStop) {
+    if let Err(e) = watch_certificate_inner(cert_path, rtsp, shutdown) {
+        error!(
+            "Unable to watch TLS certificate {} for changes: {:?}",
+            cert_path, e
+        );
+    }
+}
+
+fn watch_certificate_inner(
+    cert_path: &str,
+    rtsp: &[&RtspServer],
+    shutdown: &CameraStop,
+) -> anyhow::Result<()> {
+    let cert_file = Path::new(cert_path);
+    let cert_file = cert_file
+        .canonicalize()
+        .unwrap_or_else(|_| cert_file.to_path_buf());
+    let watch_dir = cert_file
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let (tx, rx) = channel();
+    let mut watcher = watcher(tx, Duration::from_secs(2))?;
+    watcher.watch(&watch_dir, RecursiveMode::NonRecursive)?;
+    debug!("Watching {} for TLS certificate changes", cert_path);
+
+    while !shutdown.is_stopped() {
+        let event = match rx.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(e @ RecvTimeoutError::Disconnected) => return Err(e.into()),
+        };
+        let changed = match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path)
+            | DebouncedEvent::Rename(_, path) => is_cert_file(&path, &cert_file),
+            DebouncedEvent::Rescan => true,
+            DebouncedEvent::Error(e, _) => {
+                warn!("Error while watching TLS certificate: {:?}", e);
+                false
+            }
+            _ => false,
+        };
+
+        if changed {
+            match rtsp
+                .iter()
+                .try_for_each(|server| server.reload_tls(cert_path))
+            {
+                Ok(()) => info!("Reloaded TLS certificate from {}", cert_path),
+                Err(e) => warn!(
+                    "Keeping the current TLS certificate, failed to reload {}: {:?}",
+                    cert_path, e
+                ),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_cert_file(path: &Path, cert_file: &Path) -> bool {
+    path == cert_file || matches!(path.canonicalize(), Ok(p) if p == cert_file)
+}
@@ -2,4 +2,18 @@ use structopt::StructOpt;
 
 /// The rtsp command will serve all cameras in the config over the rtsp protocol
 #[derive(StructOpt, Debug)]
-pub struct Opt {}
+pub struct Opt {
+    /// Also record the cameras using the `[record]` config. The recording
+    /// shares the camera connection of the rtsp stream
+    #[structopt(long)]
+    pub record: bool,
+    /// Print the url of each stream of the cameras and exit
+    #[structopt(long = "print-rtsp-urls")]
+    pub print_urls: bool,
+    /// Show the passwords in the printed urls instead of masking them
+    #[structopt(long)]
+    pub show_secrets: bool,
+    /// Show a live table of the camera streams in the terminal instead of the logs
+    #[structopt(long)]
+    pub tui: bool,
+}
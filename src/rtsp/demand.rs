@@ -0,0 +1,46 @@
+//! Shares one camera connection between the streams of a camera with a `default_stream`
+//!
+//! The default stream is pulled until an rtsp client plays one of the other
+//! streams. That stream is then pulled instead until its clients leave.
+//! `BcCamera` cannot change the stream of a running preview so each switch
+//! reconnects to the camera.
+use std::sync::{Condvar, Mutex};
+
+#[derive(Default)]
+pub(crate) struct StreamDemand {
+    // The number of other streams that are currently being pulled
+    active: Mutex<usize>,
+    changed: Condvar,
+}
+
+impl StreamDemand {
+    /// Mark another stream as pulled until the guard is dropped
+    pub(crate) fn begin(&self) -> DemandGuard<'_> {
+        *self.active.lock().unwrap() += 1;
+        self.changed.notify_all();
+        DemandGuard(self)
+    }
+
+    /// Whether the default stream should give way to another stream
+    pub(crate) fn is_upgraded(&self) -> bool {
+        *self.active.lock().unwrap() > 0
+    }
+
+    /// Block until no other stream is being pulled
+    pub(crate) fn wait_for_default(&self) {
+        let active = self.active.lock().unwrap();
+        let _active = self
+            .changed
+            .wait_while(active, |active| *active > 0)
+            .unwrap();
+    }
+}
+
+pub(crate) struct DemandGuard<'a>(&'a StreamDemand);
+
+impl<'a> Drop for DemandGuard<'a> {
+    fn drop(&mut self) {
+        *self.0.active.lock().unwrap() -= 1;
+        self.0.changed.notify_all();
+    }
+}
@@ -0,0 +1,137 @@
+//! Sorts the errors that end a camera session into kinds of failure
+//!
+//! The kind picks the wording of the log, the reconnect delay and which of
+//! the `/metrics` failure counters goes up. It comes from the first
+//! `neolink_core::Error` in the error chain and whether the camera had
+//! connected when it happened.
+use crate::utils::STREAM_STALLED;
+use neolink_core::Error as CoreError;
+
+/// What went wrong with a camera session
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum FailureKind {
+    /// The address of the camera could not be resolved
+    Dns,
+    /// The camera could not be reached
+    Connect,
+    /// The camera sent or was sent something that could not be understood
+    Protocol,
+    /// The login or a command was refused
+    Auth,
+    /// The camera stopped answering or sending video
+    Timeout,
+    /// The camera closed the connection
+    CameraClosed,
+    /// Anything else, such as a gstreamer error
+    Other,
+}
+
+impl FailureKind {
+    /// Every kind, in the order they are reported
+    pub(crate) const ALL: [FailureKind; 7] = [
+        FailureKind::Dns,
+        FailureKind::Connect,
+        FailureKind::Protocol,
+        FailureKind::Auth,
+        FailureKind::Timeout,
+        FailureKind::CameraClosed,
+        FailureKind::Other,
+    ];
+
+    /// The kind of the error `err`, `connected` if it happened after the camera connected
+    pub(crate) fn of(err: &anyhow::Error, connected: bool) -> Self {
+        let core_err = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<CoreError>());
+        match core_err {
+            Some(CoreError::AddrResolutionError) => FailureKind::Dns,
+            Some(CoreError::AuthFailed) | Some(CoreError::LoginLocked) => FailureKind::Auth,
+            Some(CoreError::Timeout) => FailureKind::Timeout,
+            Some(CoreError::Other(why)) if *why == STREAM_STALLED => FailureKind::Timeout,
+            Some(CoreError::DroppedConnection(_)) | Some(CoreError::TimeoutDisconnected) => {
+                FailureKind::CameraClosed
+            }
+            Some(CoreError::Communication(_)) if connected => FailureKind::CameraClosed,
+            Some(CoreError::Communication(_)) => FailureKind::Connect,
+            Some(CoreError::Deserialization(_))
+            | Some(CoreError::Serialization(_))
+            | Some(CoreError::MediaDeserialization(_))
+            | Some(CoreError::MediaSerialization(_))
+            | Some(CoreError::ConnectionError(_))
+            | Some(CoreError::UnintelligibleReply { .. })
+            | Some(CoreError::Unsupported { .. })
+            | Some(CoreError::Refused { .. }) => FailureKind::Protocol,
+            _ => FailureKind::Other,
+        }
+    }
+
+    /// The name used in the logs and the metric labels
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            FailureKind::Dns => "dns",
+            FailureKind::Connect => "connect",
+            FailureKind::Protocol => "protocol",
+            FailureKind::Auth => "auth",
+            FailureKind::Timeout => "timeout",
+            FailureKind::CameraClosed => "camera_closed",
+            FailureKind::Other => "other",
+        }
+    }
+
+    /// What happened to the camera, to follow its name in the logs
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            FailureKind::Dns => "its address did not resolve",
+            FailureKind::Connect => "it could not be reached",
+            FailureKind::Protocol => "it sent something unexpected",
+            FailureKind::Auth => "it refused the login or a command",
+            FailureKind::Timeout => "it stopped answering",
+            FailureKind::CameraClosed => "it closed the connection",
+            FailureKind::Other => "the stream failed",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_failure_kind_of() {
+        let err = |e: CoreError| {
+            Err::<(), _>(e)
+                .context("Error while streaming")
+                .unwrap_err()
+        };
+        assert_eq!(
+            FailureKind::of(&err(CoreError::AddrResolutionError), false),
+            FailureKind::Dns
+        );
+        assert_eq!(
+            FailureKind::of(&err(CoreError::AuthFailed), true),
+            FailureKind::Auth
+        );
+        assert_eq!(
+            FailureKind::of(&err(CoreError::Other(STREAM_STALLED)), true),
+            FailureKind::Timeout
+        );
+        assert_eq!(
+            FailureKind::of(&err(CoreError::TimeoutDisconnected), true),
+            FailureKind::CameraClosed
+        );
+        let refused = || std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            FailureKind::of(&err(CoreError::Communication(refused())), false),
+            FailureKind::Connect
+        );
+        assert_eq!(
+            FailureKind::of(&err(CoreError::Communication(refused())), true),
+            FailureKind::CameraClosed
+        );
+        assert_eq!(
+            FailureKind::of(&anyhow!("gstreamer failed"), true),
+            FailureKind::Other
+        );
+    }
+}
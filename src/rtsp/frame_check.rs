@@ -0,0 +1,143 @@
+//! Drops video frames that are too broken to decode
+//!
+//! A flaky camera can send truncated or garbled frames that crash the
+//! decoders of the clients. The length the camera gives each frame is
+//! already checked against its payload when the packet is parsed, so this
+//! checks that the payload is at least `min_bytes` long and made of Annex B
+//! NAL units, each starting with a start code and a valid header. The
+//! warnings are throttled but all the dropped frames are counted in the
+//! metrics, so that a camera which keeps sending them is still noticed.
+use log::*;
+use std::time::{Duration, Instant};
+
+use crate::config::FrameCheckConfig;
+
+// At most one warning about the dropped frames of a stream this often
+const WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Checks the video frames of one stream
+pub(crate) struct FrameCheck {
+    camera: String,
+    min_bytes: usize,
+    last_warning: Option<Instant>,
+    // Frames dropped since the last warning
+    unreported: u64,
+}
+
+impl FrameCheck {
+    pub(crate) fn new(camera: &str, config: &FrameCheckConfig) -> Self {
+        FrameCheck {
+            camera: camera.to_string(),
+            min_bytes: config.min_bytes,
+            last_warning: None,
+            unreported: 0,
+        }
+    }
+
+    /// Whether the frame with `data` should be forwarded, warning about it at
+    /// `now` if not and none was warned about recently
+    pub(crate) fn frame(&mut self, keyframe: bool, data: &[u8], now: Instant) -> bool {
+        let problem = match problem(data, self.min_bytes) {
+            Some(problem) => problem,
+            None => return true,
+        };
+        self.unreported += 1;
+        if !matches!(self.last_warning, Some(last) if now.duration_since(last) < WARNING_INTERVAL) {
+            let kind = match keyframe {
+                true => "keyframe",
+                false => "frame",
+            };
+            warn!(
+                "{}: Dropped a {} of {} bytes that {}, {} dropped in all since the last warning",
+                self.camera,
+                kind,
+                data.len(),
+                problem,
+                self.unreported
+            );
+            self.last_warning = Some(now);
+            self.unreported = 0;
+        }
+        false
+    }
+}
+
+/// What is wrong with the frame `data`, if anything
+fn problem(data: &[u8], min_bytes: usize) -> Option<&'static str> {
+    if data.len() < min_bytes {
+        return Some("is too small");
+    }
+    if !data.starts_with(&[0, 0, 1]) && !data.starts_with(&[0, 0, 0, 1]) {
+        return Some("does not start with a NAL unit");
+    }
+    for unit in nal_units(data) {
+        match unit.first() {
+            None => return Some("has an empty NAL unit"),
+            // The forbidden zero bit of the NAL unit header of both H264 and H265
+            Some(header) if header & 0x80 != 0 => return Some("has a corrupt NAL unit header"),
+            Some(_) => {}
+        }
+    }
+    None
+}
+
+/// The NAL units of Annex B `data` without their start codes
+fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends: Vec<usize> = starts
+        .iter()
+        .skip(1)
+        .map(|start| start - 3)
+        .chain(std::iter::once(data.len()))
+        .collect();
+    starts.into_iter().zip(ends).map(move |(start, end)| {
+        // The zero before a four byte start code belongs to it
+        let unit = &data[start..end];
+        let trailing_zeros = unit.iter().rev().take_while(|b| **b == 0).count();
+        &unit[..unit.len() - trailing_zeros]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An H264 SPS and an IDR slice, the first with a four byte start code
+    const FRAME: &[u8] = &[0, 0, 0, 1, 0x67, 0x42, 0, 0x1f, 0, 0, 1, 0x65, 0x88, 0x84];
+
+    #[test]
+    fn test_problem() {
+        assert_eq!(problem(FRAME, 8), None);
+        assert_eq!(problem(FRAME, 32), Some("is too small"));
+        assert_eq!(
+            problem(&FRAME[4..], 8),
+            Some("does not start with a NAL unit")
+        );
+        // Truncated right after a start code
+        assert_eq!(problem(&FRAME[..11], 8), Some("has an empty NAL unit"));
+        let mut corrupt = FRAME.to_vec();
+        corrupt[11] |= 0x80;
+        assert_eq!(problem(&corrupt, 8), Some("has a corrupt NAL unit header"));
+    }
+
+    #[test]
+    fn test_warnings_are_throttled() {
+        let start = Instant::now();
+        let mut check = FrameCheck::new("Garage", &FrameCheckConfig { min_bytes: 32 });
+        assert!(!check.frame(false, FRAME, start));
+        assert_eq!(check.unreported, 0);
+        assert!(!check.frame(false, FRAME, start + Duration::from_secs(1)));
+        assert_eq!(check.unreported, 1);
+        assert!(!check.frame(true, FRAME, start + Duration::from_secs(11)));
+        assert_eq!(check.unreported, 0);
+    }
+}
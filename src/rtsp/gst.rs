@@ -3,33 +3,51 @@
 pub(crate) use self::maybe_app_src::MaybeAppSrc;
 // use super::adpcm::adpcm_to_pcm;
 // use super::errors::Error;
-use gstreamer::prelude::Cast;
+use super::adaptive::AdaptiveStream;
+use super::advertise::{advertise_sdp, advertise_urls, URL_HEADERS};
+use super::demand::StreamDemand;
+use super::frame_check::FrameCheck;
+use super::keyframe_gap::KeyframeGap;
+use super::metrics::StreamMetrics;
+use super::snapshot::KeyframeCache;
+use crate::utils::{CameraStop, FirstKeyframe};
+use crossbeam::channel::{bounded, Receiver, Sender};
+use gstreamer::glib::WeakRef;
+use gstreamer::prelude::{Cast, ObjectExt};
 use gstreamer::{Bin, Structure};
 use gstreamer_app::AppSrc;
-//use gstreamer_rtsp::RTSPLowerTrans;
 use gstreamer_rtsp::RTSPAuthMethod;
+use gstreamer_rtsp::RTSPLowerTrans;
 pub use gstreamer_rtsp_server::gio::{TlsAuthenticationMode, TlsCertificate};
 use gstreamer_rtsp_server::glib;
+use gstreamer_rtsp_server::glib::translate::ToGlibPtr;
 use gstreamer_rtsp_server::prelude::*;
 use gstreamer_rtsp_server::{
-    RTSPAuth, RTSPMediaFactory, RTSPServer as GstRTSPServer, RTSPToken,
-    RTSP_PERM_MEDIA_FACTORY_ACCESS, RTSP_PERM_MEDIA_FACTORY_CONSTRUCT,
-    RTSP_TOKEN_MEDIA_FACTORY_ROLE,
+    RTSPAuth, RTSPContext, RTSPMedia, RTSPMediaFactory, RTSPMediaStatus,
+    RTSPServer as GstRTSPServer, RTSPToken, RTSP_PERM_MEDIA_FACTORY_ACCESS,
+    RTSP_PERM_MEDIA_FACTORY_CONSTRUCT, RTSP_TOKEN_MEDIA_FACTORY_ROLE,
 };
 use log::*;
 use neolink_core::{
-    bc_protocol::{StreamOutput, StreamOutputError},
+    adpcm::SAMPLE_RATE,
+    bc_protocol::{StreamOutput, StreamOutputError, StreamOutputStatus},
     bcmedia::model::*,
 };
 use std::collections::HashSet;
 use std::fs;
 use std::io;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 type Result<T> = std::result::Result<T, ()>;
 
+// The media currently being served and where to report new clients that play them
+type PlayingMedia = Arc<Mutex<Vec<(WeakRef<RTSPMedia>, Sender<()>)>>>;
+
 pub(crate) struct RtspServer {
     server: GstRTSPServer,
+    playing_media: PlayingMedia,
 }
 
 pub(crate) struct GstOutputs {
@@ -38,6 +56,27 @@ pub(crate) struct GstOutputs {
     video_format: Option<StreamFormat>,
     audio_format: Option<StreamFormat>,
     factory: RTSPMediaFactory,
+    keyframes: Option<Arc<KeyframeCache>>,
+    /// Receives a message each time an rtsp client starts to play this stream
+    pub(crate) client_plays: Receiver<()>,
+    media: Arc<Mutex<Vec<WeakRef<RTSPMedia>>>>,
+    idle_timeout: Option<Duration>,
+    idle_since: Option<Instant>,
+    demand: Option<Arc<StreamDemand>>,
+    metrics: Option<Arc<StreamMetrics>>,
+    fixed_codec: bool,
+    iframe_only: bool,
+    camera_name: String,
+    keyframe_gap: Option<KeyframeGap>,
+    keyframe_gap_sender: Sender<()>,
+    /// Receives a message each time the keyframes of this stream are overdue
+    pub(crate) keyframe_gaps: Receiver<()>,
+    adaptive: Option<AdaptiveStream>,
+    first_keyframe: Option<FirstKeyframe>,
+    frame_check: Option<FrameCheck>,
+    broken_keyframe_sender: Sender<()>,
+    /// Receives a message each time a broken keyframe of this stream is dropped
+    pub(crate) broken_keyframes: Receiver<()>,
 }
 
 // The stream from the camera will be using one of these formats
@@ -58,29 +97,67 @@ enum StreamFormat {
 
 impl StreamOutput for GstOutputs {
     fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        if self.idle_timed_out() || self.yields_to_demand() {
+            return Ok(StreamOutputStatus::Stop);
+        }
         match media {
             BcMedia::Iframe(payload) => {
                 let video_type = match payload.video_type {
                     VideoType::H264 => StreamFormat::H264,
                     VideoType::H265 => StreamFormat::H265,
                 };
+                if self.is_wrong_codec(video_type) {
+                    debug!("Dropping a {:?} frame while the codec is fixed", video_type);
+                    return Ok(StreamOutputStatus::Continue);
+                }
+                if self.switches_stream(payload.video_type, payload.microseconds) {
+                    return Ok(StreamOutputStatus::Stop);
+                }
+                if self.is_corrupt(true, &payload.data) {
+                    // The frames until the fresh keyframe can't be decoded without it
+                    let _ = self.broken_keyframe_sender.try_send(());
+                    return Ok(StreamOutputStatus::DropToKeyframe);
+                }
                 self.set_format(Some(video_type));
+                self.count_frame(video_type, true);
+                self.watch_keyframe_gap(true);
+                if let Some(keyframes) = &self.keyframes {
+                    keyframes.update(payload.video_type, &payload.data);
+                }
+                self.count_bytes(payload.data.len());
                 self.vidsrc.write_all(&payload.data)?;
+                self.note_first_keyframe();
             }
+            BcMedia::Pframe(_) if self.iframe_only => {}
             BcMedia::Pframe(payload) => {
                 let video_type = match payload.video_type {
                     VideoType::H264 => StreamFormat::H264,
                     VideoType::H265 => StreamFormat::H265,
                 };
+                if self.is_wrong_codec(video_type) {
+                    debug!("Dropping a {:?} frame while the codec is fixed", video_type);
+                    return Ok(StreamOutputStatus::Continue);
+                }
+                if self.switches_stream(payload.video_type, payload.microseconds) {
+                    return Ok(StreamOutputStatus::Stop);
+                }
+                if self.is_corrupt(false, &payload.data) {
+                    return Ok(StreamOutputStatus::Continue);
+                }
                 self.set_format(Some(video_type));
+                self.count_frame(video_type, false);
+                self.watch_keyframe_gap(false);
+                self.count_bytes(payload.data.len());
                 self.vidsrc.write_all(&payload.data)?;
             }
             BcMedia::Aac(payload) => {
                 self.set_format(Some(StreamFormat::Aac));
+                self.count_bytes(payload.data.len());
                 self.audsrc.write_all(&payload.data)?;
             }
             BcMedia::Adpcm(payload) => {
                 self.set_format(Some(StreamFormat::Adpcm(payload.data.len() as u16)));
+                self.count_bytes(payload.data.len());
                 self.audsrc.write_all(&payload.data)?;
             }
             _ => {
@@ -88,23 +165,251 @@ impl StreamOutput for GstOutputs {
             }
         }
 
-        Ok(true)
+        // Give the clients a chance to catch up rather than filling the queue
+        if self.vidsrc.is_lagging() {
+            return Ok(StreamOutputStatus::DropToKeyframe);
+        }
+        Ok(StreamOutputStatus::Continue)
     }
 }
 
 impl GstOutputs {
-    pub(crate) fn from_appsrcs(vidsrc: MaybeAppSrc, audsrc: MaybeAppSrc) -> GstOutputs {
+    pub(crate) fn from_appsrcs(
+        vidsrc: MaybeAppSrc,
+        audsrc: MaybeAppSrc,
+        client_plays: Receiver<()>,
+    ) -> GstOutputs {
+        let (keyframe_gap_sender, keyframe_gaps) = bounded(1);
+        let (broken_keyframe_sender, broken_keyframes) = bounded(1);
         let result = GstOutputs {
             vidsrc,
             audsrc,
             video_format: None,
             audio_format: None,
             factory: RTSPMediaFactory::new(),
+            keyframes: None,
+            client_plays,
+            media: Default::default(),
+            idle_timeout: None,
+            idle_since: None,
+            demand: None,
+            metrics: None,
+            fixed_codec: false,
+            iframe_only: false,
+            camera_name: String::new(),
+            keyframe_gap: None,
+            keyframe_gap_sender,
+            keyframe_gaps,
+            adaptive: None,
+            first_keyframe: None,
+            frame_check: None,
+            broken_keyframe_sender,
+            broken_keyframes,
         };
         result.apply_format();
         result
     }
 
+    /// Keep the latest keyframe of this stream in `keyframes` for the http snapshots
+    pub(crate) fn set_keyframe_cache(&mut self, keyframes: Arc<KeyframeCache>) {
+        self.keyframes = Some(keyframes);
+    }
+
+    /// Stop the stream once there have been no rtsp clients for `idle_timeout`
+    pub(crate) fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Count the bytes of media written to this stream in `metrics`
+    pub(crate) fn set_metrics(&mut self, metrics: Arc<StreamMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Warn when camera `name` sends no keyframe for much longer than its `interval`
+    pub(crate) fn set_keyframe_interval(&mut self, name: &str, interval: Duration) {
+        self.camera_name = name.to_string();
+        self.keyframe_gap = Some(KeyframeGap::new(interval));
+    }
+
+    /// Start timing the keyframe gaps afresh for a new connection
+    pub(crate) fn reset_keyframe_gap(&mut self) {
+        if let Some(keyframe_gap) = self.keyframe_gap.as_mut() {
+            keyframe_gap.reset();
+        }
+    }
+
+    /// Time the first keyframe of the connection whose video starts now
+    pub(crate) fn time_first_keyframe(&mut self) {
+        self.first_keyframe = Some(FirstKeyframe::start());
+    }
+
+    fn note_first_keyframe(&mut self) {
+        let latency = self.first_keyframe.as_mut().and_then(|f| f.keyframe());
+        if let (Some(latency), Some(metrics)) = (latency, &self.metrics) {
+            metrics.add_first_keyframe(latency);
+        }
+    }
+
+    fn watch_keyframe_gap(&mut self, keyframe: bool) {
+        let keyframe_gap = match self.keyframe_gap.as_mut() {
+            Some(keyframe_gap) => keyframe_gap,
+            None => return,
+        };
+        let now = Instant::now();
+        if keyframe {
+            if let (Some(gap), Some(metrics)) = (keyframe_gap.keyframe(now), &self.metrics) {
+                metrics.add_keyframe_gap(gap);
+            }
+        } else if let Some(overdue) = keyframe_gap.frame(now) {
+            warn!(
+                "{}: No keyframe for {:.1}s, expected one at least every {:.1}s",
+                self.camera_name,
+                overdue.as_secs_f64(),
+                keyframe_gap.limit().as_secs_f64()
+            );
+            let _ = self.keyframe_gap_sender.try_send(());
+        }
+    }
+
+    /// Drop the video frames that fail the `frame_check` from now on
+    pub(crate) fn set_frame_check(&mut self, frame_check: FrameCheck) {
+        self.frame_check = Some(frame_check);
+    }
+
+    fn is_corrupt(&mut self, keyframe: bool, data: &[u8]) -> bool {
+        let frame_check = match self.frame_check.as_mut() {
+            Some(frame_check) => frame_check,
+            None => return false,
+        };
+        if frame_check.frame(keyframe, data, Instant::now()) {
+            return false;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.add_corrupt_frame();
+        }
+        true
+    }
+
+    /// Drop the video frames that do not match the codec of the first one from now on
+    ///
+    /// Some cameras send a few frames of the wrong codec after changing it
+    pub(crate) fn set_fixed_codec(&mut self, fixed_codec: bool) {
+        self.fixed_codec = fixed_codec;
+        if fixed_codec {
+            // The codec is taken from the next frame of this connection
+            self.video_format = None;
+        }
+    }
+
+    /// Pull the stream of the camera that `adaptive` picks for this mount
+    pub(crate) fn set_adaptive(&mut self, adaptive: AdaptiveStream) {
+        self.adaptive = Some(adaptive);
+    }
+
+    pub(crate) fn adaptive(&self) -> Option<&AdaptiveStream> {
+        self.adaptive.as_ref()
+    }
+
+    pub(crate) fn adaptive_mut(&mut self) -> Option<&mut AdaptiveStream> {
+        self.adaptive.as_mut()
+    }
+
+    // Whether the session should end to pull another stream of the camera
+    fn switches_stream(&mut self, video_type: VideoType, microseconds: u32) -> bool {
+        match self.adaptive.as_mut() {
+            Some(adaptive) => adaptive.frame(video_type, microseconds, Instant::now()),
+            None => false,
+        }
+    }
+
+    /// Let the clients receive the stream only over `protocols`
+    pub(crate) fn set_protocols(&self, protocols: RTSPLowerTrans) {
+        self.factory.set_protocols(protocols);
+    }
+
+    /// Forward only the keyframes of the video from now on
+    pub(crate) fn set_iframe_only(&mut self, iframe_only: bool) {
+        self.iframe_only = iframe_only;
+    }
+
+    // A frame in another codec than the locked one
+    fn is_wrong_codec(&self, video_type: StreamFormat) -> bool {
+        self.fixed_codec && matches!(self.video_format, Some(format) if format != video_type)
+    }
+
+    /// End the stream for the rtsp clients that are still playing it
+    pub(crate) fn end_of_stream(&mut self) {
+        self.vidsrc.on_stream_error();
+        self.audsrc.on_stream_error();
+    }
+
+    pub(crate) fn metrics(&self) -> Option<&Arc<StreamMetrics>> {
+        self.metrics.as_ref()
+    }
+
+    fn count_frame(&self, video_type: StreamFormat, keyframe: bool) {
+        if let Some(metrics) = &self.metrics {
+            let codec = match video_type {
+                StreamFormat::H265 => "H265",
+                _ => "H264",
+            };
+            metrics.add_frame(codec, keyframe);
+        }
+    }
+
+    fn count_bytes(&self, bytes: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.add_bytes(bytes);
+        }
+    }
+
+    /// Stop the stream whenever another stream of the camera is in `demand`
+    pub(crate) fn set_demand(&mut self, demand: Arc<StreamDemand>) {
+        self.demand = Some(demand);
+    }
+
+    fn yields_to_demand(&self) -> bool {
+        matches!(&self.demand, Some(demand) if demand.is_upgraded())
+    }
+
+    /// Whether any rtsp client is watching this stream or waiting for it to start
+    pub(crate) fn has_clients(&self) -> bool {
+        let mut media = self.media.lock().unwrap();
+        media.retain(|media| media.upgrade().is_some());
+        media
+            .iter()
+            .filter_map(|media| media.upgrade())
+            .any(|media| {
+                matches!(
+                    media.status(),
+                    RTSPMediaStatus::Preparing | RTSPMediaStatus::Prepared
+                )
+            })
+    }
+
+    /// Block until an rtsp client wants to watch this stream or the camera is stopped
+    pub(crate) fn wait_for_client(&mut self, stop: &CameraStop) {
+        while !self.has_clients() {
+            if stop.sleep(Duration::from_millis(500)) {
+                return;
+            }
+        }
+        self.idle_since = None;
+    }
+
+    fn idle_timed_out(&mut self) -> bool {
+        let idle_timeout = match self.idle_timeout {
+            Some(idle_timeout) => idle_timeout,
+            None => return false,
+        };
+        if self.has_clients() {
+            self.idle_since = None;
+            return false;
+        }
+        let idle_since = *self.idle_since.get_or_insert_with(Instant::now);
+        idle_since.elapsed() >= idle_timeout
+    }
+
     fn set_format(&mut self, format: Option<StreamFormat>) {
         match format {
             Some(StreamFormat::H264) | Some(StreamFormat::H265) => {
@@ -125,26 +430,26 @@ impl GstOutputs {
 
     fn apply_format(&self) {
         let launch_vid = match self.video_format {
+            // The parsers move the parameter sets of the keyframes into the
+            // codec_data of the caps, from which the payloaders fill in the
+            // sprop-* of the SDP. They are updated whenever the camera changes
+            // them. config-interval=-1 still sends them in-band on each keyframe
             Some(StreamFormat::H264) => {
-                "! queue silent=true max-size-bytes=10485760  min-threshold-bytes=1024 ! h264parse ! rtph264pay name=pay0"
+                "! queue silent=true max-size-bytes=10485760  min-threshold-bytes=1024 ! h264parse ! video/x-h264,stream-format=avc,alignment=au ! rtph264pay name=pay0 config-interval=-1"
             }
             Some(StreamFormat::H265) => {
-                "! queue silent=true  max-size-bytes=10485760  min-threshold-bytes=1024 ! h265parse ! rtph265pay name=pay0"
+                "! queue silent=true  max-size-bytes=10485760  min-threshold-bytes=1024 ! h265parse ! video/x-h265,stream-format=hvc1,alignment=au ! rtph265pay name=pay0 config-interval=-1"
             }
             _ => "! fakesink",
         };
 
         let launch_aud = match self.audio_format {
-            Some(StreamFormat::Adpcm(block_size)) => format!("caps=audio/x-adpcm,layout=dvi,block_align={},channels=1,rate=8000 ! queue silent=true max-size-bytes=10485760 min-threshold-bytes=1024 ! adpcmdec  ! audioconvert ! rtpL16pay name=pay1", block_size), // DVI4 is converted to pcm in the appsrc
-            Some(StreamFormat::Aac) => "! queue silent=true max-size-bytes=10485760 min-threshold-bytes=1024 ! aacparse ! decodebin ! audioconvert ! rtpL16pay name=pay1 name=pay1".to_string(),
+            Some(StreamFormat::Adpcm(block_size)) => format!("caps=audio/x-adpcm,layout=dvi,block_align={},channels=1,rate={} ! queue silent=true max-size-bytes=10485760 min-threshold-bytes=1024 ! adpcmdec  ! audioconvert ! rtpL16pay name=pay1", block_size, SAMPLE_RATE), // DVI4 is converted to pcm in the appsrc
+            Some(StreamFormat::Aac) => "! queue silent=true max-size-bytes=10485760 min-threshold-bytes=1024 ! aacparse ! decodebin ! audioconvert ! rtpL16pay name=pay1".to_string(),
             _ => "! fakesink".to_string(),
         };
 
-        error!(
-            "Apply Format Vid: {}, Aud: {}",
-            launch_vid,
-            launch_aud
-        );
+        debug!("Apply Format Vid: {}, Aud: {}", launch_vid, launch_aud);
 
         self.factory.set_launch(
             &vec![
@@ -160,6 +465,106 @@ impl GstOutputs {
     }
 }
 
+/// The transports the clients may use for `rtsp_transport` in the config
+///
+/// `tcp` interleaves the media in the rtsp connection, which gets through
+/// firewalls and NAT, and `udp` sends it separately for the least latency
+pub(crate) fn rtsp_protocols(rtsp_transport: &str) -> RTSPLowerTrans {
+    match rtsp_transport {
+        "tcp" => RTSPLowerTrans::TCP,
+        "udp" => RTSPLowerTrans::UDP | RTSPLowerTrans::UDP_MCAST,
+        _ => RTSPLowerTrans::UDP | RTSPLowerTrans::UDP_MCAST | RTSPLowerTrans::TCP,
+    }
+}
+
+fn protocol_name(protocol: RTSPLowerTrans) -> &'static str {
+    if protocol.contains(RTSPLowerTrans::TCP) {
+        "TCP (interleaved)"
+    } else if protocol.contains(RTSPLowerTrans::UDP_MCAST) {
+        "UDP multicast"
+    } else if protocol.contains(RTSPLowerTrans::UDP) {
+        "UDP"
+    } else {
+        "an unknown transport"
+    }
+}
+
+// The address of the client, the path and the transport it set up in `ctx`
+//
+// The bindings don't give the transport of a setup so it is read from the C structs
+fn setup_transport(ctx: &RTSPContext) -> Option<(String, String, RTSPLowerTrans)> {
+    let read_str = |ptr: *const std::os::raw::c_char| match ptr.is_null() {
+        true => String::new(),
+        // Safety: a non null string of the context is nul terminated and lives for the request
+        false => unsafe { std::ffi::CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned(),
+    };
+    // Safety: the context is valid for the signal and its pointers are checked before use
+    unsafe {
+        let ctx: *mut gstreamer_rtsp_server::ffi::GstRTSPContext = ctx.to_glib_none().0;
+        if ctx.is_null() || (*ctx).trans.is_null() {
+            return None;
+        }
+        let transport =
+            gstreamer_rtsp_server::ffi::gst_rtsp_stream_transport_get_transport((*ctx).trans);
+        if transport.is_null() {
+            return None;
+        }
+        let protocol = RTSPLowerTrans::from_bits_truncate((*transport).lower_transport as u32);
+        let ip = match (*ctx).conn.is_null() {
+            true => String::new(),
+            false => read_str(gstreamer_rtsp::ffi::gst_rtsp_connection_get_ip((*ctx).conn)),
+        };
+        let path = match (*ctx).uri.is_null() {
+            true => String::new(),
+            false => read_str((*(*ctx).uri).abspath),
+        };
+        Some((ip, path, protocol))
+    }
+}
+
+// Give the clients `host` in the urls and SDP of the response `message`
+//
+// The bindings have no signal for the messages being sent so they are changed in the C structs
+fn advertise_response(message: *mut gstreamer_rtsp::ffi::GstRTSPMessage, host: &str) {
+    use gstreamer_rtsp::ffi::*;
+    use std::ffi::{CStr, CString};
+    // Safety: the message is valid for the signal, and each header value is
+    // copied before the header is replaced
+    unsafe {
+        if message.is_null() || (*message).type_ != GST_RTSP_MESSAGE_RESPONSE {
+            return;
+        }
+        for header in URL_HEADERS {
+            let name = CString::new(*header).expect("The header names have no nul");
+            let mut value = std::ptr::null_mut();
+            if gst_rtsp_message_get_header_by_name(message, name.as_ptr(), &mut value, 0)
+                != GST_RTSP_OK
+                || value.is_null()
+            {
+                continue;
+            }
+            let advertised = advertise_urls(&CStr::from_ptr(value).to_string_lossy(), host);
+            if let Ok(advertised) = CString::new(advertised) {
+                gst_rtsp_message_remove_header_by_name(message, name.as_ptr(), -1);
+                gst_rtsp_message_add_header_by_name(message, name.as_ptr(), advertised.as_ptr());
+            }
+        }
+        let mut body = std::ptr::null_mut();
+        let mut size = 0;
+        if gst_rtsp_message_get_body(message, &mut body, &mut size) != GST_RTSP_OK || body.is_null()
+        {
+            return;
+        }
+        let sdp = match std::str::from_utf8(std::slice::from_raw_parts(body, size as usize)) {
+            Ok(sdp) if sdp.starts_with("v=") => advertise_sdp(sdp, host),
+            _ => return,
+        };
+        gst_rtsp_message_set_body(message, sdp.as_ptr(), sdp.len() as u32);
+    }
+}
+
 impl Default for RtspServer {
     fn default() -> RtspServer {
         Self::new()
@@ -169,8 +574,44 @@ impl Default for RtspServer {
 impl RtspServer {
     pub(crate) fn new() -> RtspServer {
         gstreamer::init().expect("Gstreamer should not explode");
+        let server = GstRTSPServer::new();
+        let playing_media: PlayingMedia = Default::default();
+
+        // The factories are shared so there is no signal on the media when a
+        // new client joins. Instead watch each client's play requests and look
+        // up which of our media it is now playing
+        let client_media = playing_media.clone();
+        server.connect_client_connected(move |_server, client| {
+            let client_media = client_media.clone();
+            client.connect_setup_request(|_client, ctx| {
+                if let Some((ip, path, protocol)) = setup_transport(ctx) {
+                    info!(
+                        "RTSP: {} receives {} over {}",
+                        ip,
+                        path,
+                        protocol_name(protocol)
+                    );
+                }
+            });
+            client.connect_play_request(move |client, _ctx| {
+                let mut playing_media = client_media.lock().unwrap();
+                playing_media.retain(|(media, _)| media.upgrade().is_some());
+                for session in client.session_filter(None) {
+                    for session_media in session.filter(None) {
+                        let played = session_media.media();
+                        for (media, tx) in playing_media.iter() {
+                            if media.upgrade() == played {
+                                let _ = tx.try_send(()); // Camera may not be connected, don't panic if so
+                            }
+                        }
+                    }
+                }
+            });
+        });
+
         RtspServer {
-            server: GstRTSPServer::new(),
+            server,
+            playing_media,
         }
     }
 
@@ -190,8 +631,9 @@ impl RtspServer {
         // unhappy with the pipeline, so keep updating the MaybeAppSrc.
         let (maybe_app_src, tx) = MaybeAppSrc::new_with_tx();
         let (maybe_app_src_aud, tx_aud) = MaybeAppSrc::new_with_tx();
+        let (tx_plays, rx_plays) = bounded(1);
 
-        let outputs = GstOutputs::from_appsrcs(maybe_app_src, maybe_app_src_aud);
+        let outputs = GstOutputs::from_appsrcs(maybe_app_src, maybe_app_src_aud, rx_plays);
 
         let factory = &outputs.factory;
 
@@ -206,8 +648,15 @@ impl RtspServer {
 
         factory.set_shared(true);
 
+        let playing_media = self.playing_media.clone();
+        let stream_media = outputs.media.clone();
         factory.connect_media_configure(move |_factory, media| {
             debug!("RTSP: media was configured");
+            stream_media.lock().unwrap().push(media.downgrade());
+            playing_media
+                .lock()
+                .unwrap()
+                .push((media.downgrade(), tx_plays.clone()));
             let bin = media
                 .element()
                 .expect("Media should have an element")
@@ -235,6 +684,19 @@ impl RtspServer {
         Ok(outputs)
     }
 
+    /// Stop serving the stream at `paths`
+    ///
+    /// Clients that are already playing it keep their session until the stream ends
+    pub(crate) fn remove_stream(&self, paths: &[&str]) {
+        let mounts = self
+            .server
+            .mount_points()
+            .expect("The server should have mountpoints");
+        for path in paths {
+            mounts.remove_factory(path);
+        }
+    }
+
     pub(crate) fn add_permitted_roles(
         &self,
         factory: &RTSPMediaFactory,
@@ -298,9 +760,7 @@ impl RtspServer {
         debug!("Setting up TLS using {}", cert_file);
         let auth = self.server.auth().unwrap_or_else(RTSPAuth::new);
 
-        // We seperate reading the file and changing to a PEM so that we get different error messages.
-        let cert_contents = fs::read_to_string(cert_file).expect("TLS file not found");
-        let cert = TlsCertificate::from_pem(&cert_contents).expect("Not a valid TLS certificate");
+        let cert = read_tls_certificate(cert_file).expect("Failed to load TLS certificate");
         auth.set_tls_certificate(Some(&cert));
         auth.set_tls_authentication_mode(client_auth);
 
@@ -308,18 +768,79 @@ impl RtspServer {
         Ok(())
     }
 
-    pub(crate) fn run(&self, bind_addr: &str, bind_port: u16) {
+    /// Replace the TLS certificate used for new connections
+    ///
+    /// The new certificate is validated first, if it cannot be loaded the
+    /// current certificate is left in place. Sessions that have already
+    /// completed their handshake are not affected.
+    pub(crate) fn reload_tls(&self, cert_file: &str) -> anyhow::Result<()> {
+        let cert = read_tls_certificate(cert_file)?;
+        let auth = self
+            .server
+            .auth()
+            .ok_or_else(|| anyhow::anyhow!("TLS has not been set up on this server"))?;
+        auth.set_tls_certificate(Some(&cert));
+        Ok(())
+    }
+
+    /// Give the clients `host` in the responses instead of the address they reached the server at
+    pub(crate) fn advertise_host(&self, host: &str) {
+        let host = host.to_string();
+        self.server
+            .connect_client_connected(move |_server, client| {
+                let host = host.clone();
+                let connected = client.connect("send-message", false, move |values| {
+                    // Safety: the last argument of the signal is the message being sent
+                    let message = unsafe {
+                        glib::gobject_ffi::g_value_get_pointer(values[2].to_glib_none().0)
+                    };
+                    advertise_response(message as *mut _, &host);
+                    None
+                });
+                if let Err(e) = connected {
+                    warn!("RTSP: Unable to advertise the host to a client: {}", e);
+                }
+            });
+    }
+
+    /// Bind the server to the address and port
+    ///
+    /// The server will not serve anything until [`RtspServer::run_main_loop`] is called
+    pub(crate) fn attach(&self, bind_addr: &str, bind_port: u16) {
         self.server.set_address(bind_addr);
         self.server.set_service(&format!("{}", bind_port));
         // Attach server to default Glib context
         let _ = self.server.attach(None);
+    }
 
-        // Run the Glib main loop.
+    /// Run the Glib main loop which serves all the attached servers
+    ///
+    /// Returns soon after `shutdown` is stopped
+    pub(crate) fn run_main_loop(shutdown: Arc<CameraStop>) {
         let main_loop = glib::MainLoop::new(None, false);
+        let quit = main_loop.clone();
+        glib::timeout_add(Duration::from_millis(200), move || {
+            if shutdown.is_stopped() {
+                quit.quit();
+                glib::Continue(false)
+            } else {
+                glib::Continue(true)
+            }
+        });
         main_loop.run();
     }
 }
 
+fn read_tls_certificate(cert_file: &str) -> anyhow::Result<TlsCertificate> {
+    use anyhow::Context;
+    // We seperate reading the file and changing to a PEM so that we get different error messages.
+    let cert_contents = fs::read_to_string(cert_file)
+        .with_context(|| format!("TLS file {} not found", cert_file))?;
+    let cert = TlsCertificate::from_pem(&cert_contents)
+        .with_context(|| format!("{} is not a valid TLS certificate", cert_file))?;
+    Ok(cert)
+}
+
 mod maybe_app_src {
     use super::*;
     use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
@@ -348,6 +869,15 @@ mod maybe_app_src {
             }
         }
 
+        /// True once more than half of the AppSrc's queue is waiting, meaning
+        /// the pipeline is not keeping up with the camera
+        pub(crate) fn is_lagging(&mut self) -> bool {
+            match self.try_get_src() {
+                Some(src) => src.current_level_bytes() > src.max_bytes() / 2,
+                None => false,
+            }
+        }
+
         /// Attempts to retrieve the AppSrc that should be passed in by the caller of new_with_tx
         /// at some point after this struct has been created.  At that point, we swap over to
         /// owning the AppSrc directly.  This function handles either case and returns the AppSrc,
@@ -0,0 +1,143 @@
+//! Notices when a camera stream goes too long without a keyframe
+//!
+//! Packet loss can leave a stream with only P-frames, which the clients
+//! cannot decode until the next keyframe. The interval between the keyframes
+//! of the stream, its GOP, is measured from the gaps between the last few, so
+//! a gap of much more than that means the camera is dropping them. Until the
+//! stream has sent two keyframes the configured `keyframe_interval` is assumed.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// A gap longer than this many keyframe intervals is reported
+const GAP_LIMIT_INTERVALS: u32 = 2;
+
+// The number of recent gaps the interval is measured from. Their median is not
+// thrown by a few lost keyframes or by the extra ones sent when asked for
+const MEASURED_GAPS: usize = 8;
+
+/// Times the gaps between the keyframes of one stream
+pub(crate) struct KeyframeGap {
+    assumed_interval: Duration,
+    gaps: VecDeque<Duration>,
+    last_keyframe: Option<Instant>,
+    reported: bool,
+}
+
+impl KeyframeGap {
+    /// Watch for gaps in a stream, assuming a keyframe every `interval` until
+    /// the stream's own interval is measured
+    pub(crate) fn new(interval: Duration) -> Self {
+        KeyframeGap {
+            assumed_interval: interval,
+            gaps: VecDeque::with_capacity(MEASURED_GAPS),
+            last_keyframe: None,
+            reported: false,
+        }
+    }
+
+    /// Start over at a new connection, whose first keyframe has no gap and
+    /// whose interval may have been changed on the camera
+    pub(crate) fn reset(&mut self) {
+        self.gaps.clear();
+        self.last_keyframe = None;
+        self.reported = false;
+    }
+
+    /// Note a keyframe at `now` and return the gap since the one before
+    pub(crate) fn keyframe(&mut self, now: Instant) -> Option<Duration> {
+        let gap = self.last_keyframe.map(|last| now - last);
+        if let Some(gap) = gap {
+            if self.gaps.len() == MEASURED_GAPS {
+                self.gaps.pop_front();
+            }
+            self.gaps.push_back(gap);
+        }
+        self.last_keyframe = Some(now);
+        self.reported = false;
+        gap
+    }
+
+    /// The interval between the keyframes of the stream, the median of the
+    /// recent gaps or the assumed interval before there are any
+    pub(crate) fn interval(&self) -> Duration {
+        let mut gaps: Vec<Duration> = self.gaps.iter().copied().collect();
+        gaps.sort();
+        gaps.get(gaps.len() / 2)
+            .copied()
+            .unwrap_or(self.assumed_interval)
+    }
+
+    /// Note another frame at `now` and return how long the keyframe is overdue,
+    /// only the first time it goes over the limit
+    pub(crate) fn frame(&mut self, now: Instant) -> Option<Duration> {
+        let since = now - self.last_keyframe?;
+        if self.reported || since <= self.limit() {
+            return None;
+        }
+        self.reported = true;
+        Some(since)
+    }
+
+    /// The longest gap before it is reported
+    pub(crate) fn limit(&self) -> Duration {
+        self.interval() * GAP_LIMIT_INTERVALS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overdue_keyframe_is_reported_once() {
+        let start = Instant::now();
+        let mut gap = KeyframeGap::new(Duration::from_secs(2));
+        assert_eq!(gap.frame(start), None);
+        assert_eq!(gap.keyframe(start), None);
+        assert_eq!(gap.frame(start + Duration::from_secs(3)), None);
+        assert_eq!(
+            gap.frame(start + Duration::from_secs(5)),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(gap.frame(start + Duration::from_secs(6)), None);
+        assert_eq!(
+            gap.keyframe(start + Duration::from_secs(7)),
+            Some(Duration::from_secs(7))
+        );
+        // The seven second gap is now the measured interval
+        assert_eq!(gap.frame(start + Duration::from_secs(12)), None);
+        assert_eq!(
+            gap.frame(start + Duration::from_secs(22)),
+            Some(Duration::from_secs(15))
+        );
+    }
+
+    #[test]
+    fn test_interval_is_measured_from_the_stream() {
+        let start = Instant::now();
+        let mut gap = KeyframeGap::new(Duration::from_secs(2));
+        assert_eq!(gap.interval(), Duration::from_secs(2));
+        // A camera with a four second GOP, one keyframe lost and one asked for
+        for secs in &[0, 4, 8, 16, 17, 20, 24] {
+            gap.keyframe(start + Duration::from_secs(*secs));
+        }
+        assert_eq!(gap.interval(), Duration::from_secs(4));
+        assert_eq!(gap.frame(start + Duration::from_secs(30)), None);
+        assert_eq!(
+            gap.frame(start + Duration::from_secs(33)),
+            Some(Duration::from_secs(9))
+        );
+        gap.reset();
+        assert_eq!(gap.interval(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_reset_forgets_the_last_keyframe() {
+        let start = Instant::now();
+        let mut gap = KeyframeGap::new(Duration::from_secs(2));
+        gap.keyframe(start);
+        gap.reset();
+        assert_eq!(gap.frame(start + Duration::from_secs(60)), None);
+        assert_eq!(gap.keyframe(start + Duration::from_secs(60)), None);
+    }
+}
@@ -0,0 +1,808 @@
+//! Counts the media forwarded from each camera stream
+//!
+//! The bytes are counted where the media is written to the rtsp stream so
+//! that only the forwarded video and audio is included, not the protocol
+//! overhead. The average bitrate over each minute is logged and, when the
+//! `http_port` is set, served at `/metrics` in the Prometheus text format
+//! along with the packets dropped because a stream fell behind and a
+//! histogram of how long each connection took to its first keyframe.
+//!
+//! The connection state of each stream is also kept here for the `/healthz`
+//! and `/status` endpoints. `/status` also gives when each camera last saw
+//! motion. When neolink is stopped with Ctrl-C a summary of every stream is
+//! printed from the same counters, which also feed the table of `--tui`.
+use super::failure::FailureKind;
+use super::state::ConnectionState;
+use crate::lastmotion;
+use log::*;
+use neolink_core::bc_protocol::StreamQueue;
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+
+// The bitrate is averaged and logged over this window
+const BITRATE_WINDOW: Duration = Duration::from_secs(60);
+
+// The upper bounds in seconds of the buckets of the first keyframe latencies
+const LATENCY_BUCKETS: [f64; 8] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+
+/// All the camera streams being counted
+#[derive(Default)]
+pub(crate) struct Metrics {
+    streams: Mutex<Vec<Arc<StreamMetrics>>>,
+}
+
+impl Metrics {
+    /// Start counting the stream named `stream` of `camera`
+    pub(crate) fn add_stream(&self, camera: &str, stream: &str) -> Arc<StreamMetrics> {
+        let metrics = Arc::new(StreamMetrics {
+            camera: camera.to_string(),
+            stream: stream.to_string(),
+            bytes_total: AtomicU64::new(0),
+            frames_total: AtomicU64::new(0),
+            bitrate: AtomicU64::new(0),
+            queue_capacity: AtomicU64::new(0),
+            dropped_packets: Default::default(),
+            corrupt_frames: AtomicU64::new(0),
+            max_keyframe_gap_ms: AtomicU64::new(0),
+            resumes: AtomicU64::new(0),
+            full_reconnects: AtomicU64::new(0),
+            failures: Default::default(),
+            first_keyframe: Default::default(),
+            window: Mutex::new((Instant::now(), 0)),
+            status: Mutex::new(Status {
+                state: ConnectionState::Disconnected,
+                last_frame: None,
+                last_keyframe: None,
+                codec: None,
+                connects: 0,
+                streaming_since: None,
+                streamed: Duration::ZERO,
+                last_error: None,
+                first_keyframe: None,
+                failing_since: None,
+            }),
+        });
+        self.streams.lock().unwrap().push(metrics.clone());
+        metrics
+    }
+
+    /// Stop counting the streams of `camera`
+    pub(crate) fn remove_camera(&self, camera: &str) {
+        self.streams
+            .lock()
+            .unwrap()
+            .retain(|stream| stream.camera != camera);
+    }
+
+    /// Whether `all` or, if false, any of the cameras has a stream that is streaming
+    ///
+    /// The cameras whose streams are all idle until a client wants them are
+    /// left out, and with only idle cameras it is healthy
+    pub(crate) fn is_healthy(&self, all: bool) -> bool {
+        let streams = self.streams.lock().unwrap();
+        let mut cameras: Vec<&str> = streams
+            .iter()
+            .filter(|s| s.status.lock().unwrap().state != ConnectionState::Idle)
+            .map(|s| s.camera.as_str())
+            .collect();
+        cameras.sort_unstable();
+        cameras.dedup();
+        if cameras.is_empty() {
+            return !streams.is_empty();
+        }
+        let streaming = |camera: &str| {
+            streams
+                .iter()
+                .filter(|s| s.camera == camera)
+                .any(|s| s.status.lock().unwrap().state == ConnectionState::Streaming)
+        };
+        match all {
+            true => cameras.iter().all(|c| streaming(c)),
+            false => cameras.iter().any(|c| streaming(c)),
+        }
+    }
+
+    /// How long `camera` has been failing without any of its streams streaming
+    /// and the last error of the stream that failed first, or `None` if it is
+    /// streaming or has not failed
+    pub(crate) fn outage(&self, camera: &str) -> Option<(Duration, Option<String>)> {
+        let streams = self.streams.lock().unwrap();
+        let statuses: Vec<Status> = streams
+            .iter()
+            .filter(|s| s.camera == camera)
+            .map(|s| s.status.lock().unwrap().clone())
+            .collect();
+        if statuses
+            .iter()
+            .any(|status| status.state == ConnectionState::Streaming)
+        {
+            return None;
+        }
+        let (since, status) = statuses
+            .iter()
+            .filter_map(|status| status.failing_since.map(|since| (since, status)))
+            .min_by_key(|(since, _)| *since)?;
+        Some((since.elapsed(), status.last_error.clone()))
+    }
+
+    /// The status of every stream as json
+    pub(crate) fn status_json(&self, all: bool) -> String {
+        #[derive(Serialize)]
+        struct StreamStatus<'a> {
+            camera: &'a str,
+            stream: &'a str,
+            state: ConnectionState,
+            last_frame_age_seconds: Option<f64>,
+            codec: Option<&'a str>,
+            reconnects: u64,
+            first_keyframe_seconds: Option<f64>,
+            last_motion: Option<u64>,
+        }
+        #[derive(Serialize)]
+        struct Report<'a> {
+            healthy: bool,
+            streams: Vec<StreamStatus<'a>>,
+        }
+        let healthy = self.is_healthy(all);
+        let streams = self.streams.lock().unwrap();
+        let statuses: Vec<Status> = streams
+            .iter()
+            .map(|s| s.status.lock().unwrap().clone())
+            .collect();
+        let report = Report {
+            healthy,
+            streams: streams
+                .iter()
+                .zip(&statuses)
+                .map(|(stream, status)| StreamStatus {
+                    camera: &stream.camera,
+                    stream: &stream.stream,
+                    state: status.state,
+                    last_frame_age_seconds: status.last_frame.map(|t| t.elapsed().as_secs_f64()),
+                    codec: status.codec,
+                    reconnects: status.connects.saturating_sub(1),
+                    first_keyframe_seconds: status.first_keyframe.map(|t| t.as_secs_f64()),
+                    last_motion: lastmotion::last_motion(&stream.camera),
+                })
+                .collect(),
+        };
+        serde_json::to_string(&report).expect("The status is always valid json")
+    }
+
+    /// What each stream is doing now, for the status table of `--tui`
+    pub(crate) fn live(&self) -> Vec<LiveStream> {
+        let streams = self.streams.lock().unwrap();
+        streams
+            .iter()
+            .map(|stream| {
+                let status = stream.status.lock().unwrap().clone();
+                LiveStream {
+                    camera: stream.camera.clone(),
+                    stream: stream.stream.clone(),
+                    state: status.state,
+                    frames: stream.frames_total.load(Ordering::Relaxed),
+                    bytes: stream.bytes_total.load(Ordering::Relaxed),
+                    keyframe_age: status.last_keyframe.map(|t| t.elapsed()),
+                    last_error: status.last_error,
+                }
+            })
+            .collect()
+    }
+
+    /// A table of what each stream did, for when neolink stops
+    pub(crate) fn summary(&self) -> String {
+        let streams = self.streams.lock().unwrap();
+        let mut rows = vec![[
+            "CAMERA".to_string(),
+            "STREAM".to_string(),
+            "STREAMED".to_string(),
+            "RECONNECTS".to_string(),
+            "FORWARDED".to_string(),
+            "FRAMES".to_string(),
+            "MAX KEYFRAME GAP".to_string(),
+            "LAST ERROR".to_string(),
+        ]];
+        for stream in streams.iter() {
+            let status = stream.status.lock().unwrap().clone();
+            rows.push([
+                stream.camera.clone(),
+                stream.stream.clone(),
+                format_duration(status.streamed()),
+                status.connects.saturating_sub(1).to_string(),
+                format!(
+                    "{:.1} MB",
+                    stream.bytes_total.load(Ordering::Relaxed) as f64 / 1_000_000.0
+                ),
+                stream.frames_total.load(Ordering::Relaxed).to_string(),
+                format!(
+                    "{:.1}s",
+                    stream.max_keyframe_gap_ms.load(Ordering::Relaxed) as f64 / 1000.0
+                ),
+                status.last_error.unwrap_or_else(|| "-".to_string()),
+            ]);
+        }
+        let mut widths = [0; 8];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let mut out = String::new();
+        for row in &rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            let _ = writeln!(out, "{}", cells.join("  ").trim_end());
+        }
+        out
+    }
+
+    /// The metrics in the Prometheus text format
+    pub(crate) fn render(&self) -> String {
+        let streams = self.streams.lock().unwrap();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_bytes_total Bytes of media forwarded from the camera"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_bytes_total counter");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_bytes_total{} {}",
+                stream.labels(),
+                stream.bytes_total.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_bitrate_bits_per_second Average bitrate over the last minute"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_bitrate_bits_per_second gauge");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_bitrate_bits_per_second{} {}",
+                stream.labels(),
+                stream.bitrate.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_queue_capacity Packets held for the rtsp stream before they are dropped"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_queue_capacity gauge");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_queue_capacity{} {}",
+                stream.labels(),
+                stream.queue_capacity.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_dropped_packets_total Packets dropped because the rtsp stream fell behind"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_dropped_packets_total counter");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_dropped_packets_total{} {}",
+                stream.labels(),
+                stream.dropped_packets.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_corrupt_frames_total Video frames dropped because they were broken"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_corrupt_frames_total counter");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_corrupt_frames_total{} {}",
+                stream.labels(),
+                stream.corrupt_frames.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_max_keyframe_gap_seconds The longest time between two keyframes"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_max_keyframe_gap_seconds gauge");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_max_keyframe_gap_seconds{} {}",
+                stream.labels(),
+                stream.max_keyframe_gap_ms.load(Ordering::Relaxed) as f64 / 1000.0
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_resumes_total Sessions closed by the camera that were resumed"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_resumes_total counter");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_resumes_total{} {}",
+                stream.labels(),
+                stream.resumes.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_full_reconnects_total Lost connections that needed a full reconnect"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_full_reconnects_total counter");
+        for stream in streams.iter() {
+            let _ = writeln!(
+                out,
+                "neolink_stream_full_reconnects_total{} {}",
+                stream.labels(),
+                stream.full_reconnects.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_failures_total Failed sessions by the kind of failure"
+        );
+        let _ = writeln!(out, "# TYPE neolink_stream_failures_total counter");
+        for stream in streams.iter() {
+            for (kind, failures) in FailureKind::ALL.iter().zip(&stream.failures) {
+                let _ = writeln!(
+                    out,
+                    "neolink_stream_failures_total{{{},kind=\"{}\"}} {}",
+                    stream.label_pairs(),
+                    kind.name(),
+                    failures.load(Ordering::Relaxed)
+                );
+            }
+        }
+        let _ = writeln!(
+            out,
+            "# HELP neolink_stream_first_keyframe_seconds Time from starting the video of a connection to its first keyframe"
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE neolink_stream_first_keyframe_seconds histogram"
+        );
+        for stream in streams.iter() {
+            let histogram = stream.first_keyframe.lock().unwrap();
+            histogram.render(
+                &mut out,
+                "neolink_stream_first_keyframe_seconds",
+                &stream.label_pairs(),
+            );
+        }
+        out
+    }
+}
+
+/// How many of the latencies fell in each of the `LATENCY_BUCKETS`
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    // The buckets are cumulative in the Prometheus text format
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// The state and counters of a stream at one moment
+pub(crate) struct LiveStream {
+    pub(crate) camera: String,
+    pub(crate) stream: String,
+    pub(crate) state: ConnectionState,
+    pub(crate) frames: u64,
+    pub(crate) bytes: u64,
+    pub(crate) keyframe_age: Option<Duration>,
+    pub(crate) last_error: Option<String>,
+}
+
+/// The counters of one camera stream
+pub(crate) struct StreamMetrics {
+    camera: String,
+    stream: String,
+    bytes_total: AtomicU64,
+    frames_total: AtomicU64,
+    // Bits per second averaged over the last complete window
+    bitrate: AtomicU64,
+    queue_capacity: AtomicU64,
+    // Shared with the queue of the stream so it counts across reconnects
+    dropped_packets: Arc<AtomicU64>,
+    corrupt_frames: AtomicU64,
+    max_keyframe_gap_ms: AtomicU64,
+    // Sessions the camera closed that were picked up again without the full reconnect
+    resumes: AtomicU64,
+    full_reconnects: AtomicU64,
+    // The failed sessions of each kind, in the order of `FailureKind::ALL`
+    failures: [AtomicU64; FailureKind::ALL.len()],
+    first_keyframe: Mutex<Histogram>,
+    // The start of the current window and the bytes counted in it
+    window: Mutex<(Instant, u64)>,
+    status: Mutex<Status>,
+}
+
+// What `/status` reports for a stream
+#[derive(Clone)]
+struct Status {
+    state: ConnectionState,
+    last_frame: Option<Instant>,
+    last_keyframe: Option<Instant>,
+    codec: Option<&'static str>,
+    // Connection attempts, the first is not a reconnect
+    connects: u64,
+    // When the current streaming began and how long the ones before it lasted
+    streaming_since: Option<Instant>,
+    streamed: Duration,
+    last_error: Option<String>,
+    // How long the last connection took to its first keyframe
+    first_keyframe: Option<Duration>,
+    // The first error since the stream last streamed
+    failing_since: Option<Instant>,
+}
+
+impl Status {
+    fn streamed(&self) -> Duration {
+        self.streamed
+            + self
+                .streaming_since
+                .map_or(Duration::ZERO, |since| since.elapsed())
+    }
+}
+
+impl StreamMetrics {
+    /// Count `bytes` of media that were forwarded
+    pub(crate) fn add_bytes(&self, bytes: usize) {
+        let bytes = bytes as u64;
+        self.bytes_total.fetch_add(bytes, Ordering::Relaxed);
+
+        let mut window = self.window.lock().unwrap();
+        window.1 += bytes;
+        let elapsed = window.0.elapsed();
+        if elapsed >= BITRATE_WINDOW {
+            let bitrate = (window.1 as f64 * 8.0 / elapsed.as_secs_f64()) as u64;
+            self.bitrate.store(bitrate, Ordering::Relaxed);
+            info!(
+                "{}: {} averaged {:.0} kbit/s over the last minute",
+                self.camera,
+                self.stream,
+                bitrate as f64 / 1000.0
+            );
+            *window = (Instant::now(), 0);
+        }
+    }
+
+    /// Note that a video frame in `codec` was forwarded
+    pub(crate) fn add_frame(&self, codec: &'static str, keyframe: bool) {
+        self.frames_total.fetch_add(1, Ordering::Relaxed);
+        let mut status = self.status.lock().unwrap();
+        let now = Instant::now();
+        status.last_frame = Some(now);
+        if keyframe {
+            status.last_keyframe = Some(now);
+        }
+        status.codec = Some(codec);
+    }
+
+    /// Note the time between two keyframes
+    pub(crate) fn add_keyframe_gap(&self, gap: Duration) {
+        self.max_keyframe_gap_ms
+            .fetch_max(gap.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Note how long a connection took from starting the video to its first keyframe
+    pub(crate) fn add_first_keyframe(&self, latency: Duration) {
+        debug!(
+            "{}: {} got its first keyframe after {:.2}s",
+            self.camera,
+            self.stream,
+            latency.as_secs_f64()
+        );
+        self.first_keyframe
+            .lock()
+            .unwrap()
+            .observe(latency.as_secs_f64());
+        self.status.lock().unwrap().first_keyframe = Some(latency);
+    }
+
+    /// Count a video frame that was dropped as broken
+    pub(crate) fn add_corrupt_frame(&self) {
+        self.corrupt_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a session closed by the camera that is resumed
+    pub(crate) fn add_resume(&self) {
+        self.resumes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a lost connection that needs the full reconnect
+    pub(crate) fn add_full_reconnect(&self) {
+        self.full_reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a session that failed
+    pub(crate) fn add_failure(&self, kind: FailureKind) {
+        let index = FailureKind::ALL
+            .iter()
+            .position(|k| *k == kind)
+            .expect("Every kind is in the list");
+        self.failures[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Note the new connection state of the stream and the `reason` for it
+    ///
+    /// A stream that stops streaming has no bitrate, its window starts again
+    /// so the next connection is not averaged with the bytes of the last
+    pub(crate) fn set_state(&self, state: ConnectionState, reason: &str) {
+        if state != ConnectionState::Streaming {
+            self.bitrate.store(0, Ordering::Relaxed);
+            *self.window.lock().unwrap() = (Instant::now(), 0);
+        }
+        let mut status = self.status.lock().unwrap();
+        match state {
+            ConnectionState::Connecting => status.connects += 1,
+            ConnectionState::Error => {
+                status.last_error = Some(reason.to_string());
+                status.failing_since.get_or_insert_with(Instant::now);
+            }
+            ConnectionState::Streaming => status.failing_since = None,
+            _ => {}
+        }
+        if let Some(since) = status.streaming_since.take() {
+            status.streamed += since.elapsed();
+        }
+        if state == ConnectionState::Streaming {
+            status.streaming_since = Some(Instant::now());
+        }
+        status.state = state;
+    }
+
+    /// Report the queue of this stream and count its dropped packets
+    pub(crate) fn watch_queue(&self, queue: &mut StreamQueue) {
+        self.queue_capacity
+            .store(queue.capacity() as u64, Ordering::Relaxed);
+        queue.set_dropped_counter(self.dropped_packets.clone());
+    }
+
+    fn labels(&self) -> String {
+        format!("{{{}}}", self.label_pairs())
+    }
+
+    fn label_pairs(&self) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        format!(
+            "camera=\"{}\",stream=\"{}\"",
+            escape(&self.camera),
+            escape(&self.stream)
+        )
+    }
+}
+
+// Like `1h02m03s`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_render() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.3);
+        histogram.observe(1.5);
+        histogram.observe(60.0);
+        let mut out = String::new();
+        histogram.render(&mut out, "latency", "camera=\"Garage\"");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "latency_bucket{camera=\"Garage\",le=\"0.25\"} 0");
+        assert_eq!(lines[1], "latency_bucket{camera=\"Garage\",le=\"0.5\"} 1");
+        assert_eq!(lines[3], "latency_bucket{camera=\"Garage\",le=\"2\"} 2");
+        assert_eq!(lines[7], "latency_bucket{camera=\"Garage\",le=\"32\"} 2");
+        assert_eq!(lines[8], "latency_bucket{camera=\"Garage\",le=\"+Inf\"} 3");
+        assert_eq!(lines[9], "latency_sum{camera=\"Garage\"} 61.8");
+        assert_eq!(lines[10], "latency_count{camera=\"Garage\"} 3");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(900)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(60)), "1m00s");
+        assert_eq!(format_duration(Duration::from_secs(3599)), "59m59s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+        assert_eq!(format_duration(Duration::from_secs(90000)), "25h00m00s");
+    }
+
+    #[test]
+    fn test_is_healthy() {
+        let metrics = Metrics::default();
+        assert!(!metrics.is_healthy(true));
+        assert!(!metrics.is_healthy(false));
+
+        let garage = metrics.add_stream("Garage", "mainStream");
+        let garage_sub = metrics.add_stream("Garage", "subStream");
+        let door = metrics.add_stream("Door", "mainStream");
+        garage.set_state(ConnectionState::Streaming, "");
+        garage_sub.set_state(ConnectionState::Idle, "");
+        door.set_state(ConnectionState::Error, "Unable to connect");
+        assert!(!metrics.is_healthy(true));
+        assert!(metrics.is_healthy(false));
+
+        // A camera with no demand is left out
+        door.set_state(ConnectionState::Idle, "");
+        assert!(metrics.is_healthy(true));
+        garage.set_state(ConnectionState::Idle, "");
+        assert!(metrics.is_healthy(true));
+        assert!(metrics.is_healthy(false));
+
+        garage.set_state(ConnectionState::Connecting, "");
+        assert!(!metrics.is_healthy(true));
+        assert!(!metrics.is_healthy(false));
+    }
+
+    #[test]
+    fn test_status_json() {
+        let metrics = Metrics::default();
+        let main = metrics.add_stream("StatusGarage", "mainStream");
+        main.set_state(ConnectionState::Connecting, "");
+        main.set_state(ConnectionState::Error, "Connection lost");
+        main.set_state(ConnectionState::Connecting, "");
+        main.set_state(ConnectionState::Streaming, "");
+        main.add_frame("H265", true);
+        main.add_first_keyframe(Duration::from_millis(1500));
+        metrics.add_stream("StatusGarage", "subStream");
+
+        let status: serde_json::Value = serde_json::from_str(&metrics.status_json(true)).unwrap();
+        assert_eq!(status["healthy"], true);
+        let streams = status["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0]["camera"], "StatusGarage");
+        assert_eq!(streams[0]["stream"], "mainStream");
+        assert_eq!(streams[0]["state"], "Streaming");
+        assert_eq!(streams[0]["codec"], "H265");
+        assert_eq!(streams[0]["reconnects"], 1);
+        assert_eq!(streams[0]["first_keyframe_seconds"], 1.5);
+        assert!(streams[0]["last_frame_age_seconds"].as_f64().unwrap() < 60.0);
+        assert_eq!(streams[0]["last_motion"], serde_json::Value::Null);
+        assert_eq!(streams[1]["state"], "Disconnected");
+        assert_eq!(streams[1]["reconnects"], 0);
+        assert_eq!(streams[1]["codec"], serde_json::Value::Null);
+        assert_eq!(
+            streams[1]["last_frame_age_seconds"],
+            serde_json::Value::Null
+        );
+
+        main.set_state(ConnectionState::Error, "Connection lost");
+        let status: serde_json::Value = serde_json::from_str(&metrics.status_json(false)).unwrap();
+        assert_eq!(status["healthy"], false);
+    }
+
+    #[test]
+    fn test_summary() {
+        let metrics = Metrics::default();
+        let main = metrics.add_stream("Garage", "mainStream");
+        main.set_state(ConnectionState::Connecting, "");
+        main.set_state(ConnectionState::Error, "Unable to connect");
+        main.set_state(ConnectionState::Connecting, "");
+        main.add_bytes(2_500_000);
+        main.add_frame("H264", true);
+        main.add_frame("H264", false);
+        main.add_keyframe_gap(Duration::from_millis(2500));
+        metrics.add_stream("Door", "subStream");
+
+        let summary = metrics.summary();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 3);
+        let columns = |line: &str| -> Vec<String> {
+            line.split("  ")
+                .map(str::trim)
+                .filter(|cell| !cell.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        assert_eq!(
+            columns(lines[0]),
+            [
+                "CAMERA",
+                "STREAM",
+                "STREAMED",
+                "RECONNECTS",
+                "FORWARDED",
+                "FRAMES",
+                "MAX KEYFRAME GAP",
+                "LAST ERROR"
+            ]
+        );
+        assert_eq!(
+            columns(lines[1]),
+            [
+                "Garage",
+                "mainStream",
+                "0s",
+                "1",
+                "2.5 MB",
+                "2",
+                "2.5s",
+                "Unable to connect"
+            ]
+        );
+        assert_eq!(
+            columns(lines[2]),
+            ["Door", "subStream", "0s", "0", "0.0 MB", "0", "0.0s", "-"]
+        );
+        // The columns line up
+        assert_eq!(lines[0].find("STREAM "), lines[1].find("mainStream"));
+    }
+
+    #[test]
+    fn test_bitrate_resets_when_the_stream_stops() {
+        let metrics = Metrics::default();
+        let main = metrics.add_stream("Garage", "mainStream");
+        let gauge = |metrics: &Metrics| {
+            metrics
+                .render()
+                .lines()
+                .find(|line| line.starts_with("neolink_stream_bitrate_bits_per_second{"))
+                .map(str::to_string)
+        };
+        main.set_state(ConnectionState::Streaming, "");
+        main.add_bytes(1000);
+        main.bitrate.store(2_000_000, Ordering::Relaxed);
+        assert!(gauge(&metrics).unwrap().ends_with(" 2000000"));
+
+        main.set_state(ConnectionState::Error, "Connection lost");
+        assert!(gauge(&metrics).unwrap().ends_with(" 0"));
+        // The bytes of the lost connection are not carried into the next
+        assert_eq!(main.window.lock().unwrap().1, 0);
+
+        main.bitrate.store(2_000_000, Ordering::Relaxed);
+        main.set_state(ConnectionState::Idle, "");
+        assert!(gauge(&metrics).unwrap().ends_with(" 0"));
+    }
+}
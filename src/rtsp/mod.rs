@@ -28,33 +28,176 @@
 /// neolink rtsp --config=config.toml
 /// ```
 ///
-use anyhow::{Context, Result};
+/// The cameras are reloaded from the config when neolink receives a SIGHUP.
+///
+/// To see the urls the streams will be served at without serving them use
+///
+/// ```bash
+/// neolink rtsp --config=config.toml --print-rtsp-urls
+/// ```
+///
+/// To watch a live table of the streams in the terminal instead of the logs use
+///
+/// ```bash
+/// neolink rtsp --config=config.toml --tui
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use crossbeam::{sync::WaitGroup, thread::Scope};
 use log::*;
-use neolink_core::bc_protocol::{BcCamera, Stream};
-use std::collections::HashSet;
-use std::sync::Arc;
-use std::time::Duration;
+use neolink_core::bc_protocol::{BcCamera, Stream, StreamFanOut, StreamQueue};
+use std::collections::{BTreeMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
 
 // mod adpcm;
+/// Drops the main stream to the sub stream when the link can't keep up
+mod adaptive;
+/// Rewrites the rtsp responses to give the clients an advertised host
+mod advertise;
+/// Alerts when a camera has not streamed for too long
+mod alert;
+/// Reboots a camera that keeps reconnecting
+mod autoreboot;
+/// Watches the TLS certificate for changes
+mod certwatch;
 /// The command line parameters for this subcommand
 mod cmdline;
+/// Switches a camera between its default and other streams on demand
+mod demand;
+/// Sorts the errors that end a camera session into kinds
+mod failure;
+/// Drops the video frames that are too broken to decode
+mod frame_check;
 /// The errors this subcommand can raise
 mod gst;
+/// Notices when a stream goes too long without a keyframe
+mod keyframe_gap;
+/// Counts the media forwarded from each camera stream
+mod metrics;
+/// Sends JPEGs of the cameras that see motion
+mod motion_snapshot;
+/// Reloads the cameras of the config on SIGHUP
+mod reload;
+/// Serves jpeg snapshots of the cameras over http
+mod snapshot;
+/// Logs the connection state of the camera streams
+mod state;
+/// Draws a live table of the camera streams in the terminal
+mod tui;
+/// Prints the urls the streams are served at
+mod urls;
 
-use super::config::{CameraConfig, Config, UserConfig};
-use crate::utils::AddressOrUid;
+use super::config::{
+    stream_from_name, CameraConfig, Config, RecordConfig, StreamAlertConfig, UserConfig,
+};
+use crate::encoder;
+use crate::lastmotion;
+use crate::privacy;
+use crate::quirks::Quirks;
+use crate::reboot;
+use crate::record;
+use crate::recschedule;
+use crate::utils::{
+    connect_with_timeout, is_camera_hangup, is_login_locked, live_output, suppressed_changes,
+    wait_for_login_lockout, AddressOrUid, CameraStop, GiveUp, Jitter,
+};
+use adaptive::AdaptiveStream;
+use autoreboot::AutoReboot;
 pub(crate) use cmdline::Opt;
-use gst::{GstOutputs, RtspServer, TlsAuthenticationMode};
+use demand::StreamDemand;
+use failure::FailureKind;
+use frame_check::FrameCheck;
+use gst::{rtsp_protocols, GstOutputs, RtspServer, TlsAuthenticationMode};
+use metrics::Metrics;
+use reload::CameraChanges;
+pub(crate) use snapshot::keyframe_to_jpeg;
+use snapshot::{KeyframeCache, SnapshotCamera, SnapshotCameras};
+use state::{ConnectionState, StateLog};
+
+// Clients joining within this time of the last keyframe request share it
+const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_secs(2);
+
+// Streams pulled on demand, instead of the default stream, switch back after
+// this long without clients unless the camera has an `idle_timeout`
+const ON_DEMAND_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A stream thread that panics is restarted after this delay
+#[cfg(not(test))]
+const PANIC_RESTART_DELAY: Duration = Duration::from_secs(5);
+#[cfg(test)]
+const PANIC_RESTART_DELAY: Duration = Duration::from_millis(1);
+
+// A stream that keeps panicking gives up after this many restarts
+const MAX_PANIC_RESTARTS: u32 = 5;
+
+// How long an automatic reboot waits for the camera to come back
+const AUTO_REBOOT_WAIT_SECS: u64 = 120;
+
+// How long a reload or the shutdown waits for the threads of a stopped camera
+const CAMERA_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// A resumed session the camera closes again before this long is reconnected
+// in full, so a camera that keeps hanging up gets the backoff
+const RESUME_MIN_SESSION: Duration = Duration::from_secs(60);
+
+// How often a camera in privacy mode is asked whether it has been turned off
+const PRIVACY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+// The context of the error of a stream that gave up reconnecting
+const GAVE_UP: &str = "Gave up reconnecting";
+
+// How the stream of a camera is pulled
+#[derive(Clone)]
+enum StreamRole {
+    // Pulled all the time
+    Always,
+    // Pulled except when one of the other streams is in demand
+    Default(Arc<StreamDemand>),
+    // Pulled instead of the default stream while it has clients
+    OnDemand(Arc<StreamDemand>),
+}
 
 /// Entry point for the rtsp subcommand
 ///
 /// Opt is the command line options
-pub(crate) fn main(_opt: Opt, config: Config) -> Result<()> {
-    let rtsp = &RtspServer::new();
+pub(crate) fn main(
+    opt: Opt,
+    config: Config,
+    reload: &(dyn Fn() -> Result<Config> + Sync),
+) -> Result<()> {
+    if opt.print_urls {
+        urls::print_urls(&config, opt.show_secrets);
+        return Ok(());
+    }
 
-    set_up_tls(&config, rtsp);
+    let record_config = match (opt.record, &config.record) {
+        (false, _) => None,
+        (true, Some(record_config)) => Some(record_config.clone()),
+        (true, None) => return Err(anyhow!("--record needs a [record] section in the config")),
+    };
+    let record_config = record_config.as_ref();
 
-    set_up_users(&config.users, rtsp);
+    // One server for the shared port and one for each camera with its own port
+    let mut servers = BTreeMap::new();
+    for port in
+        std::iter::once(config.bind_port).chain(config.cameras.iter().filter_map(|c| c.bind_port))
+    {
+        let rtsp = RtspServer::new();
+        set_up_tls(&config, &rtsp);
+        set_up_users(&config.users, &rtsp);
+        if let Some(host) = &config.advertised_host {
+            rtsp.advertise_host(host);
+        }
+        servers.insert(port, rtsp);
+    }
+    if let Some(host) = &config.advertised_host {
+        info!("RTSP: Advertising the host {} to the clients", host);
+    }
 
     if config.certificate == None && !config.users.is_empty() {
         warn!(
@@ -62,56 +205,655 @@ pub(crate) fn main(_opt: Opt, config: Config) -> Result<()> {
         )
     }
 
+    let cert_path = config.certificate.clone();
+    let http_port = config.http_port;
+    let credentials: Vec<_> = config
+        .users
+        .iter()
+        .map(|user| (user.name.clone(), user.pass.clone()))
+        .collect();
+    let snapshot_cameras = SnapshotCameras::default();
+    let live_streams = LiveStreams::default();
+    let shutdown = Arc::new(CameraStop::default());
+    let metrics = Arc::new(Metrics::default());
+    let interrupted = shutdown.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if interrupted.is_stopped() {
+            // A second Ctrl-C does not wait for the cameras
+            tui::restore();
+            std::process::exit(1);
+        }
+        info!("Shutting down, press Ctrl-C again to exit straight away");
+        interrupted.stop();
+    }) {
+        warn!(
+            "Unable to set the Ctrl-C handler, no summary will be printed: {}",
+            e
+        );
+    }
+    let served = Served {
+        servers: &servers,
+        users: &config.users,
+        record_config,
+        scheme: if cert_path.is_some() { "rtsps" } else { "rtsp" },
+        url_host: config
+            .advertised_host
+            .as_deref()
+            .unwrap_or(&config.bind_addr),
+        bind_port: config.bind_port,
+        http_port,
+        rtsp_transport: &config.rtsp_transport,
+        stream_alert: config.stream_alert.as_ref(),
+        metrics: &metrics,
+        snapshot_cameras: &snapshot_cameras,
+        live_streams: &live_streams,
+        shutdown: &shutdown,
+    };
+    let served = &served;
+    if opt.tui {
+        tui::start(metrics.clone());
+    }
+
+    reload::install_sighup_handler();
     crossbeam::scope(|s| {
-        for camera in config.cameras {
-            if camera.format.is_some() {
-                warn!("The format config option of the camera has been removed in favour of auto detection.")
-            }
-            // Let subthreads share the camera object; in principle I think they could share
-            // the object as it sits in the config.cameras block, but I have not figured out the
-            // syntax for that.
-            let arc_cam = Arc::new(camera);
-
-            let permitted_users =
-                get_permitted_users(config.users.as_slice(), &arc_cam.permitted_users);
-
-            // Set up each main and substream according to all the RTSP mount paths we support
-            if ["all", "both", "mainStream"].iter().any(|&e| e == arc_cam.stream) {
-                let paths = &[
-                    &*format!("/{}", arc_cam.name),
-                    &*format!("/{}/mainStream", arc_cam.name),
-                ];
-                let mut outputs = rtsp
-                    .add_stream(paths, &permitted_users)
-                    .unwrap();
-                let main_camera = arc_cam.clone();
-                s.spawn(move |_| camera_loop(&*main_camera, Stream::Main, &mut outputs, true));
+        // The most important cameras are the first to connect
+        let mut cameras: Vec<_> = config.cameras.iter().collect();
+        cameras.sort_by_key(|camera| std::cmp::Reverse(camera.priority));
+        let running: Vec<_> = cameras
+            .into_iter()
+            .enumerate()
+            .filter_map(|(position, camera)| {
+                let delay = config.startup_delay(camera, position);
+                start_camera(s, served, camera.clone(), delay)
+            })
+            .collect();
+        s.spawn(move |s| reload_cameras(s, served, reload, running));
+
+        if let Some(cert_path) = &cert_path {
+            let rtsp: Vec<&RtspServer> = servers.values().collect();
+            let shutdown = &shutdown;
+            s.spawn(move |_| certwatch::watch_certificate(cert_path, &rtsp, shutdown));
+        }
+
+        if let Some(http_port) = http_port {
+            let bind_addr = &config.bind_addr;
+            let credentials = credentials.clone();
+            let cameras = snapshot_cameras.clone();
+            let metrics = metrics.clone();
+            let healthy_needs_all = config.health_check == "all";
+            let shutdown = &shutdown;
+            s.spawn(move |_| {
+                if let Err(e) = snapshot::serve(
+                    bind_addr,
+                    http_port,
+                    cameras,
+                    credentials,
+                    metrics,
+                    healthy_needs_all,
+                    shutdown,
+                ) {
+                    error!("Snapshot server failed: {:?}", e);
+                }
+            });
+        }
+
+        for (port, rtsp) in &servers {
+            rtsp.attach(&config.bind_addr, *port);
+        }
+        RtspServer::run_main_loop(shutdown.clone());
+        // The reload thread stops the cameras and waits for them
+        shutdown.stop();
+    })
+    .unwrap();
+
+    tui::restore();
+    println!("{}", metrics.summary());
+    if live_streams.every_stream_gave_up() {
+        return Err(anyhow!("Every camera stream gave up reconnecting"));
+    }
+    let suppressed = suppressed_changes();
+    if suppressed > 0 {
+        return Err(anyhow!(
+            "{} changes to the cameras were only logged in read only mode",
+            suppressed
+        ));
+    }
+    Ok(())
+}
+
+/// What the streams of all the cameras share
+struct Served<'a> {
+    servers: &'a BTreeMap<u16, RtspServer>,
+    users: &'a [UserConfig],
+    record_config: Option<&'a RecordConfig>,
+    scheme: &'a str,
+    // The host of the urls that are logged
+    url_host: &'a str,
+    bind_port: u16,
+    http_port: Option<u16>,
+    rtsp_transport: &'a str,
+    stream_alert: Option<&'a StreamAlertConfig>,
+    metrics: &'a Metrics,
+    snapshot_cameras: &'a SnapshotCameras,
+    live_streams: &'a LiveStreams,
+    // Stopped when the server shuts down
+    shutdown: &'a CameraStop,
+}
+
+/// Counts the streams of the configured cameras that are still trying to
+/// stream, so that the server shuts down once the streams that are left have
+/// all given up reconnecting
+#[derive(Default)]
+struct LiveStreams {
+    counts: Mutex<StreamCounts>,
+}
+
+#[derive(Default)]
+struct StreamCounts {
+    running: usize,
+    // The cameras with a stream that gave up
+    gave_up: HashSet<String>,
+    // A reload or the shutdown is stopping cameras
+    paused: bool,
+    every_stream_gave_up: bool,
+}
+
+impl StreamCounts {
+    // True the first time that every stream that is left has given up
+    fn check(&mut self) -> bool {
+        if self.paused || self.every_stream_gave_up {
+            return false;
+        }
+        self.every_stream_gave_up = self.running == 0 && !self.gave_up.is_empty();
+        self.every_stream_gave_up
+    }
+}
+
+impl LiveStreams {
+    fn started(&self) {
+        self.counts.lock().unwrap().running += 1;
+    }
+
+    /// A stream of `camera` ended, true if the streams that are left have all given up
+    fn ended(&self, camera: &str, gave_up: bool) -> bool {
+        let mut counts = self.counts.lock().unwrap();
+        counts.running -= 1;
+        if gave_up {
+            counts.gave_up.insert(camera.to_string());
+        }
+        counts.check()
+    }
+
+    /// Stop checking while cameras are stopped on purpose
+    fn pause(&self) {
+        self.counts.lock().unwrap().paused = true;
+    }
+
+    /// Check again after a reload, forgetting the streams of the `stopped` cameras
+    ///
+    /// True if the streams that are left have all given up
+    fn resume(&self, stopped: &[String]) -> bool {
+        let mut counts = self.counts.lock().unwrap();
+        for camera in stopped {
+            counts.gave_up.remove(camera);
+        }
+        counts.paused = false;
+        counts.check()
+    }
+
+    /// Whether the server shut down because every stream gave up
+    fn every_stream_gave_up(&self) -> bool {
+        self.counts.lock().unwrap().every_stream_gave_up
+    }
+}
+
+/// A camera whose streams are being served
+struct ServedCamera {
+    config: Arc<CameraConfig>,
+    stop: Arc<CameraStop>,
+    // Held by each thread of the camera until it finishes
+    threads: WaitGroup,
+    port: u16,
+    // The mount paths of every stream
+    paths: Vec<String>,
+}
+
+/// Mount the streams of `camera` and spawn their threads in `s`
+///
+/// Returns `None` if the camera cannot be served, which only happens for a
+/// camera added by a reload with a `bind_port` that has no server
+fn start_camera<'env>(
+    s: &Scope<'env>,
+    served: &'env Served<'env>,
+    camera: CameraConfig,
+    delay: Duration,
+) -> Option<ServedCamera> {
+    if camera.format.is_some() {
+        warn!(
+            "The format config option of the camera has been removed in favour of auto detection."
+        )
+    }
+    let port = camera.bind_port.unwrap_or(served.bind_port);
+    let rtsp = match served.servers.get(&port) {
+        Some(rtsp) => rtsp,
+        None => {
+            warn!(
+                "{}: Not serving the camera, its bind_port {} needs a restart of neolink",
+                camera.name, port
+            );
+            return None;
+        }
+    };
+    // Let subthreads share the camera object; in principle I think they could share
+    // the object as it sits in the config.cameras block, but I have not figured out the
+    // syntax for that.
+    let arc_cam = Arc::new(camera);
+    let stop = Arc::new(CameraStop::default());
+    let threads = WaitGroup::new();
+
+    let permitted_users = get_permitted_users(served.users, &arc_cam.permitted_users);
+
+    let log_urls = |paths: &[&str]| {
+        for path in paths {
+            info!(
+                "{}: Serving at {}://{}:{}{}",
+                arc_cam.name, served.scheme, served.url_host, port, path
+            );
+        }
+    };
+
+    // Snapshots are taken from the first enabled stream, usually the main stream
+    let keyframes = Arc::new(KeyframeCache::default());
+    served.snapshot_cameras.lock().unwrap().insert(
+        arc_cam.name.clone(),
+        SnapshotCamera {
+            cache: keyframes.clone(),
+            permitted_users: permitted_users.iter().map(|u| u.to_string()).collect(),
+        },
+    );
+
+    let record_config = served.record_config;
+    let camera_record =
+        record_config.filter(|rc| arc_cam.enabled_streams().contains(&rc.stream.as_str()));
+    let default_stream = match (&arc_cam.default_stream, camera_record) {
+        (Some(_), Some(_)) => {
+            warn!(
+                "{}: The default_stream is ignored while recording",
+                arc_cam.name
+            );
+            None
+        }
+        (default_stream, _) => default_stream.clone(),
+    };
+    let default_stream = default_stream.as_deref();
+    let demand = Arc::new(StreamDemand::default());
+    let managing_stream = managing_stream(&arc_cam, default_stream);
+
+    // Set up each main and substream according to all the RTSP mount paths we support
+    let mut recorded = false;
+    let mut all_paths = vec![];
+    for (i, stream_name) in arc_cam.enabled_streams().into_iter().enumerate() {
+        let paths = arc_cam.rtsp_paths(stream_name);
+        let path_refs: Vec<&str> = paths.iter().map(String::as_str).collect();
+        log_urls(&path_refs);
+        let mut outputs = rtsp.add_stream(&path_refs, &permitted_users).unwrap();
+        outputs.set_protocols(rtsp_protocols(
+            arc_cam
+                .rtsp_transport
+                .as_deref()
+                .unwrap_or(served.rtsp_transport),
+        ));
+        all_paths.extend(paths.iter().cloned());
+        if i == 0 && (served.http_port.is_some() || arc_cam.motion_snapshot.is_some()) {
+            outputs.set_keyframe_cache(keyframes.clone());
+        }
+        outputs.set_metrics(served.metrics.add_stream(&arc_cam.name, stream_name));
+        outputs.set_keyframe_interval(
+            &arc_cam.name,
+            Duration::try_from_secs_f64(arc_cam.keyframe_interval).unwrap_or_default(),
+        );
+        if let Some(frame_check) = &arc_cam.frame_check {
+            outputs.set_frame_check(FrameCheck::new(&arc_cam.name, frame_check));
+        }
+        if arc_cam.iframe_only {
+            warn!(
+                "{}: Forwarding only the keyframes of the {} stream, players will show \
+                one frame every keyframe interval",
+                arc_cam.name, stream_name
+            );
+            outputs.set_iframe_only(true);
+        }
+        let stream = stream_from_name(stream_name).expect("The enabled streams have known names");
+        let record = record_config.filter(|rc| record::record_stream(rc) == stream);
+        if let (Some(adaptive_stream), Stream::Main) = (&arc_cam.adaptive_stream, stream) {
+            if record.is_some() {
+                warn!(
+                    "{}: The adaptive_stream is ignored for the recorded {}",
+                    arc_cam.name, stream_name
+                );
+            } else {
+                outputs.set_adaptive(AdaptiveStream::new(&arc_cam.name, adaptive_stream));
             }
-            if ["all", "both", "subStream"].iter().any(|&e| e == arc_cam.stream) {
-                let paths = &[&*format!("/{}/subStream", arc_cam.name)];
-                let mut outputs = rtsp
-                    .add_stream(paths, &permitted_users)
-                    .unwrap();
-                let sub_camera = arc_cam.clone();
-                let manage = arc_cam.stream == "subStream";
-                s.spawn(move |_| camera_loop(&*sub_camera, Stream::Sub, &mut outputs, manage));
+        }
+        let manage = managing_stream == Some(stream_name);
+        let role = match default_stream {
+            None => StreamRole::Always,
+            Some(default_stream) => {
+                // The default stream is the one that is always connected
+                if default_stream == stream_name {
+                    outputs.set_demand(demand.clone());
+                    StreamRole::Default(demand.clone())
+                } else {
+                    StreamRole::OnDemand(demand.clone())
+                }
             }
-            if ["all", "externStream"].iter().any(|&e| e == arc_cam.stream) {
-                let paths = &[&*format!("/{}/externStream", arc_cam.name)];
-                let mut outputs = rtsp
-                    .add_stream(paths, &permitted_users)
-                    .unwrap();
-                let sub_camera = arc_cam.clone();
-                let manage = arc_cam.stream == "externStream";
-                s.spawn(move |_| camera_loop(&*sub_camera, Stream::Extern, &mut outputs, manage));
+        };
+        if record.is_some() {
+            recorded = true;
+            if arc_cam.idle_timeout.is_some() {
+                warn!(
+                    "{}: The idle_timeout is ignored for the recorded {}",
+                    arc_cam.name, stream_name
+                );
             }
+        } else {
+            let idle_timeout = arc_cam.idle_timeout.map(Duration::from_secs);
+            let idle_timeout = match role {
+                StreamRole::OnDemand(_) => idle_timeout.or(Some(ON_DEMAND_IDLE_TIMEOUT)),
+                _ => idle_timeout,
+            };
+            outputs.set_idle_timeout(idle_timeout);
         }
+        let stream_camera = arc_cam.clone();
+        let stream_stop = stop.clone();
+        let thread = threads.clone();
+        served.live_streams.started();
+        s.spawn(move |_| {
+            let _thread = thread;
+            if !delay.is_zero() {
+                info!(
+                    "{}: Waiting {:.1}s before connecting the {:?} stream",
+                    stream_camera.name,
+                    delay.as_secs_f64(),
+                    stream
+                );
+                // A stopped camera ends straight away in the camera loop
+                stream_stop.sleep(delay);
+            }
+            let result = supervise_stream(&stream_camera, stream, &stream_stop, || {
+                let role = role.clone();
+                camera_loop(
+                    &stream_camera,
+                    stream,
+                    &mut outputs,
+                    manage,
+                    record,
+                    role,
+                    &stream_stop,
+                )
+            });
+            let gave_up = matches!(&result, Err(e) if e.downcast_ref::<&str>() == Some(&GAVE_UP));
+            if served.live_streams.ended(&stream_camera.name, gave_up) {
+                error!("Every camera stream gave up reconnecting, shutting down");
+                served.shutdown.stop();
+            }
+            result
+        });
+    }
+    if let Some(motion_snapshot) = &arc_cam.motion_snapshot {
+        let snapshot_camera = arc_cam.clone();
+        let snapshot_stop = stop.clone();
+        let thread = threads.clone();
+        let motion_snapshot = motion_snapshot.clone();
+        s.spawn(move |_| {
+            let _thread = thread;
+            motion_snapshot::send_on_motion(
+                &snapshot_camera.name,
+                &motion_snapshot,
+                &keyframes,
+                &snapshot_stop,
+            );
+        });
+    }
+    if let Some(stream_alert) = arc_cam.stream_alert.as_ref().or(served.stream_alert) {
+        let alert_camera = arc_cam.clone();
+        let alert_stop = stop.clone();
+        let thread = threads.clone();
+        let stream_alert = stream_alert.clone();
+        s.spawn(move |_| {
+            let _thread = thread;
+            alert::watch(
+                &alert_camera.name,
+                &stream_alert,
+                served.metrics,
+                &alert_stop,
+            );
+        });
+    }
+    if let (Some(record_config), false) = (record_config, recorded) {
+        warn!(
+            "{}: Not recording because the {} is not enabled",
+            arc_cam.name, record_config.stream
+        );
+    }
 
-        rtsp.run(&config.bind_addr, config.bind_port);
+    Some(ServedCamera {
+        config: arc_cam,
+        stop,
+        threads,
+        port,
+        paths: all_paths,
     })
-    .unwrap();
+}
 
-    Ok(())
+/// Unmount the streams of `camera` and ask its threads to finish
+///
+/// The threads end the stream for the clients still playing it, wait for
+/// them with `wait_for_camera`
+fn stop_camera(served: &Served, camera: &ServedCamera) {
+    camera.stop.stop();
+    let paths: Vec<&str> = camera.paths.iter().map(String::as_str).collect();
+    served.servers[&camera.port].remove_stream(&paths);
+    served
+        .snapshot_cameras
+        .lock()
+        .unwrap()
+        .remove(&camera.config.name);
+    served.metrics.remove_camera(&camera.config.name);
+}
+
+/// Wait up to `timeout` for the threads of a stopped `camera` to finish
+///
+/// By then they have let go of their connections to the camera. A thread
+/// stuck on the network is left to finish on its own, returns false if any
+/// of them did not finish in time.
+fn wait_for_camera(camera: ServedCamera, timeout: Duration) -> bool {
+    let name = camera.config.name.clone();
+    let threads = camera.threads;
+    let (finished, done) = crossbeam::channel::bounded(1);
+    std::thread::spawn(move || {
+        threads.wait();
+        let _ = finished.send(());
+    });
+    match done.recv_timeout(timeout) {
+        Ok(()) => {
+            debug!("{}: All threads finished", name);
+            true
+        }
+        Err(_) => {
+            warn!(
+                "{}: Its threads did not finish within {}s, carrying on without them",
+                name,
+                timeout.as_secs()
+            );
+            false
+        }
+    }
+}
+
+/// Stop the removed and changed cameras of a reload and start the changed and added ones
+///
+/// A changed camera only starts again once the threads of its old section
+/// have finished or `timeout` has passed, otherwise both would hold a
+/// connection to the camera
+fn apply_changes(
+    running: &mut Vec<ServedCamera>,
+    changes: CameraChanges,
+    timeout: Duration,
+    mut stop: impl FnMut(&ServedCamera),
+    mut start: impl FnMut(CameraConfig) -> Option<ServedCamera>,
+) {
+    let mut take = |name: &str| {
+        running
+            .iter()
+            .position(|c| c.config.name == name)
+            .map(|i| running.remove(i))
+    };
+    let mut stopped = vec![];
+    for name in &changes.removed {
+        if let Some(camera) = take(name) {
+            info!("{}: Removed from the config, stopping", name);
+            stop(&camera);
+            stopped.push(camera);
+        }
+    }
+    for camera in &changes.changed {
+        if let Some(old) = take(&camera.name) {
+            info!("{}: Changed in the config, restarting", camera.name);
+            stop(&old);
+            stopped.push(old);
+        }
+    }
+    for camera in stopped {
+        wait_for_camera(camera, timeout);
+    }
+    for camera in changes.changed.into_iter().chain(changes.added) {
+        info!("{}: Starting", camera.name);
+        if let Some(camera) = start(camera) {
+            running.push(camera);
+        }
+    }
+}
+
+/// Apply the cameras of the reloaded config to the `running` ones on each SIGHUP
+///
+/// Once the server shuts down it stops the cameras and waits for their threads
+fn reload_cameras<'env>(
+    s: &Scope<'env>,
+    served: &'env Served<'env>,
+    reload: &(dyn Fn() -> Result<Config> + Sync),
+    mut running: Vec<ServedCamera>,
+) {
+    while reload::wait_for_sighup(served.shutdown) {
+        info!("Received SIGHUP, reloading the cameras of the config");
+        let config = match reload() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Keeping the running cameras, the reload failed: {:?}", e);
+                continue;
+            }
+        };
+        let current: Vec<CameraConfig> = running.iter().map(|c| (*c.config).clone()).collect();
+        let changes = CameraChanges::between(&current, &config.cameras);
+        if changes.is_empty() {
+            info!("The cameras are unchanged");
+            continue;
+        }
+        let stopped: Vec<String> = changes
+            .removed
+            .iter()
+            .cloned()
+            .chain(changes.changed.iter().map(|c| c.name.clone()))
+            .collect();
+        served.live_streams.pause();
+        apply_changes(
+            &mut running,
+            changes,
+            CAMERA_STOP_TIMEOUT,
+            |camera| stop_camera(served, camera),
+            |camera| {
+                let delay = Duration::try_from_secs_f64(camera.startup_delay).unwrap_or_default();
+                start_camera(s, served, camera, delay)
+            },
+        );
+        if served.live_streams.resume(&stopped) {
+            error!("Every camera stream that is left gave up reconnecting, shutting down");
+            served.shutdown.stop();
+        }
+    }
+    served.live_streams.pause();
+    for camera in &running {
+        stop_camera(served, camera);
+    }
+    for camera in running {
+        wait_for_camera(camera, CAMERA_STOP_TIMEOUT);
+    }
+}
+
+/// Run the `camera_loop` of a stream again if it panics
+///
+/// Otherwise a bug in the handling of one stream would leave it frozen for
+/// the rest of the process while the other streams carry on. A stream that
+/// panics `MAX_PANIC_RESTARTS` times in a row gives up, as its locks may be
+/// poisoned for good.
+fn supervise_stream<F>(
+    camera_config: &CameraConfig,
+    stream_name: Stream,
+    stop: &CameraStop,
+    mut stream_loop: F,
+) -> Result<()>
+where
+    F: FnMut() -> Result<()>,
+{
+    let mut restarts = 0;
+    loop {
+        match panic::catch_unwind(AssertUnwindSafe(&mut stream_loop)) {
+            Ok(result) => return result,
+            Err(_) if restarts >= MAX_PANIC_RESTARTS => {
+                error!(
+                    "{}: The {:?} stream panicked {} times, giving up on it",
+                    camera_config.name,
+                    stream_name,
+                    restarts + 1
+                );
+                return Err(anyhow!("The stream kept panicking").context(GAVE_UP));
+            }
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                error!(
+                    "{}: The {:?} stream panicked, restarting it in {}s: {}",
+                    camera_config.name,
+                    stream_name,
+                    PANIC_RESTART_DELAY.as_secs(),
+                    message
+                );
+                restarts += 1;
+                if stop.sleep(PANIC_RESTART_DELAY) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// The one stream of a camera that manages its settings, such as the time
+///
+/// This is the `default_stream` when there is one because it is the stream
+/// that stays connected, otherwise the first enabled stream. With `manage =
+/// false` it leaves the settings alone but still tracks the motion and, with
+/// `auto_reboot`, reboots the camera.
+fn managing_stream(
+    camera_config: &CameraConfig,
+    default_stream: Option<&str>,
+) -> Option<&'static str> {
+    let enabled = camera_config.enabled_streams();
+    default_stream
+        .and_then(|default_stream| enabled.iter().copied().find(|s| *s == default_stream))
+        .or_else(|| enabled.first().copied())
 }
 
 fn camera_loop(
@@ -119,18 +861,90 @@ fn camera_loop(
     stream_name: Stream,
     outputs: &mut GstOutputs,
     manage: bool,
+    record: Option<&RecordConfig>,
+    role: StreamRole,
+    stop: &CameraStop,
 ) -> Result<(), anyhow::Error> {
-    let min_backoff = Duration::from_secs(1);
-    let max_backoff = Duration::from_secs(15);
+    let (min_backoff, max_backoff) = camera_config.reconnect_backoff();
     let mut current_backoff = min_backoff;
+    let mut jitter = Jitter::for_camera(camera_config, &format!("rtsp {:?}", stream_name));
+    let mut state = StateLog::new(camera_config, &format!("{:?}", stream_name));
+    state.set_metrics(outputs.metrics().cloned());
+    // Set when the camera closed the last session itself, the next one then
+    // skips managing the camera settings to get the stream back sooner
+    let mut resume = false;
+    // Only the stream that manages the camera reboots it
+    let mut auto_reboot = camera_config
+        .auto_reboot
+        .as_ref()
+        .filter(|_| manage)
+        .map(AutoReboot::new);
+    let mut give_up = GiveUp::for_camera(camera_config);
 
     loop {
-        if let Err(cam_err) = camera_main(camera_config, stream_name, outputs, manage) {
+        if stop.is_stopped() {
+            state.transition(ConnectionState::Disconnected, "The camera was stopped");
+            outputs.end_of_stream();
+            return Ok(());
+        }
+        let _pulling = match &role {
+            StreamRole::OnDemand(demand) => {
+                outputs.wait_for_client(stop);
+                if stop.is_stopped() {
+                    continue;
+                }
+                info!(
+                    "{}: Rtsp client connected, switching to the {:?} stream",
+                    camera_config.name, stream_name
+                );
+                Some(demand.begin())
+            }
+            _ => None,
+        };
+
+        let resuming = std::mem::take(&mut resume);
+        let session_start = Instant::now();
+        // The mount of the main stream may be fed from the sub stream
+        let pulled = outputs.adaptive().map_or(stream_name, |a| a.wanted());
+        if let Some(adaptive) = outputs.adaptive_mut() {
+            adaptive.session_started(pulled);
+        }
+        if let Err(cam_err) = camera_main(
+            camera_config,
+            pulled,
+            outputs,
+            manage && !resuming,
+            record,
+            &mut state,
+            stop,
+        ) {
             outputs.vidsrc.on_stream_error();
             outputs.audsrc.on_stream_error();
+            let reason = match (cam_err.login_fail, cam_err.connected) {
+                (true, _) => "Login failed",
+                (false, true) => "Connection lost",
+                (false, false) => "Unable to connect",
+            };
+            state.transition(
+                ConnectionState::Error,
+                &format!("{} ({}): {:#}", reason, cam_err.kind.name(), cam_err.err),
+            );
+            if let Some(metrics) = outputs.metrics() {
+                metrics.add_failure(cam_err.kind);
+            }
+            if cam_err.connected && cam_err.kind == FailureKind::Timeout {
+                if let Some(adaptive) = outputs.adaptive_mut() {
+                    adaptive.stalled(Instant::now());
+                }
+            }
             // Authentication failures are permanent; we retry everything else
             if cam_err.connected {
                 current_backoff = min_backoff;
+                give_up.connected();
+            }
+            // An address that does not resolve is unlikely to resolve within seconds
+            if cam_err.kind == FailureKind::Dns {
+                current_backoff = max_backoff;
             }
             if cam_err.login_fail {
                 error!(
@@ -138,21 +952,143 @@ fn camera_loop(
                     camera_config.name
                 );
                 return Err(cam_err.err);
-            } else {
+            }
+            if cam_err.connected
+                && is_camera_hangup(&cam_err.err)
+                && (!resuming || session_start.elapsed() >= RESUME_MIN_SESSION)
+            {
+                info!(
+                    "{}: The camera closed the {:?} stream, resuming it",
+                    camera_config.name, stream_name
+                );
+                state.transition(
+                    ConnectionState::Disconnected,
+                    "The camera closed the session, resuming it",
+                );
+                if let Some(metrics) = outputs.metrics() {
+                    metrics.add_resume();
+                }
+                resume = true;
+                continue;
+            }
+            if let Some(metrics) = outputs.metrics() {
+                metrics.add_full_reconnect();
+            }
+            if cam_err.login_locked {
+                state.transition(
+                    ConnectionState::Disconnected,
+                    "The account is temporarily locked",
+                );
+                wait_for_login_lockout(camera_config, &mut jitter, Some(stop));
+                continue;
+            }
+            if let Some(auto_reboot) = &mut auto_reboot {
+                if auto_reboot.reconnect(Instant::now()) {
+                    let (reconnects, minutes) = auto_reboot.limit();
+                    warn!(
+                        "{}: Reconnected {} times within {} minutes, rebooting the camera",
+                        camera_config.name, reconnects, minutes
+                    );
+                    state.transition(
+                        ConnectionState::Disconnected,
+                        "Rebooting the camera after too many reconnects",
+                    );
+                    if let Err(e) =
+                        reboot::reboot_camera(camera_config, AUTO_REBOOT_WAIT_SECS, Some(stop))
+                    {
+                        warn!(
+                            "{}: The automatic reboot failed: {:?}",
+                            camera_config.name, e
+                        );
+                    }
+                }
+            }
+            let now = Instant::now();
+            if !cam_err.connected && give_up.failed(now) {
+                let (attempts, duration) = give_up.failing(now);
                 error!(
-                    "Error streaming from camera {}, will retry in {}s: {:?}",
+                    "{}: Giving up on the {:?} stream after {} attempts over {}s that could not connect: {:?}",
                     camera_config.name,
-                    current_backoff.as_secs(),
+                    stream_name,
+                    attempts,
+                    duration.as_secs(),
                     cam_err.err
-                )
+                );
+                state.transition(ConnectionState::Disconnected, GAVE_UP);
+                outputs.end_of_stream();
+                return Err(cam_err.err.context(GAVE_UP));
             }
+            let delay = jitter.apply(current_backoff);
+            error!(
+                "Error streaming from camera {}, {}, will retry in {:.1}s: {:?}",
+                camera_config.name,
+                cam_err.kind.describe(),
+                delay.as_secs_f32(),
+                cam_err.err
+            );
 
-            std::thread::sleep(current_backoff);
+            state.transition(
+                ConnectionState::Disconnected,
+                &format!("Retrying in {:.1}s", delay.as_secs_f32()),
+            );
+            stop.sleep(delay);
             current_backoff = std::cmp::min(max_backoff, current_backoff * 2);
         } else {
-            // Should not occur because we don't set the callback up
-            // in such a way that it requests graceful shutdown
-            return Ok(());
+            give_up.connected();
+            if stop.is_stopped() {
+                continue;
+            }
+            if let Some(adaptive) = outputs.adaptive().filter(|a| a.needs_switch()) {
+                state.transition(
+                    ConnectionState::Disconnected,
+                    &format!("Switching to the {:?} stream", adaptive.wanted()),
+                );
+                current_backoff = min_backoff;
+                continue;
+            }
+            match &role {
+                StreamRole::OnDemand(_) => {
+                    // The clients left so the default stream can take over again
+                    state.transition(
+                        ConnectionState::Idle,
+                        "No rtsp clients, disconnecting until one connects",
+                    );
+                    current_backoff = min_backoff;
+                }
+                StreamRole::Default(demand) if demand.is_upgraded() => {
+                    state.transition(
+                        ConnectionState::Idle,
+                        "Another stream was requested, disconnecting until it has no clients",
+                    );
+                    demand.wait_for_default();
+                    info!(
+                        "{}: Switching back to the {:?} stream",
+                        camera_config.name, stream_name
+                    );
+                    current_backoff = min_backoff;
+                }
+                _ if camera_config.idle_timeout.is_some() => {
+                    // The stream stopped because nobody was watching
+                    state.transition(
+                        ConnectionState::Idle,
+                        "No rtsp clients, disconnecting until one connects",
+                    );
+                    outputs.wait_for_client(stop);
+                    if stop.is_stopped() {
+                        continue;
+                    }
+                    info!(
+                        "{}: Rtsp client connected, reconnecting",
+                        camera_config.name
+                    );
+                    current_backoff = min_backoff;
+                }
+                _ => {
+                    // Should not occur because we don't set the callback up
+                    // in such a way that it requests graceful shutdown
+                    return Ok(());
+                }
+            }
         }
     }
 }
@@ -160,6 +1096,8 @@ fn camera_loop(
 struct CameraErr {
     connected: bool,
     login_fail: bool,
+    login_locked: bool,
+    kind: FailureKind,
     err: anyhow::Error,
 }
 
@@ -213,66 +1151,167 @@ fn camera_main(
     stream_name: Stream,
     outputs: &mut GstOutputs,
     manage: bool,
+    record: Option<&RecordConfig>,
+    state: &mut StateLog,
+    stop: &CameraStop,
 ) -> Result<(), CameraErr> {
     let mut connected = false;
     let mut login_fail = false;
+    let mut login_locked = false;
     (|| {
-        let camera_addr =
-            AddressOrUid::new(&camera_config.camera_addr, &camera_config.camera_uid).unwrap();
-        let mut camera =
-            camera_addr.connect_camera(camera_config.channel_id)
-                .with_context(|| {
-                    format!(
-                        "Failed to connect to camera {} at {} on channel {}",
-                        camera_config.name, camera_addr, camera_config.channel_id
-                    )
-                })?;
-
+        let camera_addr = AddressOrUid::for_camera(camera_config).unwrap();
+        state.transition(ConnectionState::Connecting, &format!("Connecting to {}", camera_addr));
         if camera_config.timeout.is_some() {
             warn!("The undocumented `timeout` config option has been removed and is no longer needed.");
             warn!("Please update your config file.");
         }
 
-        info!(
-            "{}: Connecting to camera at {}",
-            camera_config.name, camera_addr
-        );
-
-        info!("{}: Logging in", camera_config.name);
-        camera.login(&camera_config.username, camera_config.password.as_deref()).map_err(|e|
-            {
-                if let neolink_core::Error::AuthFailed = e {
-                    login_fail = true;
-                }
-                e
+        let mut camera = connect_with_timeout(camera_config, camera_addr).inspect_err(|e| {
+            if let Some(neolink_core::Error::AuthFailed) = e.downcast_ref::<neolink_core::Error>() {
+                login_fail = true;
             }
-        ).with_context(|| format!("Failed to login to {}", camera_config.name))?;
+            login_locked = is_login_locked(e);
+        })?;
 
         connected = true;
-        info!("{}: Connected and logged in", camera_config.name);
+        state.transition(ConnectionState::LoggedIn, &format!("Logged in as {}", camera_config.username));
 
-        if manage {
+        let quirks = Quirks::for_camera(camera_config);
+        outputs.set_fixed_codec(quirks.fixed_codec);
+        outputs.reset_keyframe_gap();
+
+        if manage && camera_config.manage {
             do_camera_management(&mut camera, camera_config).context("Failed to manage the camera settings")?;
         }
 
+        // A camera in privacy mode sends no video, wait for it instead of reconnecting on the stall
+        if privacy::is_on(&camera) {
+            state.transition(ConnectionState::LoggedIn, "Privacy mode is on, waiting for it to be turned off");
+            while privacy::is_on(&camera) {
+                if stop.sleep(PRIVACY_POLL_INTERVAL) {
+                    return Ok(());
+                }
+            }
+            info!("{}: Privacy mode was turned off", camera_config.name);
+        }
+
         let stream_display_name = match stream_name {
             Stream::Main => "Main Stream (Clear)",
             Stream::Sub => "Sub Stream (Fluent)",
             Stream::Extern => "Extern Stream (Balanced)",
         };
 
-        info!(
-            "{}: Starting video stream {}",
-            camera_config.name, stream_display_name
-        );
-        camera.start_video(outputs, stream_name).with_context(|| format!("Error while streaming {}", camera_config.name))
+        state.transition(ConnectionState::Streaming, &format!("Starting video stream {}", stream_display_name));
+        outputs.time_first_keyframe();
+        let camera = &camera;
+        let stream_video_only = |outputs: &mut GstOutputs| match record {
+            // The recording shares this camera connection with the rtsp stream
+            Some(record_config) => {
+                let mut fanout = StreamFanOut::new();
+                fanout.add("rtsp", outputs);
+                record::record_video(camera, camera_config, record_config, fanout, Some(stop))
+            }
+            None => queue_video(camera, camera_config, stream_name, outputs, stop).with_context(|| format!("Error while streaming {}", camera_config.name)),
+        };
+        // The managing stream notes the motion for `/status`, unless recording on motion already does
+        let track_motion = manage && record.is_none_or(|rc| rc.mode == "continuous");
+        let stream_video = |outputs: &mut GstOutputs| match track_motion {
+            true => lastmotion::track_motion(camera, &camera_config.name, || stream_video_only(outputs)),
+            false => stream_video_only(outputs),
+        };
+        let keyframe_on_connect = quirks.keyframe_on_connect;
+        let frame_check = camera_config.frame_check.is_some();
+        if !keyframe_on_connect && !camera_config.keyframe_gap_request && !frame_check {
+            return stream_video(outputs);
+        }
+
+        // Ask for a keyframe whenever a new client joins so they don't wait for the next one,
+        // when the keyframes stopped arriving or when a broken one was dropped
+        let client_plays = outputs.client_plays.clone();
+        let keyframe_gaps = outputs.keyframe_gaps.clone();
+        let broken_keyframes = outputs.broken_keyframes.clone();
+        let streaming = AtomicBool::new(true);
+        crossbeam::scope(|s| {
+            s.spawn(|_| {
+                let mut last_request: Option<Instant> = None;
+                while streaming.load(Ordering::Relaxed) {
+                    let reason = crossbeam::channel::select! {
+                        recv(client_plays) -> play => match play {
+                            Ok(()) if keyframe_on_connect => "a new client",
+                            _ => continue,
+                        },
+                        recv(keyframe_gaps) -> gap => match gap {
+                            Ok(()) if camera_config.keyframe_gap_request => "a stream that is missing its keyframes",
+                            _ => continue,
+                        },
+                        recv(broken_keyframes) -> broken => match broken {
+                            Ok(()) if frame_check => "a broken keyframe that was dropped",
+                            _ => continue,
+                        },
+                        default(Duration::from_secs(1)) => continue,
+                    };
+                    if matches!(last_request, Some(last) if last.elapsed() < KEYFRAME_REQUEST_INTERVAL) {
+                        continue;
+                    }
+                    last_request = Some(Instant::now());
+                    debug!("{}: Requesting a keyframe for {}", camera_config.name, reason);
+                    if let Err(e) = camera.request_keyframe(stream_name) {
+                        warn!("{}: Failed to request a keyframe: {:?}", camera_config.name, e);
+                    }
+                }
+            });
+            let result = stream_video(outputs);
+            streaming.store(false, Ordering::Relaxed);
+            result
+        })
+        .unwrap()
     })().map_err(|e| CameraErr{
         connected,
         login_fail,
+        login_locked,
+        kind: FailureKind::of(&e, connected),
         err: e,
     })
 }
 
+/// Stream the camera into `outputs` through a bounded queue
+///
+/// The camera is read on this thread while `outputs` is fed on another, so
+/// rtsp clients that fall behind lose the oldest packets instead of making
+/// the camera connection buffer without limit
+fn queue_video(
+    camera: &BcCamera,
+    camera_config: &CameraConfig,
+    stream_name: Stream,
+    outputs: &mut GstOutputs,
+    stop: &CameraStop,
+) -> Result<()> {
+    let (mut queue, receiver) = StreamQueue::new(camera_config.queue_packets);
+    if let Some(metrics) = outputs.metrics() {
+        metrics.watch_queue(&mut queue);
+    }
+    crossbeam::scope(|s| {
+        let consumer = s.spawn(|_| receiver.run(outputs).map_err(anyhow::Error::from));
+        let result = camera.start_video(
+            &mut live_output(camera_config, stream_name, Some(stop), &mut queue),
+            stream_name,
+        );
+        let dropped = queue.dropped();
+        drop(queue);
+        let consumed = consumer.join().expect("Rtsp output thread panicked");
+        if dropped > 0 {
+            warn!(
+                "{}: The {:?} stream fell behind, {} packets have been dropped",
+                camera_config.name, stream_name, dropped
+            );
+        }
+        consumed?;
+        result?;
+        Ok(())
+    })
+    .unwrap()
+}
+
 fn do_camera_management(camera: &mut BcCamera, camera_config: &CameraConfig) -> Result<()> {
     let cam_time = camera.get_time()?;
     if let Some(time) = cam_time {
@@ -280,6 +1319,11 @@ fn do_camera_management(camera: &mut BcCamera, camera_config: &CameraConfig) ->
             "{}: Camera time is already set: {}",
             camera_config.name, time
         );
+    } else if !camera_config.manage_time {
+        info!(
+            "{}: Camera has no time set, leaving it as manage_time is false",
+            camera_config.name
+        );
     } else {
         use time::OffsetDateTime;
         // We'd like now_local() but it's deprecated - try to get the local time, but if no
@@ -291,18 +1335,39 @@ fn do_camera_management(camera: &mut BcCamera, camera_config: &CameraConfig) ->
             "{}: Camera has no time set, setting to {}",
             camera_config.name, new_time
         );
-        camera.set_time(new_time)?;
-        let cam_time = camera.get_time()?;
-        if let Some(time) = cam_time {
-            info!("{}: Camera time is now set: {}", camera_config.name, time);
-        } else {
-            error!(
-                "{}: Camera did not accept new time (is {} an admin?)",
-                camera_config.name, camera_config.username
-            );
+        // A camera that rejects the time can still stream
+        match camera.set_time(new_time) {
+            Err(e @ neolink_core::Error::Refused { .. })
+            | Err(e @ neolink_core::Error::Unsupported { .. }) => warn!(
+                "{}: Camera did not accept new time: {}",
+                camera_config.name, e
+            ),
+            Err(e) => return Err(e.into()),
+            Ok(()) => {
+                let cam_time = camera.get_time()?;
+                if let Some(time) = cam_time {
+                    info!("{}: Camera time is now set: {}", camera_config.name, time);
+                } else {
+                    error!("{}: Camera did not accept new time", camera_config.name);
+                }
+            }
         }
     }
 
+    // A camera that refuses the recording settings can still stream
+    if let Err(e) = recschedule::manage(camera, camera_config) {
+        warn!(
+            "{}: Failed to set the SD card recording: {:?}",
+            camera_config.name, e
+        );
+    }
+    if let Err(e) = encoder::manage(camera, camera_config) {
+        warn!(
+            "{}: Failed to set the encoder settings: {:?}",
+            camera_config.name, e
+        );
+    }
+
     use neolink_core::bc::xml::VersionInfo;
     if let Ok(VersionInfo {
         firmwareVersion: firmware_version,
@@ -322,3 +1387,284 @@ fn do_camera_management(camera: &mut BcCamera, camera_config: &CameraConfig) ->
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users() -> Vec<UserConfig> {
+        vec![
+            UserConfig {
+                name: "admin".to_string(),
+                pass: "adminpass".to_string(),
+            },
+            UserConfig {
+                name: "guest".to_string(),
+                pass: "guestpass".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_no_permitted_users_allows_all_users() {
+        let users = users();
+        let permitted = get_permitted_users(&users, &None);
+        assert!(permitted.contains("admin"));
+        assert!(permitted.contains("guest"));
+        assert!(!permitted.contains("anonymous"));
+    }
+
+    #[test]
+    fn test_permitted_users_excludes_others() {
+        let users = users();
+        let camera_users = Some(vec!["guest".to_string()]);
+        let permitted = get_permitted_users(&users, &camera_users);
+        assert!(permitted.contains("guest"));
+        // Admin is a configured user but is not authorised for this camera
+        assert!(!permitted.contains("admin"));
+        assert!(!permitted.contains("anonymous"));
+    }
+
+    #[test]
+    fn test_anyone_allows_all_users() {
+        let users = users();
+        let camera_users = Some(vec!["anyone".to_string()]);
+        let permitted = get_permitted_users(&users, &camera_users);
+        assert!(permitted.contains("admin"));
+        assert!(permitted.contains("guest"));
+    }
+
+    // A camera whose one thread streams until it is stopped
+    fn served_camera(name: &str, log: &Arc<Mutex<Vec<String>>>) -> ServedCamera {
+        let config: CameraConfig = toml::from_str(&format!(
+            "name = \"{}\"\naddress = \"192.168.1.10\"\nusername = \"admin\"",
+            name
+        ))
+        .unwrap();
+        let stop = Arc::new(CameraStop::default());
+        let threads = WaitGroup::new();
+        let thread = threads.clone();
+        let thread_stop = stop.clone();
+        let thread_log = log.clone();
+        let thread_name = name.to_string();
+        std::thread::spawn(move || {
+            let _thread = thread;
+            while !thread_stop.sleep(Duration::from_secs(60)) {}
+            std::thread::sleep(Duration::from_millis(50));
+            thread_log
+                .lock()
+                .unwrap()
+                .push(format!("{} finished", thread_name));
+        });
+        ServedCamera {
+            config: Arc::new(config),
+            stop,
+            threads,
+            port: 8554,
+            paths: vec![format!("/{}", name)],
+        }
+    }
+
+    #[test]
+    fn test_reload_tears_down_streams() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut running = vec![
+            served_camera("Kept", &log),
+            served_camera("Removed", &log),
+            served_camera("Changed", &log),
+        ];
+        let kept_stop = running[0].stop.clone();
+        let removed_stop = running[1].stop.clone();
+        let mut changed = (*running[2].config).clone();
+        changed.username = "viewer".to_string();
+        let mut added = (*running[0].config).clone();
+        added.name = "Added".to_string();
+        let changes = CameraChanges {
+            added: vec![added],
+            removed: vec!["Removed".to_string()],
+            changed: vec![changed],
+        };
+
+        apply_changes(
+            &mut running,
+            changes,
+            Duration::from_secs(5),
+            |camera| camera.stop.stop(),
+            |camera| {
+                log.lock().unwrap().push(format!("{} started", camera.name));
+                let mut served = served_camera(&camera.name, &log);
+                served.config = Arc::new(camera);
+                Some(served)
+            },
+        );
+
+        assert!(removed_stop.is_stopped());
+        assert!(!kept_stop.is_stopped());
+        // The old threads had finished before any camera started again
+        assert_eq!(
+            log.lock().unwrap()[2..],
+            ["Changed started".to_string(), "Added started".to_string()]
+        );
+        let mut finished = log.lock().unwrap()[..2].to_vec();
+        finished.sort();
+        assert_eq!(finished, ["Changed finished", "Removed finished"]);
+        let mut names: Vec<_> = running.iter().map(|c| c.config.name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["Added", "Changed", "Kept"]);
+        let restarted = running.iter().find(|c| c.config.name == "Changed").unwrap();
+        assert_eq!(restarted.config.username, "viewer");
+        for camera in &running {
+            camera.stop.stop();
+        }
+    }
+
+    #[test]
+    fn test_live_streams_count_the_configured_cameras() {
+        let live = LiveStreams::default();
+        live.started();
+        live.started();
+        assert!(!live.ended("Garage", true));
+        // The stream that is left is removed by a reload
+        live.pause();
+        assert!(!live.ended("Door", false));
+        assert!(live.resume(&["Door".to_string()]));
+        assert!(live.every_stream_gave_up());
+
+        let live = LiveStreams::default();
+        live.started();
+        live.started();
+        assert!(!live.ended("Garage", true));
+        // The camera that gave up is removed by a reload
+        live.pause();
+        assert!(!live.resume(&["Garage".to_string()]));
+        assert!(!live.ended("Door", false));
+
+        let live = LiveStreams::default();
+        live.started();
+        live.started();
+        assert!(!live.ended("Garage", true));
+        // The other stream is stopped by the shutdown
+        live.pause();
+        assert!(!live.ended("Door", false));
+        assert!(!live.every_stream_gave_up());
+    }
+
+    #[test]
+    fn test_supervise_stream_gives_up_on_panics() {
+        let camera = camera("mainStream");
+        let stop = CameraStop::default();
+        let mut runs = 0;
+        let result = supervise_stream(&camera, Stream::Main, &stop, || {
+            runs += 1;
+            if runs < 3 {
+                panic!("bug");
+            }
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(runs, 3);
+
+        let mut runs = 0;
+        let result = supervise_stream(&camera, Stream::Main, &stop, || -> Result<()> {
+            runs += 1;
+            panic!("bug");
+        });
+        let err = result.unwrap_err();
+        assert_eq!(err.downcast_ref::<&str>(), Some(&GAVE_UP));
+        assert_eq!(runs, MAX_PANIC_RESTARTS + 1);
+
+        let mut runs = 0;
+        stop.stop();
+        let result = supervise_stream(&camera, Stream::Main, &stop, || -> Result<()> {
+            runs += 1;
+            panic!("bug");
+        });
+        // A stopped stream is not restarted
+        assert!(result.is_ok());
+        assert_eq!(runs, 1);
+    }
+
+    #[test]
+    fn test_wait_for_camera_is_bounded() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let camera = served_camera("Stuck", &log);
+        // Never stopped, so its thread does not finish
+        let stop = camera.stop.clone();
+        assert!(!wait_for_camera(camera, Duration::from_millis(100)));
+        stop.stop();
+        let camera = served_camera("Stopped", &log);
+        camera.stop.stop();
+        assert!(wait_for_camera(camera, Duration::from_secs(5)));
+    }
+
+    fn camera(stream: &str) -> CameraConfig {
+        toml::from_str(&format!(
+            "name = \"cam\"\naddress = \"192.168.1.10\"\nusername = \"admin\"\nstream = \"{}\"",
+            stream
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_manage_is_decoupled() {
+        assert!(camera("subStream").manage);
+        // Only the sub stream is published but the camera is still managed
+        let mut camera = camera("subStream");
+        assert_eq!(managing_stream(&camera, None), Some("subStream"));
+        camera.manage = false;
+        assert_eq!(managing_stream(&camera, None), Some("subStream"));
+    }
+
+    #[test]
+    fn test_managing_stream_once() {
+        for stream in ["mainStream", "subStream", "externStream", "both", "all"] {
+            let camera = camera(stream);
+            for default_stream in [None, Some("mainStream"), Some("subStream")] {
+                let managing = managing_stream(&camera, default_stream);
+                let managers = camera
+                    .enabled_streams()
+                    .into_iter()
+                    .filter(|s| Some(*s) == managing)
+                    .count();
+                assert_eq!(managers, 1, "{} with {:?}", stream, default_stream);
+            }
+        }
+    }
+
+    #[test]
+    fn test_managing_stream_single() {
+        assert_eq!(
+            managing_stream(&camera("mainStream"), None),
+            Some("mainStream")
+        );
+        assert_eq!(
+            managing_stream(&camera("subStream"), None),
+            Some("subStream")
+        );
+        assert_eq!(
+            managing_stream(&camera("externStream"), None),
+            Some("externStream")
+        );
+    }
+
+    #[test]
+    fn test_managing_stream_combined() {
+        assert_eq!(managing_stream(&camera("both"), None), Some("mainStream"));
+        assert_eq!(managing_stream(&camera("all"), None), Some("mainStream"));
+        // The default stream stays connected so it manages the camera
+        assert_eq!(
+            managing_stream(&camera("all"), Some("externStream")),
+            Some("externStream")
+        );
+        assert_eq!(
+            managing_stream(&camera("both"), Some("subStream")),
+            Some("subStream")
+        );
+    }
+
+    #[test]
+    fn test_no_users_is_anonymous() {
+        let permitted = get_permitted_users(&[], &None);
+        assert_eq!(permitted, ["anonymous"].iter().cloned().collect());
+    }
+}
@@ -0,0 +1,245 @@
+//! Sends JPEGs of a camera when it sees motion
+//!
+//! The motion noted for the camera, by its rtsp stream or its motion
+//! recording, starts an episode that lasts until the camera goes
+//! `cooldown_seconds` without motion. The first `images` keyframes of an
+//! episode, starting with the latest one, are decoded to JPEG and posted to
+//! the `url` and/or saved below the `path` of the camera's `motion_snapshot`.
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use log::*;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use super::snapshot::KeyframeCache;
+use crate::config::MotionSnapshotConfig;
+use crate::lastmotion;
+use crate::utils::CameraStop;
+
+// How often the camera stop is checked while waiting for motion
+const MOTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// How long a slow http endpoint may take to connect, receive or answer
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Send the JPEGs of `camera` in `cache` on each motion episode until it is stopped
+pub(crate) fn send_on_motion(
+    camera: &str,
+    config: &MotionSnapshotConfig,
+    cache: &KeyframeCache,
+    stop: &CameraStop,
+) {
+    let mut episodes = Episodes::new(Duration::from_secs(config.cooldown_seconds));
+    let mut since = lastmotion::last_motion(camera);
+    while !stop.is_stopped() {
+        let motion = match lastmotion::wait_for_motion(camera, since, MOTION_POLL_INTERVAL) {
+            Some(motion) => motion,
+            None => continue,
+        };
+        since = Some(motion);
+        if !episodes.starts(Instant::now()) {
+            continue;
+        }
+        debug!("{}: Motion, sending {} images", camera, config.images);
+        let mut seq = None;
+        for n in 0..config.images {
+            if n > 0 {
+                cache.wait_newer(seq);
+            }
+            if stop.is_stopped() {
+                return;
+            }
+            match cache.jpeg() {
+                Ok((jpeg_seq, jpeg)) if Some(jpeg_seq) != seq => {
+                    seq = Some(jpeg_seq);
+                    let name = file_name(camera, &Local::now(), n, config.images);
+                    send(camera, config, &name, &jpeg);
+                }
+                // No newer keyframe arrived in time
+                Ok(_) => break,
+                Err(e) => {
+                    warn!("{}: No motion snapshot: {:?}", camera, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn send(camera: &str, config: &MotionSnapshotConfig, name: &str, jpeg: &[u8]) {
+    if let Some(url) = &config.url {
+        if let Err(e) = post_jpeg(url, camera, name, jpeg) {
+            warn!("{}: Failed to post the motion snapshot: {:?}", camera, e);
+        }
+    }
+    if let Some(path) = &config.path {
+        let day = Local::now().format("%Y-%m-%d").to_string();
+        let dir: PathBuf = [path.as_str(), camera, day.as_str()].iter().collect();
+        let saved = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(dir.join(name), jpeg))
+            .with_context(|| format!("Unable to save {}", dir.join(name).display()));
+        if let Err(e) = saved {
+            warn!("{}: Failed to save the motion snapshot: {:?}", camera, e);
+        }
+    }
+}
+
+/// Starts an episode on the first motion after `cooldown` without motion
+struct Episodes {
+    cooldown: Duration,
+    last_motion: Option<Instant>,
+}
+
+impl Episodes {
+    fn new(cooldown: Duration) -> Self {
+        Episodes {
+            cooldown,
+            last_motion: None,
+        }
+    }
+
+    /// Note motion at `now`, returning whether it starts an episode
+    fn starts(&mut self, now: Instant) -> bool {
+        let starts = match self.last_motion {
+            Some(last) => now.duration_since(last) >= self.cooldown,
+            None => true,
+        };
+        self.last_motion = Some(now);
+        starts
+    }
+}
+
+// The name of image `n` of `images`, the camera and time e.g. `Garage_2026-10-14_09-30-00.jpg`
+fn file_name(camera: &str, time: &DateTime<Local>, n: u32, images: u32) -> String {
+    let time = time.format("%Y-%m-%d_%H-%M-%S");
+    match images {
+        1 => format!("{}_{}.jpg", camera, time),
+        _ => format!("{}_{}_{}.jpg", camera, time, n + 1),
+    }
+}
+
+// The host, port and path of an `http://` url
+fn parse_http_url(url: &str) -> Result<(&str, u16, &str)> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("Only http:// urls are supported: {}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rfind(':') {
+        Some(i) if !authority.ends_with(']') => (
+            &authority[..i],
+            authority[i + 1..]
+                .parse()
+                .with_context(|| format!("Invalid port in {}", url))?,
+        ),
+        _ => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(anyhow!("No host in {}", url));
+    }
+    Ok((host, port, path))
+}
+
+fn post_jpeg(url: &str, camera: &str, name: &str, jpeg: &[u8]) -> Result<()> {
+    let disposition = format!("attachment; filename=\"{}\"", name);
+    let time = Local::now().to_rfc3339();
+    http_post(
+        url,
+        "image/jpeg",
+        &[
+            ("Content-Disposition", &disposition),
+            ("X-Neolink-Camera", camera),
+            ("X-Neolink-Time", &time),
+        ],
+        jpeg,
+    )
+}
+
+/// POST `body` to an `http://` url and check that it answered with a 2xx status
+pub(super) fn http_post(
+    url: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<()> {
+    let (host, port, path) = parse_http_url(url)?;
+    let addr = (host.trim_start_matches('[').trim_end_matches(']'), port);
+    let mut stream =
+        TcpStream::connect(addr).with_context(|| format!("Unable to connect to {}", url))?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\n\
+        Host: {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n",
+        path,
+        host,
+        content_type,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("Connection: close\r\n\r\n");
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(anyhow!("{} answered {}", url, status.trim())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_episodes() {
+        let start = Instant::now();
+        let mut episodes = Episodes::new(Duration::from_secs(30));
+        assert!(episodes.starts(start));
+        assert!(!episodes.starts(start + Duration::from_secs(20)));
+        // Still the same episode, the cooldown restarts with each motion
+        assert!(!episodes.starts(start + Duration::from_secs(45)));
+        assert!(episodes.starts(start + Duration::from_secs(80)));
+    }
+
+    #[test]
+    fn test_file_name() {
+        let time = Local.with_ymd_and_hms(2026, 10, 14, 9, 30, 5).unwrap();
+        assert_eq!(
+            file_name("Garage", &time, 0, 1),
+            "Garage_2026-10-14_09-30-05.jpg"
+        );
+        assert_eq!(
+            file_name("Garage", &time, 1, 3),
+            "Garage_2026-10-14_09-30-05_2.jpg"
+        );
+    }
+
+    #[test]
+    fn test_parse_http_url() {
+        assert_eq!(
+            parse_http_url("http://hass.lan:8123/api/webhook/motion").unwrap(),
+            ("hass.lan", 8123, "/api/webhook/motion")
+        );
+        assert_eq!(
+            parse_http_url("http://192.168.1.5").unwrap(),
+            ("192.168.1.5", 80, "/")
+        );
+        assert_eq!(
+            parse_http_url("http://[fe80::1]/upload").unwrap(),
+            ("[fe80::1]", 80, "/upload")
+        );
+        assert!(parse_http_url("https://hass.lan/").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Reloads the cameras of the config file on SIGHUP
+//!
+//! The new `[[cameras]]` are compared with the running ones by name. Cameras
+//! that were added are started, cameras that were removed are stopped and
+//! cameras whose section changed are restarted. The streams of the other
+//! cameras carry on undisturbed. The clients of a stopped camera see the end
+//! of its streams, and a restarted camera only connects again once the
+//! threads of its old section have finished. The rest of the config, such as the users,
+//! ports and TLS certificate, is only read when neolink starts.
+use crate::config::CameraConfig;
+use crate::utils::CameraStop;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Set by the signal handler, which must not do more than this
+static SIGHUP_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+// How often the signal flag is checked
+const SIGHUP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+extern "C" fn on_sighup(_signal: libc::c_int) {
+    SIGHUP_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Catch SIGHUP from now on instead of being killed by it
+///
+/// This is called before any camera starts so that a SIGHUP sent while the
+/// cameras are connecting reloads them rather than ending the process
+pub(crate) fn install_sighup_handler() {
+    unsafe {
+        libc::signal(
+            libc::SIGHUP,
+            on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Block until the process receives a SIGHUP or the server shuts down
+///
+/// Returns false once `shutdown` is stopped
+pub(crate) fn wait_for_sighup(shutdown: &CameraStop) -> bool {
+    while !SIGHUP_RECEIVED.swap(false, Ordering::SeqCst) {
+        if shutdown.sleep(SIGHUP_POLL_INTERVAL) {
+            return false;
+        }
+    }
+    true
+}
+
+/// How the cameras of a reloaded config differ from the running ones
+#[derive(Debug, Default)]
+pub(crate) struct CameraChanges {
+    /// Cameras that were not running
+    pub(crate) added: Vec<CameraConfig>,
+    /// The names of the running cameras that are no longer in the config
+    pub(crate) removed: Vec<String>,
+    /// Running cameras whose section changed
+    pub(crate) changed: Vec<CameraConfig>,
+}
+
+impl CameraChanges {
+    /// Compare the `running` cameras with those of the `reloaded` config
+    pub(crate) fn between(running: &[CameraConfig], reloaded: &[CameraConfig]) -> Self {
+        let mut changes = CameraChanges::default();
+        for camera in reloaded {
+            match running.iter().find(|c| c.name == camera.name) {
+                None => changes.added.push(camera.clone()),
+                Some(old) if old != camera => changes.changed.push(camera.clone()),
+                Some(_) => {}
+            }
+        }
+        changes.removed = running
+            .iter()
+            .filter(|c| !reloaded.iter().any(|n| n.name == c.name))
+            .map(|c| c.name.clone())
+            .collect();
+        changes
+    }
+
+    /// Whether the reload changes nothing
+    pub(crate) fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cameras(config: &str) -> Vec<CameraConfig> {
+        #[derive(serde::Deserialize)]
+        struct Cameras {
+            cameras: Vec<CameraConfig>,
+        }
+        toml::from_str::<Cameras>(config).unwrap().cameras
+    }
+
+    #[test]
+    fn test_changes_between_configs() {
+        let running = cameras(
+            r#"
+            [[cameras]]
+            name = "Garage"
+            username = "admin"
+            address = "192.168.1.10"
+
+            [[cameras]]
+            name = "Porch"
+            username = "admin"
+            address = "192.168.1.11"
+
+            [[cameras]]
+            name = "Garden"
+            username = "admin"
+            address = "192.168.1.12"
+            "#,
+        );
+        let reloaded = cameras(
+            r#"
+            [[cameras]]
+            name = "Garage"
+            username = "admin"
+            address = "192.168.1.10"
+
+            [[cameras]]
+            name = "Porch"
+            username = "admin"
+            address = "192.168.1.21"
+
+            [[cameras]]
+            name = "Drive"
+            username = "admin"
+            address = "192.168.1.13"
+            "#,
+        );
+
+        let changes = CameraChanges::between(&running, &reloaded);
+        let names = |cameras: &[CameraConfig]| -> Vec<String> {
+            cameras.iter().map(|c| c.name.clone()).collect()
+        };
+        assert_eq!(names(&changes.added), vec!["Drive"]);
+        assert_eq!(changes.removed, vec!["Garden"]);
+        assert_eq!(names(&changes.changed), vec!["Porch"]);
+    }
+
+    #[test]
+    fn test_changes_across_reloads() {
+        let first = cameras(
+            r#"
+            [[cameras]]
+            name = "Garage"
+            username = "admin"
+            address = "192.168.1.10"
+            "#,
+        );
+        let second = cameras(
+            r#"
+            [[cameras]]
+            name = "Garage"
+            username = "admin"
+            address = "192.168.1.10"
+            stream = "subStream"
+
+            [[cameras]]
+            name = "Porch"
+            username = "admin"
+            address = "192.168.1.11"
+            "#,
+        );
+
+        let changes = CameraChanges::between(&first, &second);
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.changed[0].stream, "subStream");
+        assert!(changes.removed.is_empty());
+
+        // Going back to the first config undoes both
+        let changes = CameraChanges::between(&second, &first);
+        assert!(changes.added.is_empty());
+        assert_eq!(changes.changed[0].stream, "both");
+        assert_eq!(changes.removed, vec!["Porch"]);
+    }
+
+    #[test]
+    fn test_unchanged_config() {
+        let running = cameras(
+            r#"
+            [[cameras]]
+            name = "Garage"
+            username = "admin"
+            address = "192.168.1.10"
+            "#,
+        );
+        assert!(CameraChanges::between(&running, &running.clone()).is_empty());
+    }
+}
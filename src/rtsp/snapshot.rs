@@ -0,0 +1,329 @@
+//! Serves JPEG snapshots and an MJPEG feed of each camera over HTTP
+//!
+//! The most recent keyframe of each camera is cached by its rtsp stream
+//! and decoded to JPEG with gstreamer when it is first requested. Repeated
+//! requests for the same keyframe reuse the JPEG so they don't stall the
+//! camera or the decoder.
+//!
+//! - `http://host:http_port/<camera>/snapshot.jpg`
+//! - `http://host:http_port/<camera>/stream.mjpg`
+use anyhow::{anyhow, Context, Result};
+use gstreamer::{parse_launch, prelude::*, Buffer, Pipeline, State};
+use gstreamer_app::{AppSink, AppSrc};
+use log::*;
+use neolink_core::bcmedia::model::VideoType;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+use super::metrics::Metrics;
+use crate::utils::{percent_decode, CameraStop};
+
+// How often the server checks whether it is shutting down
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The latest keyframe from a camera and its JPEG once decoded
+#[derive(Default)]
+pub(crate) struct KeyframeCache {
+    frame: Mutex<Option<Keyframe>>,
+    new_frame: Condvar,
+    jpeg: Mutex<Option<(u64, Arc<Vec<u8>>)>>,
+}
+
+struct Keyframe {
+    seq: u64,
+    video_type: VideoType,
+    data: Vec<u8>,
+}
+
+impl KeyframeCache {
+    /// Replace the cached keyframe
+    pub(crate) fn update(&self, video_type: VideoType, data: &[u8]) {
+        let mut frame = self.frame.lock().unwrap();
+        let seq = frame.as_ref().map_or(0, |f| f.seq + 1);
+        *frame = Some(Keyframe {
+            seq,
+            video_type,
+            data: data.to_vec(),
+        });
+        self.new_frame.notify_all();
+    }
+
+    /// Get the JPEG for the latest keyframe, decoding it if needed
+    ///
+    /// Returns the sequence number of the keyframe with the JPEG
+    pub(crate) fn jpeg(&self) -> Result<(u64, Arc<Vec<u8>>)> {
+        let (seq, video_type, data) = {
+            let frame = self.frame.lock().unwrap();
+            let frame = frame
+                .as_ref()
+                .ok_or_else(|| anyhow!("No keyframe has been received yet"))?;
+            (frame.seq, frame.video_type, frame.data.clone())
+        };
+
+        // Hold the lock while decoding so that simultaneous requests only decode once
+        let mut jpeg = self.jpeg.lock().unwrap();
+        if let Some((jpeg_seq, data)) = jpeg.as_ref() {
+            if *jpeg_seq == seq {
+                return Ok((seq, data.clone()));
+            }
+        }
+        let decoded = Arc::new(keyframe_to_jpeg(video_type, &data)?);
+        *jpeg = Some((seq, decoded.clone()));
+        Ok((seq, decoded))
+    }
+
+    /// Wait for a keyframe newer than `seq`
+    pub(crate) fn wait_newer(&self, seq: Option<u64>) {
+        let frame = self.frame.lock().unwrap();
+        let _ = self
+            .new_frame
+            .wait_timeout_while(frame, Duration::from_secs(30), |frame| {
+                match (frame.as_ref(), seq) {
+                    (Some(frame), Some(seq)) => frame.seq <= seq,
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                }
+            })
+            .unwrap();
+    }
+}
+
+/// Decode a keyframe of the camera to a JPEG
+pub(crate) fn keyframe_to_jpeg(video_type: VideoType, data: &[u8]) -> Result<Vec<u8>> {
+    let (caps, parse, decode) = match video_type {
+        VideoType::H264 => ("video/x-h264", "h264parse", "avdec_h264"),
+        VideoType::H265 => ("video/x-h265", "h265parse", "avdec_h265"),
+    };
+    let launch = format!(
+        "appsrc name=src caps={},stream-format=byte-stream ! {} ! {} ! videoconvert ! jpegenc ! appsink name=sink",
+        caps, parse, decode
+    );
+    let pipeline = parse_launch(&launch)
+        .context(
+            "Unable to load gstreamer snapshot pipeline ensure all gstramer plugins are installed",
+        )?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| anyhow!("Unable to create gstreamer snapshot pipeline"))?;
+    let src = pipeline
+        .by_name("src")
+        .and_then(|e| e.dynamic_cast::<AppSrc>().ok())
+        .ok_or_else(|| anyhow!("Snapshot pipeline has no appsrc"))?;
+    let sink = pipeline
+        .by_name("sink")
+        .and_then(|e| e.dynamic_cast::<AppSink>().ok())
+        .ok_or_else(|| anyhow!("Snapshot pipeline has no appsink"))?;
+
+    pipeline.set_state(State::Playing)?;
+    let result = (|| {
+        src.push_buffer(Buffer::from_slice(data.to_vec()))
+            .map_err(|e| anyhow!("Failed to push the keyframe to gstreamer: {:?}", e))?;
+        src.end_of_stream()
+            .map_err(|e| anyhow!("Failed to end the snapshot stream: {:?}", e))?;
+        let sample = sink
+            .pull_sample()
+            .map_err(|_| anyhow!("Gstreamer could not decode the keyframe"))?;
+        let buffer = sample
+            .buffer()
+            .ok_or_else(|| anyhow!("Decoded snapshot has no buffer"))?;
+        let map = buffer
+            .map_readable()
+            .map_err(|_| anyhow!("Decoded snapshot is not readable"))?;
+        Ok(map.as_slice().to_vec())
+    })();
+    pipeline.set_state(State::Null)?;
+    result
+}
+
+/// The cameras that can be viewed over http
+#[derive(Clone)]
+pub(crate) struct SnapshotCamera {
+    pub(crate) cache: Arc<KeyframeCache>,
+    pub(crate) permitted_users: HashSet<String>,
+}
+
+/// The cameras by name, shared so that a reload can add and remove them
+pub(crate) type SnapshotCameras = Arc<Mutex<HashMap<String, SnapshotCamera>>>;
+
+/// Serve the snapshots until `shutdown` is stopped
+///
+/// `credentials` are the user/password pairs from the config. Unless a camera
+/// permits `anonymous` requests must use HTTP basic auth as one of its permitted users
+pub(crate) fn serve(
+    bind_addr: &str,
+    port: u16,
+    cameras: SnapshotCameras,
+    credentials: Vec<(String, String)>,
+    metrics: Arc<Metrics>,
+    healthy_needs_all: bool,
+    shutdown: &CameraStop,
+) -> Result<()> {
+    let server = Server::http((bind_addr, port))
+        .map_err(|e| anyhow!("Unable to start the http server on port {}: {}", port, e))?;
+    info!("Serving snapshots on http://{}:{}", bind_addr, port);
+
+    let credentials = Arc::new(credentials);
+    while !shutdown.is_stopped() {
+        let request = match server.recv_timeout(SHUTDOWN_POLL_INTERVAL)? {
+            Some(request) => request,
+            None => continue,
+        };
+        let cameras = cameras.clone();
+        let credentials = credentials.clone();
+        let metrics = metrics.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle(request, &cameras, &credentials, &metrics, healthy_needs_all) {
+                debug!("Snapshot request failed: {:?}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(
+    request: Request,
+    cameras: &Mutex<HashMap<String, SnapshotCamera>>,
+    credentials: &[(String, String)],
+    metrics: &Metrics,
+    healthy_needs_all: bool,
+) -> Result<()> {
+    let url = percent_decode(request.url());
+    if url == "/healthz" {
+        // Open to anyone so that container health checks need no credentials
+        return match metrics.is_healthy(healthy_needs_all) {
+            true => Ok(request.respond(Response::from_string("ok"))?),
+            false => Ok(request.respond(Response::from_string("unhealthy").with_status_code(503))?),
+        };
+    }
+    if url == "/status" {
+        if !credentials.is_empty() && basic_auth_user(&request, credentials).is_none() {
+            return respond_unauthorised(request);
+        }
+        let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+        let status = metrics.status_json(healthy_needs_all);
+        return Ok(request.respond(Response::from_string(status).with_header(header))?);
+    }
+    if url == "/metrics" {
+        // Any user may read the metrics
+        if !credentials.is_empty() && basic_auth_user(&request, credentials).is_none() {
+            return respond_unauthorised(request);
+        }
+        let header =
+            Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..]).unwrap();
+        return Ok(request.respond(Response::from_string(metrics.render()).with_header(header))?);
+    }
+
+    let (name, file) = match url.trim_start_matches('/').rsplit_once('/') {
+        Some(parts) => parts,
+        None => return respond_status(request, 404),
+    };
+    let camera = match cameras.lock().unwrap().get(name).cloned() {
+        Some(camera) => camera,
+        None => return respond_status(request, 404),
+    };
+
+    if !camera.permitted_users.contains("anonymous") {
+        let authorised = matches!(
+            basic_auth_user(&request, credentials),
+            Some(user) if camera.permitted_users.contains(&user)
+        );
+        if !authorised {
+            return respond_unauthorised(request);
+        }
+    }
+
+    match file {
+        "snapshot.jpg" => match camera.cache.jpeg() {
+            Ok((_, jpeg)) => {
+                let header = Header::from_bytes(&b"Content-Type"[..], &b"image/jpeg"[..]).unwrap();
+                Ok(request.respond(Response::from_data(jpeg.as_slice()).with_header(header))?)
+            }
+            Err(e) => {
+                warn!("{}: Unable to get a snapshot: {:?}", name, e);
+                respond_status(request, 503)
+            }
+        },
+        "stream.mjpg" => {
+            let header = Header::from_bytes(
+                &b"Content-Type"[..],
+                &b"multipart/x-mixed-replace; boundary=neolinkframe"[..],
+            )
+            .unwrap();
+            let body = MjpegStream {
+                cache: camera.cache.clone(),
+                seq: None,
+                pending: vec![],
+                sent: 0,
+            };
+            Ok(request.respond(Response::new(
+                StatusCode(200),
+                vec![header],
+                body,
+                None,
+                None,
+            ))?)
+        }
+        _ => respond_status(request, 404),
+    }
+}
+
+fn respond_unauthorised(request: Request) -> Result<()> {
+    let header =
+        Header::from_bytes(&b"WWW-Authenticate"[..], &b"Basic realm=\"neolink\""[..]).unwrap();
+    Ok(request.respond(Response::empty(401).with_header(header))?)
+}
+
+fn respond_status(request: Request, code: u16) -> Result<()> {
+    Ok(request.respond(Response::empty(code))?)
+}
+
+fn basic_auth_user(request: &Request, credentials: &[(String, String)]) -> Option<String> {
+    let header = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))?;
+    let encoded = header.value.as_str().strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+    let (user, pass) = decoded.split_once(':')?;
+    credentials
+        .iter()
+        .find(|(u, p)| u == user && p == pass)
+        .map(|(u, _)| u.clone())
+}
+
+/// The body of the MJPEG response, each new keyframe is sent as a new part
+struct MjpegStream {
+    cache: Arc<KeyframeCache>,
+    seq: Option<u64>,
+    pending: Vec<u8>,
+    sent: usize,
+}
+
+impl Read for MjpegStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.sent >= self.pending.len() {
+            self.cache.wait_newer(self.seq);
+            match self.cache.jpeg() {
+                Ok((seq, jpeg)) if Some(seq) != self.seq => {
+                    self.seq = Some(seq);
+                    self.pending = format!(
+                        "--neolinkframe\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                        jpeg.len()
+                    )
+                    .into_bytes();
+                    self.pending.extend(jpeg.iter());
+                    self.pending.extend(b"\r\n");
+                    self.sent = 0;
+                }
+                Ok(_) => {}
+                Err(e) => return Err(std::io::Error::other(e.to_string())),
+            }
+        }
+        let amount = std::cmp::min(buf.len(), self.pending.len() - self.sent);
+        buf[..amount].copy_from_slice(&self.pending[self.sent..self.sent + amount]);
+        self.sent += amount;
+        Ok(amount)
+    }
+}
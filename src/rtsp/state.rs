@@ -0,0 +1,99 @@
+//! Tracks the connection state of each camera stream
+//!
+//! Each transition is logged with the reason for it. If the camera has a
+//! `state_log` in the config the transitions are also appended to that file
+//! as one json object per line so they can be correlated with other logs.
+use super::super::config::CameraConfig;
+use super::metrics::StreamMetrics;
+use chrono::Local;
+use log::*;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) enum ConnectionState {
+    Disconnected,
+    Connecting,
+    LoggedIn,
+    Streaming,
+    Error,
+    /// Disconnected on purpose until an rtsp client wants the stream
+    Idle,
+}
+
+#[derive(Serialize)]
+struct Transition<'a> {
+    time: String,
+    camera: &'a str,
+    stream: &'a str,
+    from: ConnectionState,
+    to: ConnectionState,
+    reason: &'a str,
+}
+
+pub(crate) struct StateLog {
+    camera: String,
+    stream: String,
+    state: ConnectionState,
+    file: Option<File>,
+    metrics: Option<Arc<StreamMetrics>>,
+}
+
+impl StateLog {
+    pub(crate) fn new(camera_config: &CameraConfig, stream: &str) -> Self {
+        let file = camera_config.state_log.as_ref().and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| {
+                    warn!(
+                        "{}: Unable to open the state log {}: {}",
+                        camera_config.name, path, e
+                    )
+                })
+                .ok()
+        });
+        StateLog {
+            camera: camera_config.name.clone(),
+            stream: stream.to_string(),
+            state: ConnectionState::Disconnected,
+            file,
+            metrics: None,
+        }
+    }
+
+    /// Also keep the state in `metrics` for the http status
+    pub(crate) fn set_metrics(&mut self, metrics: Option<Arc<StreamMetrics>>) {
+        self.metrics = metrics;
+    }
+
+    pub(crate) fn transition(&mut self, to: ConnectionState, reason: &str) {
+        let transition = Transition {
+            time: Local::now().to_rfc3339(),
+            camera: &self.camera,
+            stream: &self.stream,
+            from: self.state,
+            to,
+            reason,
+        };
+        info!(
+            "{}: {} {:?} -> {:?}: {}",
+            self.camera, self.stream, self.state, to, reason
+        );
+        if let Some(file) = &mut self.file {
+            let written = serde_json::to_string(&transition)
+                .map_err(std::io::Error::from)
+                .and_then(|line| file.write_all(format!("{}\n", line).as_bytes()));
+            if let Err(e) = written {
+                warn!("{}: Unable to write to the state log: {}", self.camera, e);
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.set_state(to, reason);
+        }
+        self.state = to;
+    }
+}
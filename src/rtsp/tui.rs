@@ -0,0 +1,171 @@
+//! Draws a live table of the camera streams in the terminal
+//!
+//! With `--tui` the table of every stream is redrawn in place each second
+//! from the same counters as `/metrics` and `/status`: the connection state,
+//! the frames per second and bitrate since the last redraw, the age of the
+//! last keyframe and the last error. The table is drawn on the alternate
+//! screen of the terminal, which is left again when neolink stops. The logs
+//! would scroll the table away, so they are turned off while it is shown
+//! unless stderr is not the terminal, such as when it goes to a file.
+//!
+//! Without a terminal on stdout it is not drawn and neolink logs as usual.
+use super::metrics::{LiveStream, Metrics};
+use log::*;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How often the table is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+// The last errors are cut to this many characters to keep each stream on one line
+const MAX_ERROR_LEN: usize = 60;
+
+// Set while the table is on the alternate screen
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+// The log level to go back to when the table is no longer shown
+static LOG_LEVEL: Mutex<Option<LevelFilter>> = Mutex::new(None);
+
+/// Start redrawing the table of the `metrics`, unless stdout is not a terminal
+pub(crate) fn start(metrics: Arc<Metrics>) {
+    if !std::io::stdout().is_terminal() {
+        warn!("The status table needs a terminal, logging instead");
+        return;
+    }
+    if std::io::stderr().is_terminal() {
+        *LOG_LEVEL.lock().unwrap() = Some(log::max_level());
+        log::set_max_level(LevelFilter::Off);
+    }
+    // The alternate screen without the cursor
+    print!("\x1b[?1049h\x1b[?25l");
+    SHOWN.store(true, Ordering::SeqCst);
+    std::thread::spawn(move || {
+        let mut table = Table::default();
+        loop {
+            let out = table.draw(&metrics.live(), Instant::now());
+            // Home, then each line clears what was left of the last table
+            let mut stdout = std::io::stdout().lock();
+            let _ = write!(stdout, "\x1b[H{}\x1b[J", out.replace('\n', "\x1b[K\n"));
+            let _ = stdout.flush();
+            drop(stdout);
+            std::thread::sleep(REDRAW_INTERVAL);
+        }
+    });
+}
+
+/// Leave the alternate screen and log again if the table is shown, before neolink exits
+pub(crate) fn restore() {
+    if SHOWN.swap(false, Ordering::SeqCst) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+    }
+    if let Some(level) = LOG_LEVEL.lock().unwrap().take() {
+        log::set_max_level(level);
+    }
+}
+
+/// The rates of each stream are worked out from its counters at the last redraw
+#[derive(Default)]
+struct Table {
+    last: HashMap<(String, String), (Instant, u64, u64)>,
+}
+
+impl Table {
+    fn draw(&mut self, streams: &[LiveStream], now: Instant) -> String {
+        let mut rows = vec![[
+            "CAMERA".to_string(),
+            "STREAM".to_string(),
+            "STATE".to_string(),
+            "FPS".to_string(),
+            "BITRATE".to_string(),
+            "KEYFRAME".to_string(),
+            "LAST ERROR".to_string(),
+        ]];
+        for stream in streams {
+            let key = (stream.camera.clone(), stream.stream.clone());
+            let rates = self.last.get(&key).and_then(|(then, frames, bytes)| {
+                let secs = now.duration_since(*then).as_secs_f64();
+                (secs > 0.0).then(|| {
+                    (
+                        stream.frames.saturating_sub(*frames) as f64 / secs,
+                        stream.bytes.saturating_sub(*bytes) as f64 * 8.0 / secs,
+                    )
+                })
+            });
+            self.last.insert(key, (now, stream.frames, stream.bytes));
+            let (fps, bitrate) = match rates {
+                Some((fps, bits)) => (
+                    format!("{:.1}", fps),
+                    format!("{:.0} kbit/s", bits / 1000.0),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let mut last_error = stream.last_error.clone().unwrap_or_else(|| "-".to_string());
+            if last_error.chars().count() > MAX_ERROR_LEN {
+                last_error = last_error.chars().take(MAX_ERROR_LEN - 3).collect();
+                last_error.push_str("...");
+            }
+            rows.push([
+                stream.camera.clone(),
+                stream.stream.clone(),
+                format!("{:?}", stream.state),
+                fps,
+                bitrate,
+                stream.keyframe_age.map_or_else(
+                    || "-".to_string(),
+                    |age| format!("{:.1}s", age.as_secs_f64()),
+                ),
+                last_error,
+            ]);
+        }
+        let mut widths = [0; 7];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let mut out = String::new();
+        for row in &rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            out.push_str(cells.join("  ").trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::state::ConnectionState;
+    use super::*;
+
+    fn stream(frames: u64, bytes: u64) -> LiveStream {
+        LiveStream {
+            camera: "Garage".to_string(),
+            stream: "mainStream".to_string(),
+            state: ConnectionState::Streaming,
+            frames,
+            bytes,
+            keyframe_age: Some(Duration::from_millis(1500)),
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn test_rates_since_last_draw() {
+        let start = Instant::now();
+        let mut table = Table::default();
+        let out = table.draw(&[stream(100, 10_000)], start);
+        assert!(out.lines().nth(1).unwrap().contains("Streaming  -    -"));
+        let out = table.draw(&[stream(130, 260_000)], start + Duration::from_secs(2));
+        let row = out.lines().nth(1).unwrap();
+        assert!(row.contains("15.0  1000 kbit/s  1.5s"), "row was {}", row);
+    }
+}
@@ -0,0 +1,136 @@
+//! Prints the rtsp urls that the cameras of the config will be served at
+//!
+//! Each url includes the credentials of the first user, by name, that may
+//! watch the camera. The password is masked unless the secrets are shown.
+use crate::config::{CameraConfig, Config};
+use std::net::IpAddr;
+
+use super::get_permitted_users;
+
+// Shown in place of the passwords
+const MASKED: &str = "****";
+
+/// Print the url of each stream of every camera in `config`
+pub(crate) fn print_urls(config: &Config, show_secrets: bool) {
+    let scheme = if config.certificate.is_some() {
+        "rtsps"
+    } else {
+        "rtsp"
+    };
+    let host = match &config.advertised_host {
+        Some(host) => url_host(host),
+        None => url_host(&config.bind_addr),
+    };
+    for camera in &config.cameras {
+        let port = camera.bind_port.unwrap_or(config.bind_port);
+        let user = url_user(config, camera, show_secrets);
+        for stream in camera.enabled_streams() {
+            for path in camera.rtsp_paths(stream) {
+                println!(
+                    "{} {}: {}",
+                    camera.name,
+                    stream,
+                    rtsp_url(scheme, &host, port, user.as_deref(), &path)
+                );
+            }
+        }
+    }
+}
+
+// The `user:pass` part of the urls of `camera`, or `None` without users
+fn url_user(config: &Config, camera: &CameraConfig, show_secrets: bool) -> Option<String> {
+    let mut permitted: Vec<_> = get_permitted_users(&config.users, &camera.permitted_users)
+        .into_iter()
+        .filter(|name| *name != "anonymous")
+        .collect();
+    permitted.sort_unstable();
+    let name = permitted.first()?;
+    let pass = config
+        .users
+        .iter()
+        .find(|user| user.name == *name)
+        .map(|user| user.pass.as_str())
+        .unwrap_or_default();
+    let pass = if show_secrets {
+        encode(pass)
+    } else {
+        MASKED.to_string()
+    };
+    Some(format!("{}:{}", encode(name), pass))
+}
+
+// The host to put in the urls, the name of this host when bound to all addresses
+fn url_host(bind_addr: &str) -> String {
+    match bind_addr.parse::<IpAddr>() {
+        Ok(ip) if ip.is_unspecified() => hostname().unwrap_or_else(|| "localhost".to_string()),
+        Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+        _ => bind_addr.to_string(),
+    }
+}
+
+fn hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    let result = unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) };
+    if result != 0 {
+        return None;
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    String::from_utf8(name[..len].to_vec())
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+fn rtsp_url(scheme: &str, host: &str, port: u16, user: Option<&str>, path: &str) -> String {
+    match user {
+        Some(user) => format!("{}://{}@{}:{}{}", scheme, user, host, port, path),
+        None => format!("{}://{}:{}{}", scheme, host, port, path),
+    }
+}
+
+// Percent encode the characters that can't be in the user part of a url
+fn encode(part: &str) -> String {
+    part.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtsp_url() {
+        assert_eq!(
+            rtsp_url("rtsp", "nvr.lan", 8554, None, "/Garage"),
+            "rtsp://nvr.lan:8554/Garage"
+        );
+        assert_eq!(
+            rtsp_url(
+                "rtsps",
+                "[::1]",
+                9000,
+                Some("admin:****"),
+                "/Garage/subStream"
+            ),
+            "rtsps://admin:****@[::1]:9000/Garage/subStream"
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("p@ss:w/rd"), "p%40ss%3Aw%2Frd");
+        assert_eq!(encode("plain-name_1"), "plain-name_1");
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(url_host("192.168.1.2"), "192.168.1.2");
+        assert_eq!(url_host("fe80::1"), "[fe80::1]");
+        assert_ne!(url_host("0.0.0.0"), "0.0.0.0");
+    }
+}
@@ -0,0 +1,8 @@
+use structopt::StructOpt;
+
+/// The sdcard command will report the state of the camera's SD card
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+}
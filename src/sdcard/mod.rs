@@ -0,0 +1,68 @@
+///
+/// # Neolink SD Card
+///
+/// This module handles the sdcard subcommand
+///
+/// The subcommand reports the capacity, free space and state
+/// of the camera's SD card.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink sdcard --config=config.toml CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::xml::HddInfo;
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::find_and_connect;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the sdcard subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera = find_and_connect(&config, &opt.camera)?;
+    let hdd_info = match camera.get_hdd_info() {
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            return Err(anyhow!(
+                "{}: The camera does not support storage",
+                opt.camera
+            ))
+        }
+        result => {
+            result
+                .context("Unable to get the camera storage info")?
+                .hdd_info
+        }
+    };
+
+    if hdd_info.is_empty() {
+        println!("{}: No storage present", opt.camera);
+        return Ok(());
+    }
+
+    for hdd in &hdd_info {
+        println!("{}: {}", opt.camera, describe(hdd));
+    }
+    Ok(())
+}
+
+pub(crate) fn describe(hdd: &HddInfo) -> String {
+    let state = match (hdd.mount, hdd.format) {
+        (_, 0) => "error: not formatted",
+        (0, _) => "error: not mounted",
+        _ => "ok",
+    };
+    format!(
+        "{} {}: {:.1} GB, {:.1} GB free, {}",
+        hdd.storage_type.as_deref().unwrap_or("storage"),
+        hdd.number,
+        hdd.capacity as f64 / 1024.0,
+        hdd.remain_size as f64 / 1024.0,
+        state
+    )
+}
@@ -0,0 +1,26 @@
+use anyhow::{anyhow, Result};
+use structopt::StructOpt;
+
+fn on_off_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The siren command will sound or silence the alarm of the camera
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Whether to sound the siren or silence it
+    #[structopt(parse(try_from_str = on_off_parse), name = "on|off")]
+    pub on: bool,
+    /// Keep running and silence the siren after this many seconds
+    #[structopt(long)]
+    pub duration: Option<u64>,
+}
@@ -0,0 +1,71 @@
+///
+/// # Neolink Siren
+///
+/// This module handles the siren subcommand
+///
+/// The subcommand sounds the alarm of the camera until it is silenced, or
+/// silences it. Silencing also stops a siren that the camera sounded by itself
+/// such as on motion. With `--duration` a Ctrl-C silences the siren before
+/// the time is up.
+///
+/// # Usage
+///
+/// ```bash
+/// # To sound the siren
+/// neolink siren --config=config.toml CameraName on
+/// # Or for 10 seconds
+/// neolink siren --config=config.toml CameraName on --duration=10
+/// # Or silence it
+/// neolink siren --config=config.toml CameraName off
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::{find_and_connect, CameraStop};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the siren subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    if opt.duration.is_some() && !opt.on {
+        return Err(anyhow!("--duration can only be given to turn the siren on"));
+    }
+
+    let camera = find_and_connect(&config, &opt.camera)?;
+    match camera.siren_set(opt.on) {
+        Err(neolink_core::Error::Refused { response_code, .. }) => {
+            return Err(anyhow!(
+                "{}: The camera refused the siren with code {}, it may have no siren",
+                opt.camera,
+                response_code
+            ))
+        }
+        result => result.context("Unable to set the siren")?,
+    }
+
+    if let Some(duration) = opt.duration {
+        let interrupted = Arc::new(CameraStop::default());
+        let handler_interrupted = interrupted.clone();
+        if let Err(e) = ctrlc::set_handler(move || handler_interrupted.stop()) {
+            warn!(
+                "Unable to set the Ctrl-C handler, Ctrl-C will not silence the siren: {}",
+                e
+            );
+        }
+        info!("{}: Sounding the siren for {}s", opt.camera, duration);
+        if interrupted.sleep(Duration::from_secs(duration)) {
+            info!("{}: Interrupted, silencing the siren", opt.camera);
+        }
+        camera
+            .siren_set(false)
+            .context("Unable to silence the siren")?;
+    }
+    Ok(())
+}
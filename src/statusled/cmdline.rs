@@ -1,12 +1,27 @@
 use anyhow::{anyhow, Result};
 use structopt::StructOpt;
 
-fn onoff_parse(src: &str) -> Result<bool> {
+/// What to do with the status light
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LedMode {
+    /// Turn the light on
+    On,
+    /// Turn the light off
+    Off,
+    /// Blink the light so the camera can be found
+    Identify,
+    /// Keep running and follow the led_schedule in the config
+    Schedule,
+}
+
+fn mode_parse(src: &str) -> Result<LedMode> {
     match src {
-        "true" | "on" | "yes" => Ok(true),
-        "false" | "off" | "no" => Ok(false),
+        "true" | "on" | "yes" => Ok(LedMode::On),
+        "false" | "off" | "no" => Ok(LedMode::Off),
+        "identify" | "blink" => Ok(LedMode::Identify),
+        "schedule" => Ok(LedMode::Schedule),
         _ => Err(anyhow!(
-            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            "Could not understand {}, check your input, should be true/false, on/off, yes/no, identify or schedule",
             src
         )),
     }
@@ -17,7 +32,11 @@ fn onoff_parse(src: &str) -> Result<bool> {
 pub struct Opt {
     /// The name of the camera to change the lights of. Must be a name in the config
     pub camera: String,
-    /// Whether to turn the light on or off
-    #[structopt(parse(try_from_str = onoff_parse), name = "on|off")]
-    pub on: bool,
+    /// Whether to turn the light on or off, blink it to identify the camera,
+    /// or keep running and follow the led_schedule of the camera config
+    #[structopt(parse(try_from_str = mode_parse), name = "on|off|identify|schedule")]
+    pub mode: LedMode,
+    /// How many seconds to blink the light for in identify mode
+    #[structopt(long, default_value = "10")]
+    pub duration: u64,
 }
@@ -13,24 +13,97 @@
 /// neolink status-light --config=config.toml CameraName on
 /// # Or off
 /// neolink status-light --config=config.toml CameraName off
+/// # Or blink it for 30s to find the camera
+/// neolink status-light --config=config.toml CameraName identify --duration=30
+/// # Or keep running and follow the led_schedule in the config
+/// neolink status-light --config=config.toml CameraName schedule
 /// ```
 ///
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use neolink_core::bc_protocol::BcCamera;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 mod cmdline;
+mod schedule;
 
 use super::config::Config;
-use crate::utils::find_and_connect;
+use crate::utils::{find_and_connect, find_camera_by_name};
+use cmdline::LedMode;
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the ledstatus subcommand
 ///
 /// Opt is the command line options
 pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
-    let mut camera = find_and_connect(&config, &opt.camera)?;
+    if opt.mode == LedMode::Schedule {
+        let camera_config = find_camera_by_name(&config, &opt.camera)?;
+        return schedule::schedule_loop(camera_config);
+    }
 
+    let camera = find_and_connect(&config, &opt.camera)?;
+
+    let result = match opt.mode {
+        LedMode::On => camera
+            .led_light_set(true)
+            .context("Unable to set camera light state"),
+        LedMode::Off => camera
+            .led_light_set(false)
+            .context("Unable to set camera light state"),
+        LedMode::Identify => identify(&camera, Duration::from_secs(opt.duration)),
+        LedMode::Schedule => unreachable!(),
+    };
+    match result {
+        Err(e) if is_unsupported(&e) => {
+            Err(anyhow!("{}: The camera has no status light", opt.camera))
+        }
+        result => result,
+    }
+}
+
+fn is_unsupported(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<neolink_core::Error>(),
+        Some(neolink_core::Error::Unsupported { .. })
+    )
+}
+
+/// Blink the light at about 1Hz for `duration` then put it back how it was
+fn identify(camera: &BcCamera, duration: Duration) -> Result<()> {
+    let was_on = camera
+        .get_ledstate()
+        .context("Unable to get camera light state")?
+        .light_state
+        == "open";
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_handler = stop.clone();
+    ctrlc::set_handler(move || stop_handler.store(true, Ordering::SeqCst))
+        .context("Unable to set the Ctrl-C handler")?;
+
+    info!("Blinking the status light for {}s", duration.as_secs());
+    let half_period = Duration::from_millis(500);
+    let end = Instant::now() + duration;
+    let mut on = !was_on;
+    let mut result = Ok(());
+    while Instant::now() < end && !stop.load(Ordering::SeqCst) {
+        let toggle_start = Instant::now();
+        if let Err(e) = camera.led_light_set(on) {
+            result = Err(e).context("Unable to set camera light state");
+            break;
+        }
+        on = !on;
+        // Account for the time taken talking to the camera to keep the timing steady
+        if let Some(remaining) = half_period.checked_sub(toggle_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    info!("Restoring the status light");
     camera
-        .led_light_set(opt.on)
-        .context("Unable to set camera light state")?;
-    Ok(())
+        .led_light_set(was_on)
+        .context("Unable to restore camera light state")?;
+    result
 }
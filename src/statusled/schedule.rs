@@ -0,0 +1,182 @@
+//! Keeps the status light on or off according to the `led_schedule`
+//! of the camera config
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use log::*;
+use std::time::Duration;
+use sunrise::{Coordinates, SolarDay, SolarEvent};
+
+use super::super::config::{CameraConfig, LedScheduleConfig};
+use crate::utils::{connect_and_login, is_login_locked, wait_for_login_lockout, Jitter};
+
+/// How often the schedule is checked against the current time
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Apply the led schedule forever, reconnecting to the camera when needed
+pub(crate) fn schedule_loop(camera_config: &CameraConfig) -> Result<()> {
+    let schedule = camera_config.led_schedule.as_ref().ok_or_else(|| {
+        anyhow!(
+            "{}: No led_schedule is set in the config for this camera",
+            camera_config.name
+        )
+    })?;
+
+    let (min_backoff, max_backoff) = camera_config.reconnect_backoff();
+    let mut current_backoff = min_backoff;
+    let mut jitter = Jitter::for_camera(camera_config, "led schedule");
+
+    loop {
+        let err = schedule_main(camera_config, schedule, &mut current_backoff, min_backoff);
+        if is_login_locked(&err) {
+            wait_for_login_lockout(camera_config, &mut jitter, None);
+            continue;
+        }
+        let delay = jitter.apply(current_backoff);
+        error!(
+            "Error controlling the light of camera {}, will retry in {:.1}s: {:?}",
+            camera_config.name,
+            delay.as_secs_f32(),
+            err
+        );
+        std::thread::sleep(delay);
+        current_backoff = std::cmp::min(max_backoff, current_backoff * 2);
+    }
+}
+
+/// Connect and apply the schedule until an error occurs
+fn schedule_main(
+    camera_config: &CameraConfig,
+    schedule: &LedScheduleConfig,
+    current_backoff: &mut Duration,
+    min_backoff: Duration,
+) -> anyhow::Error {
+    let camera = match connect_and_login(camera_config) {
+        Ok(camera) => camera,
+        Err(e) => return e,
+    };
+    *current_backoff = min_backoff;
+
+    let mut applied = None;
+    loop {
+        let on = match light_should_be_on(schedule, Local::now()) {
+            Ok(on) => on,
+            Err(e) => return e,
+        };
+        if applied != Some(on) {
+            info!(
+                "{}: Turning the status light {} on schedule",
+                camera_config.name,
+                if on { "on" } else { "off" }
+            );
+            if let Err(e) = camera
+                .led_light_set(on)
+                .context("Unable to set camera light state")
+            {
+                return e;
+            }
+            applied = Some(on);
+        }
+        std::thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+/// Work out whether the light should be on at the time `now`
+fn light_should_be_on(schedule: &LedScheduleConfig, now: DateTime<Local>) -> Result<bool> {
+    match schedule {
+        LedScheduleConfig {
+            off_at: Some(off_at),
+            on_at: Some(on_at),
+            ..
+        } => {
+            let off_at = NaiveTime::parse_from_str(off_at, "%H:%M")?;
+            let on_at = NaiveTime::parse_from_str(on_at, "%H:%M")?;
+            let time = now.time();
+            let off = if off_at <= on_at {
+                off_at <= time && time < on_at
+            } else {
+                // The off window wraps around midnight
+                time >= off_at || time < on_at
+            };
+            Ok(!off)
+        }
+        LedScheduleConfig {
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+            ..
+        } => {
+            let coords = Coordinates::new(*latitude, *longitude)
+                .ok_or_else(|| anyhow!("Invalid latitude or longitude"))?;
+            let day = SolarDay::new(coords, now.date_naive());
+            let sunrise = day.event_time(SolarEvent::Sunrise);
+            let sunset = day.event_time(SolarEvent::Sunset);
+            let now = now.with_timezone(&Utc);
+            Ok(sunrise <= now && now < sunset)
+        }
+        _ => Err(anyhow!("Incomplete led_schedule in the config")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Offset, TimeZone};
+
+    fn times(off_at: &str, on_at: &str) -> LedScheduleConfig {
+        LedScheduleConfig {
+            off_at: Some(off_at.to_string()),
+            on_at: Some(on_at.to_string()),
+            latitude: None,
+            longitude: None,
+        }
+    }
+
+    fn at(hour: u32, min: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 6, 21, hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_fixed_times() {
+        let daytime_off = times("09:00", "17:30");
+        assert!(light_should_be_on(&daytime_off, at(8, 59)).unwrap());
+        assert!(!light_should_be_on(&daytime_off, at(9, 0)).unwrap());
+        assert!(!light_should_be_on(&daytime_off, at(17, 29)).unwrap());
+        assert!(light_should_be_on(&daytime_off, at(17, 30)).unwrap());
+
+        // The off window wraps around midnight
+        let night_off = times("22:00", "06:00");
+        assert!(light_should_be_on(&night_off, at(21, 59)).unwrap());
+        assert!(!light_should_be_on(&night_off, at(23, 0)).unwrap());
+        assert!(!light_should_be_on(&night_off, at(0, 30)).unwrap());
+        assert!(light_should_be_on(&night_off, at(6, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_sunrise_and_sunset() {
+        // On the equator at the longitude of the local time zone the sun
+        // rises around six and sets around six
+        let offset = Local
+            .offset_from_utc_date(&at(12, 0).date_naive())
+            .fix()
+            .local_minus_utc();
+        let schedule = LedScheduleConfig {
+            off_at: None,
+            on_at: None,
+            latitude: Some(0.0),
+            longitude: Some((offset as f64 / 240.0).clamp(-180.0, 180.0)),
+        };
+        assert!(light_should_be_on(&schedule, at(12, 0)).unwrap());
+        assert!(!light_should_be_on(&schedule, at(2, 0)).unwrap());
+        assert!(!light_should_be_on(&schedule, at(22, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_incomplete_schedule() {
+        let schedule = LedScheduleConfig {
+            off_at: Some("22:00".to_string()),
+            on_at: None,
+            latitude: None,
+            longitude: None,
+        };
+        assert!(light_should_be_on(&schedule, at(12, 0)).is_err());
+    }
+}
@@ -0,0 +1,12 @@
+use structopt::StructOpt;
+
+/// The sync-time command sets the clock of every camera that is off from this host's
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// Only set the clocks that are off by more than this many seconds
+    #[structopt(long, default_value = "10")]
+    pub threshold: u64,
+    /// Set by the global `--dry-run` to only report the clocks
+    #[structopt(skip)]
+    pub dry_run: bool,
+}
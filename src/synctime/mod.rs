@@ -0,0 +1,181 @@
+///
+/// # Neolink Sync Time
+///
+/// This module handles the sync-time subcommand
+///
+/// Every camera in the config is connected to at once and its clock compared
+/// with this host's. The clocks that are unset or off by more than the
+/// `--threshold` are set to the local time of this host. A table of how far
+/// each camera was off and what was done is printed before exiting, so it
+/// can be run from cron. With `--dry-run` the clocks are only reported.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink sync-time --config=config.toml
+/// # Only report the clocks that are more than a minute off
+/// neolink sync-time --config=config.toml --threshold=60 --dry-run
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use time::OffsetDateTime;
+
+mod cmdline;
+
+use super::config::{CameraConfig, Config};
+use crate::utils::connect_and_login;
+pub(crate) use cmdline::Opt;
+
+/// What was done with the clock of one camera
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Within the threshold so left alone
+    InSync,
+    /// Would have been set but this is a dry run
+    WouldSet,
+    /// Set to the time of this host
+    Set,
+    /// The camera did not accept the time
+    Refused,
+}
+
+/// How many seconds a camera was ahead, `None` if its clock was unset, and
+/// what was done about it
+type SyncResult = Result<(Option<i64>, Outcome)>;
+
+/// Entry point for the sync-time subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let results: Vec<(&CameraConfig, SyncResult)> = crossbeam::scope(|s| {
+        let handles: Vec<_> = config
+            .cameras
+            .iter()
+            .map(|camera_config| {
+                let opt = &opt;
+                (
+                    camera_config,
+                    s.spawn(move |_| sync_camera(camera_config, opt)),
+                )
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|(camera_config, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("The time sync panicked")));
+                (camera_config, result)
+            })
+            .collect()
+    })
+    .unwrap();
+
+    let name_width = results
+        .iter()
+        .map(|(camera_config, _)| camera_config.name.len())
+        .chain(std::iter::once("Camera".len()))
+        .max()
+        .unwrap_or_default();
+    println!("{:<width$}  Skew      Action", "Camera", width = name_width);
+    for (camera_config, result) in &results {
+        match result {
+            Ok((skew, outcome)) => println!(
+                "{:<width$}  {:<8}  {}",
+                camera_config.name,
+                match skew {
+                    Some(skew) => format!("{:+}s", skew),
+                    None => "unset".to_string(),
+                },
+                match outcome {
+                    Outcome::InSync => "left alone",
+                    Outcome::WouldSet => "would be set",
+                    Outcome::Set => "set",
+                    Outcome::Refused => "refused by the camera",
+                },
+                width = name_width
+            ),
+            Err(e) => println!(
+                "{:<width$}  {:<8}  FAIL {:#}",
+                camera_config.name,
+                "",
+                e,
+                width = name_width
+            ),
+        }
+    }
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed > 0 {
+        Err(anyhow!(
+            "{} of {} cameras could not be synced",
+            failed,
+            results.len()
+        ))
+    } else {
+        let set = results
+            .iter()
+            .filter(|(_, result)| matches!(result, Ok((_, Outcome::Set))))
+            .count();
+        info!("Set the clocks of {} of {} cameras", set, results.len());
+        Ok(())
+    }
+}
+
+/// Compare the camera's clock with this host's and set it if it is too far off
+fn sync_camera(camera_config: &CameraConfig, opt: &Opt) -> SyncResult {
+    let camera = connect_and_login(camera_config)?;
+    let skew = camera
+        .get_time()
+        .context("Unable to get the camera time")?
+        .map(|time| (time - OffsetDateTime::now_utc()).whole_seconds());
+    if !needs_setting(skew, opt.threshold) {
+        return Ok((skew, Outcome::InSync));
+    }
+    if opt.dry_run {
+        return Ok((skew, Outcome::WouldSet));
+    }
+
+    let new_time = OffsetDateTime::try_now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    info!(
+        "{}: Setting the camera time to {}",
+        camera_config.name, new_time
+    );
+    match camera.set_time(new_time) {
+        Err(e @ neolink_core::Error::Refused { .. })
+        | Err(e @ neolink_core::Error::Unsupported { .. }) => {
+            warn!(
+                "{}: The camera did not accept the time: {}",
+                camera_config.name, e
+            );
+            Ok((skew, Outcome::Refused))
+        }
+        result => {
+            result.context("Unable to set the camera time")?;
+            Ok((skew, Outcome::Set))
+        }
+    }
+}
+
+/// Whether a clock `skew` seconds from this host's, or unset, should be set
+fn needs_setting(skew: Option<i64>, threshold: u64) -> bool {
+    match skew {
+        Some(skew) => skew.unsigned_abs() > threshold,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_setting() {
+        assert!(!needs_setting(Some(0), 10));
+        assert!(!needs_setting(Some(-10), 10));
+        assert!(needs_setting(Some(11), 10));
+        assert!(needs_setting(Some(-3600), 10));
+        assert!(needs_setting(None, 10));
+    }
+}
@@ -1,6 +1,32 @@
+use anyhow::{anyhow, Error};
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
 
+/// The format of audio read from stdin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Raw signed 16 bit little endian PCM
+    S16le,
+    /// A wav file, the format is read from its header
+    Wav,
+}
+
+impl FromStr for InputFormat {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "s16le" => Ok(InputFormat::S16le),
+            "wav" => Ok(InputFormat::Wav),
+            _ => Err(anyhow!(
+                "Could not understand {}, check your input, should be s16le or wav",
+                src
+            )),
+        }
+    }
+}
+
 /// The talk command will send audio for the camera to say
 ///
 /// This data should be encoded in a way that gstreamer can understand.
@@ -11,13 +37,34 @@ use structopt::StructOpt;
 pub struct Opt {
     /// The name of the camera to talk through. Must be a name in the config
     pub camera: String,
-    /// The path to the audio file.
-    #[structopt(short, long, parse(from_os_str), conflicts_with = "microphone")]
+    /// The path to the audio file. Wav files of any common sample rate, channel count and
+    /// bit depth are converted directly, other formats are decoded with gstreamer
+    #[structopt(short, long, parse(from_os_str), conflicts_with_all = &["microphone", "stdin"])]
     pub file_path: Option<PathBuf>,
     /// Use the microphone as the source. Defaults to autoaudiosrc - Which microphone depends
     /// on [gstreamer](https://gstreamer.freedesktop.org/documentation/autodetect/autoaudiosrc.html?gi-language=c#autoaudiosrc-page)
-    #[structopt(short, long, conflicts_with = "file_path")]
+    #[structopt(short, long, conflicts_with_all = &["file_path", "stdin"])]
     pub microphone: bool,
+    /// Read audio from stdin until it is closed e.g. `some-tts | neolink talk --stdin CameraName`
+    #[structopt(long, conflicts_with_all = &["file_path", "microphone"])]
+    pub stdin: bool,
+    /// Play a sine wave of this many Hz instead of any input, to check that
+    /// audio reaches the camera speaker
+    #[structopt(long, conflicts_with_all = &["file_path", "microphone", "stdin"])]
+    pub test_tone: Option<f32>,
+    /// How many seconds to play the --test-tone for
+    #[structopt(long, default_value = "3")]
+    pub duration: f32,
+    /// The format of the audio on stdin: s16le (raw PCM) or wav
+    #[structopt(long, default_value = "s16le")]
+    pub format: InputFormat,
+    /// The sample rate of raw PCM on stdin. Wav input uses the rate from its header
+    #[structopt(long, default_value = "16000")]
+    pub rate: u32,
+    /// The number of interleaved channels of raw PCM on stdin, they are mixed down to mono.
+    /// Wav input uses the channels from its header
+    #[structopt(long, default_value = "1")]
+    pub channels: u16,
     /// Use a specific microphone like "alsasrc device=hw:1"
     #[structopt(
         short,
@@ -26,7 +73,15 @@ pub struct Opt {
         conflicts_with = "file_path"
     )]
     pub input_src: String,
-    /// Use to change the volume of the input
+    /// Encode for this sample rate instead of the one the camera reports, for a camera
+    /// whose audio plays too fast or too slow e.g. 8000 or 16000
+    #[structopt(long)]
+    pub force_rate: Option<u16>,
+    /// Use to change the volume of the input as a linear multiplier
     #[structopt(short, long, default_value = "1.0")]
     pub volume: f32,
+    /// Use to change the volume of the input in decibels e.g. 6 to double it.
+    /// Loud samples are soft limited rather than clipped
+    #[structopt(long, default_value = "0.0", allow_hyphen_values = true)]
+    pub gain: f32,
 }
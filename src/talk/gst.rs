@@ -8,17 +8,13 @@ use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
 
 use byte_slice_cast::*;
 
-pub(super) fn from_input(
-    input_src: &str,
-    volume: f32,
-    block_align: u16,
-    sample_rate: u16,
-) -> Result<Receiver<Vec<u8>>> {
-    let pipeline = create_pipeline(input_src, volume, block_align, sample_rate)?;
+/// Decode the input with gstreamer into mono 16 bit PCM at `sample_rate`
+pub(super) fn from_input(input_src: &str, sample_rate: u16) -> Result<Receiver<Vec<i16>>> {
+    let pipeline = create_pipeline(input_src, sample_rate)?;
     input(pipeline)
 }
 
-fn input(pipeline: Pipeline) -> Result<Receiver<Vec<u8>>> {
+fn input(pipeline: Pipeline) -> Result<Receiver<Vec<i16>>> {
     let appsink = get_sink(&pipeline)?;
     let (tx, rx) = sync_channel(30);
 
@@ -69,7 +65,7 @@ fn get_sink(pipeline: &Pipeline) -> Result<AppSink> {
         .map_err(|_| anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins"))
 }
 
-fn set_data_channel(appsink: &AppSink, tx: SyncSender<Vec<u8>>) {
+fn set_data_channel(appsink: &AppSink, tx: SyncSender<Vec<i16>>) {
     // Getting data out of the appsink is done by setting callbacks on it.
     // The appsink will then call those handlers, as soon as data is available.
     appsink.set_callbacks(
@@ -107,19 +103,23 @@ fn set_data_channel(appsink: &AppSink, tx: SyncSender<Vec<u8>>) {
 
                 // We know what format the data in the memory region has, since we requested
                 // it by setting the appsink's caps. So what we do here is interpret the
-                // memory region we mapped as an array of signed 8 bit integers.
-                let samples = map.as_slice_of::<u8>().map_err(|_| {
+                // memory region we mapped as an array of little endian signed 16 bit integers.
+                let bytes = map.as_slice_of::<u8>().map_err(|_| {
                     element_error!(
                         appsink,
                         ResourceError::Failed,
-                        ("Failed to interprete buffer as u8 ADPCM")
+                        ("Failed to interprete buffer as S16LE PCM")
                     );
 
                     FlowError::Error
                 })?;
+                let samples = bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect();
 
                 // Ready!
-                let _ = tx.send(samples.to_vec());
+                let _ = tx.send(samples);
 
                 Ok(FlowSuccess::Ok)
             })
@@ -127,12 +127,7 @@ fn set_data_channel(appsink: &AppSink, tx: SyncSender<Vec<u8>>) {
     );
 }
 
-fn create_pipeline(
-    source: &str,
-    volume: f32,
-    block_align: u16,
-    sample_rate: u16,
-) -> Result<Pipeline> {
+fn create_pipeline(source: &str, sample_rate: u16) -> Result<Pipeline> {
     gstreamer::init()
         .context("Unable to start gstreamer ensure it and all plugins are installed")?;
 
@@ -141,12 +136,10 @@ fn create_pipeline(
         ! decodebin \
         ! audioconvert \
         ! audioresample \
-        ! audio/x-raw,rate={},channels=1 \
-        ! volume volume={:.2} \
+        ! audio/x-raw,format=S16LE,rate={},channels=1 \
         ! queue  \
-        ! adpcmenc blockalign={} layout=dvi \
         ! appsink name=thesink",
-        source, sample_rate, volume, block_align
+        source, sample_rate
     );
 
     log::info!("{}", launch_str);
@@ -167,10 +160,10 @@ fn create_pipeline(
     // This can be set after linking the two objects, because format negotiation between
     // both elements will happen during pre-rolling of the pipeline.
     appsink.set_caps(Some(&Caps::new_simple(
-        "audio/x-adpcm",
+        "audio/x-raw",
         &[
-            ("layout", &"dvi"),
-            ("block_align", &(block_align as i32)),
+            ("format", &"S16LE"),
+            ("layout", &"interleaved"),
             ("channels", &(1i32)),
             ("rate", &(sample_rate as i32)),
         ],
@@ -9,15 +9,28 @@
 ///
 /// ```bash
 /// neolink talk --config=config.toml --adpcm-file=data.adpcm --sample-rate=16000 --block-size=512 CameraName
+/// # Or stream raw PCM from another program
+/// some-tts | neolink talk --config=config.toml --stdin --format=s16le --rate=16000 CameraName
+/// # Or check the speaker with a 1kHz tone for 3 seconds
+/// neolink talk --config=config.toml --test-tone=1000 --duration=3 CameraName
+/// # Encode for 8kHz on a camera that reports the wrong rate
+/// neolink talk --config=config.toml --file-path=hello.wav --force-rate=8000 CameraName
 /// ```
 ///
+/// The sample rate and block size are read from the talk ability of the
+/// camera and logged before the audio is sent.
+///
 use anyhow::{anyhow, Context, Result};
-use neolink_core::bc::xml::TalkConfig;
+use log::*;
+use neolink_core::{bc::xml::TalkConfig, bc_protocol::BcCamera, pcm::db_to_linear};
+use std::path::Path;
+use std::time::Duration;
 
 mod cmdline;
 mod gst;
+pub(crate) mod pcm;
 
-use super::config::Config;
+use super::config::{CameraConfig, Config};
 use crate::utils::{connect_and_login, find_camera_by_name};
 pub(crate) use cmdline::Opt;
 
@@ -28,9 +41,85 @@ pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
     let camera_config = find_camera_by_name(&config, &opt.camera)?;
     let camera = connect_and_login(camera_config)?;
 
-    let talk_ability = camera
-        .talk_ability()
-        .with_context(|| format!("Camera {} does not support talk", camera_config.name))?;
+    let (talk_config, mut camera_audio) =
+        talk_setup(&camera, camera_config, opt.volume * db_to_linear(opt.gain))?;
+    if let Some(rate) = opt.force_rate {
+        if rate == 0 {
+            return Err(anyhow!("The forced rate must be above 0Hz"));
+        }
+        // The camera is still told the rate it reported, which is the one it accepts
+        warn!(
+            "{}: Encoding at the forced {}Hz instead of the {}Hz the camera reported",
+            camera_config.name, rate, camera_audio.sample_rate
+        );
+        camera_audio.sample_rate = rate;
+    }
+    let sample_rate = camera_audio.sample_rate;
+
+    if let Some(frequency) = opt.test_tone {
+        if frequency <= 0.0 || frequency >= sample_rate as f32 / 2.0 {
+            return Err(anyhow!(
+                "The test tone must be between 0 and {}Hz for the camera's {}Hz audio",
+                sample_rate / 2,
+                sample_rate
+            ));
+        }
+        let duration = Duration::from_secs_f32(opt.duration.max(0.0));
+        let rx = pcm::test_tone(frequency, duration, camera_audio);
+        return camera
+            .talk_stream(rx, talk_config)
+            .context("The camera did not play the test tone");
+    }
+
+    let rx = match (&opt.file_path, &opt.microphone) {
+        _ if opt.stdin => pcm::from_stdin(opt.format, opt.rate, opt.channels, camera_audio)
+            .context("Failed to setup reading audio from stdin")?,
+        (Some(path), false) if is_wav(path) => pcm::from_wav_file(path, camera_audio)
+            .with_context(|| format!("Failed to setup reading the wav file: {:?}", path))?,
+        (Some(path), false) => pcm::start_encoder(
+            gst::from_input(
+                &format!(
+                    "filesrc location={}",
+                    path.to_str().expect("File path not UTF8 complient")
+                ),
+                sample_rate,
+            )
+            .with_context(|| format!("Failed to setup gst with the file: {:?}", path))?,
+            sample_rate as u32,
+            camera_audio,
+        ),
+        (None, true) => pcm::start_encoder(
+            gst::from_input(&opt.input_src, sample_rate)
+                .context("Failed to setup gst using the microphone")?,
+            sample_rate as u32,
+            camera_audio,
+        ),
+        _ => unreachable!(),
+    };
+
+    camera
+        .talk_stream(rx, talk_config)
+        .context("Talk stream ended early")?;
+
+    Ok(())
+}
+
+/// Get the talk config of the camera and the audio format it expects
+pub(crate) fn talk_setup(
+    camera: &BcCamera,
+    camera_config: &CameraConfig,
+    gain: f32,
+) -> Result<(TalkConfig, pcm::CameraAudio)> {
+    let talk_ability = match camera.talk_ability() {
+        Err(neolink_core::Error::Unsupported { .. }) => {
+            return Err(anyhow!(
+                "Camera {} does not support talk",
+                camera_config.name
+            ))
+        }
+        result => result
+            .with_context(|| format!("Unable to get the talk ability of {}", camera_config.name))?,
+    };
     if talk_ability.duplex_list.is_empty()
         || talk_ability.audio_stream_mode_list.is_empty()
         || talk_ability.audio_config_list.is_empty()
@@ -64,25 +153,21 @@ pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
         ));
     }
 
-    let rx = match (&opt.file_path, &opt.microphone) {
-        (Some(path), false) => gst::from_input(
-            &format!(
-                "filesrc location={}",
-                path.to_str().expect("File path not UTF8 complient")
-            ),
-            opt.volume,
-            block_size,
-            sample_rate,
-        )
-        .with_context(|| format!("Failed to setup gst with the file: {:?}", path))?,
-        (None, true) => gst::from_input(&opt.input_src, opt.volume, block_size, sample_rate)
-            .context("Failed to setup gst using the microphone")?,
-        _ => unreachable!(),
+    info!(
+        "{}: The camera expects {}Hz {} audio in blocks of {} bytes",
+        camera_config.name, sample_rate, talk_config.audio_config.audio_type, block_size
+    );
+    let camera_audio = pcm::CameraAudio {
+        block_size,
+        sample_rate,
+        gain,
     };
 
-    camera
-        .talk_stream(rx, talk_config)
-        .context("Talk stream ended early")?;
+    Ok((talk_config, camera_audio))
+}
 
-    Ok(())
+fn is_wav(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("wav"))
+        .unwrap_or(false)
 }
@@ -0,0 +1,231 @@
+//! Reads raw PCM or WAV audio from stdin or a file and encodes it
+//! to ADPCM for the camera
+//!
+//! Any common WAV format is accepted, the sample rate, channel count
+//! and bit depth are read from the header and the audio is converted
+//! to mono at the camera's rate.
+//!
+//! Audio decoded by gstreamer is also passed through here so that the
+//! gain and encoding are the same for every input.
+use anyhow::{Context, Result};
+use log::*;
+use neolink_core::{
+    adpcm::AdpcmEncoder,
+    pcm::{apply_gain, downmix, float_to_i16, int_to_i16, Resampler, SineWave},
+};
+use std::fs::File;
+use std::io::{stdin, BufReader, Read};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::time::Duration;
+
+use super::cmdline::InputFormat;
+
+/// How many samples are read at a time
+const CHUNK_SAMPLES: usize = 1024;
+
+/// The talk parameters from the camera
+#[derive(Clone, Copy)]
+pub(crate) struct CameraAudio {
+    pub(crate) block_size: u16,
+    pub(crate) sample_rate: u16,
+    /// Linear gain applied before encoding
+    pub(crate) gain: f32,
+}
+
+pub(super) fn from_stdin(
+    format: InputFormat,
+    input_rate: u32,
+    channels: u16,
+    camera: CameraAudio,
+) -> Result<Receiver<Vec<u8>>> {
+    match format {
+        InputFormat::S16le => {
+            let (pcm_tx, pcm_rx) = sync_channel(30);
+            std::thread::spawn(move || {
+                if let Err(e) = read_s16le(stdin(), channels, pcm_tx) {
+                    error!("Failed to read audio from stdin: {:?}", e);
+                }
+            });
+            Ok(start_encoder(pcm_rx, input_rate, camera))
+        }
+        InputFormat::Wav => {
+            // The header is read before starting so that bad input fails early
+            let reader =
+                hound::WavReader::new(stdin()).context("Failed to read the wav header on stdin")?;
+            Ok(from_wav(reader, camera))
+        }
+    }
+}
+
+pub(super) fn from_wav_file(path: &Path, camera: CameraAudio) -> Result<Receiver<Vec<u8>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let reader = hound::WavReader::new(BufReader::new(file))
+        .with_context(|| format!("Failed to read the wav header of {:?}", path))?;
+    Ok(from_wav(reader, camera))
+}
+
+fn from_wav<R: Read + Send + 'static>(
+    reader: hound::WavReader<R>,
+    camera: CameraAudio,
+) -> Receiver<Vec<u8>> {
+    let spec = reader.spec();
+    info!(
+        "Converting {}Hz {} channel {} bit wav to {}Hz mono",
+        spec.sample_rate, spec.channels, spec.bits_per_sample, camera.sample_rate
+    );
+    let (pcm_tx, pcm_rx) = sync_channel(30);
+    std::thread::spawn(move || {
+        if let Err(e) = read_wav(reader, pcm_tx) {
+            error!("Failed to read wav audio: {:?}", e);
+        }
+    });
+    start_encoder(pcm_rx, spec.sample_rate, camera)
+}
+
+fn read_s16le<R: Read>(mut input: R, channels: u16, tx: SyncSender<Vec<i16>>) -> Result<()> {
+    let frame_bytes = 2 * channels.max(1) as usize;
+    let mut buffer = vec![0u8; CHUNK_SAMPLES * frame_bytes];
+    let mut filled = 0;
+    loop {
+        let read = input.read(&mut buffer[filled..])?;
+        if read == 0 {
+            // EOF
+            return Ok(());
+        }
+        filled += read;
+        // Keep any partial frame until the rest of it arrives
+        let usable = filled - filled % frame_bytes;
+        let samples: Vec<i16> = buffer[..usable]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        buffer.copy_within(usable..filled, 0);
+        filled -= usable;
+        if tx.send(downmix(&samples, channels)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn read_wav<R: Read>(reader: hound::WavReader<R>, tx: SyncSender<Vec<i16>>) -> Result<()> {
+    let spec = reader.spec();
+    let chunk = CHUNK_SAMPLES * spec.channels as usize;
+    let mut samples = Vec::with_capacity(chunk);
+    let send = |samples: &mut Vec<i16>| {
+        let ok = tx.send(downmix(samples, spec.channels)).is_ok();
+        samples.clear();
+        ok
+    };
+
+    match spec.sample_format {
+        hound::SampleFormat::Int => {
+            for sample in reader.into_samples::<i32>() {
+                samples.push(int_to_i16(sample?, spec.bits_per_sample));
+                if samples.len() == chunk && !send(&mut samples) {
+                    return Ok(());
+                }
+            }
+        }
+        hound::SampleFormat::Float => {
+            for sample in reader.into_samples::<f32>() {
+                samples.push(float_to_i16(sample?));
+                if samples.len() == chunk && !send(&mut samples) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    if !samples.is_empty() {
+        send(&mut samples);
+    }
+    Ok(())
+}
+
+/// Encode a sine wave of `frequency` Hz lasting `duration` to ADPCM blocks
+///
+/// The blocks are handed over one at a time as the talk stream asks for them,
+/// and the talk stream only asks once the camera has accepted the talk config.
+/// So the first block being taken confirms that the camera opened the channel
+pub(super) fn test_tone(
+    frequency: f32,
+    duration: Duration,
+    camera: CameraAudio,
+) -> Receiver<Vec<u8>> {
+    let (tx, rx) = sync_channel(0);
+    std::thread::spawn(move || {
+        let mut encoder = AdpcmEncoder::new(camera.block_size as usize);
+        let samples_per_block = encoder.samples_per_block();
+        let mut tone = SineWave::new(frequency, camera.sample_rate as u32);
+        let total = (duration.as_secs_f32() * camera.sample_rate as f32) as usize;
+        let mut sent = 0;
+        while sent < total {
+            let mut samples = tone.samples(samples_per_block.min(total - sent));
+            apply_gain(&mut samples, camera.gain);
+            if tx.send(encoder.encode_block(&samples)).is_err() {
+                return;
+            }
+            if sent == 0 {
+                info!(
+                    "The camera opened the talk channel, playing a {}Hz tone for {:.1}s",
+                    frequency,
+                    duration.as_secs_f32()
+                );
+            }
+            sent += samples.len();
+        }
+    });
+    rx
+}
+
+/// Encode mono PCM at `input_rate` to ADPCM blocks in the camera's format
+pub(crate) fn start_encoder(
+    rx: Receiver<Vec<i16>>,
+    input_rate: u32,
+    camera: CameraAudio,
+) -> Receiver<Vec<u8>> {
+    let (tx, adpcm_rx) = sync_channel(30);
+    std::thread::spawn(move || {
+        let mut resampler = Resampler::new(input_rate, camera.sample_rate as u32);
+        let mut encoder = AdpcmEncoder::new(camera.block_size as usize);
+        let samples_per_block = encoder.samples_per_block();
+        let block_duration =
+            Duration::from_secs_f32(samples_per_block as f32 / camera.sample_rate as f32);
+
+        let mut pending: Vec<i16> = vec![];
+        loop {
+            match rx.recv_timeout(block_duration) {
+                Ok(mut samples) => {
+                    apply_gain(&mut samples, camera.gain);
+                    pending.extend(resampler.process(&samples));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // Underrun: keep the camera fed with silence until more audio arrives
+                    debug!("Audio underrun, sending silence");
+                    let block = encoder.encode_block(&pending);
+                    pending.clear();
+                    if tx.send(block).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    // Input closed, flush whatever is left
+                    if !pending.is_empty() {
+                        let _ = tx.send(encoder.encode_block(&pending));
+                    }
+                    return;
+                }
+            }
+
+            while pending.len() >= samples_per_block {
+                let block = encoder.encode_block(&pending[..samples_per_block]);
+                pending.drain(..samples_per_block);
+                if tx.send(block).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    adpcm_rx
+}
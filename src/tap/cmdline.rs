@@ -0,0 +1,16 @@
+use crate::config::parse_stream;
+use neolink_core::bc_protocol::Stream;
+use structopt::StructOpt;
+
+/// The tap command will log each packet of media that the camera sends
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera to tap. Must be a name in the config
+    pub camera: String,
+    /// The stream to tap: mainStream (main, clear), subStream (sub, fluent) or externStream (extern, balanced)
+    #[structopt(long, default_value = "mainStream", parse(try_from_str = parse_stream))]
+    pub stream: Stream,
+    /// Log at most this many packets each second, the rest are counted
+    #[structopt(long, default_value = "20")]
+    pub rate: u32,
+}
@@ -0,0 +1,109 @@
+///
+/// # Neolink Tap
+///
+/// This module handles the tap subcommand
+///
+/// The subcommand streams from the camera and logs one line for each
+/// packet of media: its kind, codec and size. It is meant for diagnosing
+/// streams without a packet capture. To keep the log readable only the
+/// first `--rate` packets of each second are logged and the rest are counted.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink tap --config=config.toml CameraName
+/// # The sub stream with every packet up to 100 a second
+/// neolink tap --config=config.toml --stream=subStream --rate=100 CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+use log::*;
+use neolink_core::{
+    bc_protocol::{StreamOutput, StreamOutputError, StreamOutputStatus},
+    bcmedia::model::*,
+};
+use std::time::{Duration, Instant};
+
+mod cmdline;
+
+use super::config::Config;
+use crate::utils::find_and_connect;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the tap subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let stream = opt.stream;
+    let camera = find_and_connect(&config, &opt.camera)?;
+    info!("Tapping the {:?} stream of {}", stream, opt.camera);
+    camera
+        .start_video(&mut TapOutput::new(opt.rate), stream)
+        .context("Error while tapping the camera")
+}
+
+/// Logs each packet, up to `rate` each second
+struct TapOutput {
+    rate: u32,
+    second: Instant,
+    logged: u32,
+    skipped: u32,
+}
+
+impl TapOutput {
+    fn new(rate: u32) -> Self {
+        TapOutput {
+            rate,
+            second: Instant::now(),
+            logged: 0,
+            skipped: 0,
+        }
+    }
+}
+
+impl StreamOutput for TapOutput {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        if self.second.elapsed() >= Duration::from_secs(1) {
+            if self.skipped > 0 {
+                info!("... and {} more packets that second", self.skipped);
+            }
+            self.second = Instant::now();
+            self.logged = 0;
+            self.skipped = 0;
+        }
+        if self.logged >= self.rate {
+            self.skipped += 1;
+            return Ok(StreamOutputStatus::Continue);
+        }
+        self.logged += 1;
+        info!("{}", describe(&media));
+        Ok(StreamOutputStatus::Continue)
+    }
+}
+
+fn describe(media: &BcMedia) -> String {
+    match media {
+        BcMedia::InfoV1(info) => format!(
+            "Info   V1    {}x{} at {} fps",
+            info.video_width, info.video_height, info.fps
+        ),
+        BcMedia::InfoV2(info) => format!(
+            "Info   V2    {}x{} at {} fps",
+            info.video_width, info.video_height, info.fps
+        ),
+        BcMedia::Iframe(frame) => format!(
+            "I      {:?}  {} bytes at {}us",
+            frame.video_type,
+            frame.data.len(),
+            frame.microseconds
+        ),
+        BcMedia::Pframe(frame) => format!(
+            "P      {:?}  {} bytes at {}us",
+            frame.video_type,
+            frame.data.len(),
+            frame.microseconds
+        ),
+        BcMedia::Aac(audio) => format!("Audio  AAC   {} bytes", audio.data.len()),
+        BcMedia::Adpcm(audio) => format!("Audio  ADPCM {} bytes", audio.data.len()),
+    }
+}
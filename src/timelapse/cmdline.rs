@@ -0,0 +1,23 @@
+use crate::config::parse_stream;
+use neolink_core::bc_protocol::Stream;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// The timelapse command saves a JPEG of a camera at an interval
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Save a JPEG every this many seconds
+    #[structopt(long, default_value = "60")]
+    pub interval: f64,
+    /// Save the JPEGs in this directory, which is created if needed
+    #[structopt(long, parse(from_os_str))]
+    pub out: PathBuf,
+    /// Stop after saving this many JPEGs, otherwise run until Ctrl-C
+    #[structopt(long)]
+    pub max_frames: Option<u32>,
+    /// The stream to take the JPEGs from: mainStream (main, clear), subStream (sub, fluent) or externStream (extern, balanced)
+    #[structopt(long, default_value = "mainStream", parse(try_from_str = parse_stream))]
+    pub stream: Stream,
+}
@@ -0,0 +1,161 @@
+///
+/// # Neolink Timelapse
+///
+/// This module handles the timelapse subcommand
+///
+/// The subcommand saves a JPEG of a camera every `--interval` seconds into
+/// the `--out` directory, named after the camera and the time it was taken.
+/// The stream is only pulled for long enough to get the keyframe that a
+/// camera sends first when its stream starts, and stopped again until the next
+/// JPEG is due. The keyframe is decoded like the snapshots of the rtsp
+/// server. A camera that drops the connection is connected to again for the
+/// next JPEG. It runs until `--max-frames` have been saved or Ctrl-C.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink timelapse --config=config.toml --interval=60 --out=timelapse CameraName
+/// # Stop after a day of JPEGs from the sub stream
+/// neolink timelapse --config=config.toml --interval=60 --out=timelapse --max-frames=1440 --stream=subStream CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use log::*;
+use neolink_core::{
+    bc_protocol::{BcCamera, Stream, StreamOutput, StreamOutputError, StreamOutputStatus},
+    bcmedia::model::*,
+};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod cmdline;
+
+use super::config::{CameraConfig, Config};
+use crate::rtsp::keyframe_to_jpeg;
+use crate::utils::{connect_and_login, find_camera_by_name, live_output, CameraStop};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the timelapse subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    if !opt.interval.is_finite() || opt.interval < 1.0 {
+        return Err(anyhow!("The interval must be at least a second"));
+    }
+    let interval = Duration::from_secs_f64(opt.interval);
+    let camera_config = find_camera_by_name(&config, &opt.camera)?;
+    gstreamer::init().context("Unable to start gstreamer")?;
+    fs::create_dir_all(&opt.out)
+        .with_context(|| format!("Unable to create {}", opt.out.display()))?;
+
+    let stop = Arc::new(CameraStop::default());
+    let stop_handler = stop.clone();
+    ctrlc::set_handler(move || stop_handler.stop()).context("Unable to set the Ctrl-C handler")?;
+
+    let start = Instant::now();
+    let mut camera = None;
+    let mut saved = 0;
+    while opt.max_frames.is_none_or(|max| saved < max) {
+        match capture(&mut camera, camera_config, opt.stream, &stop) {
+            Ok(Some(jpeg)) => {
+                let path = opt.out.join(file_name(&opt.camera, &Local::now()));
+                fs::write(&path, jpeg)
+                    .with_context(|| format!("Unable to save {}", path.display()))?;
+                saved += 1;
+                info!("{}: Saved {}", opt.camera, path.display());
+            }
+            // Stopped before the keyframe arrived
+            Ok(None) => {}
+            Err(e) => {
+                warn!("{}: Unable to take a JPEG: {:?}", opt.camera, e);
+                camera = None;
+            }
+        }
+        let next = next_capture(start, interval, Instant::now());
+        if stop.sleep(next.saturating_duration_since(Instant::now())) {
+            break;
+        }
+    }
+    info!("{}: Saved {} JPEGs", opt.camera, saved);
+    Ok(())
+}
+
+/// Pull the stream of the camera until its first keyframe and decode it
+///
+/// Connects to the camera first when `camera` is not connected
+fn capture(
+    camera: &mut Option<BcCamera>,
+    camera_config: &CameraConfig,
+    stream: Stream,
+    stop: &CameraStop,
+) -> Result<Option<Vec<u8>>> {
+    let camera = match camera {
+        Some(camera) => camera,
+        None => camera.insert(connect_and_login(camera_config)?),
+    };
+    let mut output = KeyframeOutput { keyframe: None };
+    camera
+        .start_video(
+            &mut live_output(camera_config, stream, Some(stop), &mut output),
+            stream,
+        )
+        .context("Error while streaming from the camera")?;
+    camera.stop_video(stream)?;
+    match output.keyframe {
+        Some((video_type, data)) => Ok(Some(keyframe_to_jpeg(video_type, &data)?)),
+        None => Ok(None),
+    }
+}
+
+/// When the next JPEG is due, skipping those that were missed
+fn next_capture(start: Instant, interval: Duration, now: Instant) -> Instant {
+    let taken = now.duration_since(start).as_secs_f64() / interval.as_secs_f64();
+    start + interval.mul_f64(taken.floor() + 1.0)
+}
+
+// The name of the JPEG of the camera taken at `time` e.g. `Garage_2026-10-14_09-30-00.jpg`
+fn file_name(camera: &str, time: &DateTime<Local>) -> String {
+    format!("{}_{}.jpg", camera, time.format("%Y-%m-%d_%H-%M-%S"))
+}
+
+/// Keeps the first keyframe of the stream and stops it
+struct KeyframeOutput {
+    keyframe: Option<(VideoType, Vec<u8>)>,
+}
+
+impl StreamOutput for KeyframeOutput {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        match media {
+            BcMedia::Iframe(frame) => {
+                self.keyframe = Some((frame.video_type, frame.data));
+                Ok(StreamOutputStatus::Stop)
+            }
+            _ => Ok(StreamOutputStatus::Continue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_next_capture() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(60);
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(next_capture(start, interval, at(0)), at(60));
+        assert_eq!(next_capture(start, interval, at(5)), at(60));
+        // A JPEG that took longer than the interval skips the one it missed
+        assert_eq!(next_capture(start, interval, at(130)), at(180));
+    }
+
+    #[test]
+    fn test_file_name() {
+        let time = Local.with_ymd_and_hms(2026, 10, 14, 9, 30, 5).unwrap();
+        assert_eq!(file_name("Garage", &time), "Garage_2026-10-14_09-30-05.jpg");
+    }
+}
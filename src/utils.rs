@@ -4,8 +4,22 @@ use log::*;
 
 use super::config::{CameraConfig, Config};
 use anyhow::{anyhow, Context, Error, Result};
-use neolink_core::bc_protocol::BcCamera;
+use crossbeam::channel::bounded;
+use lazy_static::lazy_static;
+use neolink_core::bc_protocol::{
+    BcCamera, BcDump, Stream, StreamOutput, StreamOutputError, StreamOutputStatus,
+};
+use neolink_core::bcmedia::{model::BcMedia, sps::parse_sps_size};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{hash_map::DefaultHasher, HashMap};
 use std::fmt::{Display, Error as FmtError, Formatter};
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
 
 pub(crate) enum AddressOrUid {
     Address(String),
@@ -22,23 +36,171 @@ impl Display for AddressOrUid {
 }
 
 impl AddressOrUid {
+    pub(crate) fn for_camera(camera_config: &CameraConfig) -> Result<Self, Error> {
+        Self::new(
+            &camera_config.camera_addr,
+            &camera_config.camera_uid,
+            camera_config.camera_port,
+        )
+    }
+
     // Created by translating the config fields directly
-    pub(crate) fn new(address: &Option<String>, uid: &Option<String>) -> Result<Self, Error> {
+    //
+    // The address always gets its port, from the address itself, the `port`
+    // or the default, so that the logged address is the one connected to
+    pub(crate) fn new(
+        address: &Option<String>,
+        uid: &Option<String>,
+        port: Option<u16>,
+    ) -> Result<Self, Error> {
         match (address, uid) {
             (None, None) => Err(anyhow!("Neither address or uid given")),
             (Some(_), Some(_)) => Err(anyhow!("Either address or uid should be given not both")),
-            (Some(host), None) => Ok(AddressOrUid::Address(host.clone())),
-            (None, Some(host)) => Ok(AddressOrUid::Uid(host.clone())),
+            (Some(addr), None) => {
+                let (host, addr_port) = split_host_port(addr)?;
+                let port = match (addr_port, port) {
+                    (Some(addr_port), Some(port)) if addr_port != port => {
+                        return Err(anyhow!(
+                            "The address {} has another port than the port {}",
+                            addr,
+                            port
+                        ))
+                    }
+                    (addr_port, port) => addr_port.or(port).unwrap_or(DEFAULT_CAMERA_PORT),
+                };
+                // IPv6 hosts need the brackets before a port
+                let host = match host.contains(':') {
+                    true => format!("[{}]", host),
+                    false => host.to_string(),
+                };
+                Ok(AddressOrUid::Address(format!("{}:{}", host, port)))
+            }
+            (None, Some(_)) if port.is_some() => Err(anyhow!(
+                "The port only applies to an address, a uid camera replies to the discovery \
+                 from a port it chooses itself"
+            )),
+            (None, Some(uid)) => Ok(AddressOrUid::Uid(uid.clone())),
         }
     }
 
     // Convience method to get the BcCamera with the appropiate method
-    pub(crate) fn connect_camera(&self, channel_id: u8) -> Result<BcCamera, Error> {
+    pub(crate) fn connect_camera(
+        &self,
+        channel_id: u8,
+        source: Option<IpAddr>,
+    ) -> Result<BcCamera, Error> {
         match self {
-            AddressOrUid::Address(host) => Ok(BcCamera::new_with_addr(host, channel_id)?),
-            AddressOrUid::Uid(host) => Ok(BcCamera::new_with_uid(host, channel_id)?),
+            AddressOrUid::Address(host) => {
+                let mut addrs = resolve_address(host)?;
+                debug!("Resolved {} to {:?}", host, addrs);
+                // Only the addresses of its own family can be reached from the source
+                if let Some(source) = source {
+                    addrs.retain(|addr| addr.is_ipv6() == source.is_ipv6());
+                    if addrs.is_empty() {
+                        return Err(anyhow!(
+                            "{} has no address of the same family as the source_ip {}",
+                            host,
+                            source
+                        ));
+                    }
+                }
+                Ok(BcCamera::new_with_addr_from(
+                    &addrs[..],
+                    channel_id,
+                    source,
+                )?)
+            }
+            AddressOrUid::Uid(host) => Ok(BcCamera::new_with_uid_from(host, channel_id, source)?),
+        }
+    }
+}
+
+/// The port of the camera when the address doesn't give one
+const DEFAULT_CAMERA_PORT: u16 = 9000;
+
+/// Splits a config address into the host and the port if there is one
+///
+/// IPv6 hosts must be in brackets when a port is given, e.g. `[2001:db8::1]:9000`,
+/// and may have a zone id such as `fe80::1%eth0`
+pub(crate) fn split_host_port(addr: &str) -> Result<(&str, Option<u16>)> {
+    let (host, port) = if let Some(bracketed) = addr.strip_prefix('[') {
+        let (host, rest) = bracketed
+            .split_once(']')
+            .ok_or_else(|| anyhow!("Missing ] in the address {}", addr))?;
+        match rest {
+            "" => (host, None),
+            _ => match rest.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None => return Err(anyhow!("Unexpected text after ] in the address {}", addr)),
+            },
+        }
+    } else {
+        match addr.rsplit_once(':') {
+            // More than one colon is a bare IPv6 address
+            Some((host, port)) if !host.contains(':') => (host, Some(port)),
+            _ => (addr, None),
+        }
+    };
+
+    if host.trim().is_empty() {
+        return Err(anyhow!("The address {} has no host", addr));
+    }
+    let port = port
+        .map(|port| {
+            port.parse::<u16>()
+                .with_context(|| format!("Invalid port in the address {}", addr))
+        })
+        .transpose()?;
+    Ok((host, port))
+}
+
+/// Resolves a config address, which may be an IPv4 or IPv6 address or a hostname
+/// with an optional port, into the socket addresses to try
+pub(crate) fn resolve_address(addr: &str) -> Result<Vec<SocketAddr>> {
+    let (host, port) = split_host_port(addr)?;
+    let port = port.unwrap_or(DEFAULT_CAMERA_PORT);
+
+    if let Some((ip, zone)) = host.split_once('%') {
+        let ip: Ipv6Addr = ip
+            .parse()
+            .with_context(|| format!("Only IPv6 addresses may have a zone id in {}", addr))?;
+        let scope_id = zone_to_scope_id(zone)
+            .with_context(|| format!("Unknown zone id {} in the address {}", zone, addr))?;
+        return Ok(vec![SocketAddr::V6(SocketAddrV6::new(
+            ip, port, 0, scope_id,
+        ))]);
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+
+    let addrs: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve the address {}", addr))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(anyhow!("The address {} did not resolve to anything", addr));
+    }
+    Ok(addrs)
+}
+
+/// Zone ids are either the interface index or, on unix, the interface name
+fn zone_to_scope_id(zone: &str) -> Result<u32> {
+    if let Ok(index) = zone.parse::<u32>() {
+        return Ok(index);
+    }
+
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(zone)?;
+        // Safety: `name` is a valid nul terminated string that outlives the call
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index != 0 {
+            return Ok(index);
         }
     }
+
+    Err(anyhow!("No network interface called {}", zone))
 }
 
 pub(crate) fn find_and_connect(config: &Config, name: &str) -> Result<BcCamera> {
@@ -47,32 +209,398 @@ pub(crate) fn find_and_connect(config: &Config, name: &str) -> Result<BcCamera>
 }
 
 pub(crate) fn connect_and_login(camera_config: &CameraConfig) -> Result<BcCamera> {
-    let camera_addr =
-        AddressOrUid::new(&camera_config.camera_addr, &camera_config.camera_uid).unwrap();
+    let camera_addr = AddressOrUid::for_camera(camera_config)?;
     info!(
         "{}: Connecting to camera at {}",
         camera_config.name, camera_addr
     );
 
-    let mut camera = camera_addr
-        .connect_camera(camera_config.channel_id)
-        .with_context(|| {
-            format!(
-                "Failed to connect to camera {} at {} on channel {}",
-                camera_config.name, camera_addr, camera_config.channel_id
-            )
-        })?;
-
-    info!("{}: Logging in", camera_config.name);
-    camera
-        .login(&camera_config.username, camera_config.password.as_deref())
-        .with_context(|| format!("Failed to login to {}", camera_config.name))?;
+    let camera = connect_with_timeout(camera_config, camera_addr)?;
 
     info!("{}: Connected and logged in", camera_config.name);
 
     Ok(camera)
 }
 
+lazy_static! {
+    static ref CONNECT_SLOTS: ConnectSlots = ConnectSlots::default();
+    // The password that last logged in to each camera
+    static ref WORKING_PASSWORDS: Mutex<HashMap<String, WorkingPassword>> = Default::default();
+    // Counts the changes refused by the cameras in read only mode, if it is on
+    static ref READ_ONLY: Mutex<Option<Arc<AtomicU64>>> = Default::default();
+    // Where the bc frames of the cameras are dumped, if anywhere
+    static ref BC_DUMP: Mutex<Option<Arc<BcDump>>> = Default::default();
+}
+
+/// Dump the bc frames of the cameras that connect from now on to the file at `path`
+pub(crate) fn dump_bc_to(path: &Path) -> Result<()> {
+    let dump = BcDump::create(path)
+        .with_context(|| format!("Unable to create the bc dump {}", path.display()))?;
+    warn!(
+        "Dumping the bc protocol of the cameras to {}. The passwords are redacted but it holds \
+         everything else the cameras send, such as their UIDs, settings and network details, \
+         so only share it with those you trust",
+        path.display()
+    );
+    *BC_DUMP.lock().unwrap() = Some(Arc::new(dump));
+    Ok(())
+}
+
+/// Only log the changes to the cameras that connect from now on instead of making them
+pub(crate) fn set_read_only(read_only: bool) {
+    *READ_ONLY.lock().unwrap() = read_only.then(Default::default);
+}
+
+/// How many changes to the cameras were refused in read only mode
+pub(crate) fn suppressed_changes() -> u64 {
+    READ_ONLY
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or(0, |suppressed| suppressed.load(Ordering::Relaxed))
+}
+
+/// Let at most `max` cameras connect and log in at the same time, or any number if `None`
+///
+/// Streaming is not limited, only the connection storm when many cameras
+/// start or reconnect together
+pub(crate) fn limit_concurrent_connects(max: Option<usize>) {
+    CONNECT_SLOTS.set_max(max);
+}
+
+/// Counts the cameras that are connecting and logging in
+#[derive(Default)]
+struct ConnectSlots {
+    state: Mutex<SlotState>,
+    freed: Condvar,
+}
+
+#[derive(Default)]
+struct SlotState {
+    max: Option<usize>,
+    in_use: usize,
+    // The priorities of the cameras waiting for a slot
+    waiting: Vec<i32>,
+}
+
+impl SlotState {
+    // A free slot goes to the waiting camera with the highest priority
+    fn may_take(&self, priority: i32) -> bool {
+        match self.max {
+            None => true,
+            Some(max) => {
+                self.in_use < max && self.waiting.iter().all(|&waiting| waiting <= priority)
+            }
+        }
+    }
+}
+
+impl ConnectSlots {
+    fn set_max(&self, max: Option<usize>) {
+        self.state.lock().unwrap().max = max;
+        self.freed.notify_all();
+    }
+
+    /// Block until a slot is free for a camera of `priority` and hold it
+    /// until the guard is dropped
+    fn acquire(&self, priority: i32) -> ConnectSlot<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.waiting.push(priority);
+        let mut state = self
+            .freed
+            .wait_while(state, |state| !state.may_take(priority))
+            .unwrap();
+        let index = state
+            .waiting
+            .iter()
+            .position(|&waiting| waiting == priority)
+            .expect("A waiting camera is in the list");
+        state.waiting.swap_remove(index);
+        state.in_use += 1;
+        // Cameras of a lower priority may take a slot that is still free
+        self.freed.notify_all();
+        ConnectSlot(self)
+    }
+}
+
+struct ConnectSlot<'a>(&'a ConnectSlots);
+
+impl<'a> Drop for ConnectSlot<'a> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().in_use -= 1;
+        self.0.freed.notify_all();
+    }
+}
+
+/// Connects and logs in on a helper thread so that a camera which accepts the
+/// connection but never answers can't hang the caller past `connect_timeout`
+///
+/// An abandoned attempt is left to finish on its own and the camera it
+/// returns, if any, is dropped. It keeps its connect slot until then, and the
+/// timeout only starts once a slot is free
+pub(crate) fn connect_with_timeout(
+    camera_config: &CameraConfig,
+    camera_addr: AddressOrUid,
+) -> Result<BcCamera> {
+    let timeout = Duration::from_secs(camera_config.connect_timeout);
+    let name = camera_config.name.clone();
+    let camera_config = camera_config.clone();
+    let slot = CONNECT_SLOTS.acquire(camera_config.priority);
+    let (sender, receiver) = bounded(1);
+    spawn(move || {
+        let _slot = slot;
+        let _ = sender.send(connect_and_login_blocking(&camera_config, &camera_addr));
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(Error::from(neolink_core::Error::Timeout).context(format!(
+            "Timed out after {}s connecting to camera {}",
+            timeout.as_secs(),
+            name
+        ))),
+    }
+}
+
+// Connect to the camera and set the tcp options of the connection
+fn connect_camera(camera_config: &CameraConfig, camera_addr: &AddressOrUid) -> Result<BcCamera> {
+    let mut camera =
+        camera_addr.connect_camera(camera_config.channel_id, camera_config.source_ip())?;
+    if let Some(suppressed) = READ_ONLY.lock().unwrap().as_ref() {
+        camera.set_read_only(suppressed.clone());
+    }
+    if let Some(dump) = BC_DUMP.lock().unwrap().as_ref() {
+        camera.set_dump(dump.clone(), &camera_config.name);
+    }
+    match camera.set_tcp_options(&camera_config.tcp_options()) {
+        Ok(Some(effective)) => debug!("{}: Tcp options {:?}", camera_config.name, effective),
+        Ok(None) => {}
+        Err(e) => warn!(
+            "{}: Unable to set the tcp options: {:?}",
+            camera_config.name, e
+        ),
+    }
+    Ok(camera)
+}
+
+fn connect_and_login_blocking(
+    camera_config: &CameraConfig,
+    camera_addr: &AddressOrUid,
+) -> Result<BcCamera> {
+    let camera = connect_camera(camera_config, camera_addr).with_context(|| {
+        format!(
+            "Failed to connect to camera {} at {} on channel {}",
+            camera_config.name, camera_addr, camera_config.channel_id
+        )
+    })?;
+
+    let passwords = camera_config.password_candidates()?;
+    let mut first = Some(camera);
+    login_with_passwords(&camera_config.name, &passwords, |password| {
+        let mut camera = match first.take() {
+            Some(camera) => camera,
+            // The camera may hang up after a failed login so start afresh
+            None => connect_camera(camera_config, camera_addr)
+                .with_context(|| format!("Failed to reconnect to camera {}", camera_config.name))?,
+        };
+        info!("{}: Logging in", camera_config.name);
+        camera.login(&camera_config.username, password.as_deref())?;
+        Ok(camera)
+    })
+}
+
+/// The password that last logged in to a camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WorkingPassword {
+    // Its hash so that no other copy of it is kept
+    hash: u64,
+    // It was refused once since, which was taken as a lockout
+    refused: bool,
+}
+
+/// Log in to the camera `name` with each of `passwords` in turn, starting
+/// with the one that last worked
+fn login_with_passwords<T>(
+    name: &str,
+    passwords: &[Option<String>],
+    mut login: impl FnMut(&Option<String>) -> Result<T>,
+) -> Result<T> {
+    let remembered = WORKING_PASSWORDS.lock().unwrap().get(name).copied();
+    let order = password_order(passwords, remembered.map(|working| working.hash));
+    let last = order.len() - 1;
+    for (attempt, index) in order.into_iter().enumerate() {
+        let password = &passwords[index];
+        let e = match login(password) {
+            Ok(logged_in) => {
+                if passwords.len() > 1 {
+                    info!(
+                        "{}: Logged in with password {} of {}",
+                        name,
+                        index + 1,
+                        passwords.len()
+                    );
+                }
+                let working = WorkingPassword {
+                    hash: password_hash(password),
+                    refused: false,
+                };
+                WORKING_PASSWORDS
+                    .lock()
+                    .unwrap()
+                    .insert(name.to_string(), working);
+                return Ok(logged_in);
+            }
+            Err(e) => e,
+        };
+        let refused = matches!(
+            e.downcast_ref::<neolink_core::Error>(),
+            Some(neolink_core::Error::AuthFailed)
+        );
+        if !refused {
+            return Err(e.context(format!("Failed to login to {}", name)));
+        }
+        match login_refusal(password, remembered) {
+            neolink_core::Error::LoginLocked => {
+                // Every other password tried now would extend the lockout. If the
+                // password is refused again once it is over then it was changed on
+                // the camera and the others are tried
+                if let Some(working) = WORKING_PASSWORDS.lock().unwrap().get_mut(name) {
+                    working.refused = true;
+                }
+                return Err(neolink_core::Error::LoginLocked)
+                    .with_context(|| format!("Failed to login to {}", name));
+            }
+            _ if attempt < last => {
+                warn!(
+                    "{}: Password {} of {} was refused, trying the next",
+                    name,
+                    index + 1,
+                    passwords.len()
+                );
+            }
+            e => return Err(e).with_context(|| format!("Failed to login to {}", name)),
+        }
+    }
+    unreachable!("There is always at least one password to try")
+}
+
+/// The error for a login refused with `password`
+///
+/// The camera refuses wrong credentials and an account that it has locked
+/// with the same reply, so the first refusal of the password that last
+/// logged in to it is taken as a lockout
+fn login_refusal(
+    password: &Option<String>,
+    remembered: Option<WorkingPassword>,
+) -> neolink_core::Error {
+    match remembered {
+        Some(working) if working.hash == password_hash(password) && !working.refused => {
+            neolink_core::Error::LoginLocked
+        }
+        _ => neolink_core::Error::AuthFailed,
+    }
+}
+
+/// The order to try the indices of `passwords` in, starting with the one that last worked
+///
+/// The password is remembered rather than its place in the list so that a
+/// reordered list still starts with it
+fn password_order(passwords: &[Option<String>], remembered: Option<u64>) -> Vec<usize> {
+    let first = passwords
+        .iter()
+        .position(|password| Some(password_hash(password)) == remembered);
+    first
+        .into_iter()
+        .chain((0..passwords.len()).filter(|index| Some(*index) != first))
+        .collect()
+}
+
+// Remembers a password without keeping another copy of it
+fn password_hash(password: &Option<String>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    password.hash(&mut hasher);
+    hasher.finish()
+}
+
+// How long to wait before logging in again once the camera has locked the account
+const LOGIN_LOCKED_BACKOFF: Duration = Duration::from_secs(300);
+
+/// True if the camera refused to log in because it has locked the account for a while
+pub(crate) fn is_login_locked(e: &Error) -> bool {
+    e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<neolink_core::Error>(),
+            Some(neolink_core::Error::LoginLocked)
+        )
+    })
+}
+
+/// True if the camera refused the username or password
+pub(crate) fn is_auth_failed(e: &Error) -> bool {
+    e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<neolink_core::Error>(),
+            Some(neolink_core::Error::AuthFailed)
+        )
+    })
+}
+
+/// True if the camera closed the connection itself, as some firmwares do
+/// every so often, rather than it timing out
+pub(crate) fn is_camera_hangup(e: &Error) -> bool {
+    e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<neolink_core::Error>(),
+            Some(neolink_core::Error::DroppedConnection(_))
+                | Some(neolink_core::Error::TimeoutDisconnected)
+        )
+    })
+}
+
+/// Wait out a login lockout of the camera
+///
+/// This is much longer than the usual reconnect delay because each attempt
+/// while the account is locked can extend the lockout. The wait ends early
+/// once `stop` is stopped.
+pub(crate) fn wait_for_login_lockout(
+    camera_config: &CameraConfig,
+    jitter: &mut Jitter,
+    stop: Option<&CameraStop>,
+) {
+    let delay = jitter.apply(LOGIN_LOCKED_BACKOFF);
+    warn!(
+        "{}: The camera has temporarily locked the account {} after too many failed logins, waiting {:.0}s before logging in again",
+        camera_config.name,
+        camera_config.username,
+        delay.as_secs_f32()
+    );
+    match stop {
+        Some(stop) => {
+            stop.sleep(delay);
+        }
+        None => std::thread::sleep(delay),
+    }
+}
+
+/// Decodes the `%XX` escapes of a url or ONVIF scope
+pub(crate) fn percent_decode(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(Ok(byte)) = url.get(i + 1..i + 3).map(|hex| u8::from_str_radix(hex, 16)) {
+                result.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
 pub(crate) fn find_camera_by_name<'a, 'b>(
     config: &'a Config,
     name: &'b str,
@@ -83,3 +611,714 @@ pub(crate) fn find_camera_by_name<'a, 'b>(
         .find(|c| c.name == name)
         .ok_or_else(|| anyhow!("Camera {} not found in the config file", name))
 }
+
+// Use the size from the SPS if this many keyframes arrive without a stream info
+const KEYFRAMES_BEFORE_SPS: usize = 2;
+
+/// Passes the media on to `inner` while logging the codec, size and frame rate
+/// of the stream once its info and first keyframe have arrived
+///
+/// The size is checked against the SPS of the keyframe, which is preferred
+/// when they disagree. Some firmwares never send the stream info, in that
+/// case the size is read from the SPS and the fps is unknown
+pub(crate) struct StreamInfoLog<'a, T: StreamOutput> {
+    camera_config: &'a CameraConfig,
+    stream: Stream,
+    inner: T,
+    // The width, height and fps from the stream info
+    info: Option<(u32, u32, Option<u8>)>,
+    keyframes_without_info: usize,
+    logged: bool,
+}
+
+impl<'a, T: StreamOutput> StreamInfoLog<'a, T> {
+    pub(crate) fn new(camera_config: &'a CameraConfig, stream: Stream, inner: T) -> Self {
+        Self {
+            camera_config,
+            stream,
+            inner,
+            info: None,
+            keyframes_without_info: 0,
+            logged: false,
+        }
+    }
+}
+
+impl<'a, T: StreamOutput> StreamOutput for StreamInfoLog<'a, T> {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        if !self.logged {
+            let name = &self.camera_config.name;
+            match &media {
+                BcMedia::InfoV1(info) => {
+                    self.info = Some((info.video_width, info.video_height, Some(info.fps)))
+                }
+                BcMedia::InfoV2(info) => {
+                    self.info = Some((info.video_width, info.video_height, Some(info.fps)))
+                }
+                BcMedia::Iframe(frame) => {
+                    let sps = parse_sps_size(frame.video_type, &frame.data);
+                    match (self.info, sps) {
+                        (Some((width, height, fps)), Some(size))
+                            if (width, height) != (size.width, size.height) =>
+                        {
+                            warn!(
+                                "{}: The stream info declares {}x{} but the video is {}x{}, using the size of the video",
+                                name, width, height, size.width, size.height
+                            );
+                            self.info = Some((size.width, size.height, fps));
+                        }
+                        (None, sps) => {
+                            self.keyframes_without_info += 1;
+                            if let Some(size) =
+                                sps.filter(|_| self.keyframes_without_info >= KEYFRAMES_BEFORE_SPS)
+                            {
+                                warn!(
+                                    "{}: No stream info after {} keyframes, using the SPS size {}x{}",
+                                    name, self.keyframes_without_info, size.width, size.height
+                                );
+                                self.info = Some((size.width, size.height, None));
+                            }
+                        }
+                        _ => {}
+                    }
+                    if let Some((width, height, fps)) = self.info {
+                        let fps = fps.map_or("an unknown".to_string(), |fps| fps.to_string());
+                        info!(
+                            "{}: {:?} stream is {:?} {}x{} at {} fps",
+                            name, self.stream, frame.video_type, width, height, fps
+                        );
+                        self.logged = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.inner.stream_recv(media)
+    }
+}
+
+/// The error of a stream failed by the [`StallWatchdog`]
+pub(crate) const STREAM_STALLED: &str = "The video stream stalled";
+
+/// Fails the stream when the camera stops sending video frames
+///
+/// A half open connection can keep the stream alive while no frames arrive,
+/// such as when the camera only sends audio or stream info. The error makes
+/// the stream reconnect like any other dropped connection.
+pub(crate) struct StallWatchdog<'a, T: StreamOutput> {
+    camera_config: &'a CameraConfig,
+    inner: T,
+    last_frame: Instant,
+}
+
+impl<'a, T: StreamOutput> StallWatchdog<'a, T> {
+    pub(crate) fn new(camera_config: &'a CameraConfig, inner: T) -> Self {
+        Self {
+            camera_config,
+            inner,
+            last_frame: Instant::now(),
+        }
+    }
+}
+
+impl<'a, T: StreamOutput> StreamOutput for StallWatchdog<'a, T> {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        match &media {
+            BcMedia::Iframe(_) | BcMedia::Pframe(_) => self.last_frame = Instant::now(),
+            _ => {
+                let stalled = self.last_frame.elapsed();
+                if stalled >= Duration::from_secs(self.camera_config.stall_timeout) {
+                    warn!(
+                        "{}: No video frames for {}s, reconnecting",
+                        self.camera_config.name,
+                        stalled.as_secs()
+                    );
+                    return Err(neolink_core::Error::Other(STREAM_STALLED));
+                }
+            }
+        }
+        self.inner.stream_recv(media)
+    }
+}
+
+/// Drops the kinds of media that are not in the camera's `media_filter`
+pub(crate) struct MediaFilter<'a, T: StreamOutput> {
+    camera_config: &'a CameraConfig,
+    inner: T,
+}
+
+impl<'a, T: StreamOutput> MediaFilter<'a, T> {
+    pub(crate) fn new(camera_config: &'a CameraConfig, inner: T) -> Self {
+        Self {
+            camera_config,
+            inner,
+        }
+    }
+}
+
+impl<'a, T: StreamOutput> StreamOutput for MediaFilter<'a, T> {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        let kind = match &media {
+            BcMedia::Iframe(_) | BcMedia::Pframe(_) => Some("video"),
+            BcMedia::Aac(_) | BcMedia::Adpcm(_) => Some("audio"),
+            _ => None,
+        };
+        match kind {
+            Some(kind) if !self.camera_config.forwards_media(kind) => {
+                Ok(StreamOutputStatus::Continue)
+            }
+            _ => self.inner.stream_recv(media),
+        }
+    }
+}
+
+/// Times how long a stream takes from `start_video` to its first keyframe
+///
+/// The video can't be decoded before the first keyframe, so this is how long a
+/// new connection goes without a picture
+#[derive(Debug)]
+pub(crate) struct FirstKeyframe {
+    started: Instant,
+    arrived: bool,
+}
+
+impl FirstKeyframe {
+    /// Start timing as the video of a connection is started
+    pub(crate) fn start() -> Self {
+        FirstKeyframe {
+            started: Instant::now(),
+            arrived: false,
+        }
+    }
+
+    /// Note a keyframe reaching the output, returning the latency if it is the first
+    pub(crate) fn keyframe(&mut self) -> Option<Duration> {
+        if self.arrived {
+            return None;
+        }
+        self.arrived = true;
+        Some(self.started.elapsed())
+    }
+}
+
+/// Tells the threads of a camera to finish, as when it is removed from the config
+#[derive(Default)]
+pub(crate) struct CameraStop {
+    stopped: Mutex<bool>,
+    changed: Condvar,
+}
+
+impl CameraStop {
+    /// Ask the threads of the camera to finish
+    pub(crate) fn stop(&self) {
+        *self.stopped.lock().unwrap() = true;
+        self.changed.notify_all();
+    }
+
+    /// Whether the threads of the camera should finish
+    pub(crate) fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap()
+    }
+
+    /// Sleep for `duration` or until the camera is stopped
+    ///
+    /// Returns true if the camera was stopped
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap();
+        let (stopped, _) = self
+            .changed
+            .wait_timeout_while(stopped, duration, |stopped| !*stopped)
+            .unwrap();
+        *stopped
+    }
+}
+
+/// Asks the stream to stop once the camera is stopped
+pub(crate) struct StopOutput<'a, T: StreamOutput> {
+    stop: Option<&'a CameraStop>,
+    inner: T,
+}
+
+impl<'a, T: StreamOutput> StreamOutput for StopOutput<'a, T> {
+    fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+        match self.stop {
+            Some(stop) if stop.is_stopped() => Ok(StreamOutputStatus::Stop),
+            _ => self.inner.stream_recv(media),
+        }
+    }
+}
+
+/// Wrap the outputs of a live camera stream with the handling all live streams share
+///
+/// The stall watchdog sees the media before the `media_filter` so that a
+/// camera filtered to audio is not mistaken for a stalled one. The stream
+/// ends once `stop` is stopped
+pub(crate) fn live_output<'a, T: StreamOutput + 'a>(
+    camera_config: &'a CameraConfig,
+    stream: Stream,
+    stop: Option<&'a CameraStop>,
+    outputs: T,
+) -> impl StreamOutput + 'a {
+    StopOutput {
+        stop,
+        inner: StallWatchdog::new(
+            camera_config,
+            StreamInfoLog::new(
+                camera_config,
+                stream,
+                MediaFilter::new(camera_config, outputs),
+            ),
+        ),
+    }
+}
+
+/// Randomises the reconnect delays so that cameras which dropped at the
+/// same time, such as after a PoE switch reboot, don't all retry together
+pub(crate) struct Jitter {
+    fraction: f64,
+    rng: StdRng,
+}
+
+impl Jitter {
+    /// The rng is seeded from the camera name and `purpose` so that each
+    /// camera connection gets its own sequence which is the same every run
+    pub(crate) fn for_camera(camera_config: &CameraConfig, purpose: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        (&camera_config.name, purpose).hash(&mut hasher);
+        Self::with_seed(camera_config.reconnect_jitter, hasher.finish())
+    }
+
+    pub(crate) fn with_seed(fraction: f64, seed: u64) -> Self {
+        Jitter {
+            fraction,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Scale the delay by a random amount within ±fraction
+    pub(crate) fn apply(&mut self, delay: Duration) -> Duration {
+        if self.fraction <= 0.0 {
+            return delay;
+        }
+        let scale = 1.0 + self.rng.gen_range(-self.fraction..=self.fraction);
+        delay.mul_f64(scale)
+    }
+}
+
+/// Decides when to stop reconnecting to a camera that can't be reached, such
+/// as one that was unplugged for good
+///
+/// Only the attempts that could not connect at all count, and a connection
+/// starts the count over. Without `max_reconnects` or `max_retry_duration` it
+/// never gives up.
+pub(crate) struct GiveUp {
+    max_reconnects: Option<u32>,
+    max_duration: Option<Duration>,
+    failures: u32,
+    failing_since: Option<Instant>,
+}
+
+impl GiveUp {
+    pub(crate) fn for_camera(camera_config: &CameraConfig) -> Self {
+        Self::new(
+            camera_config.max_reconnects,
+            camera_config.max_retry_duration.map(Duration::from_secs),
+        )
+    }
+
+    pub(crate) fn new(max_reconnects: Option<u32>, max_duration: Option<Duration>) -> Self {
+        GiveUp {
+            max_reconnects,
+            max_duration,
+            failures: 0,
+            failing_since: None,
+        }
+    }
+
+    /// The camera was connected to
+    pub(crate) fn connected(&mut self) {
+        self.failures = 0;
+        self.failing_since = None;
+    }
+
+    /// Count an attempt at `now` that could not connect, true once it is time to give up
+    pub(crate) fn failed(&mut self, now: Instant) -> bool {
+        self.failures += 1;
+        let since = *self.failing_since.get_or_insert(now);
+        self.max_reconnects.is_some_and(|max| self.failures >= max)
+            || self
+                .max_duration
+                .is_some_and(|max| now.duration_since(since) >= max)
+    }
+
+    /// How many attempts failed in a row and for how long, for the log
+    pub(crate) fn failing(&self, now: Instant) -> (u32, Duration) {
+        let duration = self
+            .failing_since
+            .map_or(Duration::ZERO, |since| now.duration_since(since));
+        (self.failures, duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passwords(passwords: &[&str]) -> Vec<Option<String>> {
+        passwords.iter().map(|p| Some(p.to_string())).collect()
+    }
+
+    #[test]
+    fn test_password_order() {
+        let old_new = passwords(&["old", "new", "newer"]);
+        let new = password_hash(&old_new[1]);
+        assert_eq!(password_order(&old_new, None), vec![0, 1, 2]);
+        assert_eq!(password_order(&old_new, Some(new)), vec![1, 0, 2]);
+        // The list was reordered on a reload
+        let reordered = passwords(&["newer", "old", "new"]);
+        assert_eq!(password_order(&reordered, Some(new)), vec![2, 0, 1]);
+        // A remembered password that was removed from the config
+        let removed = passwords(&["old", "newer"]);
+        assert_eq!(password_order(&removed, Some(new)), vec![0, 1]);
+    }
+
+    fn working(password: &Option<String>, refused: bool) -> Option<WorkingPassword> {
+        Some(WorkingPassword {
+            hash: password_hash(password),
+            refused,
+        })
+    }
+
+    #[test]
+    fn test_login_refusal() {
+        let old_new = passwords(&["old", "new"]);
+        assert!(matches!(
+            login_refusal(&old_new[0], None),
+            neolink_core::Error::AuthFailed
+        ));
+        assert!(matches!(
+            login_refusal(&old_new[1], working(&old_new[0], false)),
+            neolink_core::Error::AuthFailed
+        ));
+        // The password that worked before
+        assert!(matches!(
+            login_refusal(&old_new[1], working(&old_new[1], false)),
+            neolink_core::Error::LoginLocked
+        ));
+        assert!(matches!(
+            login_refusal(&None, working(&None, false)),
+            neolink_core::Error::LoginLocked
+        ));
+        // Refused again after the lockout
+        assert!(matches!(
+            login_refusal(&old_new[1], working(&old_new[1], true)),
+            neolink_core::Error::AuthFailed
+        ));
+    }
+
+    #[test]
+    fn test_login_after_the_password_changed() {
+        let name = "test_login_after_the_password_changed";
+        let old_new = passwords(&["old", "new"]);
+        // The camera accepts `accepted` and records the passwords it was sent
+        let login = |accepted: &'static str, tried: &mut Vec<String>| {
+            let result = login_with_passwords(name, &old_new, |password| {
+                let password = password.clone().unwrap();
+                tried.push(password.clone());
+                match password == accepted {
+                    true => Ok(password),
+                    false => Err(neolink_core::Error::AuthFailed.into()),
+                }
+            });
+            (result, std::mem::take(tried))
+        };
+        let mut tried = vec![];
+
+        let (result, sent) = login("old", &mut tried);
+        assert_eq!(result.unwrap(), "old");
+        assert_eq!(sent, ["old"]);
+
+        // The password was changed on the camera, the first refusal looks like a lockout
+        let (result, sent) = login("new", &mut tried);
+        assert!(is_login_locked(&result.unwrap_err()));
+        assert_eq!(sent, ["old"]);
+
+        // Once the lockout wait is over the other passwords are tried
+        let (result, sent) = login("new", &mut tried);
+        assert_eq!(result.unwrap(), "new");
+        assert_eq!(sent, ["old", "new"]);
+        let (result, sent) = login("new", &mut tried);
+        assert_eq!(result.unwrap(), "new");
+        assert_eq!(sent, ["new"]);
+
+        // None of them are right
+        let (result, sent) = login("newer", &mut tried);
+        assert!(is_login_locked(&result.unwrap_err()));
+        assert_eq!(sent, ["new"]);
+        let (result, sent) = login("newer", &mut tried);
+        let err = result.unwrap_err();
+        assert!(is_auth_failed(&err) && !is_login_locked(&err));
+        assert_eq!(sent, ["new", "old"]);
+    }
+
+    #[test]
+    fn test_address_ipv4() {
+        assert_eq!(
+            resolve_address("192.168.1.187:9000").unwrap(),
+            vec!["192.168.1.187:9000".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolve_address("192.168.1.187").unwrap(),
+            vec!["192.168.1.187:9000".parse::<SocketAddr>().unwrap()]
+        );
+        assert!(resolve_address("192.168.1.187:port").is_err());
+    }
+
+    #[test]
+    fn test_address_ipv6() {
+        assert_eq!(
+            resolve_address("[2001:db8::1]:9001").unwrap(),
+            vec!["[2001:db8::1]:9001".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolve_address("[2001:db8::1]").unwrap(),
+            vec!["[2001:db8::1]:9000".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolve_address("2001:db8::1").unwrap(),
+            vec!["[2001:db8::1]:9000".parse::<SocketAddr>().unwrap()]
+        );
+        assert!(resolve_address("[2001:db8::1").is_err());
+        assert!(resolve_address("[2001:db8::1]9000").is_err());
+    }
+
+    #[test]
+    fn test_address_ipv6_zone() {
+        let expected = SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 9000, 0, 3));
+        assert_eq!(resolve_address("[fe80::1%3]:9000").unwrap(), vec![expected]);
+        assert_eq!(resolve_address("fe80::1%3").unwrap(), vec![expected]);
+        assert!(resolve_address("[fe80::1%no-such-interface]:9000").is_err());
+        assert!(resolve_address("192.168.1.187%3").is_err());
+    }
+
+    #[test]
+    fn test_address_or_uid_port() {
+        let address = |addr: &str, port| {
+            AddressOrUid::new(&Some(addr.to_string()), &None, port).map(|a| a.to_string())
+        };
+        assert_eq!(address("camera", None).unwrap(), "Address: camera:9000");
+        assert_eq!(
+            address("camera", Some(9100)).unwrap(),
+            "Address: camera:9100"
+        );
+        assert_eq!(
+            address("camera:9100", None).unwrap(),
+            "Address: camera:9100"
+        );
+        assert_eq!(
+            address("camera:9100", Some(9100)).unwrap(),
+            "Address: camera:9100"
+        );
+        assert!(address("camera:9100", Some(9200)).is_err());
+        assert_eq!(
+            address("2001:db8::1", Some(9100)).unwrap(),
+            "Address: [2001:db8::1]:9100"
+        );
+        assert_eq!(
+            resolve_address(&address("fe80::1%3", None).unwrap()["Address: ".len()..]).unwrap(),
+            vec![SocketAddr::V6(SocketAddrV6::new(
+                "fe80::1".parse().unwrap(),
+                9000,
+                0,
+                3
+            ))]
+        );
+
+        let uid = |port| AddressOrUid::new(&None, &Some("95270000ABCDEFGH".to_string()), port);
+        assert_eq!(uid(None).unwrap().to_string(), "UID: 95270000ABCDEFGH");
+        // The port of a uid comes from the discovery
+        assert!(uid(Some(9100)).is_err());
+    }
+
+    #[test]
+    fn test_address_hostname() {
+        assert_eq!(
+            split_host_port("camera.lan:9000").unwrap(),
+            ("camera.lan", Some(9000))
+        );
+        assert_eq!(split_host_port("camera.lan").unwrap(), ("camera.lan", None));
+        assert!(split_host_port(":9000").is_err());
+
+        let addrs = resolve_address("localhost:9000").unwrap();
+        assert!(!addrs.is_empty());
+        assert!(addrs
+            .iter()
+            .all(|a| a.port() == 9000 && a.ip().is_loopback()));
+    }
+
+    #[test]
+    fn test_jitter_reproducible() {
+        let mut a = Jitter::with_seed(0.25, 42);
+        let mut b = Jitter::with_seed(0.25, 42);
+        for _ in 0..10 {
+            let delay = Duration::from_secs(4);
+            assert_eq!(a.apply(delay), b.apply(delay));
+        }
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let mut jitter = Jitter::with_seed(0.25, 7);
+        let delay = Duration::from_secs(8);
+        let delays: Vec<_> = (0..100).map(|_| jitter.apply(delay)).collect();
+        assert!(delays
+            .iter()
+            .all(|d| *d >= Duration::from_secs(6) && *d <= Duration::from_secs(10)));
+        // It should actually spread them out
+        assert!(delays.iter().any(|d| *d != delay));
+    }
+
+    #[test]
+    fn test_jitter_disabled() {
+        let mut jitter = Jitter::with_seed(0.0, 7);
+        assert_eq!(jitter.apply(Duration::from_secs(2)), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_give_up() {
+        let start = Instant::now();
+        let secs = |s: u64| start + Duration::from_secs(s);
+        let mut unlimited = GiveUp::new(None, None);
+        assert!((0..100).all(|s| !unlimited.failed(secs(s * 3600))));
+
+        let mut give_up = GiveUp::new(Some(3), None);
+        assert!(!give_up.failed(secs(0)));
+        assert!(!give_up.failed(secs(1)));
+        // A connection starts the count over
+        give_up.connected();
+        assert!(!give_up.failed(secs(2)));
+        assert!(!give_up.failed(secs(3)));
+        assert!(give_up.failed(secs(4)));
+        assert_eq!(give_up.failing(secs(4)), (3, Duration::from_secs(2)));
+
+        let mut give_up = GiveUp::new(None, Some(Duration::from_secs(60)));
+        assert!(!give_up.failed(secs(10)));
+        assert!(!give_up.failed(secs(69)));
+        assert!(give_up.failed(secs(70)));
+    }
+
+    // Collects the kinds of media that it was given
+    #[derive(Default)]
+    struct Kinds(Vec<&'static str>);
+
+    impl StreamOutput for Kinds {
+        fn stream_recv(&mut self, media: BcMedia) -> StreamOutputError {
+            self.0.push(match media {
+                BcMedia::Iframe(_) | BcMedia::Pframe(_) => "video",
+                _ => "audio",
+            });
+            Ok(StreamOutputStatus::Continue)
+        }
+    }
+
+    // The kinds of a keyframe, an aac frame and an adpcm frame that get through `media_filter`
+    fn filtered(media_filter: &str) -> Vec<&'static str> {
+        use neolink_core::bcmedia::model::*;
+        let camera_config: CameraConfig = toml::from_str(&format!(
+            "name = \"Garage\"\nusername = \"admin\"\naddress = \"192.168.1.10\"\n{}",
+            media_filter
+        ))
+        .unwrap();
+        let mut kinds = Kinds::default();
+        let mut filter = MediaFilter::new(&camera_config, &mut kinds);
+        let media = vec![
+            BcMedia::Iframe(BcMediaIframe {
+                video_type: VideoType::H264,
+                microseconds: 0,
+                time: None,
+                data: vec![0; 8],
+            }),
+            BcMedia::Aac(BcMediaAac { data: vec![0; 8] }),
+            BcMedia::Adpcm(BcMediaAdpcm { data: vec![0; 8] }),
+        ];
+        for media in media {
+            assert!(matches!(
+                filter.stream_recv(media),
+                Ok(StreamOutputStatus::Continue)
+            ));
+        }
+        kinds.0
+    }
+
+    #[test]
+    fn test_media_filter() {
+        assert_eq!(filtered(""), ["video", "audio", "audio"]);
+        assert_eq!(filtered("media_filter = [\"video\"]"), ["video"]);
+        assert_eq!(filtered("media_filter = [\"audio\"]"), ["audio", "audio"]);
+        assert_eq!(
+            filtered("media_filter = [\"audio\", \"video\"]"),
+            ["video", "audio", "audio"]
+        );
+    }
+
+    #[test]
+    fn test_first_keyframe() {
+        let mut first = FirstKeyframe::start();
+        assert!(first.keyframe().is_some());
+        assert_eq!(first.keyframe(), None);
+    }
+
+    #[test]
+    fn test_camera_stop_interrupts_sleep() {
+        let stop = CameraStop::default();
+        assert!(!stop.sleep(Duration::from_millis(1)));
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                stop.stop();
+            });
+            assert!(stop.sleep(Duration::from_secs(60)));
+        });
+        assert!(stop.is_stopped());
+    }
+
+    #[test]
+    fn test_connect_slots_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let slots = ConnectSlots::default();
+        slots.set_max(Some(2));
+        let connecting = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..6 {
+                s.spawn(|| {
+                    let _slot = slots.acquire(0);
+                    let now = connecting.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    connecting.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_connect_slots_priority() {
+        let state = SlotState {
+            max: Some(1),
+            in_use: 0,
+            waiting: vec![0, 5, 0],
+        };
+        assert!(state.may_take(5));
+        assert!(!state.may_take(0));
+        let full = SlotState { in_use: 1, ..state };
+        assert!(!full.may_take(5));
+    }
+
+    #[test]
+    fn test_connect_slots_unlimited() {
+        let slots = ConnectSlots::default();
+        let _held: Vec<_> = (0..100).map(|_| slots.acquire(0)).collect();
+    }
+}
@@ -0,0 +1,9 @@
+use structopt::StructOpt;
+
+/// The version-info command will print the firmware of every camera in the config
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// Print comma separated values for importing into a spreadsheet
+    #[structopt(long)]
+    pub csv: bool,
+}
@@ -0,0 +1,137 @@
+///
+/// # Neolink Version Info
+///
+/// This module handles the version-info subcommand
+///
+/// The subcommand connects to every camera in the config and prints a
+/// table of their hardware and firmware versions. Cameras that cannot be
+/// reached are listed with the error instead.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink version-info --config=config.toml
+/// # For a spreadsheet
+/// neolink version-info --config=config.toml --csv > firmware.csv
+/// ```
+///
+use anyhow::{anyhow, Result};
+use neolink_core::bc::xml::VersionInfo;
+
+mod cmdline;
+
+use super::config::{CameraConfig, Config};
+use crate::utils::connect_and_login;
+pub(crate) use cmdline::Opt;
+
+const HEADINGS: [&str; 6] = ["Camera", "Hardware", "Firmware", "Build", "Serial", "Error"];
+
+/// Entry point for the version-info subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt, config: Config) -> Result<()> {
+    let results: Vec<(&CameraConfig, Result<VersionInfo>)> = crossbeam::scope(|s| {
+        let handles: Vec<_> = config
+            .cameras
+            .iter()
+            .map(|camera_config| {
+                (
+                    camera_config,
+                    s.spawn(move |_| fetch_version(camera_config)),
+                )
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|(camera_config, handle)| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("The version fetch panicked")));
+                (camera_config, result)
+            })
+            .collect()
+    })
+    .unwrap();
+
+    let rows: Vec<[String; 6]> = results
+        .iter()
+        .map(|(camera_config, result)| match result {
+            Ok(version) => [
+                camera_config.name.clone(),
+                version.hardwareVersion.clone(),
+                version.firmwareVersion.clone(),
+                version.buildDay.clone(),
+                version.serialNumber.clone(),
+                String::new(),
+            ],
+            Err(e) => [
+                camera_config.name.clone(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                format!("{:#}", e),
+            ],
+        })
+        .collect();
+
+    if opt.csv {
+        println!("{}", HEADINGS.join(","));
+        for row in &rows {
+            let fields: Vec<_> = row.iter().map(|field| csv_field(field)).collect();
+            println!("{}", fields.join(","));
+        }
+    } else {
+        print_table(&rows);
+    }
+    Ok(())
+}
+
+fn fetch_version(camera_config: &CameraConfig) -> Result<VersionInfo> {
+    let camera = connect_and_login(camera_config)?;
+    camera
+        .version()
+        .map_err(|e| anyhow!("Could not fetch version information: {}", e))
+}
+
+fn print_table(rows: &[[String; 6]]) {
+    let mut widths = HEADINGS.map(str::len);
+    for row in rows {
+        for (width, field) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(field.len());
+        }
+    }
+    let line = |fields: Vec<&str>| {
+        let padded: Vec<_> = fields
+            .iter()
+            .zip(widths.iter())
+            .map(|(field, width)| format!("{:<width$}", field, width = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(HEADINGS.to_vec());
+    for row in rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
+
+// Quote a field if it contains anything that would break the csv
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("v2.0.0.587_19110800"), "v2.0.0.587_19110800");
+        assert_eq!(csv_field("Timed out, retrying"), "\"Timed out, retrying\"");
+        assert_eq!(csv_field("a \"b\""), "\"a \"\"b\"\"\"");
+    }
+}